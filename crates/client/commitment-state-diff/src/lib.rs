@@ -6,6 +6,8 @@ use std::task::Poll;
 use futures::channel::mpsc;
 use futures::{Stream, StreamExt};
 use indexmap::{IndexMap, IndexSet};
+use mc_db::BonsaiDbs;
+use mc_deoxys::commitments::lib::state_root_at;
 use mp_hashers::HasherT;
 use mp_storage::{SN_COMPILED_CLASS_HASH_PREFIX, SN_CONTRACT_CLASS_HASH_PREFIX, SN_NONCE_PREFIX, SN_STORAGE_PREFIX};
 use pallet_starknet_runtime_api::StarknetRuntimeApi;
@@ -27,7 +29,12 @@ pub struct BlockDAData {
     pub num_addr_accessed: usize,
     pub block_number: u64,
     pub config_hash: StarkHash,
+    /// State root after the block, computed from the state tries
+    ///
+    /// The sync only imports a block once this root matches the one advertised by the feeder
+    /// gateway, so it is not checked again here.
     pub new_state_root: StarkHash,
+    /// State root before the block, computed from the state tries
     pub previous_state_root: StarkHash,
 }
 
@@ -88,6 +95,7 @@ where
                                  diff: {e}",
                             );
 
+                            cx.waker().wake_by_ref();
                             return Poll::Pending;
                         }
                     }
@@ -135,6 +143,8 @@ pub enum BuildCommitmentStateDiffError {
     DigestLogNotFound(#[from] mp_digest_log::FindLogError),
     #[error("failed to get config hash")]
     FailedToGetConfigHash(#[from] sp_api::ApiError),
+    #[error("state tries not available at block {0}")]
+    StateTriesNotFound(u64),
 }
 
 fn build_commitment_state_diff<B: BlockT, C, H>(
@@ -234,23 +244,45 @@ where
 
     let config_hash = client.runtime_api().config_hash(storage_notification.block)?;
 
+    let block_number = current_block.header().block_number;
+    let bonsai_dbs = BonsaiDbs {
+        contract: Arc::clone(backend.bonsai_contract()),
+        class: Arc::clone(backend.bonsai_class()),
+        storage: Arc::clone(backend.bonsai_storage()),
+    };
+    let state_root = |block_number| {
+        state_root_at(&bonsai_dbs, block_number)
+            .map(StarkHash::from)
+            .ok_or(BuildCommitmentStateDiffError::StateTriesNotFound(block_number))
+    };
+
+    let new_state_root = state_root(block_number)?;
+    let previous_state_root = match block_number.checked_sub(1) {
+        Some(parent) => state_root(parent)?,
+        None => StarkHash::default(),
+    };
+
     Ok(BlockDAData {
         block_hash: current_block.header().hash::<H>().into(),
         state_diff: commitment_state_diff,
         num_addr_accessed: accessed_addrs.len(),
-        block_number: current_block.header().block_number,
+        block_number,
         config_hash,
-        // TODO: fix when we implement state root
-        new_state_root: backend.temporary_global_state_root_getter(),
-        previous_state_root: backend.temporary_global_state_root_getter(),
+        new_state_root,
+        previous_state_root,
     })
 }
 
+/// Consumes the data availability data of the imported blocks, whose state roots the sync checked
+/// against the ones advertised by the feeder gateway before importing them.
 pub async fn verify_l2(mut rx: mpsc::Receiver<BlockDAData>) {
-    while let Some(_rx) = rx.next().await {
-        // TODO: retrieve and deal with state commitment accross L1
-        // println!("➡️ block_hash {:?} with {:?}", block_hash, state_commitment(csd).0);
-        // update_l2({block_number, block_hash, state_commitment})
+    while let Some(block_da_data) = rx.next().await {
+        log::debug!(
+            "✅ Verified state root of block #{}: {} -> {}",
+            block_da_data.block_number,
+            block_da_data.previous_state_root,
+            block_da_data.new_state_root
+        );
     }
 }
 
@@ -1,5 +1,6 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use bonsai_trie::id::Id;
 use bonsai_trie::{BonsaiDatabase, BonsaiPersistentDatabase, DatabaseKey};
@@ -8,14 +9,17 @@ use sp_runtime::traits::Block as BlockT;
 
 use crate::error::BonsaiDbError;
 
-#[derive(Debug)]
+/// Number of blocks for which the state of the tries can be reopened.
+pub const MAX_SAVED_SNAPSHOTS: u64 = 1000;
+
+#[derive(Debug, Clone, Copy)]
 pub enum TrieColumn {
     Class,
     Contract,
     Storage,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum KeyType {
     Trie,
     Flat,
@@ -44,6 +48,110 @@ impl TrieColumn {
     }
 }
 
+impl KeyType {
+    /// Tag of the key type in the snapshot journal. Trie logs are not journaled.
+    fn journal_tag(&self) -> Option<u8> {
+        match self {
+            KeyType::Trie => Some(0),
+            KeyType::Flat => Some(1),
+            KeyType::TrieLog => None,
+        }
+    }
+
+    fn from_journal_tag(tag: u8) -> Self {
+        match tag {
+            0 => KeyType::Trie,
+            _ => KeyType::Flat,
+        }
+    }
+}
+
+/// Snapshot journal, stored in the trie-log columns alongside the bonsai trie logs.
+///
+/// Every write to the trie and flat columns records the value the key had before the block being
+/// imported, first in a pending area and then, once the block is committed, under its block number.
+/// The state at block `n` is the current state with the journals of blocks `n + 1..` applied, so
+/// the value of a key at block `n` is the one recorded by the first block after `n` which changed
+/// it. The blocks which changed each key are indexed, so that it is found in a single lookup.
+///
+/// Bonsai trie logs are keyed by their commit id, which never starts with `0xff`.
+mod journal {
+    const TAG: &[u8] = b"\xffjournal";
+    const PENDING: u8 = 0;
+    const BLOCK: u8 = 1;
+    const MARKER: u8 = 2;
+    const HEAD: u8 = 3;
+    const INDEX: u8 = 4;
+
+    pub fn pending_prefix() -> Vec<u8> {
+        [TAG, &[PENDING]].concat()
+    }
+
+    pub fn pending(key_tag: u8, key: &[u8]) -> Vec<u8> {
+        [TAG, &[PENDING, key_tag], key].concat()
+    }
+
+    pub fn block_prefix(block_number: u64) -> Vec<u8> {
+        [TAG, &[BLOCK], &block_number.to_be_bytes()].concat()
+    }
+
+    pub fn blocks_prefix() -> Vec<u8> {
+        [TAG, &[BLOCK]].concat()
+    }
+
+    pub fn block(block_number: u64, key_tag: u8, key: &[u8]) -> Vec<u8> {
+        [block_prefix(block_number).as_slice(), &[key_tag], key].concat()
+    }
+
+    /// Written when a block is committed, so that pruned snapshots can be told apart from blocks
+    /// that did not change the trie.
+    pub fn marker(block_number: u64) -> Vec<u8> {
+        [TAG, &[MARKER], &block_number.to_be_bytes()].concat()
+    }
+
+    /// Last block whose changes to all the tries were committed.
+    pub fn head() -> Vec<u8> {
+        [TAG, &[HEAD]].concat()
+    }
+
+    /// Blocks whose journal records a value of the key, in ascending order.
+    pub fn index(key_tag: u8, key: &[u8]) -> Vec<u8> {
+        [TAG, &[INDEX, key_tag], key].concat()
+    }
+
+    pub fn encode_index(blocks: &[u64]) -> Vec<u8> {
+        blocks.iter().flat_map(|block_number| block_number.to_be_bytes()).collect()
+    }
+
+    pub fn decode_index(value: &[u8]) -> Vec<u64> {
+        value.chunks_exact(8).map(|chunk| u64::from_be_bytes(chunk.try_into().expect("chunks of 8 bytes"))).collect()
+    }
+
+    /// The first block of `first..=last` in an index, if any.
+    pub fn first_indexed(index: &[u64], first: u64, last: u64) -> Option<u64> {
+        index.get(index.partition_point(|block_number| *block_number < first)).copied().filter(|n| *n <= last)
+    }
+
+    pub fn encode(value: Option<&[u8]>) -> Vec<u8> {
+        match value {
+            Some(value) => [&[1u8][..], value].concat(),
+            None => vec![0],
+        }
+    }
+
+    pub fn decode(value: &[u8]) -> Option<Vec<u8>> {
+        match value.split_first() {
+            Some((&1, value)) => Some(value.to_vec()),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a bonsai commit id into the block number it was committed at.
+fn block_number<ID: Id>(id: &ID) -> Option<u64> {
+    id.to_bytes().try_into().ok().map(u64::from_be_bytes)
+}
+
 /// Represents a Bonsai database instance parameterized by a block type.
 pub struct BonsaiDb<B: BlockT> {
     /// Database interface for key-value operations.
@@ -52,6 +160,242 @@ pub struct BonsaiDb<B: BlockT> {
     pub(crate) _marker: PhantomData<B>,
     /// Set current column to give trie context
     pub(crate) current_column: TrieColumn,
+    /// Prefix prepended to every key, used to give each contract its own storage trie
+    pub(crate) prefix: Vec<u8>,
+    /// Keys journaled since the last committed block, shared by all the views of the trie, so that
+    /// the pending journal is not looked up on every write. `None` until the pending journal is
+    /// known to be empty, e.g. after a restart.
+    pub(crate) pending: Arc<Mutex<Option<HashSet<Vec<u8>>>>>,
+}
+
+impl<B: BlockT> BonsaiDb<B> {
+    /// Opens the trie stored in `current_column`.
+    pub(crate) fn new(db: Arc<dyn KeyValueDB>, current_column: TrieColumn) -> Self {
+        Self { db, _marker: PhantomData, current_column, prefix: Vec::new(), pending: Default::default() }
+    }
+
+    /// Returns a view of the same database where every key is namespaced under `prefix`.
+    ///
+    /// This is used to store one storage trie per contract in the same columns.
+    pub fn with_prefix(&self, prefix: &[u8]) -> Self {
+        Self {
+            db: Arc::clone(&self.db),
+            _marker: PhantomData,
+            current_column: self.current_column,
+            prefix: prefix.to_vec(),
+            pending: Arc::clone(&self.pending),
+        }
+    }
+
+    fn prefixed_key(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), key].concat()
+    }
+
+    fn log_column(&self) -> u32 {
+        self.current_column.to_index(KeyType::TrieLog)
+    }
+
+    /// Returns the last block whose changes to the tries were fully committed.
+    pub fn head(&self) -> Result<Option<u64>, BonsaiDbError> {
+        let head = self.db.get(self.log_column(), &journal::head())?;
+        Ok(head.and_then(|head| head.try_into().ok()).map(u64::from_be_bytes))
+    }
+
+    /// Opens a read-only view of the trie as it was at `block_number`.
+    ///
+    /// Returns `None` if the block is ahead of the last committed block or if its snapshot was
+    /// pruned.
+    pub fn at_block(&self, block_number: u64) -> Option<TransactionWrapper> {
+        let head = self.head().ok()??;
+        if block_number > head {
+            return None;
+        }
+        if block_number < head && !self.db.has_key(self.log_column(), &journal::marker(block_number + 1)).ok()? {
+            return None;
+        }
+
+        Some(TransactionWrapper {
+            db: Arc::clone(&self.db),
+            current_column: self.current_column,
+            prefix: self.prefix.clone(),
+            first_block: block_number + 1,
+            head,
+            changes: BTreeMap::new(),
+        })
+    }
+
+    /// Marks the pending journal as empty once it was committed or reverted, or as unknown if the
+    /// write failed.
+    pub(crate) fn reset_pending(&self, written: bool) {
+        *self.pending.lock().expect("poisoned bonsai pending journal") = written.then(HashSet::new);
+    }
+
+    /// Records the value `key` had before the block being imported, unless it is already recorded.
+    fn journal(
+        &self,
+        key_type: KeyType,
+        key: &[u8],
+        previous_value: Option<&[u8]>,
+        batch: &mut DBTransaction,
+    ) -> Result<(), BonsaiDbError> {
+        let Some(key_tag) = key_type.journal_tag() else {
+            return Ok(());
+        };
+
+        let log_column = self.log_column();
+        let pending_key = journal::pending(key_tag, key);
+        let mut pending = self.pending.lock().expect("poisoned bonsai pending journal");
+        let journaled = match pending.as_mut() {
+            Some(pending) => !pending.insert(pending_key.clone()),
+            None => self.db.has_key(log_column, &pending_key)?,
+        };
+        if !journaled {
+            batch.put(log_column, &pending_key, &journal::encode(previous_value));
+        }
+
+        Ok(())
+    }
+
+    /// Moves the pending journal under `block_number`, and adds the block to the index of each key.
+    fn flush_pending(&self, block_number: u64, transaction: &mut DBTransaction) -> Result<(), BonsaiDbError> {
+        let log_column = self.log_column();
+        let pending_prefix = journal::pending_prefix();
+
+        for pair in self.db.iter_with_prefix(log_column, &pending_prefix) {
+            let (key, value) = pair?;
+            let Some((key_tag, journaled_key)) = key[pending_prefix.len()..].split_first() else {
+                continue;
+            };
+            let index_key = journal::index(*key_tag, journaled_key);
+            let mut index =
+                self.db.get(log_column, &index_key)?.map(|index| journal::decode_index(&index)).unwrap_or_default();
+
+            // The journal of a block keeps the oldest value of each key.
+            if !index.contains(&block_number) {
+                // Blocks which were pruned or reverted are dropped from the index
+                let pruned = block_number.checked_sub(MAX_SAVED_SNAPSHOTS);
+                index.retain(|n| *n < block_number && pruned.map_or(true, |pruned| *n > pruned));
+                index.push(block_number);
+                transaction.put(log_column, &index_key, &journal::encode_index(&index));
+                transaction.put(log_column, &journal::block(block_number, *key_tag, journaled_key), &value);
+            }
+            transaction.delete(log_column, &key);
+        }
+
+        Ok(())
+    }
+
+    /// Adds to `transaction` the writes moving the pending journal under `block_number` and
+    /// marking it as fully committed, so that a block is either entirely committed or not at all.
+    pub(crate) fn commit_block(&self, block_number: u64, transaction: &mut DBTransaction) -> Result<(), BonsaiDbError> {
+        let log_column = self.log_column();
+        self.flush_pending(block_number, transaction)?;
+        transaction.put(log_column, &journal::marker(block_number), &[]);
+        transaction.put(log_column, &journal::head(), &block_number.to_be_bytes());
+
+        if let Some(pruned) = block_number.checked_sub(MAX_SAVED_SNAPSHOTS) {
+            transaction.delete_prefix(log_column, &journal::block_prefix(pruned));
+            transaction.delete(log_column, &journal::marker(pruned));
+        }
+
+        Ok(())
+    }
+
+    /// Blocks with a journal strictly after `block_number`, or all of them if `None`.
+    fn journaled_blocks_after(&self, block_number: Option<u64>) -> Result<Vec<u64>, BonsaiDbError> {
+        let log_column = self.log_column();
+        if let (Some(block_number), Some(head)) = (block_number, self.head()?) {
+            return Ok((block_number + 1..=head + 1)
+                .filter(|n| self.db.iter_with_prefix(log_column, &journal::block_prefix(*n)).next().is_some())
+                .collect());
+        }
+
+        let blocks_prefix = journal::blocks_prefix();
+        let mut blocks = BTreeSet::new();
+        for pair in self.db.iter_with_prefix(log_column, &blocks_prefix) {
+            let (key, _) = pair?;
+            let number = key[blocks_prefix.len()..blocks_prefix.len() + 8].try_into().map(u64::from_be_bytes);
+            match number {
+                Ok(number) if block_number.map_or(true, |block_number| number > block_number) => {
+                    blocks.insert(number);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(blocks.into_iter().collect())
+    }
+
+    /// Adds to `transaction` the writes restoring the trie to its state at `block_number`, or to an
+    /// empty trie if `None`. Uncommitted changes are always reverted.
+    pub(crate) fn revert_to(
+        &self,
+        block_number: Option<u64>,
+        transaction: &mut DBTransaction,
+    ) -> Result<(), BonsaiDbError> {
+        let log_column = self.log_column();
+        let mut restored = BTreeMap::new();
+
+        let mut reverted_keys = BTreeSet::new();
+
+        // Blocks are visited from the oldest so that the value before the first change wins.
+        for number in self.journaled_blocks_after(block_number)? {
+            let block_prefix = journal::block_prefix(number);
+            for pair in self.db.iter_with_prefix(log_column, &block_prefix) {
+                let (key, value) = pair?;
+                reverted_keys.insert(key[block_prefix.len()..].to_vec());
+                restored.entry(key[block_prefix.len()..].to_vec()).or_insert(value);
+            }
+            transaction.delete_prefix(log_column, &block_prefix);
+            transaction.delete(log_column, &journal::marker(number));
+        }
+
+        // The reverted blocks are dropped from the index of the keys they changed
+        for entry in reverted_keys {
+            let Some((key_tag, key)) = entry.split_first() else {
+                continue;
+            };
+            let index_key = journal::index(*key_tag, key);
+            let Some(index) = self.db.get(log_column, &index_key)? else {
+                continue;
+            };
+            let mut index = journal::decode_index(&index);
+            index.retain(|n| block_number.is_some_and(|block_number| *n <= block_number));
+            if index.is_empty() {
+                transaction.delete(log_column, &index_key);
+            } else {
+                transaction.put(log_column, &index_key, &journal::encode_index(&index));
+            }
+        }
+
+        let pending_prefix = journal::pending_prefix();
+        for pair in self.db.iter_with_prefix(log_column, &pending_prefix) {
+            let (key, value) = pair?;
+            restored.entry(key[pending_prefix.len()..].to_vec()).or_insert(value);
+        }
+        transaction.delete_prefix(log_column, &pending_prefix);
+
+        for (entry, value) in restored {
+            let Some((key_tag, key)) = entry.split_first() else {
+                continue;
+            };
+            let column = self.current_column.to_index(KeyType::from_journal_tag(*key_tag));
+            match journal::decode(&value) {
+                Some(value) => transaction.put(column, key, &value),
+                None => transaction.delete(column, key),
+            }
+        }
+
+        match (block_number, self.head()?) {
+            (Some(block_number), Some(head)) if block_number < head => {
+                transaction.put(log_column, &journal::head(), &block_number.to_be_bytes())
+            }
+            (None, _) => transaction.delete(log_column, &journal::head()),
+            _ => {}
+        }
+
+        Ok(())
+    }
 }
 
 pub fn key_type(key: &DatabaseKey) -> KeyType {
@@ -75,27 +419,30 @@ impl<B: BlockT> BonsaiDatabase for &BonsaiDb<B> {
     fn get(&self, key: &DatabaseKey) -> Result<Option<Vec<u8>>, Self::DatabaseError> {
         let key_type = key_type(key);
         let column = self.current_column.to_index(key_type);
-        let key_slice = key.as_slice();
+        let key_slice = &self.prefixed_key(key.as_slice())[..];
         self.db.get(column, key_slice).map_err(Into::into)
     }
 
     /// Inserts a key-value pair into the database, optionally within a provided batch.
+    ///
+    /// The previous value is recorded in the snapshot journal.
     fn insert(
         &mut self,
         key: &DatabaseKey,
         value: &[u8],
         batch: Option<&mut Self::Batch>,
     ) -> Result<Option<Vec<u8>>, Self::DatabaseError> {
-        // println!("Key and keytype: {:?} {:?}", self.current_column, key_type(key));
         let key_type = key_type(key);
         let column = self.current_column.to_index(key_type);
-        let key_slice = key.as_slice();
+        let key_slice = &self.prefixed_key(key.as_slice())[..];
         let previous_value = self.db.get(column, key_slice)?;
 
         if let Some(batch) = batch {
+            self.journal(key_type, key_slice, previous_value.as_deref(), batch)?;
             batch.put(column, key_slice, value);
         } else {
             let mut transaction = self.create_batch();
+            self.journal(key_type, key_slice, previous_value.as_deref(), &mut transaction)?;
             transaction.put(column, key_slice, value);
             self.db.write(transaction)?;
         }
@@ -107,7 +454,7 @@ impl<B: BlockT> BonsaiDatabase for &BonsaiDb<B> {
     fn contains(&self, key: &DatabaseKey) -> Result<bool, Self::DatabaseError> {
         let key_type = key_type(key);
         let column = self.current_column.to_index(key_type);
-        let key_slice = key.as_slice();
+        let key_slice = &self.prefixed_key(key.as_slice())[..];
         self.db.has_key(column, key_slice).map_err(Into::into)
     }
 
@@ -115,18 +462,20 @@ impl<B: BlockT> BonsaiDatabase for &BonsaiDb<B> {
     fn get_by_prefix(&self, prefix: &DatabaseKey) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::DatabaseError> {
         let key_type = key_type(prefix);
         let column = self.current_column.to_index(key_type);
-        let prefix_slice = prefix.as_slice();
+        let prefix_slice = &self.prefixed_key(prefix.as_slice())[..];
         let mut result = Vec::new();
 
         for pair in self.db.iter_with_prefix(column, prefix_slice) {
             let pair = pair.map_err(|e| BonsaiDbError::from(e))?;
-            result.push((pair.0.into_vec(), pair.1));
+            result.push((pair.0[self.prefix.len()..].to_vec(), pair.1));
         }
 
         Ok(result)
     }
 
     /// Removes a key-value pair from the database, optionally within a provided batch.
+    ///
+    /// The previous value is recorded in the snapshot journal.
     fn remove(
         &mut self,
         key: &DatabaseKey,
@@ -134,13 +483,15 @@ impl<B: BlockT> BonsaiDatabase for &BonsaiDb<B> {
     ) -> Result<Option<Vec<u8>>, Self::DatabaseError> {
         let key_type = key_type(key);
         let column = self.current_column.to_index(key_type);
-        let key_slice = key.as_slice();
+        let key_slice = &self.prefixed_key(key.as_slice())[..];
         let previous_value = self.db.get(column, key_slice)?;
 
         if let Some(batch) = batch {
+            self.journal(key_type, key_slice, previous_value.as_deref(), batch)?;
             batch.delete(column, key_slice);
         } else {
             let mut transaction = self.create_batch();
+            self.journal(key_type, key_slice, previous_value.as_deref(), &mut transaction)?;
             transaction.delete(column, key_slice);
             self.db.write(transaction)?;
         }
@@ -149,11 +500,19 @@ impl<B: BlockT> BonsaiDatabase for &BonsaiDb<B> {
     }
 
     /// Removes all key-value pairs starting with a given prefix.
+    ///
+    /// The previous values are recorded in the snapshot journal.
     fn remove_by_prefix(&mut self, prefix: &DatabaseKey) -> Result<(), Self::DatabaseError> {
         let key_type = key_type(prefix);
         let column = self.current_column.to_index(key_type);
-        let prefix_slice = prefix.as_slice();
+        let prefix_slice = &self.prefixed_key(prefix.as_slice())[..];
         let mut transaction = self.create_batch();
+
+        for pair in self.db.iter_with_prefix(column, prefix_slice) {
+            let (key, value) = pair?;
+            self.journal(key_type, &key, Some(&value), &mut transaction)?;
+        }
+
         transaction.delete_prefix(column, prefix_slice);
         self.db.write(transaction).map_err(Into::into)
     }
@@ -164,13 +523,75 @@ impl<B: BlockT> BonsaiDatabase for &BonsaiDb<B> {
     }
 }
 
-/// A wrapper around a database transaction.
+/// A read-only view of a trie at a past block, obtained from [`BonsaiDb::at_block`].
+///
+/// Writes are kept in memory and only reach the database through
+/// [`BonsaiPersistentDatabase::merge`].
 pub struct TransactionWrapper {
-    /// The underlying database transaction.
-    _transaction: DBTransaction,
+    /// The underlying database.
+    db: Arc<dyn KeyValueDB>,
+    /// Column of the trie being viewed.
+    current_column: TrieColumn,
+    /// Prefix of the trie being viewed, see [`BonsaiDb::with_prefix`].
+    prefix: Vec<u8>,
+    /// The journals of the blocks `first_block..=head` are applied to the current state.
+    first_block: u64,
+    head: u64,
+    /// Changes made through this view, keyed by column and prefixed key.
+    changes: BTreeMap<(u32, Vec<u8>), Option<Vec<u8>>>,
+}
+
+impl TransactionWrapper {
+    /// Returns a view of the same snapshot where every key is namespaced under `prefix`.
+    pub fn with_prefix(&self, prefix: &[u8]) -> Self {
+        Self {
+            db: Arc::clone(&self.db),
+            current_column: self.current_column,
+            prefix: prefix.to_vec(),
+            first_block: self.first_block,
+            head: self.head,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    fn prefixed_key(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), key].concat()
+    }
+
+    fn get_at_snapshot(&self, key_type: KeyType, key: &[u8]) -> Result<Option<Vec<u8>>, BonsaiDbError> {
+        let column = self.current_column.to_index(key_type);
+        if let Some(value) = self.changes.get(&(column, key.to_vec())) {
+            return Ok(value.clone());
+        }
+
+        if let Some(key_tag) = key_type.journal_tag() {
+            let log_column = self.current_column.to_index(KeyType::TrieLog);
+            if let Some(index) = self.db.get(log_column, &journal::index(key_tag, key))? {
+                if let Some(value) = self.journaled(key_tag, key, &index)? {
+                    return Ok(value);
+                }
+            }
+            if let Some(value) = self.db.get(log_column, &journal::pending(key_tag, key))? {
+                return Ok(journal::decode(&value));
+            }
+        }
+
+        self.db.get(column, key).map_err(Into::into)
+    }
+
+    /// Returns the value recorded for `key` by the first block of the snapshot which changed it,
+    /// according to the index of its journal.
+    fn journaled(&self, key_tag: u8, key: &[u8], index: &[u8]) -> Result<Option<Option<Vec<u8>>>, BonsaiDbError> {
+        let Some(number) = journal::first_indexed(&journal::decode_index(index), self.first_block, self.head) else {
+            return Ok(None);
+        };
+        let log_column = self.current_column.to_index(KeyType::TrieLog);
+        let value = self.db.get(log_column, &journal::block(number, key_tag, key))?;
+
+        Ok(value.map(|value| journal::decode(&value)))
+    }
 }
 
-/// This implementation is a stub to mute BonsaiPersistentDatabase that is currently not needed.
 impl BonsaiDatabase for TransactionWrapper {
     type Batch = DBTransaction;
     type DatabaseError = BonsaiDbError;
@@ -180,67 +601,308 @@ impl BonsaiDatabase for TransactionWrapper {
         DBTransaction::new()
     }
 
-    /// Retrieves a value by its database key.
-    fn get(&self, _key: &DatabaseKey) -> Result<Option<Vec<u8>>, Self::DatabaseError> {
-        // Simulate database access without performing real operations
-        Ok(None)
+    /// Retrieves a value by its database key, as it was at the snapshot.
+    fn get(&self, key: &DatabaseKey) -> Result<Option<Vec<u8>>, Self::DatabaseError> {
+        self.get_at_snapshot(key_type(key), &self.prefixed_key(key.as_slice()))
     }
 
-    /// Inserts a key-value pair into the database.
+    /// Inserts a key-value pair in the view. The batch is not used.
     fn insert(
         &mut self,
-        _key: &DatabaseKey,
-        _value: &[u8],
+        key: &DatabaseKey,
+        value: &[u8],
         _batch: Option<&mut Self::Batch>,
     ) -> Result<Option<Vec<u8>>, Self::DatabaseError> {
-        Ok(None)
+        let key_type = key_type(key);
+        let key_slice = self.prefixed_key(key.as_slice());
+        let previous_value = self.get_at_snapshot(key_type, &key_slice)?;
+        self.changes.insert((self.current_column.to_index(key_type), key_slice), Some(value.to_vec()));
+
+        Ok(previous_value)
     }
 
-    /// Checks if a key exists in the database.
-    fn contains(&self, _key: &DatabaseKey) -> Result<bool, Self::DatabaseError> {
-        Ok(false)
+    /// Checks if a key exists in the view.
+    fn contains(&self, key: &DatabaseKey) -> Result<bool, Self::DatabaseError> {
+        self.get(key).map(|value| value.is_some())
     }
 
-    /// Retrieves all key-value pairs starting with a given prefix.
-    fn get_by_prefix(&self, _prefix: &DatabaseKey) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::DatabaseError> {
-        Ok(Vec::new())
+    /// Retrieves all key-value pairs starting with a given prefix, as they were at the snapshot.
+    fn get_by_prefix(&self, prefix: &DatabaseKey) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::DatabaseError> {
+        let key_type = key_type(prefix);
+        let column = self.current_column.to_index(key_type);
+        let prefix_slice = self.prefixed_key(prefix.as_slice());
+        let mut values = BTreeMap::new();
+
+        for pair in self.db.iter_with_prefix(column, &prefix_slice) {
+            let (key, value) = pair?;
+            values.insert(key.to_vec(), Some(value));
+        }
+
+        // The pending journal is applied before the journals of the blocks, whose values are older
+        if let Some(key_tag) = key_type.journal_tag() {
+            let log_column = self.current_column.to_index(KeyType::TrieLog);
+            let pending_prefix = journal::pending(key_tag, &[]);
+            let search_prefix = [pending_prefix.as_slice(), &prefix_slice].concat();
+            for pair in self.db.iter_with_prefix(log_column, &search_prefix) {
+                let (key, value) = pair?;
+                values.insert(key[pending_prefix.len()..].to_vec(), journal::decode(&value));
+            }
+
+            let index_prefix = journal::index(key_tag, &[]);
+            let search_prefix = [index_prefix.as_slice(), &prefix_slice].concat();
+            for pair in self.db.iter_with_prefix(log_column, &search_prefix) {
+                let (index_key, index) = pair?;
+                let key = &index_key[index_prefix.len()..];
+                if let Some(value) = self.journaled(key_tag, key, &index)? {
+                    values.insert(key.to_vec(), value);
+                }
+            }
+        }
+
+        for ((change_column, key), value) in &self.changes {
+            if *change_column == column && key.starts_with(&prefix_slice) {
+                values.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(values
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key[self.prefix.len()..].to_vec(), value)))
+            .collect())
     }
 
-    /// Removes a key-value pair from the database.
+    /// Removes a key-value pair from the view. The batch is not used.
     fn remove(
         &mut self,
-        _key: &DatabaseKey,
+        key: &DatabaseKey,
         _batch: Option<&mut Self::Batch>,
     ) -> Result<Option<Vec<u8>>, Self::DatabaseError> {
-        Ok(None)
+        let key_type = key_type(key);
+        let key_slice = self.prefixed_key(key.as_slice());
+        let previous_value = self.get_at_snapshot(key_type, &key_slice)?;
+        self.changes.insert((self.current_column.to_index(key_type), key_slice), None);
+
+        Ok(previous_value)
     }
 
-    /// Removes all key-value pairs starting with a given prefix.
-    fn remove_by_prefix(&mut self, _prefix: &DatabaseKey) -> Result<(), Self::DatabaseError> {
+    /// Removes all key-value pairs starting with a given prefix from the view.
+    fn remove_by_prefix(&mut self, prefix: &DatabaseKey) -> Result<(), Self::DatabaseError> {
+        let key_type = key_type(prefix);
+        let column = self.current_column.to_index(key_type);
+        for (key, _) in self.get_by_prefix(prefix)? {
+            self.changes.insert((column, self.prefixed_key(&key)), None);
+        }
+
         Ok(())
     }
 
-    /// Writes a batch of changes to the database.
+    /// Changes are applied to the view as they are made, so there is nothing left to write.
     fn write_batch(&mut self, _batch: Self::Batch) -> Result<(), Self::DatabaseError> {
         Ok(())
     }
 }
 
-/// This implementation is a stub to mute any error but is is currently not used.
+/// Snapshots are keyed by block number: bonsai commit ids must be the number of the block being
+/// imported.
 impl<B: BlockT, ID: Id> BonsaiPersistentDatabase<ID> for &BonsaiDb<B> {
     type Transaction = TransactionWrapper;
     type DatabaseError = BonsaiDbError;
 
-    /// Snapshots the current database state, associated with an ID.
-    fn snapshot(&mut self, _id: ID) {}
+    /// Nothing is written here: the changes made since the last block stay in the pending journal
+    /// until [`crate::BonsaiDbs::commit_block`] moves them under the block `id` and marks it as
+    /// committed, in a single write once all the tries have been updated.
+    fn snapshot(&mut self, id: ID) {
+        if block_number(&id).is_none() {
+            log::error!("Bonsai commit id is not a block number");
+        }
+    }
 
-    /// Begins a new transaction associated with a snapshot ID.
-    fn transaction(&self, _id: ID) -> Option<Self::Transaction> {
-        None
+    /// Opens a read-only view of the trie at the block `id`.
+    fn transaction(&self, id: ID) -> Option<Self::Transaction> {
+        self.at_block(block_number(&id)?)
     }
 
-    /// Merges a completed transaction into the persistent database.
-    fn merge(&mut self, _transaction: Self::Transaction) -> Result<(), Self::DatabaseError> {
-        Ok(())
+    /// Writes the changes made to a view into the persistent database.
+    ///
+    /// These changes are not recorded in the snapshot journal.
+    fn merge(&mut self, transaction: Self::Transaction) -> Result<(), Self::DatabaseError> {
+        let mut batch = self.create_batch();
+        for ((column, key), value) in transaction.changes {
+            match value {
+                Some(value) => batch.put(column, &key, &value),
+                None => batch.delete(column, &key),
+            }
+        }
+
+        self.db.write(batch).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bonsai_trie::id::BasicId;
+    use sp_runtime::generic::{Block, Header};
+    use sp_runtime::traits::BlakeTwo256;
+    use sp_runtime::OpaqueExtrinsic;
+
+    use super::*;
+    use crate::BonsaiDbs;
+
+    type TestBlock = Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>;
+
+    fn open(db: &Arc<dyn KeyValueDB>) -> BonsaiDbs<TestBlock> {
+        let trie = |current_column| Arc::new(BonsaiDb::new(Arc::clone(db), current_column));
+        BonsaiDbs {
+            contract: trie(TrieColumn::Contract),
+            class: trie(TrieColumn::Class),
+            storage: trie(TrieColumn::Storage),
+        }
+    }
+
+    fn memory_db() -> Arc<dyn KeyValueDB> {
+        Arc::new(kvdb_memorydb::create(crate::columns::NUM_COLUMNS))
+    }
+
+    fn set(db: &BonsaiDb<TestBlock>, key: &[u8], value: &[u8]) {
+        let mut db = db;
+        let mut batch = db.create_batch();
+        db.insert(&DatabaseKey::Trie(key), value, Some(&mut batch)).unwrap();
+        db.insert(&DatabaseKey::Flat(key), value, Some(&mut batch)).unwrap();
+        db.write_batch(batch).unwrap();
+    }
+
+    fn get(db: &BonsaiDb<TestBlock>, key: &[u8]) -> Option<Vec<u8>> {
+        let value = (&db).get(&DatabaseKey::Trie(key)).unwrap();
+        assert_eq!(value, (&db).get(&DatabaseKey::Flat(key)).unwrap());
+        value
+    }
+
+    fn get_at(db: &BonsaiDb<TestBlock>, block_number: u64, key: &[u8]) -> Option<Option<Vec<u8>>> {
+        let snapshot = db.at_block(block_number)?;
+        let value = snapshot.get(&DatabaseKey::Trie(key)).unwrap();
+        assert_eq!(value, snapshot.get(&DatabaseKey::Flat(key)).unwrap());
+        Some(value)
+    }
+
+    #[test]
+    fn commit_revert_and_recover() {
+        let bonsai_dbs = open(&memory_db());
+
+        set(&bonsai_dbs.contract, b"a", b"1");
+        set(&bonsai_dbs.class, b"c", b"1");
+        bonsai_dbs.commit_block(1).unwrap();
+
+        set(&bonsai_dbs.contract, b"a", b"2");
+        set(&bonsai_dbs.contract, b"b", b"2");
+        bonsai_dbs.commit_block(2).unwrap();
+
+        assert_eq!(bonsai_dbs.head().unwrap(), Some(2));
+        assert_eq!(get_at(&bonsai_dbs.contract, 1, b"a"), Some(Some(b"1".to_vec())));
+        assert_eq!(get_at(&bonsai_dbs.contract, 1, b"b"), Some(None));
+        assert_eq!(get_at(&bonsai_dbs.contract, 2, b"a"), Some(Some(b"2".to_vec())));
+        assert_eq!(get_at(&bonsai_dbs.contract, 3, b"a"), None);
+
+        bonsai_dbs.revert_to_block(1).unwrap();
+        assert_eq!(bonsai_dbs.head().unwrap(), Some(1));
+        assert_eq!(get(&bonsai_dbs.contract, b"a"), Some(b"1".to_vec()));
+        assert_eq!(get(&bonsai_dbs.contract, b"b"), None);
+        assert_eq!(get(&bonsai_dbs.class, b"c"), Some(b"1".to_vec()));
+        assert_eq!(get_at(&bonsai_dbs.contract, 2, b"a"), None);
+
+        // Uncommitted changes are dropped by the next recovery
+        set(&bonsai_dbs.contract, b"a", b"3");
+        set(&bonsai_dbs.class, b"c", b"3");
+        bonsai_dbs.recover().unwrap();
+        assert_eq!(bonsai_dbs.head().unwrap(), Some(1));
+        assert_eq!(get(&bonsai_dbs.contract, b"a"), Some(b"1".to_vec()));
+        assert_eq!(get(&bonsai_dbs.class, b"c"), Some(b"1".to_vec()));
+
+        bonsai_dbs.revert_to_block(0).unwrap();
+        assert_eq!(bonsai_dbs.head().unwrap(), Some(0));
+        assert_eq!(get(&bonsai_dbs.contract, b"a"), None);
+        assert_eq!(get(&bonsai_dbs.class, b"c"), None);
+    }
+
+    #[test]
+    fn crash_before_commit_is_recovered_on_restart() {
+        let db = memory_db();
+        let bonsai_dbs = open(&db);
+        set(&bonsai_dbs.contract, b"a", b"1");
+        set(&bonsai_dbs.storage, b"s", b"1");
+        bonsai_dbs.commit_block(1).unwrap();
+
+        // The tries of block 2 are partially written, then the node stops before the commit
+        set(&bonsai_dbs.contract, b"a", b"2");
+        set(&bonsai_dbs.storage, b"s", b"2");
+        set(&bonsai_dbs.storage.with_prefix(b"contract"), b"s", b"2");
+        // Snapshots taken by the tries themselves do not commit anything
+        BonsaiPersistentDatabase::<BasicId>::snapshot(&mut &*bonsai_dbs.contract, BasicId::new(2));
+        drop(bonsai_dbs);
+
+        let bonsai_dbs = open(&db);
+        assert_eq!(bonsai_dbs.head().unwrap(), Some(1));
+        assert_eq!(get_at(&bonsai_dbs.contract, 2, b"a"), None);
+
+        bonsai_dbs.recover().unwrap();
+        assert_eq!(get(&bonsai_dbs.contract, b"a"), Some(b"1".to_vec()));
+        assert_eq!(get(&bonsai_dbs.storage, b"s"), Some(b"1".to_vec()));
+        assert_eq!(get(&bonsai_dbs.storage.with_prefix(b"contract"), b"s"), None);
+
+        // Block 2 is imported again after the restart
+        set(&bonsai_dbs.contract, b"a", b"2");
+        bonsai_dbs.commit_block(2).unwrap();
+        assert_eq!(get_at(&bonsai_dbs.contract, 1, b"a"), Some(Some(b"1".to_vec())));
+        assert_eq!(get_at(&bonsai_dbs.contract, 2, b"a"), Some(Some(b"2".to_vec())));
+    }
+
+    #[test]
+    fn reimported_blocks_replace_their_reverted_journal() {
+        let bonsai_dbs = open(&memory_db());
+        for block_number in 1..=3 {
+            set(&bonsai_dbs.contract, b"a", &block_number.to_be_bytes());
+            set(&bonsai_dbs.contract, b"b", b"unchanged");
+            bonsai_dbs.commit_block(block_number).unwrap();
+        }
+
+        bonsai_dbs.revert_to_block(1).unwrap();
+        set(&bonsai_dbs.contract, b"a", b"reimported");
+        bonsai_dbs.commit_block(2).unwrap();
+
+        assert_eq!(get_at(&bonsai_dbs.contract, 1, b"a"), Some(Some(1_u64.to_be_bytes().to_vec())));
+        assert_eq!(get_at(&bonsai_dbs.contract, 2, b"a"), Some(Some(b"reimported".to_vec())));
+        assert_eq!(get_at(&bonsai_dbs.contract, 1, b"b"), Some(Some(b"unchanged".to_vec())));
+        assert_eq!(get_at(&bonsai_dbs.contract, 3, b"a"), None);
+    }
+
+    #[test]
+    fn first_indexed_block_of_a_snapshot() {
+        let index = [3, 5, 9];
+        assert_eq!(journal::first_indexed(&index, 1, 10), Some(3));
+        assert_eq!(journal::first_indexed(&index, 4, 10), Some(5));
+        assert_eq!(journal::first_indexed(&index, 6, 8), None);
+        assert_eq!(journal::first_indexed(&index, 10, 12), None);
+        assert_eq!(journal::decode_index(&journal::encode_index(&index)), index);
+    }
+
+    #[test]
+    fn snapshots_older_than_max_saved_snapshots_are_pruned() {
+        let bonsai_dbs = open(&memory_db());
+        let head = MAX_SAVED_SNAPSHOTS + 2;
+        for block_number in 1..=head {
+            set(&bonsai_dbs.contract, b"a", &block_number.to_be_bytes());
+            bonsai_dbs.commit_block(block_number).unwrap();
+        }
+
+        assert_eq!(get_at(&bonsai_dbs.contract, 1, b"a"), None);
+        for block_number in [2, head / 2, head - 1, head] {
+            assert_eq!(
+                get_at(&bonsai_dbs.contract, block_number, b"a"),
+                Some(Some(block_number.to_be_bytes().to_vec()))
+            );
+        }
+
+        // Blocks whose snapshot is kept can still be reverted to
+        bonsai_dbs.revert_to_block(head / 2).unwrap();
+        assert_eq!(get(&bonsai_dbs.contract, b"a"), Some((head / 2).to_be_bytes().to_vec()));
     }
 }
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use parity_scale_codec::{Decode, Encode};
+use sp_database::Database;
+use starknet_api::hash::StarkFelt;
+
+use crate::{DbError, DbHash};
+
+/// Cache of the CASM classes compiled from the Sierra classes imported by the sync
+///
+/// Compiled classes are keyed by compiled class hash, and each Sierra class hash is mapped to the
+/// compiled class hash declared for it, so that a class is compiled only once.
+pub struct CasmClassesDb {
+    pub(crate) db: Arc<dyn Database<DbHash>>,
+}
+
+impl CasmClassesDb {
+    /// Stores a compiled class
+    ///
+    /// # Arguments
+    ///
+    /// * `class_hash` - The hash of the Sierra class
+    /// * `compiled_class_hash` - The hash of the compiled class, checked against the one declared
+    /// * `casm_class` - The JSON serialization of the compiled class
+    pub fn store_casm_class(
+        &self,
+        class_hash: StarkFelt,
+        compiled_class_hash: StarkFelt,
+        casm_class: &[u8],
+    ) -> Result<(), DbError> {
+        let mut transaction = sp_database::Transaction::new();
+
+        transaction.set(crate::columns::CASM_CLASSES, &compiled_class_hash.encode(), casm_class);
+        transaction.set(crate::columns::COMPILED_CLASS_HASHES, &class_hash.encode(), &compiled_class_hash.encode());
+
+        self.db.commit(transaction)?;
+
+        Ok(())
+    }
+
+    /// Returns the JSON serialization of a compiled class, or `None` if it is not cached
+    pub fn get_casm_class(&self, compiled_class_hash: StarkFelt) -> Result<Option<Vec<u8>>, DbError> {
+        Ok(self.db.get(crate::columns::CASM_CLASSES, &compiled_class_hash.encode()))
+    }
+
+    /// Returns the compiled class hash of a Sierra class, or `None` if it was not compiled yet
+    pub fn get_compiled_class_hash(&self, class_hash: StarkFelt) -> Result<Option<StarkFelt>, DbError> {
+        match self.db.get(crate::columns::COMPILED_CLASS_HASHES, &class_hash.encode()) {
+            Some(raw) => Ok(Some(StarkFelt::decode(&mut &raw[..])?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -6,6 +6,8 @@ use bonsai_trie::DBError;
 pub enum DbError {
     #[error("Failed to commit DB Update: `{0}`")]
     CommitError(#[from] sp_database::error::DatabaseError),
+    #[error("Failed to access the DB: `{0}`")]
+    Io(#[from] std::io::Error),
     #[error("Failed to deserialize DB Data: `{0}`")]
     DeserializeError(#[from] parity_scale_codec::Error),
     #[error("Failed to build Uuid: `{0}`")]
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use kvdb::{DBTransaction, KeyValueDB};
+use parity_scale_codec::{Decode, Encode};
+use sp_core::hashing::blake2_128;
+use starknet_api::hash::{StarkFelt, StarkHash};
+
+use crate::DbError;
+
+/// Bits of the bloom filter of a block for each element inserted in it. Along with
+/// [`BLOOM_HASHES`], this keeps the false positive rate under 0.1% however many events a block has.
+const BLOOM_BITS_PER_ELEMENT: usize = 16;
+/// Number of bits set in the bloom filter for each element.
+const BLOOM_HASHES: u64 = 6;
+/// Minimum size of the bloom filter of a block, in bytes.
+const MIN_BLOOM_BYTES: usize = 32;
+/// Position used to insert the emitter of the events in the bloom filter, so that an address
+/// never matches an event key.
+const ADDRESS_POSITION: u32 = u32::MAX;
+
+/// An event stored in the events index
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct IndexedEvent {
+    pub block_hash: StarkHash,
+    pub transaction_hash: StarkHash,
+    pub from_address: StarkFelt,
+    pub keys: Vec<StarkFelt>,
+    pub data: Vec<StarkFelt>,
+}
+
+/// A bloom filter over the emitters and keys of the events of a block
+///
+/// Keys are inserted along with their position in the event, so that the filter can be checked
+/// against the keys filter of `getEvents`, which is positional. The filter is sized to the number
+/// of elements inserted, so that it does not saturate on blocks with many events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventBloom(Vec<u8>);
+
+impl EventBloom {
+    /// Builds the bloom filter of the given events.
+    pub fn from_events(events: &[IndexedEvent]) -> Self {
+        let elements: usize = events.iter().map(|event| 1 + event.keys.len()).sum();
+        let mut bloom = Self(vec![0; (elements * BLOOM_BITS_PER_ELEMENT).div_ceil(8).max(MIN_BLOOM_BYTES)]);
+        for event in events {
+            bloom.insert(ADDRESS_POSITION, &event.from_address);
+            for (position, key) in event.keys.iter().enumerate() {
+                bloom.insert(position as u32, key);
+            }
+        }
+        bloom
+    }
+
+    /// Returns `false` if no event of the block has been emitted by `from_address`.
+    pub fn may_contain_address(&self, from_address: &StarkFelt) -> bool {
+        self.contains(ADDRESS_POSITION, from_address)
+    }
+
+    /// Returns `false` if no event of the block has `key` at the given position.
+    pub fn may_contain_key(&self, position: usize, key: &StarkFelt) -> bool {
+        // Events never have that many keys
+        u32::try_from(position).map_or(false, |position| position != ADDRESS_POSITION && self.contains(position, key))
+    }
+
+    fn insert(&mut self, position: u32, element: &StarkFelt) {
+        for bit in self.bits(position, element) {
+            self.0[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    fn contains(&self, position: u32, element: &StarkFelt) -> bool {
+        self.bits(position, element).all(|bit| self.0[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// The bits of an element, derived from two halves of its hash by double hashing.
+    fn bits(&self, position: u32, element: &StarkFelt) -> impl Iterator<Item = usize> {
+        let hash = blake2_128(&[&position.to_be_bytes()[..], element.bytes()].concat());
+        let (first, second) = hash.split_at(8);
+        let first = u64::from_be_bytes(first.try_into().expect("blake2_128 returns 16 bytes"));
+        let second = u64::from_be_bytes(second.try_into().expect("blake2_128 returns 16 bytes"));
+        let len = self.0.len() as u64 * 8;
+        (0..BLOOM_HASHES).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+}
+
+/// Index of the events emitted in the blocks synced by the mapping worker
+///
+/// Events are keyed by emitter, block number and position in the block, so that the events of a
+/// contract in a block can be read without fetching the block. Each indexed block also has a
+/// bloom filter over the emitters and keys of its events, which allows skipping the blocks without
+/// a matching event.
+pub struct EventsDb {
+    pub(crate) db: Arc<dyn KeyValueDB>,
+}
+
+fn event_key(from_address: &StarkFelt, block_number: u64, event_index: u32) -> Vec<u8> {
+    [from_address.bytes(), &block_number.to_be_bytes(), &event_index.to_be_bytes()].concat()
+}
+
+fn events_prefix(from_address: &StarkFelt, block_number: u64) -> Vec<u8> {
+    [from_address.bytes(), &block_number.to_be_bytes()].concat()
+}
+
+impl EventsDb {
+    /// Indexes the events of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `block_number` - The Starknet block number
+    /// * `events` - All the events emitted in the block, in order
+    pub fn write_block(&self, block_number: u64, events: &[IndexedEvent]) -> Result<(), DbError> {
+        let mut transaction = DBTransaction::new();
+
+        for (event_index, event) in events.iter().enumerate() {
+            transaction.put(
+                crate::columns::EVENTS,
+                &event_key(&event.from_address, block_number, event_index as u32),
+                &event.encode(),
+            );
+        }
+        transaction.put(crate::columns::EVENTS_BLOOM, &block_number.to_be_bytes(), &EventBloom::from_events(events).0);
+
+        self.db.write(transaction)?;
+
+        Ok(())
+    }
+
+    /// Removes the events of a block written by [`EventsDb::write_block`]
+    pub fn revert_block(&self, block_number: u64, events: &[IndexedEvent]) -> Result<(), DbError> {
+        let mut transaction = DBTransaction::new();
+
+        for event in events {
+            transaction.delete_prefix(crate::columns::EVENTS, &events_prefix(&event.from_address, block_number));
+        }
+        transaction.delete(crate::columns::EVENTS_BLOOM, &block_number.to_be_bytes());
+
+        self.db.write(transaction)?;
+
+        Ok(())
+    }
+
+    /// Returns the bloom filter of the events of a block, or `None` if the block has not been
+    /// indexed
+    pub fn bloom(&self, block_number: u64) -> Result<Option<EventBloom>, DbError> {
+        match self.db.get(crate::columns::EVENTS_BLOOM, &block_number.to_be_bytes())? {
+            Some(raw) if raw.is_empty() => Err(parity_scale_codec::Error::from("Empty event bloom").into()),
+            Some(raw) => Ok(Some(EventBloom(raw))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the events emitted by a contract in a block, in order
+    ///
+    /// # Arguments
+    ///
+    /// * `from_address` - The address of the contract which emitted the events
+    /// * `block_number` - The Starknet block number
+    pub fn events_from_address(
+        &self,
+        from_address: &StarkFelt,
+        block_number: u64,
+    ) -> Result<Vec<IndexedEvent>, DbError> {
+        let prefix = events_prefix(from_address, block_number);
+
+        let mut events = Vec::new();
+        for pair in self.db.iter_with_prefix(crate::columns::EVENTS, &prefix) {
+            let (_, value) = pair?;
+            events.push(IndexedEvent::decode(&mut &value[..])?);
+        }
+
+        Ok(events)
+    }
+}
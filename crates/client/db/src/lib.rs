@@ -15,33 +15,52 @@ mod error;
 pub use error::{BonsaiDbError, DbError};
 
 mod mapping_db;
-use kvdb::KeyValueDB;
+use kvdb::{DBTransaction, KeyValueDB};
 pub use mapping_db::MappingCommitment;
 use sierra_classes_db::SierraClassesDb;
+pub use sierra_classes_db::StoredSierraClass;
 use starknet_api::hash::StarkHash;
+mod casm_classes_db;
 mod da_db;
 mod db_opening_utils;
+mod events_db;
 mod messaging_db;
+mod receipts_db;
 mod sierra_classes_db;
+pub use events_db::{EventBloom, IndexedEvent};
 pub use messaging_db::LastSyncedEventBlock;
+pub use receipts_db::{ReceiptEvent, ReceiptExecutionResources, ReceiptMessageToL1, StoredReceipt};
 pub mod bonsai_db;
 mod l1_handler_tx_fee;
 mod meta_db;
-
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use bonsai_db::{BonsaiDb, TrieColumn};
+pub use casm_classes_db::CasmClassesDb;
 use da_db::DaDb;
+use events_db::EventsDb;
 use l1_handler_tx_fee::L1HandlerTxFeeDb;
 use mapping_db::MappingDb;
 use messaging_db::MessagingDb;
 use meta_db::MetaDb;
+pub use meta_db::{L1BlockState, L1StateMismatch};
+use parity_scale_codec::{Decode, Encode};
+use receipts_db::ReceiptsDb;
 use sc_client_db::DatabaseSource;
 use sp_database::Database;
 use sp_runtime::traits::Block as BlockT;
 
+/// The version of the layout of the database, bumped on every change which makes the data written
+/// by previous versions unreadable. The node refuses to open a database of another version.
+///
+/// - 1: storage tries are namespaced by contract address.
+/// - 2: the snapshot journal of the tries is indexed by key.
+/// - 3: the event blooms are sized to the events of their block.
+/// - 4: the Sierra classes are stored along with their contract class version.
+pub const DB_VERSION: u32 = 4;
+
 const DB_HASH_LEN: usize = 32;
 /// Hash type that this backend uses for the database.
 pub type DbHash = [u8; DB_HASH_LEN];
@@ -56,7 +75,7 @@ pub(crate) mod columns {
     // ===== /!\ ===================================================================================
     // MUST BE INCREMENTED WHEN A NEW COLUMN IN ADDED
     // ===== /!\ ===================================================================================
-    pub const NUM_COLUMNS: u32 = 18;
+    pub const NUM_COLUMNS: u32 = 24;
 
     pub const META: u32 = 0;
     pub const BLOCK_MAPPING: u32 = 1;
@@ -96,12 +115,35 @@ pub(crate) mod columns {
     pub const TRIE_BONSAI_STORAGE: u32 = 15;
     pub const FLAT_BONSAI_STORAGE: u32 = 16;
     pub const LOG_BONSAI_STORAGE: u32 = 17;
+
+    /// This column indexes the events emitted in each block by emitter address.
+    pub const EVENTS: u32 = 18;
+
+    /// This column stores a bloom filter over the emitters and keys of the events of each block.
+    pub const EVENTS_BLOOM: u32 = 19;
+
+    /// This column stores the receipts of the transactions imported by the sync.
+    pub const RECEIPTS: u32 = 20;
+
+    /// This column maps L1 transaction hashes to the L1 handler transactions triggered by the
+    /// messages they sent to L2.
+    pub const L1_HANDLER_TRANSACTIONS: u32 = 21;
+
+    /// This column caches the CASM classes compiled by the sync, by compiled class hash.
+    pub const CASM_CLASSES: u32 = 22;
+
+    /// This column maps Sierra class hashes to the hash of their compiled class.
+    pub const COMPILED_CLASS_HASHES: u32 = 23;
 }
 
 pub mod static_keys {
+    pub const DB_VERSION: &[u8] = b"DB_VERSION";
     pub const CURRENT_SYNCING_TIPS: &[u8] = b"CURRENT_SYNCING_TIPS";
     pub const LAST_PROVED_BLOCK: &[u8] = b"LAST_PROVED_BLOCK";
     pub const LAST_SYNCED_L1_EVENT_BLOCK: &[u8] = b"LAST_SYNCED_L1_EVENT_BLOCK";
+    pub const L1_VERIFIED_STATE: &[u8] = b"L1_VERIFIED_STATE";
+    /// Prefix of the L1 state mismatches, followed by the big endian number of their block.
+    pub const L1_STATE_MISMATCH: &[u8] = b"L1_STATE_MISMATCH";
 }
 
 /// The Bonsai databases backend
@@ -112,13 +154,60 @@ pub struct BonsaiDbs<B: BlockT> {
     pub storage: Arc<BonsaiDb<B>>,
 }
 
+impl<B: BlockT> BonsaiDbs<B> {
+    /// Returns the last block whose changes to the tries were fully committed.
+    pub fn head(&self) -> Result<Option<u64>, BonsaiDbError> {
+        self.contract.head()
+    }
+
+    /// Reverts the changes made to the tries after the last committed block, such as those left by
+    /// a crash in the middle of a state root update.
+    pub fn recover(&self) -> Result<(), BonsaiDbError> {
+        let head = self.head()?;
+        self.write(|db, transaction| db.revert_to(head, transaction))
+    }
+
+    /// Snapshots the tries at `block_number` and marks it as the last committed block.
+    pub fn commit_block(&self, block_number: u64) -> Result<(), BonsaiDbError> {
+        self.write(|db, transaction| db.commit_block(block_number, transaction))
+    }
+
+    /// Reverts the tries to their state at `block_number`.
+    pub fn revert_to_block(&self, block_number: u64) -> Result<(), BonsaiDbError> {
+        self.write(|db, transaction| db.revert_to(Some(block_number), transaction))
+    }
+
+    /// Applies `f` to each trie within a single transaction, so that the tries are always left in
+    /// the same state.
+    fn write<F>(&self, f: F) -> Result<(), BonsaiDbError>
+    where
+        F: Fn(&BonsaiDb<B>, &mut DBTransaction) -> Result<(), BonsaiDbError>,
+    {
+        let mut transaction = DBTransaction::new();
+        for db in [&self.contract, &self.class, &self.storage] {
+            f(db, &mut transaction)?;
+        }
+        let result = self.contract.db.write(transaction);
+
+        // Every write leaves the pending journals empty
+        for db in [&self.contract, &self.class, &self.storage] {
+            db.reset_pending(result.is_ok());
+        }
+        result.map_err(Into::into)
+    }
+}
+
 /// The Madara client database backend
 ///
-/// Contains five distinct databases: `meta`, `mapping`, `messaging`, `da` and `bonsai``.
+/// Contains eight distinct databases: `meta`, `mapping`, `messaging`, `da`, `events`, `receipts`,
+/// `casm_classes` and `bonsai``.
 /// `mapping` is used to map Starknet blocks to Substrate ones.
 /// `meta` is used to store data about the current state of the chain
 /// `messaging` is used to store data regarding l1 messagings.
 /// `da` is used to store the data availaiblity facts that need to be written to the L1.
+/// `events` is used to index the events emitted in each block.
+/// `receipts` is used to store the receipts of the transactions imported by the sync.
+/// `casm_classes` is used to cache the classes compiled by the sync.
 /// `bonsai` is used to store the commitment tries.
 pub struct Backend<B: BlockT> {
     meta: Arc<MetaDb<B>>,
@@ -127,6 +216,9 @@ pub struct Backend<B: BlockT> {
     messaging: Arc<MessagingDb>,
     sierra_classes: Arc<SierraClassesDb>,
     l1_handler_paid_fee: Arc<L1HandlerTxFeeDb>,
+    events: Arc<EventsDb>,
+    receipts: Arc<ReceiptsDb>,
+    casm_classes: Arc<CasmClassesDb>,
     bonsai: BonsaiDbs<B>,
 }
 
@@ -165,15 +257,12 @@ impl<B: BlockT> Backend<B> {
         let db = db_opening_utils::open_database(config)?;
         let kvdb: Arc<dyn KeyValueDB> = db.0;
         let spdb: Arc<dyn Database<DbHash>> = db.1;
+        check_db_version(kvdb.as_ref())?;
 
         let bonsai_dbs = BonsaiDbs {
-            contract: Arc::new(BonsaiDb {
-                db: kvdb.clone(),
-                _marker: PhantomData,
-                current_column: TrieColumn::Contract,
-            }),
-            class: Arc::new(BonsaiDb { db: kvdb.clone(), _marker: PhantomData, current_column: TrieColumn::Class }),
-            storage: Arc::new(BonsaiDb { db: kvdb, _marker: PhantomData, current_column: TrieColumn::Storage }),
+            contract: Arc::new(BonsaiDb::new(kvdb.clone(), TrieColumn::Contract)),
+            class: Arc::new(BonsaiDb::new(kvdb.clone(), TrieColumn::Class)),
+            storage: Arc::new(BonsaiDb::new(kvdb.clone(), TrieColumn::Storage)),
         };
 
         Ok(Self {
@@ -183,6 +272,9 @@ impl<B: BlockT> Backend<B> {
             messaging: Arc::new(MessagingDb { db: spdb.clone() }),
             sierra_classes: Arc::new(SierraClassesDb { db: spdb.clone() }),
             l1_handler_paid_fee: Arc::new(L1HandlerTxFeeDb { db: spdb.clone() }),
+            receipts: Arc::new(ReceiptsDb { db: spdb.clone() }),
+            casm_classes: Arc::new(CasmClassesDb { db: spdb.clone() }),
+            events: Arc::new(EventsDb { db: kvdb }),
             bonsai: bonsai_dbs,
         })
     }
@@ -212,6 +304,21 @@ impl<B: BlockT> Backend<B> {
         &self.sierra_classes
     }
 
+    /// Return the events index database manager
+    pub fn events(&self) -> &Arc<EventsDb> {
+        &self.events
+    }
+
+    /// Return the receipts database manager
+    pub fn receipts(&self) -> &Arc<ReceiptsDb> {
+        &self.receipts
+    }
+
+    /// Return the compiled classes database manager
+    pub fn casm_classes(&self) -> &Arc<CasmClassesDb> {
+        &self.casm_classes
+    }
+
     pub fn bonsai_contract(&self) -> &Arc<BonsaiDb<B>> {
         &self.bonsai.contract
     }
@@ -236,3 +343,58 @@ impl<B: BlockT> Backend<B> {
         Default::default()
     }
 }
+
+/// Checks that the database was written with the layout of [`DB_VERSION`], stamping it with this
+/// version if it is empty.
+fn check_db_version(db: &dyn KeyValueDB) -> Result<(), String> {
+    let version = db
+        .get(columns::META, static_keys::DB_VERSION)
+        .map_err(|e| format!("Failed to read the database version: {e}"))?;
+
+    match version {
+        Some(raw) => {
+            let version =
+                u32::decode(&mut &raw[..]).map_err(|e| format!("Failed to decode the database version: {e}"))?;
+            if version != DB_VERSION {
+                return Err(format!(
+                    "The database is at version {version} but this node expects version {DB_VERSION}, remove it and \
+                     sync again"
+                ));
+            }
+            Ok(())
+        }
+        None if (0..columns::NUM_COLUMNS).all(|column| db.iter(column).next().is_none()) => {
+            let mut transaction = DBTransaction::new();
+            transaction.put(columns::META, static_keys::DB_VERSION, &DB_VERSION.encode());
+            db.write(transaction).map_err(|e| format!("Failed to write the database version: {e}"))
+        }
+        None => Err(format!(
+            "The database predates versioning but this node expects version {DB_VERSION}, remove it and sync again"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn databases_of_another_version_are_refused() {
+        let db = kvdb_memorydb::create(columns::NUM_COLUMNS);
+        check_db_version(&db).unwrap();
+        assert_eq!(db.get(columns::META, static_keys::DB_VERSION).unwrap(), Some(DB_VERSION.encode()));
+        check_db_version(&db).unwrap();
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(columns::META, static_keys::DB_VERSION, &(DB_VERSION - 1).encode());
+        db.write(transaction).unwrap();
+        assert!(check_db_version(&db).unwrap_err().contains("remove it"));
+
+        // A database written before versioning holds data but no version
+        let db = kvdb_memorydb::create(columns::NUM_COLUMNS);
+        let mut transaction = DBTransaction::new();
+        transaction.put(columns::TRIE_BONSAI_STORAGE, b"node", b"value");
+        db.write(transaction).unwrap();
+        assert!(check_db_version(&db).unwrap_err().contains("predates versioning"));
+    }
+}
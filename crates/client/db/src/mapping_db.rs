@@ -113,6 +113,65 @@ impl<B: BlockT> MappingDb<B> {
         Ok(())
     }
 
+    /// Forget a Substrate block registered with [`MappingDb::write_none`]
+    pub fn revert_none(&self, block_hash: B::Hash) -> Result<(), DbError> {
+        let _lock = self.write_lock.lock();
+
+        let mut transaction = sp_database::Transaction::new();
+
+        transaction.remove(crate::columns::SYNCED_MAPPING, &block_hash.encode());
+
+        self.db.commit(transaction)?;
+
+        Ok(())
+    }
+
+    /// Remove the mapping written by [`MappingDb::write_hashes`] for a Substrate block which has
+    /// been reverted
+    ///
+    /// Other Substrate blocks wrapping the same Starknet block are kept.
+    pub fn revert_hashes(&self, commitment: MappingCommitment<B>) -> Result<(), DbError> {
+        let _lock = self.write_lock.lock();
+
+        let mut transaction = sp_database::Transaction::new();
+
+        let substrate_hashes: Vec<B::Hash> = self
+            .block_hash(commitment.starknet_block_hash)?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|hash| hash != &commitment.block_hash)
+            .collect();
+
+        if substrate_hashes.is_empty() {
+            transaction.remove(crate::columns::BLOCK_MAPPING, &commitment.starknet_block_hash.encode());
+
+            if self.cache_more_things {
+                transaction.remove(
+                    crate::columns::STARKNET_TRANSACTION_HASHES_CACHE,
+                    &commitment.starknet_block_hash.encode(),
+                );
+            }
+        } else {
+            transaction.set(
+                crate::columns::BLOCK_MAPPING,
+                &commitment.starknet_block_hash.encode(),
+                &substrate_hashes.encode(),
+            );
+        }
+
+        transaction.remove(crate::columns::SYNCED_MAPPING, &commitment.block_hash.encode());
+
+        for transaction_hash in commitment.starknet_transaction_hashes.iter() {
+            if self.block_hash_from_transaction_hash(*transaction_hash)? == Some(commitment.block_hash) {
+                transaction.remove(crate::columns::TRANSACTION_MAPPING, &transaction_hash.encode());
+            }
+        }
+
+        self.db.commit(transaction)?;
+
+        Ok(())
+    }
+
     /// Retrieves the substrate block hash
     /// associated with the given transaction hash, if any.
     ///
@@ -157,3 +216,56 @@ impl<B: BlockT> MappingDb<B> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sp_core::H256;
+    use sp_database::MemDb;
+    use sp_runtime::generic::{Block, Header};
+    use sp_runtime::traits::BlakeTwo256;
+    use sp_runtime::OpaqueExtrinsic;
+
+    use super::*;
+
+    type TestBlock = Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>;
+
+    fn commitment(block_number: u64) -> MappingCommitment<TestBlock> {
+        MappingCommitment {
+            block_hash: H256::repeat_byte(block_number as u8),
+            starknet_block_hash: StarkHash::from(block_number),
+            starknet_transaction_hashes: vec![StarkHash::from(1000 + block_number)],
+        }
+    }
+
+    #[test]
+    fn reverted_blocks_are_unmapped() {
+        let mapping = MappingDb::<TestBlock>::new(Arc::new(MemDb::default()), true);
+        for block_number in 1..=3 {
+            mapping.write_hashes(commitment(block_number)).unwrap();
+        }
+
+        // Blocks are reverted from the last one, as `revert_blocks` does
+        for block_number in (2..=3).rev() {
+            mapping.revert_hashes(commitment(block_number)).unwrap();
+        }
+
+        let kept = commitment(1);
+        assert_eq!(mapping.block_hash(kept.starknet_block_hash).unwrap(), Some(vec![kept.block_hash]));
+        assert!(mapping.is_synced(&kept.block_hash).unwrap());
+        assert_eq!(
+            mapping.block_hash_from_transaction_hash(kept.starknet_transaction_hashes[0]).unwrap(),
+            Some(kept.block_hash)
+        );
+
+        for block_number in 2..=3 {
+            let reverted = commitment(block_number);
+            assert_eq!(mapping.block_hash(reverted.starknet_block_hash).unwrap(), None);
+            assert!(!mapping.is_synced(&reverted.block_hash).unwrap());
+            assert_eq!(
+                mapping.block_hash_from_transaction_hash(reverted.starknet_transaction_hashes[0]).unwrap(),
+                None
+            );
+            assert_eq!(mapping.cached_transaction_hashes_from_block_hash(reverted.starknet_block_hash).unwrap(), None);
+        }
+    }
+}
@@ -2,7 +2,9 @@ use std::sync::Arc;
 
 // Substrate
 use parity_scale_codec::{Decode, Encode};
+use sp_core::H256;
 use sp_database::Database;
+use starknet_api::hash::StarkHash;
 
 use crate::error::DbError;
 use crate::DbHash;
@@ -47,4 +49,43 @@ impl MessagingDb {
 
         Ok(())
     }
+
+    /// Records an L1 handler transaction triggered by a message sent to L2 in an L1 transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `l1_transaction_hash` - The hash of the L1 transaction which sent the message
+    /// * `l1_handler_transaction_hash` - The hash of the L1 handler transaction consuming it on L2
+    pub fn store_l1_handler_transaction(
+        &self,
+        l1_transaction_hash: H256,
+        l1_handler_transaction_hash: StarkHash,
+    ) -> Result<(), DbError> {
+        let mut l1_handler_transactions = self.l1_handler_transactions(l1_transaction_hash)?;
+        if l1_handler_transactions.contains(&l1_handler_transaction_hash) {
+            return Ok(());
+        }
+        l1_handler_transactions.push(l1_handler_transaction_hash);
+
+        let mut transaction = sp_database::Transaction::new();
+
+        transaction.set(
+            crate::columns::L1_HANDLER_TRANSACTIONS,
+            l1_transaction_hash.as_bytes(),
+            &l1_handler_transactions.encode(),
+        );
+
+        self.db.commit(transaction)?;
+
+        Ok(())
+    }
+
+    /// Returns the L1 handler transactions triggered by the messages sent to L2 in an L1
+    /// transaction, in the order the messages were sent
+    pub fn l1_handler_transactions(&self, l1_transaction_hash: H256) -> Result<Vec<StarkHash>, DbError> {
+        match self.db.get(crate::columns::L1_HANDLER_TRANSACTIONS, l1_transaction_hash.as_bytes()) {
+            Some(raw) => Ok(Vec::<StarkHash>::decode(&mut &raw[..])?),
+            None => Ok(Vec::new()),
+        }
+    }
 }
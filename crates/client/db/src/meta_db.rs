@@ -3,15 +3,38 @@ use std::sync::Arc;
 
 // Substrate
 use parity_scale_codec::{Decode, Encode};
+use sp_core::H256;
 use sp_database::Database;
 use sp_runtime::traits::Block as BlockT;
+use starknet_api::hash::StarkHash;
 
 use crate::{DbError, DbHash};
 
+/// The state of a Starknet block as posted on L1
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct L1BlockState {
+    pub block_number: u64,
+    pub block_hash: StarkHash,
+    pub global_root: StarkHash,
+    /// Hash of the L1 transaction which posted the state, unknown when it was read from the core
+    /// contract storage
+    pub transaction_hash: Option<H256>,
+}
+
+/// A state posted on L1 which does not match the block imported by the node
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct L1StateMismatch {
+    pub posted: L1BlockState,
+    pub block_hash: StarkHash,
+    pub global_root: StarkHash,
+}
+
 /// Allow interaction with the meta db
 ///
 /// The meta db store the tips of the synced chain.
 /// In case of forks, there can be multiple tips.
+///
+/// It also stores the outcome of the verification of the chain against the states posted on L1.
 pub struct MetaDb<B: BlockT> {
     pub(crate) db: Arc<dyn Database<DbHash>>,
     pub(crate) _marker: PhantomData<B>,
@@ -36,4 +59,87 @@ impl<B: BlockT> MetaDb<B> {
 
         Ok(())
     }
+
+    /// Retrieve the last state posted on L1 which matches the imported chain
+    pub fn l1_verified_state(&self) -> Result<Option<L1BlockState>, DbError> {
+        match self.db.get(crate::columns::META, crate::static_keys::L1_VERIFIED_STATE) {
+            Some(raw) => Ok(Some(L1BlockState::decode(&mut &raw[..])?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the last state posted on L1 which matches the imported chain
+    pub fn write_l1_verified_state(&self, state: &L1BlockState) -> Result<(), DbError> {
+        let mut transaction = sp_database::Transaction::new();
+
+        transaction.set(crate::columns::META, crate::static_keys::L1_VERIFIED_STATE, &state.encode());
+
+        self.db.commit(transaction)?;
+
+        Ok(())
+    }
+
+    /// Retrieve the state posted on L1 for the given block, if it did not match the imported chain
+    pub fn l1_state_mismatch(&self, block_number: u64) -> Result<Option<L1StateMismatch>, DbError> {
+        match self.db.get(crate::columns::META, &l1_state_mismatch_key(block_number)) {
+            Some(raw) => Ok(Some(L1StateMismatch::decode(&mut &raw[..])?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record a state posted on L1 which does not match the imported chain
+    ///
+    /// Mismatches are keyed by block number, so checking the same block again replaces its
+    /// mismatch rather than growing the meta db.
+    pub fn write_l1_state_mismatch(&self, mismatch: L1StateMismatch) -> Result<(), DbError> {
+        let mut transaction = sp_database::Transaction::new();
+
+        transaction.set(crate::columns::META, &l1_state_mismatch_key(mismatch.posted.block_number), &mismatch.encode());
+
+        self.db.commit(transaction)?;
+
+        Ok(())
+    }
+}
+
+fn l1_state_mismatch_key(block_number: u64) -> Vec<u8> {
+    [crate::static_keys::L1_STATE_MISMATCH, &block_number.to_be_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use sp_database::MemDb;
+    use sp_runtime::generic::{Block, Header};
+    use sp_runtime::traits::BlakeTwo256;
+    use sp_runtime::OpaqueExtrinsic;
+
+    use super::*;
+
+    type TestBlock = Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>;
+
+    fn mismatch(block_number: u64, block_hash: u64) -> L1StateMismatch {
+        L1StateMismatch {
+            posted: L1BlockState {
+                block_number,
+                block_hash: StarkHash::from(block_number),
+                global_root: StarkHash::from(block_number),
+                transaction_hash: None,
+            },
+            block_hash: StarkHash::from(block_hash),
+            global_root: StarkHash::from(block_number),
+        }
+    }
+
+    #[test]
+    fn l1_state_mismatches_are_kept_once_per_block() {
+        let meta = MetaDb::<TestBlock> { db: Arc::new(MemDb::default()), _marker: PhantomData };
+
+        meta.write_l1_state_mismatch(mismatch(1, 100)).unwrap();
+        meta.write_l1_state_mismatch(mismatch(2, 200)).unwrap();
+        meta.write_l1_state_mismatch(mismatch(1, 101)).unwrap();
+
+        assert_eq!(meta.l1_state_mismatch(1).unwrap(), Some(mismatch(1, 101)));
+        assert_eq!(meta.l1_state_mismatch(2).unwrap(), Some(mismatch(2, 200)));
+        assert_eq!(meta.l1_state_mismatch(3).unwrap(), None);
+    }
 }
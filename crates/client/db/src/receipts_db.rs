@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use parity_scale_codec::{Decode, Encode};
+use sp_database::Database;
+use starknet_api::hash::StarkFelt;
+
+use crate::{DbError, DbHash};
+
+/// An event emitted by a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ReceiptEvent {
+    pub from_address: StarkFelt,
+    pub keys: Vec<StarkFelt>,
+    pub data: Vec<StarkFelt>,
+}
+
+/// A message sent to L1 by a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ReceiptMessageToL1 {
+    pub from_address: StarkFelt,
+    pub to_address: StarkFelt,
+    pub payload: Vec<StarkFelt>,
+}
+
+/// The resources used by the execution of a transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ReceiptExecutionResources {
+    pub steps: u64,
+    pub memory_holes: Option<u64>,
+    pub range_check_builtin_applications: u64,
+    pub pedersen_builtin_applications: u64,
+    pub poseidon_builtin_applications: u64,
+    pub ec_op_builtin_applications: u64,
+    pub ecdsa_builtin_applications: u64,
+    pub bitwise_builtin_applications: u64,
+    pub keccak_builtin_applications: u64,
+}
+
+/// The outcome of the execution of a transaction, as served by the feeder gateway
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct StoredReceipt {
+    pub actual_fee: StarkFelt,
+    pub events: Vec<ReceiptEvent>,
+    pub messages_sent: Vec<ReceiptMessageToL1>,
+    /// The reason of the revert, or `None` if the transaction succeeded
+    pub revert_reason: Option<String>,
+    pub execution_resources: ReceiptExecutionResources,
+}
+
+/// Receipts of the transactions imported by the sync, keyed by transaction hash
+///
+/// Receipts are written before their block is imported, so a receipt should only be served once
+/// the mapping of its transaction has been written.
+pub struct ReceiptsDb {
+    pub(crate) db: Arc<dyn Database<DbHash>>,
+}
+
+impl ReceiptsDb {
+    /// Stores the receipts of the transactions of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `receipts` - The hashes of the transactions of the block along with their receipt
+    pub fn store_receipts(&self, receipts: &[(StarkFelt, StoredReceipt)]) -> Result<(), DbError> {
+        let mut transaction = sp_database::Transaction::new();
+
+        for (transaction_hash, receipt) in receipts {
+            transaction.set(crate::columns::RECEIPTS, &transaction_hash.encode(), &receipt.encode());
+        }
+
+        self.db.commit(transaction)?;
+
+        Ok(())
+    }
+
+    /// Returns the stored receipt of a transaction, or `None` if it was not imported by the sync
+    pub fn get_receipt(&self, transaction_hash: StarkFelt) -> Result<Option<StoredReceipt>, DbError> {
+        match self.db.get(crate::columns::RECEIPTS, &transaction_hash.encode()) {
+            Some(raw) => Ok(Some(StoredReceipt::decode(&mut &raw[..])?)),
+            None => Ok(None),
+        }
+    }
+}
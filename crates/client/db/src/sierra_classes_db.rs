@@ -7,16 +7,33 @@ use starknet_api::state::ContractClass;
 
 use crate::{DbError, DbHash};
 
+/// A Sierra class as declared, along with the version of the Sierra language it is written in.
+///
+/// Its encoding differs from the one of a bare [`ContractClass`], which the databases before
+/// version 4 of [`crate::DB_VERSION`] hold.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct StoredSierraClass {
+    pub contract_class_version: String,
+    pub class: ContractClass,
+}
+
 /// Allow interaction with the sierra classes db
 pub struct SierraClassesDb {
     pub(crate) db: Arc<dyn Database<DbHash>>,
 }
 
 impl SierraClassesDb {
-    pub fn store_sierra_class(&self, class_hash: ClassHash, class: ContractClass) -> Result<(), DbError> {
+    /// Stores Sierra classes as they were downloaded from the feeder gateway
+    ///
+    /// # Arguments
+    ///
+    /// * `classes` - The hashes of the classes along with their definition
+    pub fn store_sierra_classes(&self, classes: &[(ClassHash, StoredSierraClass)]) -> Result<(), DbError> {
         let mut transaction = sp_database::Transaction::new();
 
-        transaction.set(crate::columns::SIERRA_CONTRACT_CLASSES, &class_hash.encode(), &class.encode());
+        for (class_hash, class) in classes {
+            transaction.set(crate::columns::SIERRA_CONTRACT_CLASSES, &class_hash.encode(), &class.encode());
+        }
 
         self.db.commit(transaction)?;
 
@@ -24,10 +41,15 @@ impl SierraClassesDb {
     }
 
     pub fn get_sierra_class(&self, class_hash: ClassHash) -> Result<Option<ContractClass>, DbError> {
+        Ok(self.get_stored_sierra_class(class_hash)?.map(|stored| stored.class))
+    }
+
+    /// Returns a Sierra class along with its version, or `None` if it was not stored by the sync
+    pub fn get_stored_sierra_class(&self, class_hash: ClassHash) -> Result<Option<StoredSierraClass>, DbError> {
         let opt_contract_class = self
             .db
             .get(crate::columns::SIERRA_CONTRACT_CLASSES, &class_hash.encode())
-            .map(|raw| ContractClass::decode(&mut &raw[..]))
+            .map(|raw| StoredSierraClass::decode(&mut &raw[..]))
             .transpose()?;
 
         Ok(opt_contract_class)
@@ -0,0 +1,143 @@
+//! Content-addressed archive of the responses of the feeder gateway.
+//!
+//! Every response is stored once under `objects/<blake2_256 of the content>.json`. The [`INDEX`]
+//! file maps the keys used by [`crate::source::ReplaySource`], such as `block/12` or
+//! `class/0x1234`, to their object, one `<key> <object>` pair per line. Lines are only ever
+//! appended: a key recorded twice, for instance after a reorg, resolves to its last object.
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use sp_core::hashing::blake2_256;
+
+/// Name of the index file at the root of an archive.
+pub const INDEX: &str = "index";
+/// Directory of the objects of an archive.
+pub const OBJECTS_DIR: &str = "objects";
+
+/// An archive opened for recording.
+pub struct Archive {
+    root: PathBuf,
+    index: Mutex<File>,
+}
+
+impl Archive {
+    /// Opens the archive at `root`, creating it if needed. Existing records are kept.
+    pub fn open(root: &Path) -> Result<Self, String> {
+        std::fs::create_dir_all(root.join(OBJECTS_DIR))
+            .map_err(|e| format!("failed to create archive {}: {e}", root.display()))?;
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(root.join(INDEX))
+            .map_err(|e| format!("failed to open archive index: {e}"))?;
+
+        Ok(Self { root: root.to_path_buf(), index: Mutex::new(index) })
+    }
+
+    /// Stores `content` and points `key` to it.
+    pub fn record(&self, key: &str, content: &[u8]) -> Result<(), String> {
+        let object = hex::encode(blake2_256(content));
+        let path = object_path(&self.root, &object);
+
+        if !path.exists() {
+            // Written aside then renamed so that an interrupted write never leaves a truncated object
+            let partial = path.with_extension("partial");
+            std::fs::write(&partial, content).map_err(|e| format!("failed to write object {object}: {e}"))?;
+            std::fs::rename(&partial, &path).map_err(|e| format!("failed to write object {object}: {e}"))?;
+        }
+
+        let mut index = self.index.lock().unwrap();
+        writeln!(index, "{key} {object}").map_err(|e| format!("failed to write archive index: {e}"))
+    }
+}
+
+/// Reads the index of the archive at `root`, if there is one.
+///
+/// # Returns
+///
+/// The path of the object of each key.
+pub fn read_index(root: &Path) -> Result<Option<HashMap<String, PathBuf>>, String> {
+    let file = match File::open(root.join(INDEX)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("failed to open archive index: {e}")),
+    };
+
+    let mut index = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("failed to read archive index: {e}"))?;
+        // A line cut by a crash while recording is ignored
+        match line.split_once(' ') {
+            Some((key, object)) if !key.is_empty() && is_object_name(object) => {
+                index.insert(key.to_string(), object_path(root, object));
+            }
+            _ => log::debug!("Ignoring malformed archive index line: {line}"),
+        }
+    }
+
+    Ok(Some(index))
+}
+
+/// Whether `object` is the hex encoded blake2 hash naming an object.
+fn is_object_name(object: &str) -> bool {
+    object.len() == 64 && object.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn object_path(root: &Path, object: &str) -> PathBuf {
+    root.join(OBJECTS_DIR).join(format!("{object}.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_record_of_a_key_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let archive = Archive::open(root).unwrap();
+
+        archive.record("block/1", b"{\"a\":1}").unwrap();
+        archive.record("block/2", b"{\"a\":1}").unwrap();
+        archive.record("block/1", b"{\"a\":2}").unwrap();
+
+        let index = read_index(root).unwrap().unwrap();
+        assert_eq!(std::fs::read(&index["block/1"]).unwrap(), b"{\"a\":2}");
+        assert_eq!(index["block/2"], object_path(root, &hex::encode(blake2_256(b"{\"a\":1}"))));
+        assert_eq!(std::fs::read_dir(root.join(OBJECTS_DIR)).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn malformed_index_lines_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let object = hex::encode(blake2_256(b"{}"));
+        let index = [
+            format!("block/1 {object}"),
+            "block/2".to_string(),
+            format!("block/3 {}", &object[..20]),
+            "block/4 not-an-object-name-but-still-sixty-four-characters-long!".to_string(),
+            format!(" {object}"),
+            String::new(),
+            format!("block/5 {object} trailing"),
+            format!("state_update/1 {}", object.to_uppercase()),
+        ];
+        std::fs::write(root.join(INDEX), index.join("\n")).unwrap();
+
+        let index = read_index(root).unwrap().unwrap();
+        let mut keys: Vec<_> = index.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["block/1"]);
+        assert_eq!(index["block/1"], object_path(root, &object));
+    }
+
+    #[test]
+    fn archive_without_index_is_not_an_archive() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(read_index(dir.path()), Ok(None));
+    }
+}
@@ -0,0 +1,144 @@
+//! Typed access to the chain imported by the node.
+//!
+//! The sync and verification workers run in the node process, so they query the Substrate client,
+//! the Madara db and the storage overrides directly rather than going through the node's own
+//! JSON-RPC server.
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use mc_rpc_core::utils::get_block_by_block_hash;
+use mc_storage::OverrideHandle;
+use mp_block::Header;
+use mp_felt::Felt252Wrapper;
+use mp_hashers::HasherT;
+use sc_client_api::backend::{Backend, StorageProvider};
+use sp_blockchain::HeaderBackend;
+use sp_core::H256;
+use sp_runtime::traits::{Block as BlockT, UniqueSaturatedInto};
+use starknet_api::api_core::ClassHash;
+use starknet_ff::FieldElement;
+
+use crate::l2::L2StateUpdate;
+
+/// Read access to the blocks imported by the node.
+pub trait ChainHandle: Send + Sync {
+    /// Returns the number of the best imported block.
+    fn best_block_number(&self) -> u64;
+
+    /// Returns the hash of the Substrate block wrapping the Starknet block with the given number.
+    fn substrate_block_hash(&self, block_number: u64) -> Option<H256>;
+
+    /// Returns the hash of the imported Starknet block with the given number, as served by the
+    /// feeder gateway.
+    fn block_hash(&self, block_number: u64) -> Option<FieldElement>;
+
+    /// Returns the hash and state root of the Starknet block with the given number, once it has
+    /// been mapped by the mapping sync worker.
+    fn state_update(&self, block_number: u64) -> Option<L2StateUpdate>;
+
+    /// Whether a class is stored as of the Starknet block with the given number.
+    fn has_class(&self, block_number: u64, class_hash: ClassHash) -> bool;
+}
+
+/// Returns the hash of a block as served by the feeder gateway.
+///
+/// The sync keeps that hash in the extra data of the header, since the hash of the header itself
+/// only matches it for the blocks hashed with the same scheme as `H`. Blocks without extra data
+/// fall back to the hash of their header.
+pub fn feeder_block_hash<H: HasherT>(header: &Header) -> Option<FieldElement> {
+    match header.extra_data {
+        Some(extra_data) => {
+            let mut bytes = [0u8; 32];
+            extra_data.to_big_endian(&mut bytes);
+            FieldElement::from_bytes_be(&bytes).ok()
+        }
+        None => Some(header.hash::<H>().into()),
+    }
+}
+
+/// A [`ChainHandle`] backed by the Substrate client of the node.
+pub struct ClientChainHandle<B: BlockT, C, BE, H> {
+    client: Arc<C>,
+    backend: Arc<mc_db::Backend<B>>,
+    overrides: Arc<OverrideHandle<B>>,
+    _marker: PhantomData<fn() -> (BE, H)>,
+}
+
+impl<B: BlockT, C, BE, H> ClientChainHandle<B, C, BE, H> {
+    pub fn new(client: Arc<C>, backend: Arc<mc_db::Backend<B>>, overrides: Arc<OverrideHandle<B>>) -> Self {
+        Self { client, backend, overrides, _marker: PhantomData }
+    }
+}
+
+impl<B, C, BE, H> ChainHandle for ClientChainHandle<B, C, BE, H>
+where
+    B: BlockT<Hash = H256>,
+    C: HeaderBackend<B> + StorageProvider<B, BE> + Send + Sync + 'static,
+    BE: Backend<B> + 'static,
+    H: HasherT + Send + Sync + 'static,
+{
+    fn best_block_number(&self) -> u64 {
+        UniqueSaturatedInto::<u64>::unique_saturated_into(self.client.info().best_number)
+    }
+
+    fn substrate_block_hash(&self, block_number: u64) -> Option<H256> {
+        self.client.hash(UniqueSaturatedInto::unique_saturated_into(block_number)).ok().flatten()
+    }
+
+    fn block_hash(&self, block_number: u64) -> Option<FieldElement> {
+        let substrate_block_hash = self.substrate_block_hash(block_number)?;
+        let block = get_block_by_block_hash(self.client.as_ref(), substrate_block_hash).ok()?;
+
+        feeder_block_hash::<H>(block.header())
+    }
+
+    fn state_update(&self, block_number: u64) -> Option<L2StateUpdate> {
+        let substrate_block_hash = self.substrate_block_hash(block_number)?;
+        let block = get_block_by_block_hash(self.client.as_ref(), substrate_block_hash).ok()?;
+
+        // Blocks are mapped under the hash of their header, which differs from the feeder hash
+        let mapped = self.backend.mapping().block_hash(block.header().hash::<H>().into()).ok().flatten()?;
+        if !mapped.contains(&substrate_block_hash) {
+            return None;
+        }
+
+        Some(L2StateUpdate {
+            block_number,
+            global_root: block.header().global_state_root,
+            block_hash: Felt252Wrapper::from(feeder_block_hash::<H>(block.header())?).into(),
+        })
+    }
+
+    fn has_class(&self, block_number: u64, class_hash: ClassHash) -> bool {
+        let Some(substrate_block_hash) = self.substrate_block_hash(block_number) else {
+            return false;
+        };
+
+        // A change in class definition results in a change in class hash, so the hash is enough
+        self.overrides
+            .for_block_hash(self.client.as_ref(), substrate_block_hash)
+            .contract_class_by_class_hash(substrate_block_hash, class_hash)
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mp_hashers::pedersen::PedersenHasher;
+
+    use super::*;
+    use crate::commitments::verify::GatewayBlock;
+    use crate::utils::convert;
+
+    #[tokio::test]
+    async fn blocks_hashed_with_poseidon_keep_their_feeder_hash() {
+        let gateway: GatewayBlock =
+            serde_json::from_str(include_str!("../resources/blocks/poseidon_block.json")).unwrap();
+        let served_hash = gateway.block.block_hash.unwrap();
+        let chain_id = FieldElement::from_byte_slice_be(b"SN_MAIN").unwrap();
+        let block = convert::block(gateway.block, chain_id).await;
+
+        assert_eq!(feeder_block_hash::<PedersenHasher>(block.header()), Some(served_hash));
+        assert_ne!(FieldElement::from(block.header().hash::<PedersenHasher>()), served_hash);
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use bitvec::vec::BitVec;
-use bonsai_trie::id::{BasicId, BasicIdBuilder};
+use bonsai_trie::id::BasicId;
 use bonsai_trie::{BonsaiStorage, BonsaiStorageConfig};
 use mc_db::bonsai_db::BonsaiDb;
 use mc_db::BonsaiDbError;
@@ -40,6 +40,7 @@ pub fn calculate_class_commitment_leaf_hash<H: HasherT>(compiled_class_hash: Fel
 /// * `class_hash` - The hash of the class.
 /// * `compiled_class_hash` - The hash of the compiled class.
 /// * `bonsai_db` - The bonsai database responsible to compute the tries
+/// * `block_number` - The number of the block being imported, used as the commit id.
 ///
 /// # Returns
 ///
@@ -48,6 +49,7 @@ pub fn update_class_trie<B: BlockT>(
     class_hash: Felt252Wrapper,
     compiled_class_hash: Felt252Wrapper,
     backend: &Arc<BonsaiDb<B>>,
+    block_number: u64,
 ) -> Result<Felt252Wrapper, BonsaiDbError> {
     let config = BonsaiStorageConfig::default();
     let bonsai_db = backend.as_ref();
@@ -60,9 +62,7 @@ pub fn update_class_trie<B: BlockT>(
         .insert(key.as_bitslice(), &class_commitment_leaf_hash.into())
         .expect("Failed to insert into bonsai storage");
 
-    let mut id_builder = BasicIdBuilder::new();
-    let id = id_builder.new_id();
-    bonsai_storage.commit(id).expect("Failed to commit to bonsai storage");
+    bonsai_storage.commit(BasicId::new(block_number)).expect("Failed to commit to bonsai storage");
 
     let root_hash = bonsai_storage.root_hash().expect("Failed to get root hash");
     Ok(Felt252Wrapper::from(root_hash))
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use bitvec::prelude::BitVec;
 use blockifier::state::cached_state::CommitmentStateDiff;
-use bonsai_trie::id::{BasicId, BasicIdBuilder};
+use bonsai_trie::id::BasicId;
 use bonsai_trie::{BonsaiStorage, BonsaiStorageConfig};
 use mc_db::bonsai_db::BonsaiDb;
 use mc_db::BonsaiDbError;
@@ -22,10 +22,15 @@ pub struct ContractLeafParams {
 /// Calculates the storage root.
 ///
 /// `storage_root` is the root of another Merkle-Patricia trie of height 251 that is constructed
-/// from the contract’s storage.
+/// from the contract’s storage. Each contract has its own storage trie, namespaced in the storage
+/// columns by the contract address.
 ///
 /// # Arguments
 ///
+/// * `contract_address` - The address of the contract whose storage is updated.
+/// * `csd` - The commitment state diff inducing unprocessed state changes.
+/// * `bonsai_db` - The database responsible for storing computing the state tries.
+/// * `block_number` - The number of the block being imported, used as the commit id.
 ///
 /// # Returns
 ///
@@ -34,11 +39,12 @@ pub fn update_storage_trie<B: BlockT>(
     contract_address: &ContractAddress,
     csd: &Arc<CommitmentStateDiff>,
     bonsai_db: &Arc<BonsaiDb<B>>,
+    block_number: u64,
 ) -> Result<Felt252Wrapper, BonsaiDbError> {
     let config = BonsaiStorageConfig::default();
-    let bonsai_db = bonsai_db.as_ref();
+    let bonsai_db = storage_trie_db(contract_address, bonsai_db);
     let mut bonsai_storage: BonsaiStorage<BasicId, &BonsaiDb<B>, Pedersen> =
-        BonsaiStorage::<_, _, Pedersen>::new(bonsai_db, config).expect("Failed to create bonsai storage");
+        BonsaiStorage::<_, _, Pedersen>::new(&bonsai_db, config).expect("Failed to create bonsai storage");
 
     if let Some(updates) = csd.storage_updates.get(contract_address) {
         for (storage_key, storage_value) in updates {
@@ -48,9 +54,7 @@ pub fn update_storage_trie<B: BlockT>(
         }
     }
 
-    let mut id_builder = BasicIdBuilder::new();
-    let id = id_builder.new_id();
-    bonsai_storage.commit(id).expect("Failed to commit to bonsai storage");
+    bonsai_storage.commit(BasicId::new(block_number)).expect("Failed to commit to bonsai storage");
 
     let root_hash = bonsai_storage.root_hash().expect("Failed to get root hash");
 
@@ -61,21 +65,30 @@ pub fn update_storage_trie<B: BlockT>(
 ///
 /// # Arguments
 ///
+/// * `contract_address` - The address of the contract.
 /// * `bonsai_db` - The database responsible for storing computing the state tries.
 ///
 /// # Returns
 ///
-/// The contract state root hash as a `Felt252Wrapper` or a `BonsaiDbError`.
-pub fn get_storage_trie_root<B: BlockT>(bonsai_db: &Arc<BonsaiDb<B>>) -> Result<Felt252Wrapper, BonsaiDbError> {
+/// The contract storage root hash as a `Felt252Wrapper` or a `BonsaiDbError`.
+pub fn get_storage_trie_root<B: BlockT>(
+    contract_address: &ContractAddress,
+    bonsai_db: &Arc<BonsaiDb<B>>,
+) -> Result<Felt252Wrapper, BonsaiDbError> {
     let config = BonsaiStorageConfig::default();
-    let bonsai_db = bonsai_db.as_ref();
+    let bonsai_db = storage_trie_db(contract_address, bonsai_db);
     let bonsai_storage: BonsaiStorage<BasicId, &BonsaiDb<B>, Pedersen> =
-        BonsaiStorage::<_, _, Pedersen>::new(bonsai_db, config).expect("Failed to create bonsai storage");
+        BonsaiStorage::<_, _, Pedersen>::new(&bonsai_db, config).expect("Failed to create bonsai storage");
 
     let root_hash = bonsai_storage.root_hash().expect("Failed to get root hash");
     Ok(Felt252Wrapper::from(root_hash))
 }
 
+/// Returns a view of the storage database restricted to the storage trie of a single contract.
+pub fn storage_trie_db<B: BlockT>(contract_address: &ContractAddress, bonsai_db: &Arc<BonsaiDb<B>>) -> BonsaiDb<B> {
+    bonsai_db.with_prefix(contract_address.0.key().bytes())
+}
+
 /// Calculates the contract state hash.
 ///
 /// # Arguments
@@ -108,6 +121,7 @@ pub fn calculate_contract_state_leaf_hash<H: HasherT>(contract_leaf_params: Cont
 /// * `contract_hash` - The hash of the contract.
 /// * `contract_leaf_params` - A struct containing the class hash, storage root and nonce.
 /// * `bonsai_db` - The database responsible for storing computing the state tries.
+/// * `block_number` - The number of the block being imported, used as the commit id.
 ///
 /// # Returns
 ///
@@ -116,6 +130,7 @@ pub fn update_contract_trie<B: BlockT>(
     contract_hash: Felt252Wrapper,
     contract_leaf_params: ContractLeafParams,
     bonsai_db: &Arc<BonsaiDb<B>>,
+    block_number: u64,
 ) -> anyhow::Result<Felt252Wrapper> {
     let config = BonsaiStorageConfig::default();
     let bonsai_db = bonsai_db.as_ref();
@@ -128,9 +143,7 @@ pub fn update_contract_trie<B: BlockT>(
         .insert(key.as_bitslice(), &class_commitment_leaf_hash.into())
         .expect("Failed to insert into bonsai storage");
 
-    let mut id_builder = BasicIdBuilder::new();
-    let id = id_builder.new_id();
-    bonsai_storage.commit(id).expect("Failed to commit to bonsai storage");
+    bonsai_storage.commit(BasicId::new(block_number)).expect("Failed to commit to bonsai storage");
 
     let root_hash = bonsai_storage.root_hash().expect("Failed to get root hash");
     Ok(Felt252Wrapper::from(root_hash))
@@ -14,12 +14,14 @@ use starknet_api::api_core::{ClassHash, CompiledClassHash, ContractAddress, Nonc
 use starknet_api::hash::StarkFelt;
 use starknet_api::state::StorageKey;
 use starknet_api::transaction::Event;
+use starknet_types_core::hash::{Pedersen, Poseidon};
 use tokio::join;
 use tokio::task::{spawn_blocking, JoinSet};
 
 use super::classes::{get_class_trie_root, update_class_trie};
 use super::contracts::{get_contract_trie_root, update_contract_trie, update_storage_trie, ContractLeafParams};
 use super::events::memory_event_commitment;
+use super::proofs::trie_at;
 use super::transactions::memory_transaction_commitment;
 
 /// Calculate the transaction and event commitment.
@@ -107,7 +109,8 @@ pub fn build_commitment_state_diff(state_update_wrapper: StateUpdateWrapper) ->
 ///
 /// The state commitment is the digest that uniquely (up to hash collisions) encodes the state.
 /// It combines the roots of two binary Merkle-Patricia tries of height 251 using Poseidon/Pedersen
-/// hashers.
+/// hashers. Until the first Sierra class is declared the class trie is empty, and the state
+/// commitment is the root of the contracts trie alone.
 ///
 /// # Arguments
 ///
@@ -124,6 +127,10 @@ pub fn calculate_state_root<H: HasherT>(
 where
     H: HasherT,
 {
+    if classes_trie_root == Felt252Wrapper::ZERO {
+        return contracts_trie_root;
+    }
+
     let starknet_state_prefix = Felt252Wrapper::try_from("STARKNET_STATE_V0".as_bytes()).unwrap();
 
     let state_commitment_hash =
@@ -138,10 +145,14 @@ where
 /// It combines the roots of two binary Merkle-Patricia tries of height 251 using Poseidon/Pedersen
 /// hashers.
 ///
+/// The tries are snapshotted at `block_number` once all of them are updated. Changes left by a
+/// previous update that did not complete are reverted first.
+///
 /// # Arguments
 ///
 /// * `CommitmentStateDiff` - The commitment state diff inducing unprocessed state changes.
 /// * `BonsaiDb` - The database responsible for storing computing the state tries.
+/// * `block_number` - The number of the block being imported.
 ///
 /// # Returns
 ///
@@ -149,23 +160,33 @@ where
 pub async fn update_state_root<B: BlockT>(
     csd: CommitmentStateDiff,
     bonsai_dbs: BonsaiDbs<B>,
+    block_number: u64,
 ) -> anyhow::Result<Felt252Wrapper> {
+    bonsai_dbs.recover()?;
+
     let arc_csd = Arc::new(csd);
     let arc_bonsai_dbs = Arc::new(bonsai_dbs);
 
-    let contract_trie_root = contract_trie_root(Arc::clone(&arc_csd), Arc::clone(&arc_bonsai_dbs)).await?;
+    update_contract_tries(Arc::clone(&arc_csd), Arc::clone(&arc_bonsai_dbs), block_number).await?;
 
-    let class_trie_root = class_trie_root(Arc::clone(&arc_csd), Arc::clone(&arc_bonsai_dbs))?;
+    update_class_tries(Arc::clone(&arc_csd), Arc::clone(&arc_bonsai_dbs), block_number)?;
 
+    arc_bonsai_dbs.commit_block(block_number)?;
+
+    // The roots are read back from the tries, which are left untouched by a block which does not
+    // update them
+    let contract_trie_root = get_contract_trie_root(&arc_bonsai_dbs.contract)?;
+    let class_trie_root = get_class_trie_root(&arc_bonsai_dbs.class)?;
     let state_root = calculate_state_root::<PoseidonHasher>(contract_trie_root, class_trie_root);
 
     Ok(state_root)
 }
 
-async fn contract_trie_root<B: BlockT>(
+async fn update_contract_tries<B: BlockT>(
     csd: Arc<CommitmentStateDiff>,
     bonsai_dbs: Arc<BonsaiDbs<B>>,
-) -> anyhow::Result<Felt252Wrapper> {
+    block_number: u64,
+) -> anyhow::Result<()> {
     // Risk of starving the thread pool (execution over 1s in some cases), must be run in a
     // blocking-safe thread. Main bottleneck is still calling `commit` on the Bonsai db.
     let mut task_set = spawn_blocking(move || {
@@ -180,6 +201,7 @@ async fn contract_trie_root<B: BlockT>(
                 bonsai_dbs_clone,
                 contract_address.clone(),
                 class_hash.clone(),
+                block_number,
             ));
         });
 
@@ -187,20 +209,16 @@ async fn contract_trie_root<B: BlockT>(
     })
     .await?;
 
-    // The order in which contract trie roots are waited for is not important since each call to
-    // `update_contract_trie` in `contract_trie_root` mutates the Deoxys db.
-    let mut contract_trie_root = Felt252Wrapper::ZERO;
+    // The order in which contract trie updates are waited for is not important since each call to
+    // `update_contract_trie` in `contract_trie_root_loop` mutates the Deoxys db.
     while let Some(res) = task_set.join_next().await {
-        contract_trie_root = match res? {
-            Ok(trie_root) => trie_root,
-            Err(e) => {
-                task_set.abort_all();
-                return Err(e);
-            }
+        if let Err(e) = res? {
+            task_set.abort_all();
+            return Err(e);
         }
     }
 
-    Ok(contract_trie_root)
+    Ok(())
 }
 
 async fn contract_trie_root_loop<B: BlockT>(
@@ -208,32 +226,36 @@ async fn contract_trie_root_loop<B: BlockT>(
     bonsai_dbs: Arc<BonsaiDbs<B>>,
     contract_address: ContractAddress,
     class_hash: ClassHash,
+    block_number: u64,
 ) -> anyhow::Result<Felt252Wrapper> {
-    let storage_root =
-        update_storage_trie(&contract_address, &csd, &bonsai_dbs.storage).expect("Failed to update storage trie");
+    let storage_root = update_storage_trie(&contract_address, &csd, &bonsai_dbs.storage, block_number)
+        .expect("Failed to update storage trie");
     let nonce = csd.address_to_nonce.get(&contract_address).unwrap_or(&Felt252Wrapper::default().into()).clone();
 
     let contract_leaf_params =
         ContractLeafParams { class_hash: class_hash.clone().into(), storage_root, nonce: nonce.into() };
 
-    update_contract_trie(contract_address.into(), contract_leaf_params, &bonsai_dbs.contract)
+    update_contract_trie(contract_address.into(), contract_leaf_params, &bonsai_dbs.contract, block_number)
 }
 
-fn class_trie_root<B: BlockT>(
+fn update_class_tries<B: BlockT>(
     csd: Arc<CommitmentStateDiff>,
     bonsai_dbs: Arc<BonsaiDbs<B>>,
-) -> anyhow::Result<Felt252Wrapper> {
-    let mut class_trie_root = Felt252Wrapper::default();
-
+    block_number: u64,
+) -> anyhow::Result<()> {
     // Based on benchmarks the execution cost of computing the class tried root is negligible
     // compared to the contract trie root. It is likely that parallelizing this would yield no
     // observalble benefits.
     for (class_hash, compiled_class_hash) in csd.class_hash_to_compiled_class_hash.iter() {
-        class_trie_root =
-            update_class_trie(class_hash.clone().into(), compiled_class_hash.clone().into(), &bonsai_dbs.class)?;
+        update_class_trie(
+            class_hash.clone().into(),
+            compiled_class_hash.clone().into(),
+            &bonsai_dbs.class,
+            block_number,
+        )?;
     }
 
-    Ok(class_trie_root)
+    Ok(())
 }
 
 /// Retrieves and compute the actual state root.
@@ -259,3 +281,23 @@ where
 
     calculate_state_root::<H>(contract_trie_root, class_trie_root)
 }
+
+/// Computes the state root from the tries as they were at `block_number`.
+///
+/// # Arguments
+///
+/// * `bonsai_dbs` - The databases holding the state tries.
+/// * `block_number` - The block at which the tries are read.
+///
+/// # Returns
+///
+/// The state root as a `Felt252Wrapper`, or `None` if no snapshot is kept for this block.
+pub fn state_root_at<B: BlockT>(bonsai_dbs: &BonsaiDbs<B>, block_number: u64) -> Option<Felt252Wrapper> {
+    let contract_trie = trie_at::<B, Pedersen>(&bonsai_dbs.contract, block_number)?;
+    let class_trie = trie_at::<B, Poseidon>(&bonsai_dbs.class, block_number)?;
+
+    let contract_trie_root = Felt252Wrapper::from(contract_trie.root_hash().expect("Failed to get root hash"));
+    let class_trie_root = Felt252Wrapper::from(class_trie.root_hash().expect("Failed to get root hash"));
+
+    Some(calculate_state_root::<PoseidonHasher>(contract_trie_root, class_trie_root))
+}
@@ -2,4 +2,7 @@ pub mod classes;
 pub mod contracts;
 pub mod events;
 pub mod lib;
+pub mod poseidon;
+pub mod proofs;
 pub mod transactions;
+pub mod verify;
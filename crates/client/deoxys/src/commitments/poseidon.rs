@@ -0,0 +1,205 @@
+//! Commitments of the blocks hashed with Poseidon, from Starknet 0.13.2.
+//!
+//! They are computed from the block and the state update as served by the feeder gateway: the
+//! transaction hashes are taken as served, since this node does not compute the hash of every
+//! transaction version such blocks may include. The mismatches found in such blocks state it, see
+//! [`super::verify::POSEIDON_TRANSACTION_HASHES_NOTE`].
+
+use bitvec::vec::BitVec;
+use bonsai_trie::databases::HashMapDb;
+use bonsai_trie::id::{BasicId, BasicIdBuilder};
+use bonsai_trie::{BonsaiStorage, BonsaiStorageConfig};
+use mp_felt::Felt252Wrapper;
+use mp_hashers::poseidon::PoseidonHasher;
+use mp_hashers::HasherT;
+use starknet_core::types::MsgToL1;
+use starknet_core::utils::{cairo_short_string_to_felt, starknet_keccak};
+use starknet_ff::FieldElement;
+use starknet_providers::sequencer::models as p;
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::Poseidon;
+
+use super::verify::ReceiptGas;
+
+/// Calculate the transaction commitment of a block.
+///
+/// # Arguments
+///
+/// * `block` - The block, as served by the feeder gateway
+///
+/// # Returns
+///
+/// The root of the trie of the hashes of the transactions along with their signature.
+pub fn transaction_commitment(block: &p::Block) -> FieldElement {
+    let leaves = block.transactions.iter().map(|transaction| {
+        let (transaction_hash, signature) = match transaction {
+            p::TransactionType::InvokeFunction(tx) => (tx.transaction_hash, tx.signature.as_slice()),
+            p::TransactionType::Declare(tx) => (tx.transaction_hash, tx.signature.as_slice()),
+            p::TransactionType::DeployAccount(tx) => (tx.transaction_hash, tx.signature.as_slice()),
+            p::TransactionType::Deploy(tx) => (tx.transaction_hash, [].as_slice()),
+            p::TransactionType::L1Handler(tx) => (tx.transaction_hash, [].as_slice()),
+        };
+
+        // An empty signature is hashed as a single zero
+        let signature = if signature.is_empty() { &[FieldElement::ZERO][..] } else { signature };
+        PoseidonHasher::compute_hash_on_elements(&[&[transaction_hash], signature].concat())
+    });
+
+    commitment(leaves.collect())
+}
+
+/// Calculate the event commitment of a block.
+///
+/// # Arguments
+///
+/// * `block` - The block, as served by the feeder gateway
+///
+/// # Returns
+///
+/// The root of the trie of the hashes of the events along with the transaction emitting them.
+pub fn event_commitment(block: &p::Block) -> FieldElement {
+    let leaves = block.transaction_receipts.iter().flat_map(|receipt| {
+        receipt.events.iter().map(|event| {
+            let mut elements = vec![event.from_address, receipt.transaction_hash];
+            elements.push(FieldElement::from(event.keys.len()));
+            elements.extend_from_slice(&event.keys);
+            elements.push(FieldElement::from(event.data.len()));
+            elements.extend_from_slice(&event.data);
+
+            PoseidonHasher::compute_hash_on_elements(&elements)
+        })
+    });
+
+    commitment(leaves.collect())
+}
+
+/// Calculate the receipt commitment of a block.
+///
+/// # Arguments
+///
+/// * `block` - The block, as served by the feeder gateway
+/// * `gas` - The gas consumed by each transaction of the block
+///
+/// # Returns
+///
+/// The root of the trie of the hashes of the receipts.
+pub fn receipt_commitment(block: &p::Block, gas: &[ReceiptGas]) -> FieldElement {
+    let leaves = block.transaction_receipts.iter().enumerate().map(|(i, receipt)| {
+        let revert_reason = match receipt.execution_status {
+            Some(p::TransactionExecutionStatus::Reverted) => {
+                starknet_keccak(receipt.revert_error.as_deref().unwrap_or_default().as_bytes())
+            }
+            _ => FieldElement::ZERO,
+        };
+        let gas = gas.get(i).map(ReceiptGas::total_gas_consumed).unwrap_or_default();
+
+        PoseidonHasher::compute_hash_on_elements(&[
+            receipt.transaction_hash,
+            receipt.actual_fee,
+            messages_hash(receipt),
+            revert_reason,
+            FieldElement::ZERO, // l2 gas consumed
+            FieldElement::from(gas.l1_gas),
+            FieldElement::from(gas.l1_data_gas),
+        ])
+    });
+
+    commitment(leaves.collect())
+}
+
+/// Calculate the state diff commitment of a block.
+///
+/// # Arguments
+///
+/// * `state_update` - The state update of the block, as served by the feeder gateway
+///
+/// # Returns
+///
+/// The Poseidon hash of the sorted state diff, along with the number of entries of the state diff.
+pub fn state_diff_commitment(state_update: &p::StateUpdate) -> (FieldElement, u64) {
+    let state_diff = &state_update.state_diff;
+
+    let mut deployed: Vec<_> = state_diff
+        .deployed_contracts
+        .iter()
+        .chain(&state_diff.replaced_classes)
+        .map(|contract| (contract.address, contract.class_hash))
+        .collect();
+    deployed.sort();
+    let mut declared: Vec<_> =
+        state_diff.declared_classes.iter().map(|class| (class.class_hash, class.compiled_class_hash)).collect();
+    declared.sort();
+    let mut old_declared = state_diff.old_declared_contracts.clone();
+    old_declared.sort();
+    let mut storage_diffs: Vec<_> = state_diff
+        .storage_diffs
+        .iter()
+        .filter(|(_, diffs)| !diffs.is_empty())
+        .map(|(address, diffs)| {
+            let mut diffs: Vec<_> = diffs.iter().map(|diff| (diff.key, diff.value)).collect();
+            diffs.sort();
+            (*address, diffs)
+        })
+        .collect();
+    storage_diffs.sort();
+    let mut nonces: Vec<_> = state_diff.nonces.iter().map(|(address, nonce)| (*address, *nonce)).collect();
+    nonces.sort();
+
+    let mut elements = vec![cairo_short_string_to_felt("STARKNET_STATE_DIFF0").unwrap()];
+    elements.push(FieldElement::from(deployed.len()));
+    elements.extend(deployed.iter().flat_map(|(address, class_hash)| [*address, *class_hash]));
+    elements.push(FieldElement::from(declared.len()));
+    elements.extend(declared.iter().flat_map(|(class_hash, compiled_class_hash)| [*class_hash, *compiled_class_hash]));
+    elements.push(FieldElement::from(old_declared.len()));
+    elements.extend(&old_declared);
+    // Placeholder of the data availability modes of the storage and nonce updates
+    elements.extend([FieldElement::ONE, FieldElement::ZERO]);
+    elements.push(FieldElement::from(storage_diffs.len()));
+    for (address, diffs) in &storage_diffs {
+        elements.extend([*address, FieldElement::from(diffs.len())]);
+        elements.extend(diffs.iter().flat_map(|(key, value)| [*key, *value]));
+    }
+    elements.push(FieldElement::from(nonces.len()));
+    elements.extend(nonces.iter().flat_map(|(address, nonce)| [*address, *nonce]));
+
+    let length = storage_diffs.iter().map(|(_, diffs)| diffs.len()).sum::<usize>()
+        + nonces.len()
+        + deployed.len()
+        + declared.len()
+        + old_declared.len();
+
+    (PoseidonHasher::compute_hash_on_elements(&elements), length as u64)
+}
+
+/// Hashes the messages sent to L1 by a transaction.
+fn messages_hash(receipt: &p::ConfirmedTransactionReceipt) -> FieldElement {
+    let mut elements = vec![FieldElement::from(receipt.l2_to_l1_messages.len())];
+    for message in &receipt.l2_to_l1_messages {
+        let message = MsgToL1::from(message.clone());
+        elements.extend([message.from_address, message.to_address, FieldElement::from(message.payload.len())]);
+        elements.extend(message.payload);
+    }
+
+    PoseidonHasher::compute_hash_on_elements(&elements)
+}
+
+/// Computes the root of a Poseidon trie whose leaves are keyed by their 64 bits index.
+fn commitment(leaves: Vec<FieldElement>) -> FieldElement {
+    if leaves.is_empty() {
+        return FieldElement::ZERO;
+    }
+
+    let mut bonsai_storage =
+        BonsaiStorage::<_, _, Poseidon>::new(HashMapDb::<BasicId>::default(), BonsaiStorageConfig::default())
+            .expect("Failed to create bonsai storage");
+    for (i, leaf) in leaves.into_iter().enumerate() {
+        let key = BitVec::from_vec((i as u64).to_be_bytes().to_vec());
+        let value = Felt::from(Felt252Wrapper::from(leaf));
+        bonsai_storage.insert(key.as_bitslice(), &value).expect("Failed to insert into bonsai storage");
+    }
+
+    bonsai_storage.commit(BasicIdBuilder::new().new_id()).expect("Failed to commit to bonsai storage");
+    let root_hash = bonsai_storage.root_hash().expect("Failed to get root hash");
+
+    Felt252Wrapper::from(root_hash).into()
+}
@@ -0,0 +1,409 @@
+use bitvec::prelude::{BitSlice, BitVec, Msb0};
+use bonsai_trie::id::BasicId;
+use bonsai_trie::{BonsaiDatabase, BonsaiStorage, BonsaiStorageConfig, ProofNode as BonsaiProofNode};
+use mc_db::bonsai_db::{BonsaiDb, TransactionWrapper};
+use mc_db::BonsaiDbs;
+use mc_rpc_core::{ContractData, EdgePath, GetStorageProofResult, ProofNode};
+use mp_felt::Felt252Wrapper;
+use mp_hashers::pedersen::PedersenHasher;
+use mp_hashers::poseidon::PoseidonHasher;
+use mp_hashers::HasherT;
+use sp_runtime::traits::Block as BlockT;
+use starknet_api::api_core::ContractAddress;
+use starknet_ff::FieldElement;
+use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash};
+
+use super::contracts::{calculate_contract_state_leaf_hash, storage_trie_db, ContractLeafParams};
+use super::lib::calculate_state_root;
+
+/// Errors returned when a proof does not match the expected root.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ProofError {
+    #[error("node hash does not match its parent at depth {0}")]
+    HashMismatch(usize),
+    #[error("proof walks {0} bits but the key has {1}")]
+    InvalidLength(usize, usize),
+    #[error("proof is empty but the root is not zero")]
+    EmptyProof,
+    #[error("state commitment does not match the contract and class trie roots")]
+    StateCommitmentMismatch,
+    #[error("contract leaf does not match the contract data")]
+    ContractLeafMismatch,
+    #[error("expected {0} storage proofs, got {1}")]
+    StorageProofCount(usize, usize),
+    #[error("the state tries are not available at block {0}")]
+    SnapshotUnavailable(u64),
+    #[error("failed to read the trie: {0}")]
+    Trie(String),
+}
+
+/// Converts a felt into the key used to index the Bonsai tries.
+///
+/// # Arguments
+///
+/// * `felt` - The contract address, class hash or storage key.
+///
+/// # Returns
+///
+/// The trie key as a `BitVec`.
+pub fn trie_key(felt: FieldElement) -> BitVec<u8, Msb0> {
+    BitVec::from_vec(felt.to_bytes_be()[..31].to_vec())
+}
+
+/// Opens the trie stored in `bonsai_db` as it was at `block_number`.
+///
+/// # Arguments
+///
+/// * `bonsai_db` - The database holding the trie.
+/// * `block_number` - The block at which the trie is read.
+///
+/// # Returns
+///
+/// A read-only view of the trie, or `ProofError::SnapshotUnavailable` if no snapshot is kept for
+/// this block.
+pub fn trie_at<B: BlockT, H: StarkHash + Send + Sync>(
+    bonsai_db: &BonsaiDb<B>,
+    block_number: u64,
+) -> Result<BonsaiStorage<BasicId, TransactionWrapper, H>, ProofError> {
+    let config = BonsaiStorageConfig::default();
+    let bonsai_storage: BonsaiStorage<BasicId, &BonsaiDb<B>, H> =
+        BonsaiStorage::<_, _, H>::new(bonsai_db, config).map_err(|e| ProofError::Trie(format!("{e:?}")))?;
+
+    bonsai_storage
+        .get_transactional_state(BasicId::new(block_number), BonsaiStorageConfig::default())
+        .map_err(|e| ProofError::Trie(format!("{e:?}")))?
+        .ok_or(ProofError::SnapshotUnavailable(block_number))
+}
+
+/// Generates the Merkle proof of a key in a trie.
+///
+/// # Arguments
+///
+/// * `bonsai_storage` - The trie, see [`trie_at`].
+/// * `key` - The key to prove, see [`trie_key`].
+///
+/// # Returns
+///
+/// The nodes from the root of the trie down to the key. When the key is not a member, the nodes
+/// down to the edge where it diverges from the trie, which prove its absence.
+pub fn get_proof<DB: BonsaiDatabase, H: StarkHash + Send + Sync>(
+    bonsai_storage: &BonsaiStorage<BasicId, DB, H>,
+    key: &BitSlice<u8, Msb0>,
+) -> Result<Vec<ProofNode>, ProofError> {
+    let proof = bonsai_storage.get_proof(key).map_err(|e| ProofError::Trie(format!("{e:?}")))?;
+    Ok(proof.into_iter().map(to_rpc_proof_node).collect())
+}
+
+/// Returns the root of a trie, zero if it is empty.
+fn root_of<DB: BonsaiDatabase, H: StarkHash + Send + Sync>(
+    bonsai_storage: &BonsaiStorage<BasicId, DB, H>,
+) -> Result<FieldElement, ProofError> {
+    let root = bonsai_storage.root_hash().map_err(|e| ProofError::Trie(format!("{e:?}")))?;
+    Ok(Felt252Wrapper::from(root).into())
+}
+
+/// Generates the proof of a contract in the contract trie at a given block, along with the proofs
+/// of its class in the class trie and of the requested storage keys in its own storage trie.
+///
+/// # Arguments
+///
+/// * `block_number` - The block at which the proofs are generated.
+/// * `contract_address` - The address of the contract.
+/// * `keys` - The storage keys to prove.
+/// * `class_hash` - The class hash of the contract, `None` if it is not deployed.
+/// * `nonce` - The nonce of the contract.
+/// * `bonsai_dbs` - The databases holding the state tries.
+///
+/// # Returns
+///
+/// The proofs as a `GetStorageProofResult`. Contracts and keys which are not in the state are
+/// proven absent. Fails with `ProofError::SnapshotUnavailable` if no snapshot is kept for this
+/// block.
+pub fn get_storage_proof<B: BlockT>(
+    block_number: u64,
+    contract_address: ContractAddress,
+    keys: &[FieldElement],
+    class_hash: Option<FieldElement>,
+    nonce: FieldElement,
+    bonsai_dbs: &BonsaiDbs<B>,
+) -> Result<GetStorageProofResult, ProofError> {
+    let contract_trie = trie_at::<B, Pedersen>(&bonsai_dbs.contract, block_number)?;
+    let class_trie = trie_at::<B, Poseidon>(&bonsai_dbs.class, block_number)?;
+
+    let address = FieldElement::from(Felt252Wrapper::from(contract_address));
+    let contract_proof = get_proof(&contract_trie, &trie_key(address))?;
+
+    let contract_root = root_of(&contract_trie)?;
+    let class_commitment = root_of(&class_trie)?;
+    let state_commitment = calculate_state_root::<PoseidonHasher>(contract_root.into(), class_commitment.into()).into();
+
+    let contract_data = match class_hash {
+        Some(class_hash) => {
+            let storage_db = storage_trie_db(&contract_address, &bonsai_dbs.storage);
+            let storage_trie = trie_at::<B, Pedersen>(&storage_db, block_number)?;
+            let storage_proofs =
+                keys.iter().map(|key| get_proof(&storage_trie, &trie_key(*key))).collect::<Result<_, _>>()?;
+
+            Some(ContractData {
+                class_hash,
+                nonce,
+                root: root_of(&storage_trie)?,
+                contract_state_hash_version: FieldElement::ZERO,
+                class_proof: get_proof(&class_trie, &trie_key(class_hash))?,
+                storage_proofs,
+            })
+        }
+        None => None,
+    };
+
+    Ok(GetStorageProofResult { state_commitment, class_commitment, contract_proof, contract_data })
+}
+
+/// Verifies a Merkle proof against a trie root.
+///
+/// # Arguments
+///
+/// * `root` - The expected root of the trie.
+/// * `key` - The key being proven, see [`trie_key`].
+/// * `proof` - The nodes from the root down to the key.
+///
+/// # Returns
+///
+/// `Some(value)` if the key is in the trie, `None` if the proof shows that it is not, or a
+/// `ProofError` if the proof is invalid.
+pub fn verify_proof<H: HasherT>(
+    root: FieldElement,
+    key: &BitSlice<u8, Msb0>,
+    proof: &[ProofNode],
+) -> Result<Option<FieldElement>, ProofError> {
+    if proof.is_empty() {
+        return if root == FieldElement::ZERO { Ok(None) } else { Err(ProofError::EmptyProof) };
+    }
+
+    let mut expected = root;
+    let mut depth = 0;
+
+    for node in proof {
+        if node_hash::<H>(node) != expected {
+            return Err(ProofError::HashMismatch(depth));
+        }
+
+        match node {
+            ProofNode::Binary { left, right } => {
+                let bit = *key.get(depth).ok_or(ProofError::InvalidLength(depth + 1, key.len()))?;
+                expected = if bit { *right } else { *left };
+                depth += 1;
+            }
+            ProofNode::Edge { child, path } => {
+                let key_path =
+                    key.get(depth..depth + path.len).ok_or(ProofError::InvalidLength(depth + path.len, key.len()))?;
+                if key_path != path_bits(path).as_bitslice() {
+                    // The key diverges from the only path of the trie: it is not a member.
+                    return Ok(None);
+                }
+                expected = *child;
+                depth += path.len;
+            }
+        }
+    }
+
+    if depth != key.len() {
+        return Err(ProofError::InvalidLength(depth, key.len()));
+    }
+
+    Ok(Some(expected))
+}
+
+/// Verifies a `GetStorageProofResult` offline.
+///
+/// # Arguments
+///
+/// * `contract_address` - The address of the contract.
+/// * `keys` - The storage keys that were requested, in the same order.
+/// * `result` - The result returned by `starknet_getStorageProof`.
+///
+/// # Returns
+///
+/// The value of each storage key (`None` for unset keys), or a `ProofError` if any proof is
+/// invalid. If the contract is proven not deployed, all the keys are unset.
+pub fn verify_storage_proof(
+    contract_address: FieldElement,
+    keys: &[FieldElement],
+    result: &GetStorageProofResult,
+) -> Result<Vec<Option<FieldElement>>, ProofError> {
+    let contract_root = match result.contract_proof.first() {
+        Some(node) => node_hash::<PedersenHasher>(node),
+        None => FieldElement::ZERO,
+    };
+    let state_commitment: FieldElement =
+        calculate_state_root::<PoseidonHasher>(contract_root.into(), result.class_commitment.into()).into();
+    if state_commitment != result.state_commitment {
+        return Err(ProofError::StateCommitmentMismatch);
+    }
+
+    let leaf = verify_proof::<PedersenHasher>(contract_root, &trie_key(contract_address), &result.contract_proof)?;
+
+    let contract_data = match (&result.contract_data, leaf) {
+        (Some(contract_data), Some(leaf)) => {
+            let expected_leaf = calculate_contract_state_leaf_hash::<PedersenHasher>(ContractLeafParams {
+                class_hash: contract_data.class_hash.into(),
+                storage_root: contract_data.root.into(),
+                nonce: contract_data.nonce.into(),
+            });
+            if FieldElement::from(expected_leaf) != leaf {
+                return Err(ProofError::ContractLeafMismatch);
+            }
+            contract_data
+        }
+        (None, None) => return Ok(vec![None; keys.len()]),
+        _ => return Err(ProofError::ContractLeafMismatch),
+    };
+
+    // Legacy classes are not in the class trie, so the class may be proven absent
+    verify_proof::<PoseidonHasher>(
+        result.class_commitment,
+        &trie_key(contract_data.class_hash),
+        &contract_data.class_proof,
+    )?;
+
+    if contract_data.storage_proofs.len() != keys.len() {
+        return Err(ProofError::StorageProofCount(keys.len(), contract_data.storage_proofs.len()));
+    }
+
+    keys.iter()
+        .zip(contract_data.storage_proofs.iter())
+        .map(|(key, proof)| verify_proof::<PedersenHasher>(contract_data.root, &trie_key(*key), proof))
+        .collect()
+}
+
+/// Hash of a proof node, as stored in its parent.
+fn node_hash<H: HasherT>(node: &ProofNode) -> FieldElement {
+    match node {
+        ProofNode::Binary { left, right } => H::hash_elements(*left, *right),
+        ProofNode::Edge { child, path } => H::hash_elements(*child, path.value) + FieldElement::from(path.len as u64),
+    }
+}
+
+/// The `len` least significant bits of an edge path, most significant first.
+fn path_bits(path: &EdgePath) -> BitVec<u8, Msb0> {
+    let bytes = path.value.to_bytes_be();
+    let bits = BitSlice::<u8, Msb0>::from_slice(&bytes);
+    bits[bits.len().saturating_sub(path.len)..].to_bitvec()
+}
+
+fn to_rpc_proof_node(node: BonsaiProofNode) -> ProofNode {
+    match node {
+        BonsaiProofNode::Binary { left, right } => {
+            ProofNode::Binary { left: Felt252Wrapper::from(left).into(), right: Felt252Wrapper::from(right).into() }
+        }
+        BonsaiProofNode::Edge { child, path } => {
+            let len = path.0.len();
+            let mut bits = BitVec::<u8, Msb0>::repeat(false, 256 - len);
+            bits.extend_from_bitslice(&path.0);
+            let value = FieldElement::from_byte_slice_be(bits.as_raw_slice()).expect("Edge path should fit in a felt");
+
+            ProofNode::Edge { child: Felt252Wrapper::from(child).into(), path: EdgePath { value, len } }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bonsai_trie::databases::HashMapDb;
+    use bonsai_trie::id::BasicIdBuilder;
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+
+    fn edge(child: FieldElement, path: &BitSlice<u8, Msb0>) -> ProofNode {
+        let mut bits = BitVec::<u8, Msb0>::repeat(false, 256 - path.len());
+        bits.extend_from_bitslice(path);
+        let value = FieldElement::from_byte_slice_be(bits.as_raw_slice()).unwrap();
+        ProofNode::Edge { child, path: EdgePath { value, len: path.len() } }
+    }
+
+    #[test]
+    fn verify_single_leaf_proof() {
+        let key = trie_key(FieldElement::from(0x123400_u64));
+        let value = FieldElement::from(42_u64);
+        let proof = vec![edge(value, &key)];
+        let root = node_hash::<PedersenHasher>(&proof[0]);
+
+        assert_eq!(verify_proof::<PedersenHasher>(root, &key, &proof), Ok(Some(value)));
+
+        let other_key = trie_key(FieldElement::from(0x123500_u64));
+        assert_eq!(verify_proof::<PedersenHasher>(root, &other_key, &proof), Ok(None));
+
+        let wrong_root = root + FieldElement::ONE;
+        assert_eq!(verify_proof::<PedersenHasher>(wrong_root, &key, &proof), Err(ProofError::HashMismatch(0)));
+    }
+
+    #[test]
+    fn verify_binary_proof() {
+        let key = trie_key(FieldElement::from(0x123400_u64));
+        let value = FieldElement::from(42_u64);
+        let leaf_edge = edge(value, &key[1..]);
+        let sibling = FieldElement::from(7_u64);
+        let proof =
+            vec![ProofNode::Binary { left: node_hash::<PedersenHasher>(&leaf_edge), right: sibling }, leaf_edge];
+        let root = node_hash::<PedersenHasher>(&proof[0]);
+
+        assert_eq!(verify_proof::<PedersenHasher>(root, &key, &proof), Ok(Some(value)));
+        assert_eq!(verify_proof::<PedersenHasher>(FieldElement::ZERO, &key, &[]), Ok(None));
+    }
+
+    fn memory_trie(leaves: &[(u64, u64)]) -> BonsaiStorage<BasicId, HashMapDb<BasicId>, Pedersen> {
+        let mut trie = BonsaiStorage::new(HashMapDb::<BasicId>::default(), BonsaiStorageConfig::default()).unwrap();
+        for (key, value) in leaves {
+            trie.insert(&trie_key(FieldElement::from(*key)), &Felt::from(*value)).unwrap();
+        }
+        trie.commit(BasicIdBuilder::new().new_id()).unwrap();
+        trie
+    }
+
+    #[test]
+    fn bonsai_proofs_prove_membership_and_absence() {
+        let leaves = [(0x123400, 1), (0x567800, 2), (0x567900, 3)];
+        let trie = memory_trie(&leaves);
+        let root = root_of(&trie).unwrap();
+
+        for (key, value) in leaves {
+            let key = trie_key(FieldElement::from(key));
+            let proof = get_proof(&trie, &key).unwrap();
+            assert_eq!(verify_proof::<PedersenHasher>(root, &key, &proof), Ok(Some(FieldElement::from(value))));
+        }
+
+        for absent in [0x567a00_u64, 0xff0000, 0] {
+            let key = trie_key(FieldElement::from(absent));
+            let proof = get_proof(&trie, &key).unwrap();
+            assert!(!proof.is_empty());
+            assert_eq!(verify_proof::<PedersenHasher>(root, &key, &proof), Ok(None));
+        }
+    }
+
+    #[test]
+    fn undeployed_contract_is_proven_absent() {
+        let trie = memory_trie(&[(0x123400, 1), (0x567800, 2)]);
+        let contract_root = root_of(&trie).unwrap();
+        let class_commitment = FieldElement::from(7_u64);
+        let address = FieldElement::from(0x567900_u64);
+
+        let mut result = GetStorageProofResult {
+            state_commitment: calculate_state_root::<PoseidonHasher>(contract_root.into(), class_commitment.into())
+                .into(),
+            class_commitment,
+            contract_proof: get_proof(&trie, &trie_key(address)).unwrap(),
+            contract_data: None,
+        };
+        let keys = [FieldElement::ONE, FieldElement::TWO];
+
+        assert_eq!(verify_storage_proof(address, &keys, &result), Ok(vec![None, None]));
+
+        // A deployed contract cannot be passed off as undeployed
+        result.contract_proof = get_proof(&trie, &trie_key(FieldElement::from(0x567800_u64))).unwrap();
+        assert_eq!(
+            verify_storage_proof(FieldElement::from(0x567800_u64), &keys, &result),
+            Err(ProofError::ContractLeafMismatch)
+        );
+    }
+}
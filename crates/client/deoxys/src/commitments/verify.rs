@@ -0,0 +1,676 @@
+//! Verification of the header served by the feeder gateway along with a block.
+//!
+//! The block hash and the commitments are recomputed from the content of the block and its state
+//! diff, and checked against the header before the state tries are updated. The state root is
+//! checked once they are.
+
+use std::fmt;
+use std::ops::Range;
+
+use mp_block::{Header, MAINNET_V0_7_BLOCK_NUMBER};
+use mp_felt::Felt252Wrapper;
+use mp_hashers::pedersen::PedersenHasher;
+use mp_hashers::poseidon::PoseidonHasher;
+use mp_hashers::HasherT;
+use serde::Deserialize;
+use starknet_api::api_core::ContractAddress;
+use starknet_api::hash::StarkFelt;
+use starknet_core::utils::cairo_short_string_to_felt;
+use starknet_ff::FieldElement;
+use starknet_providers::sequencer::models as p;
+
+use super::poseidon;
+
+/// First version of Starknet hashing its blocks with Poseidon.
+const POSEIDON_STARKNET_VERSION: (u64, u64, u64) = (0, 13, 2);
+
+/// The rules by which the blocks of a chain are hashed, which differ from one chain to another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainRules {
+    /// The id of the chain, hashed by the blocks produced before Starknet 0.7.0.
+    pub chain_id: FieldElement,
+    /// The first block produced by Starknet 0.7.0, which changed the block hash formula.
+    pub v0_7_block_number: u64,
+    /// Blocks whose hash cannot be recomputed from their content. Their commitments are still
+    /// checked.
+    pub unverifiable_blocks: Range<u64>,
+    /// The sequencer address hashed by the blocks for which the feeder gateway serves none.
+    pub fallback_sequencer_address: Option<FieldElement>,
+}
+
+impl ChainRules {
+    /// Returns the rules of the chain with the given id. Chains other than mainnet and the Goerli
+    /// testnet started after Starknet 0.7.0.
+    pub fn of(chain_id: FieldElement) -> Self {
+        let rules = |v0_7_block_number, unverifiable_blocks, fallback_sequencer_address: Option<&str>| Self {
+            chain_id,
+            v0_7_block_number,
+            unverifiable_blocks,
+            fallback_sequencer_address: fallback_sequencer_address
+                .map(|address| FieldElement::from_hex_be(address).expect("invalid sequencer address")),
+        };
+
+        if chain_id == short_string("SN_MAIN") {
+            rules(
+                MAINNET_V0_7_BLOCK_NUMBER,
+                0..0,
+                Some("0x021f4b90b0377c82bf330b7b5295820769e72d79d8acd0effa0ebde6e9988bc5"),
+            )
+        } else if chain_id == short_string("SN_GOERLI") {
+            rules(47_028, 119_802..148_428, Some("0x046a89ae102987331d369645031b49c27738ed096f2789c24449966da4c6de6b"))
+        } else {
+            rules(0, 0..0, None)
+        }
+    }
+}
+
+/// The block hash formulas used by Starknet over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockHashScheme {
+    /// Blocks produced before Starknet 0.7.0, whose hash commits to the chain id in place of the
+    /// sequencer address, the timestamp and the events.
+    PreV0_7,
+    /// Pedersen hash of the header, from Starknet 0.7.0 to 0.13.1.
+    Pedersen,
+    /// Poseidon hash of the header, from Starknet 0.13.2. It also commits to the receipts, the
+    /// state diff, the gas prices and the data availability mode.
+    Poseidon,
+}
+
+impl BlockHashScheme {
+    /// Returns the scheme used to hash a block.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - The rules of the chain of the block
+    /// * `block_number` - The number of the block
+    /// * `starknet_version` - The version of Starknet which produced the block, absent from the
+    ///   oldest blocks
+    pub fn of(rules: &ChainRules, block_number: u64, starknet_version: Option<&str>) -> Self {
+        if block_number < rules.v0_7_block_number {
+            Self::PreV0_7
+        } else if starknet_version.and_then(parse_version).is_some_and(|version| version >= POSEIDON_STARKNET_VERSION) {
+            Self::Poseidon
+        } else {
+            Self::Pedersen
+        }
+    }
+}
+
+/// A field of a block header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderField {
+    BlockHash,
+    TransactionCommitment,
+    EventCommitment,
+    ReceiptCommitment,
+    StateDiffCommitment,
+    StateRoot,
+}
+
+impl fmt::Display for HeaderField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockHash => write!(f, "block hash"),
+            Self::TransactionCommitment => write!(f, "transaction commitment"),
+            Self::EventCommitment => write!(f, "event commitment"),
+            Self::ReceiptCommitment => write!(f, "receipt commitment"),
+            Self::StateDiffCommitment => write!(f, "state diff commitment"),
+            Self::StateRoot => write!(f, "state root"),
+        }
+    }
+}
+
+/// A header field which does not match the value recomputed from the block.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("{field} mismatch: expected {expected:#x}, computed {computed:#x}")]
+pub struct FieldMismatch {
+    pub field: HeaderField,
+    pub expected: FieldElement,
+    pub computed: FieldElement,
+}
+
+/// The limitation of the verification of the blocks hashed with Poseidon, whose transactions may be
+/// of versions this node cannot hash.
+pub const POSEIDON_TRANSACTION_HASHES_NOTE: &str =
+    "the transaction hashes of blocks hashed with Poseidon are taken as served, not recomputed";
+
+/// Errors returned when a block does not match its header.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BlockVerificationError {
+    #[error(
+        "block {block_number} does not match its header: {}{}",
+        display_mismatches(.mismatches),
+        display_note(.note)
+    )]
+    Mismatches {
+        block_number: u64,
+        mismatches: Vec<FieldMismatch>,
+        /// What the recomputed fields rely on without checking it, if anything
+        note: Option<&'static str>,
+    },
+    #[error("block {0} is served without the {1} its hash commits to")]
+    MissingField(u64, &'static str),
+}
+
+/// The header fields of the blocks hashed with Poseidon, which the feeder gateway models of this
+/// node lack. They are parsed from the same response as the block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct PoseidonHeaderFields {
+    pub state_diff_commitment: Option<FieldElement>,
+    pub receipt_commitment: Option<FieldElement>,
+    pub l1_gas_price: Option<GasPrices>,
+    pub l1_data_gas_price: Option<GasPrices>,
+    #[serde(default)]
+    pub l1_da_mode: L1DataAvailabilityMode,
+    #[serde(default)]
+    pub transaction_receipts: Vec<ReceiptGas>,
+}
+
+/// A gas price, in wei and in fri.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct GasPrices {
+    pub price_in_wei: FieldElement,
+    pub price_in_fri: FieldElement,
+}
+
+/// How the state diff of a block is published on L1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum L1DataAvailabilityMode {
+    #[default]
+    Calldata,
+    Blob,
+}
+
+/// The gas consumed by a transaction, as served in its receipt.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ReceiptGas {
+    #[serde(default)]
+    execution_resources: Option<ReceiptResources>,
+}
+
+impl ReceiptGas {
+    pub fn total_gas_consumed(&self) -> GasConsumed {
+        self.execution_resources.as_ref().and_then(|resources| resources.total_gas_consumed).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct ReceiptResources {
+    #[serde(default)]
+    total_gas_consumed: Option<GasConsumed>,
+}
+
+/// The L1 gas consumed by a transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct GasConsumed {
+    #[serde(default)]
+    pub l1_gas: u64,
+    #[serde(default)]
+    pub l1_data_gas: u64,
+}
+
+/// A block as served by the feeder gateway, along with the header fields its model lacks.
+#[derive(Debug, Clone)]
+pub struct GatewayBlock {
+    pub block: p::Block,
+    pub header_fields: PoseidonHeaderFields,
+}
+
+impl<'de> Deserialize<'de> for GatewayBlock {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let header_fields = PoseidonHeaderFields::deserialize(&value).map_err(serde::de::Error::custom)?;
+        let block = p::Block::deserialize(value).map_err(serde::de::Error::custom)?;
+
+        Ok(Self { block, header_fields })
+    }
+}
+
+/// Checks the header served by the feeder gateway against the one recomputed from the block.
+///
+/// Every field is checked, so that all the mismatches of a block are reported at once. Fields the
+/// feeder gateway does not serve for a block are not checked. The state root is hashed as served,
+/// and checked by [`verify_state_root`] once the tries are updated.
+///
+/// # Arguments
+///
+/// * `rules` - The rules of the chain of the block
+/// * `gateway_block` - The block served by the feeder gateway
+/// * `state_update` - The state update of the block
+/// * `header` - The header built from the block, whose commitments are recomputed from its
+///   transactions and events
+pub fn verify_header(
+    rules: &ChainRules,
+    gateway_block: &GatewayBlock,
+    state_update: &p::StateUpdate,
+    header: &Header,
+) -> Result<(), BlockVerificationError> {
+    let block = &gateway_block.block;
+    let block_number = header.block_number;
+
+    // The Pedersen commitments already follow the hashing rules of the block, such as the legacy
+    // transaction hashes of the blocks before `LEGACY_BLOCK_NUMBER`
+    let scheme = BlockHashScheme::of(rules, block_number, block.starknet_version.as_deref());
+    let note = matches!(scheme, BlockHashScheme::Poseidon).then_some(POSEIDON_TRANSACTION_HASHES_NOTE);
+    let computed = match scheme {
+        BlockHashScheme::PreV0_7 | BlockHashScheme::Pedersen => {
+            let mut header = header.clone();
+            if let (None, Some(address)) = (block.sequencer_address, rules.fallback_sequencer_address) {
+                header.sequencer_address = ContractAddress::from(Felt252Wrapper::from(address));
+            }
+
+            vec![
+                (
+                    HeaderField::BlockHash,
+                    block.block_hash,
+                    header.hash_on_chain::<PedersenHasher>(rules.chain_id.into(), rules.v0_7_block_number).into(),
+                ),
+                (HeaderField::TransactionCommitment, block.transaction_commitment, felt(header.transaction_commitment)),
+                (HeaderField::EventCommitment, block.event_commitment, felt(header.event_commitment)),
+            ]
+        }
+        BlockHashScheme::Poseidon => {
+            let fields = &gateway_block.header_fields;
+            let commitments = PoseidonCommitments::new(gateway_block, state_update);
+
+            vec![
+                (HeaderField::BlockHash, block.block_hash, poseidon_block_hash(rules, gateway_block, &commitments)?),
+                (HeaderField::TransactionCommitment, block.transaction_commitment, commitments.transaction),
+                (HeaderField::EventCommitment, block.event_commitment, commitments.event),
+                (HeaderField::ReceiptCommitment, fields.receipt_commitment, commitments.receipt),
+                (HeaderField::StateDiffCommitment, fields.state_diff_commitment, commitments.state_diff),
+            ]
+        }
+    };
+
+    let mismatches: Vec<_> = computed
+        .into_iter()
+        .filter(|(field, _, _)| *field != HeaderField::BlockHash || !rules.unverifiable_blocks.contains(&block_number))
+        .filter_map(|(field, expected, computed)| {
+            expected.filter(|expected| *expected != computed).map(|expected| FieldMismatch {
+                field,
+                expected,
+                computed,
+            })
+        })
+        .collect();
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(BlockVerificationError::Mismatches { block_number, mismatches, note })
+    }
+}
+
+/// Checks the state root served by the feeder gateway against the root of the updated tries.
+///
+/// # Arguments
+///
+/// * `block_number` - The number of the block
+/// * `expected` - The state root served by the feeder gateway, if any
+/// * `computed` - The state root of the tries once updated with the state diff of the block
+pub fn verify_state_root(
+    block_number: u64,
+    expected: Option<FieldElement>,
+    computed: FieldElement,
+) -> Result<(), BlockVerificationError> {
+    match expected {
+        Some(expected) if expected != computed => Err(BlockVerificationError::Mismatches {
+            block_number,
+            mismatches: vec![FieldMismatch { field: HeaderField::StateRoot, expected, computed }],
+            note: None,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The commitments of a block hashed with Poseidon.
+struct PoseidonCommitments {
+    transaction: FieldElement,
+    event: FieldElement,
+    receipt: FieldElement,
+    state_diff: FieldElement,
+    state_diff_length: u64,
+}
+
+impl PoseidonCommitments {
+    fn new(gateway_block: &GatewayBlock, state_update: &p::StateUpdate) -> Self {
+        let block = &gateway_block.block;
+        let (state_diff, state_diff_length) = poseidon::state_diff_commitment(state_update);
+
+        Self {
+            transaction: poseidon::transaction_commitment(block),
+            event: poseidon::event_commitment(block),
+            receipt: poseidon::receipt_commitment(block, &gateway_block.header_fields.transaction_receipts),
+            state_diff,
+            state_diff_length,
+        }
+    }
+}
+
+/// Computes the Poseidon hash of a block produced since Starknet 0.13.2.
+fn poseidon_block_hash(
+    rules: &ChainRules,
+    gateway_block: &GatewayBlock,
+    commitments: &PoseidonCommitments,
+) -> Result<FieldElement, BlockVerificationError> {
+    let block = &gateway_block.block;
+    let fields = &gateway_block.header_fields;
+    let block_number = block.block_number.expect("no block number provided");
+    let missing = |field| BlockVerificationError::MissingField(block_number, field);
+
+    let l1_gas_price = fields.l1_gas_price.ok_or_else(|| missing("l1 gas price"))?;
+    let l1_data_gas_price = fields.l1_data_gas_price.ok_or_else(|| missing("l1 data gas price"))?;
+    let starknet_version = block
+        .starknet_version
+        .as_deref()
+        .and_then(|version| cairo_short_string_to_felt(version).ok())
+        .ok_or_else(|| missing("starknet version"))?;
+
+    // The counts are packed in a single felt: 64 bits each for the transactions, the events and
+    // the state diff entries, then a byte for the data availability mode
+    let event_count = block.transaction_receipts.iter().map(|receipt| receipt.events.len()).sum::<usize>();
+    let mut counts = [0u8; 32];
+    counts[..8].copy_from_slice(&(block.transactions.len() as u64).to_be_bytes());
+    counts[8..16].copy_from_slice(&(event_count as u64).to_be_bytes());
+    counts[16..24].copy_from_slice(&commitments.state_diff_length.to_be_bytes());
+    if fields.l1_da_mode == L1DataAvailabilityMode::Blob {
+        counts[24] = 0b1000_0000;
+    }
+    let counts = FieldElement::from_bytes_be(&counts).expect("counts fit in a felt");
+
+    let gas_prices = PoseidonHasher::compute_hash_on_elements(&[
+        short_string("STARKNET_GAS_PRICES0"),
+        l1_gas_price.price_in_wei,
+        l1_gas_price.price_in_fri,
+        l1_data_gas_price.price_in_wei,
+        l1_data_gas_price.price_in_fri,
+    ]);
+
+    Ok(PoseidonHasher::compute_hash_on_elements(&[
+        short_string("STARKNET_BLOCK_HASH0"),
+        FieldElement::from(block_number),
+        block.state_root.ok_or_else(|| missing("state root"))?,
+        block.sequencer_address.or(rules.fallback_sequencer_address).unwrap_or(FieldElement::ZERO),
+        FieldElement::from(block.timestamp),
+        counts,
+        commitments.state_diff,
+        commitments.transaction,
+        commitments.event,
+        commitments.receipt,
+        gas_prices,
+        starknet_version,
+        FieldElement::ZERO,
+        block.parent_block_hash,
+    ]))
+}
+
+fn short_string(string: &str) -> FieldElement {
+    cairo_short_string_to_felt(string).expect("invalid short string")
+}
+
+fn felt(felt: StarkFelt) -> FieldElement {
+    Felt252Wrapper::from(felt).into()
+}
+
+/// Parses a Starknet version such as `0.12.3`, whose patch number may be omitted.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next().unwrap_or(Some(0))?;
+
+    Some((major, minor, patch))
+}
+
+fn display_mismatches(mismatches: &[FieldMismatch]) -> String {
+    mismatches.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn display_note(note: &Option<&'static str>) -> String {
+    note.map(|note| format!(" ({note})")).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const STATE_ROOT: &str = "0x3ceee867d50b5926bb88c0ec7e0b9c20ae6b537e74aac44b8fcf6bb6da138d9";
+    const TRANSACTION_COMMITMENT: &str = "0x301a3e7f3ae29c3463a5f753da62e63dcdfd0f0e3cb1eea1d36c3a3d2c1e9f6";
+    const EVENT_COMMITMENT: &str = "0x70d9aea8b3f0c8a2c4f9b1e9f8d2a7c3b1e0d9c8b7a6f5e4d3c2b1a0f9e8d7c";
+    const PARENT_BLOCK_HASH: &str = "0x2b8c2b1e9e2d4a5f6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9";
+    const SEQUENCER_ADDRESS: &str = "0x1176a1bd84444c89232ec27754698e5d2e7e1a7f1539f12027f28b23ec9f3d8";
+
+    fn hex(hex: &str) -> FieldElement {
+        FieldElement::from_hex_be(hex).unwrap()
+    }
+
+    fn rules(chain_id: &str) -> ChainRules {
+        ChainRules::of(short_string(chain_id))
+    }
+
+    /// A header with 2 transactions and 3 events, as built from a block by the sync.
+    fn header(block_number: u64, sequencer_address: Option<&str>) -> Header {
+        Header {
+            parent_block_hash: Felt252Wrapper::from(hex(PARENT_BLOCK_HASH)).into(),
+            block_number,
+            global_state_root: Felt252Wrapper::from(hex(STATE_ROOT)).into(),
+            sequencer_address: ContractAddress::from(Felt252Wrapper::from(hex(sequencer_address.unwrap_or("0x0")))),
+            block_timestamp: 1_650_000_000,
+            transaction_count: 2,
+            transaction_commitment: Felt252Wrapper::from(hex(TRANSACTION_COMMITMENT)).into(),
+            event_count: 3,
+            event_commitment: Felt252Wrapper::from(hex(EVENT_COMMITMENT)).into(),
+            ..Default::default()
+        }
+    }
+
+    /// The block served by the feeder gateway along with `header`.
+    fn gateway_block(header: &Header, block_hash: &str, sequencer_address: Option<&str>) -> GatewayBlock {
+        let mut block = json!({
+            "block_hash": block_hash,
+            "block_number": header.block_number,
+            "parent_block_hash": PARENT_BLOCK_HASH,
+            "state_root": STATE_ROOT,
+            "timestamp": header.block_timestamp,
+            "transaction_commitment": TRANSACTION_COMMITMENT,
+            "event_commitment": EVENT_COMMITMENT,
+            "status": "ACCEPTED_ON_L1",
+            "eth_l1_gas_price": "0x0",
+            "strk_l1_gas_price": "0x0",
+            "transactions": [],
+            "transaction_receipts": [],
+        });
+        if let Some(sequencer_address) = sequencer_address {
+            block["sequencer_address"] = sequencer_address.into();
+        }
+
+        serde_json::from_value(block).unwrap()
+    }
+
+    fn empty_state_update() -> p::StateUpdate {
+        serde_json::from_value(json!({
+            "new_root": STATE_ROOT,
+            "old_root": STATE_ROOT,
+            "state_diff": {
+                "storage_diffs": {},
+                "deployed_contracts": [],
+                "old_declared_contracts": [],
+                "declared_classes": [],
+                "nonces": {},
+                "replaced_classes": [],
+            },
+        }))
+        .unwrap()
+    }
+
+    fn poseidon_block() -> (GatewayBlock, p::StateUpdate) {
+        let block = serde_json::from_str(include_str!("../../resources/blocks/poseidon_block.json")).unwrap();
+        let state_update =
+            serde_json::from_str(include_str!("../../resources/blocks/poseidon_state_update.json")).unwrap();
+        (block, state_update)
+    }
+
+    #[test]
+    fn block_hash_scheme_depends_on_the_chain() {
+        let mainnet = rules("SN_MAIN");
+        assert_eq!(BlockHashScheme::of(&mainnet, 0, None), BlockHashScheme::PreV0_7);
+        assert_eq!(BlockHashScheme::of(&mainnet, 832, None), BlockHashScheme::PreV0_7);
+        assert_eq!(BlockHashScheme::of(&mainnet, 833, None), BlockHashScheme::Pedersen);
+        assert_eq!(BlockHashScheme::of(&mainnet, 500_000, Some("0.13.1")), BlockHashScheme::Pedersen);
+        assert_eq!(BlockHashScheme::of(&mainnet, 500_000, Some("0.13.1.1")), BlockHashScheme::Pedersen);
+        assert_eq!(BlockHashScheme::of(&mainnet, 700_000, Some("0.13.2")), BlockHashScheme::Poseidon);
+
+        let goerli = rules("SN_GOERLI");
+        assert_eq!(BlockHashScheme::of(&goerli, 833, None), BlockHashScheme::PreV0_7);
+        assert_eq!(BlockHashScheme::of(&goerli, 47_027, None), BlockHashScheme::PreV0_7);
+        assert_eq!(BlockHashScheme::of(&goerli, 47_028, None), BlockHashScheme::Pedersen);
+
+        let sepolia = rules("SN_SEPOLIA");
+        assert_eq!(BlockHashScheme::of(&sepolia, 0, Some("0.12.3")), BlockHashScheme::Pedersen);
+        assert_eq!(BlockHashScheme::of(&sepolia, 80_000, Some("0.13.2")), BlockHashScheme::Poseidon);
+    }
+
+    #[test]
+    fn pre_v0_7_block_hash_commits_to_the_chain_id() {
+        let header = header(500, None);
+        let state_update = empty_state_update();
+        let mainnet_block =
+            gateway_block(&header, "0x2d8f5ebc24d2e2c737fc00bbb22c06f61ff3ffe591b9d291887c45284afa536", None);
+        let goerli_block =
+            gateway_block(&header, "0x7167e41a82413718e98c758921e73c99f5983a7e8f84482eee5e880a5ab9b41", None);
+
+        assert_eq!(verify_header(&rules("SN_MAIN"), &mainnet_block, &state_update, &header), Ok(()));
+        assert_eq!(verify_header(&rules("SN_GOERLI"), &goerli_block, &state_update, &header), Ok(()));
+        assert_eq!(
+            verify_header(&rules("SN_GOERLI"), &mainnet_block, &state_update, &header),
+            Err(BlockVerificationError::Mismatches {
+                block_number: 500,
+                mismatches: vec![FieldMismatch {
+                    field: HeaderField::BlockHash,
+                    expected: hex("0x2d8f5ebc24d2e2c737fc00bbb22c06f61ff3ffe591b9d291887c45284afa536"),
+                    computed: hex("0x7167e41a82413718e98c758921e73c99f5983a7e8f84482eee5e880a5ab9b41"),
+                }],
+                note: None,
+            })
+        );
+    }
+
+    #[test]
+    fn pedersen_block_hash_falls_back_to_the_sequencer_of_the_chain() {
+        let state_update = empty_state_update();
+
+        let header_with_sequencer = header(50_000, Some(SEQUENCER_ADDRESS));
+        let block = gateway_block(
+            &header_with_sequencer,
+            "0x1b759535aaad2b58c79c045b7a13554b80d085d323792b2c9396e6099fb158d",
+            Some(SEQUENCER_ADDRESS),
+        );
+        assert_eq!(verify_header(&rules("SN_MAIN"), &block, &state_update, &header_with_sequencer), Ok(()));
+
+        // The sync builds the header of a block served without sequencer address with a zero one
+        let header = header(50_000, None);
+        let block = gateway_block(&header, "0x194bcdbecba5bfc4ed6ec91f8cf4e7f5faee1f80ea53a5aa20321152e24972a", None);
+        assert_eq!(verify_header(&rules("SN_GOERLI"), &block, &state_update, &header), Ok(()));
+        assert!(verify_header(&rules("SN_SEPOLIA"), &block, &state_update, &header).is_err());
+    }
+
+    #[test]
+    fn unverifiable_block_hashes_are_not_checked() {
+        let header = header(120_000, None);
+        let state_update = empty_state_update();
+        let mut block = gateway_block(&header, "0x1", None);
+
+        assert_eq!(verify_header(&rules("SN_GOERLI"), &block, &state_update, &header), Ok(()));
+        assert!(verify_header(&rules("SN_MAIN"), &block, &state_update, &header).is_err());
+
+        block.block.transaction_commitment = Some(FieldElement::ONE);
+        assert!(verify_header(&rules("SN_GOERLI"), &block, &state_update, &header).is_err());
+    }
+
+    #[test]
+    fn poseidon_block_matches_its_header() {
+        let (block, state_update) = poseidon_block();
+        let header = Header { block_number: 700_000, ..Default::default() };
+
+        assert_eq!(verify_header(&rules("SN_MAIN"), &block, &state_update, &header), Ok(()));
+        assert_eq!(verify_header(&rules("SN_SEPOLIA"), &block, &state_update, &header), Ok(()));
+    }
+
+    #[test]
+    fn poseidon_block_hash_commits_to_the_receipts_and_the_state_diff() {
+        let (mut block, mut state_update) = poseidon_block();
+        let header = Header { block_number: 700_000, ..Default::default() };
+        let expected_hash = block.block.block_hash.unwrap();
+
+        block.header_fields.transaction_receipts[0] = ReceiptGas::default();
+        state_update.state_diff.nonces.clear();
+
+        let Err(BlockVerificationError::Mismatches { block_number, mismatches, note }) =
+            verify_header(&rules("SN_MAIN"), &block, &state_update, &header)
+        else {
+            panic!("the block should not match its header");
+        };
+        assert_eq!(block_number, 700_000);
+        assert_eq!(note, Some(POSEIDON_TRANSACTION_HASHES_NOTE));
+        let fields: Vec<_> = mismatches.iter().map(|mismatch| mismatch.field).collect();
+        assert_eq!(fields, [HeaderField::BlockHash, HeaderField::ReceiptCommitment, HeaderField::StateDiffCommitment]);
+        assert_eq!(mismatches[0].expected, expected_hash);
+    }
+
+    #[test]
+    fn poseidon_block_without_gas_prices_cannot_be_verified() {
+        let (mut block, state_update) = poseidon_block();
+        let header = Header { block_number: 700_000, ..Default::default() };
+        block.header_fields.l1_data_gas_price = None;
+
+        assert_eq!(
+            verify_header(&rules("SN_MAIN"), &block, &state_update, &header),
+            Err(BlockVerificationError::MissingField(700_000, "l1 data gas price"))
+        );
+    }
+
+    #[test]
+    fn every_mismatching_field_is_reported() {
+        let header = header(1_000, Some(SEQUENCER_ADDRESS));
+        let block_hash = header.hash::<PedersenHasher>();
+        let mut block = gateway_block(&header, &format!("{:#x}", block_hash.0), Some(SEQUENCER_ADDRESS));
+        block.block.transaction_commitment = Some(FieldElement::ONE);
+        block.block.event_commitment = None;
+
+        assert_eq!(
+            verify_header(&rules("SN_MAIN"), &block, &empty_state_update(), &header),
+            Err(BlockVerificationError::Mismatches {
+                block_number: 1_000,
+                mismatches: vec![FieldMismatch {
+                    field: HeaderField::TransactionCommitment,
+                    expected: FieldElement::ONE,
+                    computed: hex(TRANSACTION_COMMITMENT),
+                }],
+                note: None,
+            })
+        );
+    }
+
+    #[test]
+    fn state_root_is_checked_against_the_tries() {
+        assert_eq!(verify_state_root(1, Some(FieldElement::TWO), FieldElement::TWO), Ok(()));
+        assert_eq!(verify_state_root(1, None, FieldElement::TWO), Ok(()));
+        assert_eq!(
+            verify_state_root(1, Some(FieldElement::ONE), FieldElement::TWO),
+            Err(BlockVerificationError::Mismatches {
+                block_number: 1,
+                mismatches: vec![FieldMismatch {
+                    field: HeaderField::StateRoot,
+                    expected: FieldElement::ONE,
+                    computed: FieldElement::TWO,
+                }],
+                note: None,
+            })
+        );
+    }
+}
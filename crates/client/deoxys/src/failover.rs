@@ -0,0 +1,616 @@
+//! A [`BlockSource`] spreading the sync over several feeder gateways.
+//!
+//! Requests go to the healthiest gateway first, and fail over to the next one when it errors or
+//! rate-limits the node. Each gateway may be given an API key and a rate limit of its own. When
+//! more than one gateway is configured, every block is fetched from a second gateway as well, and
+//! rejected unless both agree on its hash. A gateway serving a block the other one disagrees with
+//! is benched, so that the block is fetched from another gateway when retried.
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cairo_lang_casm_contract_class::CasmContractClass;
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use starknet_core::types::{ContractClass, FieldElement};
+use starknet_providers::sequencer::models::{Block, BlockId, DeployedClass, StateUpdate};
+use tokio::time::Instant;
+
+use crate::commitments::verify::GatewayBlock;
+use crate::source::BlockSource;
+
+/// The header lifting the rate limit of the public feeder gateways for the holders of an API key.
+const API_KEY_HEADER: &str = "X-Throttling-Bypass";
+/// How long a gateway which rate-limited the node is left aside, unless it says otherwise, and how
+/// long a gateway which timed out is left aside.
+const DEFAULT_BENCH_DURATION: Duration = Duration::from_secs(10);
+/// How long the connection to a gateway may take to be established.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a request to a gateway may take, including the download of the response, which can be
+/// a large class.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A feeder gateway the sync fetches from.
+#[derive(Clone, PartialEq, Eq)]
+pub struct FeederSource {
+    /// The URL of the feeder gateway.
+    pub url: Url,
+    /// The API key sent to the feeder gateway, if any.
+    pub api_key: Option<String>,
+    /// The maximum number of requests per second sent to the feeder gateway, unlimited if `None`.
+    pub rate_limit: Option<NonZeroU32>,
+}
+
+impl FeederSource {
+    pub fn new(url: Url) -> Self {
+        Self { url, api_key: None, rate_limit: None }
+    }
+}
+
+// The API key is left out of the logs
+impl fmt::Debug for FeederSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeederSource")
+            .field("url", &self.url.as_str())
+            .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+            .field("rate_limit", &self.rate_limit)
+            .finish()
+    }
+}
+
+/// Parses a source given as `<url>[,api-key=<key>][,rate-limit=<requests per second>]`.
+impl FromStr for FeederSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let url = parts.next().unwrap_or_default();
+        let mut source = Self::new(url.parse().map_err(|e| format!("invalid feeder gateway url {url}: {e}"))?);
+
+        for option in parts {
+            match option.split_once('=') {
+                Some(("api-key", key)) if !key.is_empty() => source.api_key = Some(key.to_string()),
+                Some(("rate-limit", limit)) => {
+                    source.rate_limit = Some(limit.parse().map_err(|e| format!("invalid rate limit {limit}: {e}"))?)
+                }
+                _ => {
+                    return Err(format!(
+                        "invalid feeder gateway option {option}, expected api-key=... or rate-limit=..."
+                    ));
+                }
+            }
+        }
+
+        Ok(source)
+    }
+}
+
+/// The health of a feeder gateway, by which gateways are ordered.
+#[derive(Debug, Clone, Copy, Default)]
+struct Health {
+    /// The number of requests which failed since the last successful one.
+    consecutive_failures: u32,
+    /// Set when the gateway rate-limited the node or timed out, which does not query it again
+    /// before then.
+    benched_until: Option<Instant>,
+}
+
+impl Health {
+    /// The score of the gateway, the lower the better.
+    fn score(&self, now: Instant) -> (bool, u32) {
+        (self.benched_until.is_some_and(|until| until > now), self.consecutive_failures)
+    }
+}
+
+/// Two feeder gateways serving the same block with a different hash.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error(
+    "feeder gateways disagree on the hash of block {block_number:?}: {served_hash:?} according to {served_by}, \
+     {other_hash:?} according to {other_by}"
+)]
+pub struct GatewayDisagreement {
+    pub block_number: Option<u64>,
+    pub served_by: Url,
+    pub served_hash: Option<FieldElement>,
+    pub other_by: Url,
+    pub other_hash: Option<FieldElement>,
+}
+
+/// An error returned by a feeder gateway.
+enum GatewayError {
+    /// The gateway rate-limited the node, which should not query it again for this long.
+    RateLimited(Duration),
+    /// The gateway did not connect or answer in time.
+    TimedOut,
+    Other(String),
+}
+
+impl From<reqwest::Error> for GatewayError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() { Self::TimedOut } else { Self::Other(e.to_string()) }
+    }
+}
+
+/// A single feeder gateway, along with its rate limit and health.
+struct Gateway {
+    client: reqwest::Client,
+    url: Url,
+    /// The minimum delay between two requests, if the gateway is rate limited.
+    interval: Option<Duration>,
+    /// The earliest time at which the next request may be sent.
+    next_request: tokio::sync::Mutex<Instant>,
+    health: Mutex<Health>,
+}
+
+impl Gateway {
+    /// Creates a client of the gateway, whose requests fail once they take longer than
+    /// `request_timeout`.
+    fn new(source: FeederSource, request_timeout: Duration) -> Result<Self, String> {
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = &source.api_key {
+            let api_key = HeaderValue::from_str(api_key).map_err(|_| "invalid feeder gateway api key".to_string())?;
+            headers.insert(API_KEY_HEADER, api_key);
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(CONNECT_TIMEOUT.min(request_timeout))
+            .timeout(request_timeout)
+            .build()
+            .map_err(|e| format!("failed to build the feeder gateway client: {e}"))?;
+
+        Ok(Self {
+            client,
+            url: source.url,
+            interval: source.rate_limit.map(|limit| Duration::from_secs(1) / limit.get()),
+            next_request: tokio::sync::Mutex::new(Instant::now()),
+            health: Mutex::new(Health::default()),
+        })
+    }
+
+    fn health(&self) -> Health {
+        *self.health.lock().expect("poisoned feeder gateway health")
+    }
+
+    /// Waits until the rate limit of the gateway allows another request.
+    async fn throttle(&self) {
+        let Some(interval) = self.interval else { return };
+
+        let mut next_request = self.next_request.lock().await;
+        tokio::time::sleep_until(*next_request).await;
+        *next_request = Instant::now() + interval;
+    }
+
+    /// Fetches and parses the response of a feeder gateway endpoint, updating the health of the
+    /// gateway accordingly.
+    ///
+    /// # Returns
+    ///
+    /// The parsed response, along with its content as served.
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        params: &[(&str, String)],
+    ) -> Result<(T, Vec<u8>), String> {
+        self.throttle().await;
+
+        let result = self.request(endpoint, params).await.and_then(|raw| {
+            let response = serde_json::from_slice(&raw)
+                .map_err(|e| GatewayError::Other(format!("failed to parse response: {e}")))?;
+            Ok((response, raw))
+        });
+        let mut health = self.health.lock().expect("poisoned feeder gateway health");
+        match result {
+            Ok(response) => {
+                *health = Health::default();
+                Ok(response)
+            }
+            Err(GatewayError::RateLimited(duration)) => {
+                health.consecutive_failures += 1;
+                health.benched_until = Some(Instant::now() + duration);
+                Err(format!("{} rate-limited {endpoint}, benched for {duration:?}", self.url))
+            }
+            Err(GatewayError::TimedOut) => {
+                health.consecutive_failures += 1;
+                health.benched_until = Some(Instant::now() + DEFAULT_BENCH_DURATION);
+                Err(format!("{} timed out serving {endpoint}, benched for {DEFAULT_BENCH_DURATION:?}", self.url))
+            }
+            Err(GatewayError::Other(e)) => {
+                health.consecutive_failures += 1;
+                Err(format!("{} failed to serve {endpoint}: {e}", self.url))
+            }
+        }
+    }
+
+    /// Records a failure of the gateway outside of a request, benching it for `bench` if given.
+    fn record_failure(&self, bench: Option<Duration>) {
+        let mut health = self.health.lock().expect("poisoned feeder gateway health");
+        health.consecutive_failures += 1;
+        if let Some(bench) = bench {
+            health.benched_until = Some(Instant::now() + bench);
+        }
+    }
+
+    async fn request(&self, endpoint: &str, params: &[(&str, String)]) -> Result<Vec<u8>, GatewayError> {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .map_err(|_| GatewayError::Other("invalid feeder gateway url".to_string()))?
+            .pop_if_empty()
+            .push(endpoint);
+        url.query_pairs_mut().extend_pairs(params);
+
+        let response = self.client.get(url).send().await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .map_or(DEFAULT_BENCH_DURATION, Duration::from_secs);
+            return Err(GatewayError::RateLimited(retry_after));
+        }
+
+        let content = response.error_for_status()?.bytes().await?;
+
+        Ok(content.to_vec())
+    }
+}
+
+/// A response of a feeder gateway.
+pub(crate) struct Served<T> {
+    pub value: T,
+    /// The content of the response, as served.
+    pub raw: Vec<u8>,
+    /// The index of the gateway which served it.
+    gateway: usize,
+}
+
+/// A block along with its state update, as served by `get_state_update` with `includeBlock`.
+#[derive(Deserialize)]
+struct BlockWithStateUpdate {
+    block: Block,
+    state_update: StateUpdate,
+}
+
+/// Fetches from several feeder gateways, failing over from one to the next.
+pub struct FailoverSource {
+    gateways: Vec<Gateway>,
+}
+
+impl FailoverSource {
+    /// Creates a source over the given feeder gateways, the first ones being preferred while they
+    /// are equally healthy.
+    pub fn new(sources: Vec<FeederSource>) -> Result<Self, String> {
+        if sources.is_empty() {
+            return Err("no feeder gateway configured".to_string());
+        }
+
+        let gateways = sources.into_iter().map(|source| Gateway::new(source, REQUEST_TIMEOUT));
+
+        Ok(Self { gateways: gateways.collect::<Result<_, _>>()? })
+    }
+
+    /// Returns the indices of the gateways, healthiest first.
+    fn by_health(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut indices: Vec<_> = (0..self.gateways.len()).collect();
+        indices.sort_by_key(|&i| self.gateways[i].health().score(now));
+        indices
+    }
+
+    /// Fetches from the healthiest gateway which answers.
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        params: &[(&str, String)],
+        exclude: Option<usize>,
+    ) -> Result<Served<T>, String> {
+        let mut errors = vec![];
+        for i in self.by_health().into_iter().filter(|i| Some(*i) != exclude) {
+            match self.gateways[i].fetch(endpoint, params).await {
+                Ok((value, raw)) => return Ok(Served { value, raw, gateway: i }),
+                Err(e) => {
+                    log::debug!("Failing over to the next feeder gateway: {e}");
+                    errors.push(e);
+                }
+            }
+        }
+
+        Err(errors.join("; "))
+    }
+
+    /// Checks a block against the one served by another gateway.
+    ///
+    /// A gateway which cannot serve the block, e.g. because it lags behind, does not fail the
+    /// check: the block is rejected only if both gateways serve it with a different hash. Both
+    /// gateways are then marked as failing, and the one which served the block is benched, so that
+    /// the block is served by another gateway when retried.
+    async fn cross_check(
+        &self,
+        block: &Block,
+        params: &[(&str, String)],
+        served_by: usize,
+    ) -> Result<(), GatewayDisagreement> {
+        let other = match self.fetch::<Block>("get_block", params, Some(served_by)).await {
+            Ok(other) => other,
+            Err(e) => {
+                log::debug!("Failed to cross-check block {:?}: {e}", block.block_number);
+                return Ok(());
+            }
+        };
+
+        if other.value.block_hash != block.block_hash {
+            self.gateways[served_by].record_failure(Some(DEFAULT_BENCH_DURATION));
+            self.gateways[other.gateway].record_failure(None);
+
+            return Err(GatewayDisagreement {
+                block_number: block.block_number,
+                served_by: self.gateways[served_by].url.clone(),
+                served_hash: block.block_hash,
+                other_by: self.gateways[other.gateway].url.clone(),
+                other_hash: other.value.block_hash,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fetches a block, cross-checking its hash with another gateway if there is one.
+    pub(crate) async fn fetch_block(&self, block_id: BlockId) -> Result<Served<GatewayBlock>, String> {
+        let params = [block_id_param(block_id)];
+        let block = self
+            .fetch::<GatewayBlock>("get_block", &params, None)
+            .await
+            .map_err(|e| format!("failed to get block: {e}"))?;
+
+        // The latest and pending blocks move too fast to be compared
+        if matches!(block_id, BlockId::Number(_) | BlockId::Hash(_)) && self.gateways.len() > 1 {
+            // Disagreements are retried like any other failure, and logged since they should not
+            // happen between honest gateways
+            if let Err(e) = self.cross_check(&block.value.block, &params, block.gateway).await {
+                log::warn!("{e}");
+                return Err(e.to_string());
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Fetches the state update of a block.
+    pub(crate) async fn fetch_state_update(&self, block_id: BlockId) -> Result<Served<StateUpdate>, String> {
+        self.fetch("get_state_update", &[block_id_param(block_id)], None)
+            .await
+            .map_err(|e| format!("failed to get state update: {e}"))
+    }
+
+    /// Fetches the pending block along with its state update.
+    ///
+    /// Both are served by a single request, so that they describe the same pending block: fetched
+    /// one after the other, the pending block may be replaced in between.
+    pub async fn fetch_pending_block(&self) -> Result<(Block, StateUpdate), String> {
+        let params = [block_id_param(BlockId::Pending), ("includeBlock", "true".to_string())];
+        let served = self
+            .fetch::<BlockWithStateUpdate>("get_state_update", &params, None)
+            .await
+            .map_err(|e| format!("failed to get the pending block: {e}"))?;
+
+        Ok((served.value.block, served.value.state_update))
+    }
+
+    /// Fetches the definition of a class, as of the given block.
+    pub(crate) async fn fetch_class(
+        &self,
+        class_hash: FieldElement,
+        block_number: u64,
+    ) -> Result<Served<DeployedClass>, String> {
+        let params = [("classHash", format!("{class_hash:#x}")), ("blockNumber", block_number.to_string())];
+        self.fetch("get_class_by_hash", &params, None)
+            .await
+            .map_err(|e| format!("failed to get class {class_hash:#x}: {e}"))
+    }
+
+    /// Fetches the CASM class the sequencer compiled a Sierra class to, as of the given block.
+    pub(crate) async fn fetch_compiled_class(
+        &self,
+        class_hash: FieldElement,
+        block_number: u64,
+    ) -> Result<Served<CasmContractClass>, String> {
+        let params = [("classHash", format!("{class_hash:#x}")), ("blockNumber", block_number.to_string())];
+        self.fetch("get_compiled_class_by_class_hash", &params, None)
+            .await
+            .map_err(|e| format!("failed to get the compiled class of {class_hash:#x}: {e}"))
+    }
+}
+
+fn block_id_param(block_id: BlockId) -> (&'static str, String) {
+    match block_id {
+        BlockId::Hash(block_hash) => ("blockHash", format!("{block_hash:#x}")),
+        BlockId::Number(block_number) => ("blockNumber", block_number.to_string()),
+        BlockId::Pending => ("blockNumber", "pending".to_string()),
+        BlockId::Latest => ("blockNumber", "latest".to_string()),
+    }
+}
+
+#[async_trait]
+impl BlockSource for FailoverSource {
+    async fn get_block(&self, block_id: BlockId) -> Result<Block, String> {
+        Ok(self.get_gateway_block(block_id).await?.block)
+    }
+
+    async fn get_gateway_block(&self, block_id: BlockId) -> Result<GatewayBlock, String> {
+        Ok(self.fetch_block(block_id).await?.value)
+    }
+
+    async fn get_state_update(&self, block_id: BlockId) -> Result<StateUpdate, String> {
+        Ok(self.fetch_state_update(block_id).await?.value)
+    }
+
+    async fn get_class(&self, class_hash: FieldElement, block_number: u64) -> Result<ContractClass, String> {
+        let class = self.fetch_class(class_hash, block_number).await?.value;
+
+        ContractClass::try_from(class).map_err(|_| format!("failed to convert class {class_hash:#x}"))
+    }
+
+    async fn get_compiled_class(
+        &self,
+        class_hash: FieldElement,
+        block_number: u64,
+    ) -> Result<CasmContractClass, String> {
+        Ok(self.fetch_compiled_class(class_hash, block_number).await?.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_feeder_source() {
+        let source: FeederSource = "https://alpha-mainnet.starknet.io/feeder_gateway".parse().unwrap();
+        assert_eq!(source, FeederSource::new("https://alpha-mainnet.starknet.io/feeder_gateway".parse().unwrap()));
+
+        let source: FeederSource =
+            "https://alpha-mainnet.starknet.io/feeder_gateway,api-key=secret,rate-limit=5".parse().unwrap();
+        assert_eq!(source.api_key.as_deref(), Some("secret"));
+        assert_eq!(source.rate_limit, NonZeroU32::new(5));
+        assert!(!format!("{source:?}").contains("secret"));
+
+        assert!("https://alpha-mainnet.starknet.io/feeder_gateway,rate-limit=0".parse::<FeederSource>().is_err());
+        assert!("https://alpha-mainnet.starknet.io/feeder_gateway,key=secret".parse::<FeederSource>().is_err());
+        assert!("not a url".parse::<FeederSource>().is_err());
+    }
+
+    #[test]
+    fn gateways_are_ordered_by_health() {
+        let source = FailoverSource::new(vec![
+            "http://first.example".parse().unwrap(),
+            "http://second.example".parse().unwrap(),
+            "http://third.example".parse().unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(source.by_health(), vec![0, 1, 2]);
+
+        source.gateways[0].health.lock().unwrap().consecutive_failures = 2;
+        source.gateways[1].health.lock().unwrap().benched_until = Some(Instant::now() + DEFAULT_BENCH_DURATION);
+        assert_eq!(source.by_health(), vec![2, 0, 1]);
+    }
+
+    /// Serves block 7 with the given hash at `/<path>/get_block`.
+    fn mock_block(path: &str, block_hash: &str) -> mockito::Mock {
+        let block = serde_json::json!({
+            "block_hash": block_hash,
+            "block_number": 7,
+            "parent_block_hash": "0x6",
+            "timestamp": 0,
+            "sequencer_address": "0x0",
+            "state_root": "0x0",
+            "transaction_commitment": "0x0",
+            "event_commitment": "0x0",
+            "status": "ACCEPTED_ON_L2",
+            "eth_l1_gas_price": "0x0",
+            "strk_l1_gas_price": "0x0",
+            "transactions": [],
+            "transaction_receipts": [],
+            "starknet_version": "0.12.3",
+        });
+        mockito::mock("GET", format!("/{path}/get_block").as_str())
+            .match_query(mockito::Matcher::UrlEncoded("blockNumber".to_string(), "7".to_string()))
+            .with_body(block.to_string())
+            .create()
+    }
+
+    #[tokio::test]
+    async fn disagreeing_gateways_reject_the_block_and_rotate() {
+        let _first = mock_block("failover-disagreement/first", "0x7");
+        let _second = mock_block("failover-disagreement/second", "0x77");
+        let first: Url = format!("{}/failover-disagreement/first", mockito::server_url()).parse().unwrap();
+        let second: Url = format!("{}/failover-disagreement/second", mockito::server_url()).parse().unwrap();
+        let source =
+            FailoverSource::new(vec![FeederSource::new(first.clone()), FeederSource::new(second.clone())]).unwrap();
+
+        let block = source.fetch::<Block>("get_block", &[block_id_param(BlockId::Number(7))], None).await.unwrap();
+        let error = source.cross_check(&block.value, &[block_id_param(BlockId::Number(7))], block.gateway).await;
+        assert_eq!(
+            error,
+            Err(GatewayDisagreement {
+                block_number: Some(7),
+                served_by: first,
+                served_hash: Some(FieldElement::from(7_u64)),
+                other_by: second,
+                other_hash: Some(FieldElement::from(0x77_u64)),
+            })
+        );
+
+        // The gateway which served the block is benched, so that the other one serves it next
+        assert_eq!(source.by_health(), vec![1, 0]);
+        assert!(source.get_block(BlockId::Number(7)).await.unwrap_err().contains("disagree"));
+        assert_eq!(source.by_health(), vec![0, 1]);
+        assert!(source.gateways[1].health().benched_until.is_some());
+        assert_eq!(source.gateways[0].health().consecutive_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn gateway_timing_out_is_benched() {
+        // A gateway which accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/feeder_gateway", listener.local_addr().unwrap()).parse().unwrap();
+        let _server = tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+        let gateway = Gateway::new(FeederSource::new(url), Duration::from_millis(100)).unwrap();
+
+        let error = gateway.fetch::<Block>("get_block", &[block_id_param(BlockId::Number(7))]).await.unwrap_err();
+
+        assert!(error.contains("timed out"), "{error}");
+        let health = gateway.health();
+        assert_eq!(health.consecutive_failures, 1);
+        assert!(health.benched_until.is_some_and(|until| until > Instant::now()));
+    }
+
+    #[tokio::test]
+    async fn pending_block_is_fetched_with_its_state_update() {
+        let response = serde_json::json!({
+            "block": {
+                "parent_block_hash": "0x6",
+                "timestamp": 0,
+                "sequencer_address": "0x0",
+                "status": "PENDING",
+                "eth_l1_gas_price": "0x0",
+                "strk_l1_gas_price": "0x0",
+                "transactions": [],
+                "transaction_receipts": [],
+                "starknet_version": "0.12.3",
+            },
+            "state_update": {
+                "old_root": "0x66",
+                "state_diff": {
+                    "storage_diffs": {},
+                    "deployed_contracts": [],
+                    "old_declared_contracts": [],
+                    "declared_classes": [],
+                    "nonces": {},
+                    "replaced_classes": [],
+                },
+            },
+        });
+        let _mock = mockito::mock("GET", "/failover-pending/get_state_update")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("blockNumber".to_string(), "pending".to_string()),
+                mockito::Matcher::UrlEncoded("includeBlock".to_string(), "true".to_string()),
+            ]))
+            .with_body(response.to_string())
+            .create();
+        let url: Url = format!("{}/failover-pending", mockito::server_url()).parse().unwrap();
+        let source = FailoverSource::new(vec![FeederSource::new(url)]).unwrap();
+
+        let (block, state_update) = source.fetch_pending_block().await.unwrap();
+        assert_eq!(block.parent_block_hash, FieldElement::from(6_u64));
+        assert_eq!(state_update.old_root, FieldElement::from(0x66_u64));
+    }
+}
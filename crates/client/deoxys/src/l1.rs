@@ -1,26 +1,33 @@
 //! Contains the necessaries to perform an L1 verification of the state
 
-use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::Result;
-use ethers::contract::{abigen, EthEvent};
-use ethers::providers::{Http, Middleware, Provider};
+use ethers::contract::{abigen, EthEvent, LogMeta};
+use ethers::providers::{Http, Middleware, Provider, ProviderError};
 use ethers::types::transaction::eip2718::TypedTransaction;
 use ethers::types::{Address, BlockNumber as EthBlockNumber, Filter, TransactionRequest, I256, U64};
 use ethers::utils::hex::decode;
 use futures::stream::StreamExt;
 use lazy_static::lazy_static;
+use mc_db::{L1BlockState, L1StateMismatch, LastSyncedEventBlock};
 use mp_felt::Felt252Wrapper;
+use mp_hashers::pedersen::PedersenHasher;
+use mp_transactions::compute_hash::ComputeTransactionHash;
+use mp_transactions::HandleL1MessageTransaction;
 use primitive_types::{H256, U256};
 use reqwest::Url;
 use serde::Deserialize;
 use serde_json::Value;
+use sp_runtime::traits::Block as BlockT;
 use starknet_api::hash::StarkHash;
+use starknet_ff::FieldElement;
 
-use crate::l2::STARKNET_STATE_UPDATE;
-use crate::utility::{event_to_l1_state_update, get_state_update_at};
-use crate::utils::constant::{starknet_core_address, LOG_STATE_UPDTATE_TOPIC};
+use crate::chain::ChainHandle;
+use crate::l2::L2StateUpdate;
+use crate::utility::event_to_l1_state_update;
+use crate::utils::constant::LOG_STATE_UPDTATE_TOPIC;
 
 lazy_static! {
     /// Shared latest L2 state update verified on L1
@@ -28,15 +35,24 @@ lazy_static! {
         block_number: u64::default(),
         global_root: StarkHash::default(),
         block_hash: StarkHash::default(),
+        transaction_hash: None,
     }));
 }
 
+/// Delay between two checks of the L1 head against the imported blocks.
+const L1_VERIFICATION_INTERVAL: Duration = Duration::from_secs(10);
+/// Number of L1 blocks whose messages to L2 are fetched at once when catching up.
+const MESSAGES_BACKFILL_CHUNK: u64 = 5_000;
+
 /// Contains the Starknet verified state on L1
 #[derive(Debug, Clone, Deserialize)]
 pub struct L1StateUpdate {
     pub block_number: u64,
     pub global_root: StarkHash,
     pub block_hash: StarkHash,
+    /// Hash of the L1 transaction which posted the state update, unknown when it was read from
+    /// the core contract storage
+    pub transaction_hash: Option<H256>,
 }
 
 /// Starknet core LogStateUpdate event
@@ -48,19 +64,39 @@ pub struct LogStateUpdate {
     pub block_hash: U256,
 }
 
+/// The configuration of the L1 verification of the state.
+#[derive(Clone, Debug)]
+pub struct L1Config {
+    /// The URL of the Ethereum RPC endpoint.
+    pub url: Url,
+    /// The address of the Starknet core contract the network settles on.
+    pub core_contract: Address,
+    /// The ID of the Ethereum chain hosting the core contract, checked against the endpoint unless
+    /// `None`, as for devnets.
+    pub chain_id: Option<u64>,
+    /// The chain id of the Starknet network, which the hashes of the L1 handler transactions
+    /// commit to.
+    pub l2_chain_id: FieldElement,
+    /// The L1 block the messages sent to L2 are indexed from on the first run, by default the
+    /// block the core contract was deployed in.
+    pub messages_from_block: Option<u64>,
+}
+
 /// Ethereum client to interact with L1
 #[derive(Clone)]
 pub struct EthereumClient {
     provider: Arc<Provider<Http>>,
     url: Url,
+    core_contract: Address,
 }
 
 /// Implementation of the Ethereum client to interact with L1
 impl EthereumClient {
-    /// Create a new EthereumClient instance with the given RPC URL
-    pub async fn new(url: Url) -> Result<Self> {
+    /// Create a new EthereumClient instance with the given RPC URL, watching the given Starknet
+    /// core contract
+    pub async fn new(url: Url, core_contract: Address) -> Result<Self> {
         let provider = Provider::<Http>::try_from(url.as_str())?;
-        Ok(Self { provider: Arc::new(provider), url })
+        Ok(Self { provider: Arc::new(provider), url, core_contract })
     }
 
     /// Get current RPC URL
@@ -83,13 +119,12 @@ impl EthereumClient {
     /// Get the block number of the last occurrence of a given event.
     pub async fn get_last_event_block_number(&self) -> Result<u64, Box<dyn std::error::Error>> {
         let topic = H256::from_slice(&hex::decode(&LOG_STATE_UPDTATE_TOPIC[2..])?);
-        let address =
-            Address::from_str(starknet_core_address::MAINNET).map_err(|e| format!("Failed to parse address: {}", e))?;
+        let address = self.core_contract;
         let latest_block = self.get_latest_block_number().await.expect("Failed to retrieve latest block number");
 
         // Assuming an avg Block time of 15sec we check for a LogStateUpdate occurence in the last ~24h
         let filter = Filter::new()
-            .from_block(latest_block - 6000)
+            .from_block(latest_block.as_u64().saturating_sub(6000))
             .to_block(EthBlockNumber::Latest)
             .address(vec![address])
             .topic0(topic);
@@ -104,10 +139,36 @@ impl EthereumClient {
         }
     }
 
+    /// Finds the L1 block the core contract was deployed in, as the first block where it has code.
+    ///
+    /// The endpoint must serve the state of past blocks, as archive nodes do.
+    pub async fn get_core_contract_deployment_block(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        let latest_block = self.get_latest_block_number().await?.as_u64();
+        if !self.has_core_contract_code_at(latest_block).await? {
+            return Err(format!("no contract is deployed at {:?}", self.core_contract).into());
+        }
+
+        let (mut low, mut high) = (0, latest_block);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.has_core_contract_code_at(middle).await? {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+
+        Ok(low)
+    }
+
+    async fn has_core_contract_code_at(&self, block_number: u64) -> Result<bool, ProviderError> {
+        Ok(!self.provider.get_code(self.core_contract, Some(block_number.into())).await?.is_empty())
+    }
+
     /// Get the last Starknet block number verified on L1
     pub async fn get_last_block_number(&self) -> Result<u64> {
         let data = decode("35befa5d")?;
-        let to: Address = starknet_core_address::MAINNET.parse()?;
+        let to = self.core_contract;
         let tx_request = TransactionRequest::new().to(to).data(data);
         let tx = TypedTransaction::Legacy(tx_request);
         let result = self.provider.call(&tx, None).await.expect("Failed to get last block number");
@@ -121,7 +182,7 @@ impl EthereumClient {
     /// Get the last Starknet state root verified on L1
     pub async fn get_last_state_root(&self) -> Result<StarkHash> {
         let data = decode("9588eca2")?;
-        let to: Address = starknet_core_address::MAINNET.parse()?;
+        let to = self.core_contract;
         let tx_request = TransactionRequest::new().to(to).data(data);
         let tx = TypedTransaction::Legacy(tx_request);
         let result = self.provider.call(&tx, None).await.expect("Failed to get last state root");
@@ -131,7 +192,7 @@ impl EthereumClient {
     /// Get the last Starknet block hash verified on L1
     pub async fn get_last_block_hash(&self) -> Result<StarkHash> {
         let data = decode("0x382d83e3")?;
-        let to: Address = starknet_core_address::MAINNET.parse()?;
+        let to = self.core_contract;
         let tx_request = TransactionRequest::new().to(to).data(data);
         let tx = TypedTransaction::Legacy(tx_request);
         let result = self.provider.call(&tx, None).await.expect("Failed to get last block hash");
@@ -153,14 +214,14 @@ impl EthereumClient {
             ()
         })?;
 
-        Ok(L1StateUpdate { global_root, block_number, block_hash })
+        Ok(L1StateUpdate { global_root, block_number, block_hash, transaction_hash: None })
     }
 
     /// Subscribes to the LogStateUpdate event from the Starknet core contract and store latest
     /// verified state
     pub async fn listen_and_update_state(&self, start_block: u64) -> Result<(), Box<dyn std::error::Error>> {
         let client = self.provider.clone();
-        let address: Address = starknet_core_address::MAINNET.parse().expect("Failed to parse Starknet core address");
+        let address = self.core_contract;
         abigen!(
             StarknetCore,
             "crates/client/deoxys/src/utils/abis/starknet_core.json",
@@ -170,18 +231,108 @@ impl EthereumClient {
 
         let event_filter = contract.event::<LogStateUpdate>().from_block(start_block).to_block(EthBlockNumber::Latest);
 
-        let mut event_stream = event_filter.stream().await.expect("Failed to initiate event stream");
+        let mut event_stream = event_filter.stream_with_meta().await.expect("Failed to initiate event stream");
 
         while let Some(event_result) = event_stream.next().await {
             match event_result {
-                Ok(log) => {
-                    println!("Log event in log format: {:?}", log.clone());
-                    let format_event =
-                        event_to_l1_state_update(log.clone()).expect("Failed to format event into an L1StateUpdate");
-                    println!("LogStateUpdate event: {:?}, format event: {:?}", log, format_event.clone());
+                Ok((log, meta)) => {
+                    log::debug!("LogStateUpdate event in L1 transaction {:?}: {:?}", meta.transaction_hash, log);
+                    let mut format_event =
+                        event_to_l1_state_update(log).expect("Failed to format event into an L1StateUpdate");
+                    format_event.transaction_hash = Some(meta.transaction_hash);
+                    // The new L1 head is logged at the info level once stored
                     update_l1(format_event);
                 }
-                Err(e) => println!("Error while listening for events: {:?}", e),
+                Err(e) => log::error!("Error while listening for LogStateUpdate events: {:?}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Subscribes to the LogMessageToL2 event from the Starknet core contract and indexes the L1
+    /// handler transactions triggered by each L1 transaction
+    ///
+    /// Indexing resumes from the last L1 block indexed, or `start_block` on the first run. The
+    /// messages of the blocks up to the latest one are fetched first, then the new ones are
+    /// streamed.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_block` - The L1 block to index the messages from on the first run
+    /// * `chain_id` - The chain id of the Starknet network, which the hashes of the L1 handler
+    ///   transactions commit to
+    /// * `backend` - The database the messages are indexed in
+    pub async fn listen_and_index_messages<B: BlockT>(
+        &self,
+        start_block: u64,
+        chain_id: FieldElement,
+        backend: Arc<mc_db::Backend<B>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.provider.clone();
+        let address = self.core_contract;
+        abigen!(
+            StarknetCore,
+            "crates/client/deoxys/src/utils/abis/starknet_core.json",
+            event_derives(serde::Deserialize, serde::Serialize)
+        );
+        let contract = StarknetCore::new(address, client);
+
+        let last_synced = backend.messaging().last_synced_l1_block_with_event()?;
+        let mut from_block = if last_synced.block_number == 0 { start_block } else { last_synced.block_number };
+
+        let index = |event: LogMessageToL2Filter, meta: LogMeta| {
+            let Some(transaction) = l1_handler_transaction(
+                event.from_address,
+                event.to_address,
+                event.selector,
+                &event.payload,
+                event.nonce,
+            ) else {
+                return;
+            };
+            let transaction_hash = transaction.compute_hash::<PedersenHasher>(chain_id.into(), false, None);
+
+            let indexed = backend
+                .messaging()
+                .store_l1_handler_transaction(meta.transaction_hash, transaction_hash.into())
+                .and_then(|()| {
+                    backend.messaging().update_last_synced_l1_block_with_event(&LastSyncedEventBlock::new(
+                        meta.block_number.as_u64(),
+                        meta.log_index.as_u64(),
+                    ))
+                });
+            if let Err(e) = indexed {
+                log::error!("Failed to index the message sent in L1 transaction {:?}: {}", meta.transaction_hash, e);
+            }
+        };
+
+        // The stream only yields the messages of new blocks, past ones are queried in chunks
+        let latest_block = self.get_latest_block_number().await?.as_u64();
+        while from_block <= latest_block {
+            let to_block = latest_block.min(from_block + MESSAGES_BACKFILL_CHUNK - 1);
+            let events = contract
+                .event::<LogMessageToL2Filter>()
+                .from_block(from_block)
+                .to_block(to_block)
+                .query_with_meta()
+                .await?;
+            for (event, meta) in events {
+                index(event, meta);
+            }
+            log::debug!("Indexed the messages sent to L2 up to L1 block {}", to_block);
+            from_block = to_block + 1;
+        }
+
+        let event_filter =
+            contract.event::<LogMessageToL2Filter>().from_block(from_block).to_block(EthBlockNumber::Latest);
+
+        let mut event_stream = event_filter.stream_with_meta().await?;
+
+        while let Some(event_result) = event_stream.next().await {
+            match event_result {
+                Ok((event, meta)) => index(event, meta),
+                Err(e) => log::error!("Error while listening for messages to L2: {:?}", e),
             }
         }
 
@@ -189,6 +340,35 @@ impl EthereumClient {
     }
 }
 
+/// Builds the L1 handler transaction consuming a message sent to L2, or `None` if the message
+/// holds values out of the felt range and can never be consumed.
+///
+/// The first element of the calldata of an L1 handler is the L1 sender of the message, followed by
+/// the payload of the message.
+pub fn l1_handler_transaction(
+    from_address: Address,
+    to_address: U256,
+    selector: U256,
+    payload: &[U256],
+    nonce: U256,
+) -> Option<HandleL1MessageTransaction> {
+    let felt = |value: U256| {
+        let mut bytes = [0u8; 32];
+        value.to_big_endian(&mut bytes);
+        Felt252Wrapper::try_from(&bytes[..]).ok()
+    };
+
+    let sender = Felt252Wrapper::try_from(from_address.as_bytes()).ok();
+    let calldata = std::iter::once(sender).chain(payload.iter().copied().map(felt)).collect::<Option<Vec<_>>>()?;
+
+    Some(HandleL1MessageTransaction {
+        nonce: nonce.low_u64(),
+        contract_address: felt(to_address)?,
+        entry_point_selector: felt(selector)?,
+        calldata,
+    })
+}
+
 /// Update the L1 state with the latest data
 pub fn update_l1(state_update: L1StateUpdate) {
     log::info!(
@@ -205,43 +385,119 @@ pub fn update_l1(state_update: L1StateUpdate) {
     }
 }
 
-/// Verify the L1 state with the latest data
-pub async fn verify_l1(state_update: L1StateUpdate, rpc_port: u16) -> Result<(), String> {
-    let starknet_state = STARKNET_STATE_UPDATE.lock().map_err(|e| e.to_string())?;
-    let starknet_state_block_number = starknet_state.block_number;
+/// Outcome of the verification of a state update posted on L1 against the imported chain.
+#[derive(Debug, Clone)]
+pub enum L1Verification {
+    /// The block has not been imported and mapped yet.
+    Pending,
+    /// The imported block has the hash and state root posted on L1.
+    Verified,
+    /// The imported block differs from the one posted on L1.
+    Mismatch(L2StateUpdate),
+}
 
+/// Verify the L1 state with the latest data
+pub fn verify_l1(state_update: &L1StateUpdate, chain: &dyn ChainHandle) -> L1Verification {
     // Check if the node reached the latest verified state on Ethereum
-    if state_update.block_number > starknet_state_block_number {
-        return Err("🚨 L1 state verification failed: Node still syncing".into());
+    if state_update.block_number > chain.best_block_number() {
+        return L1Verification::Pending;
     }
 
-    if state_update.block_number <= starknet_state_block_number {
-        let current_state_update = get_state_update_at(rpc_port, state_update.block_number)
-            .await
-            .map_err(|e| format!("Error retrieving state update: {}", e))?;
+    let Some(current_state_update) = chain.state_update(state_update.block_number) else {
+        return L1Verification::Pending;
+    };
 
-        // Verifying Block Number, Block Hash and State Root against L2
-        if current_state_update.block_number != state_update.block_number
-            || current_state_update.global_root != state_update.global_root
-            || current_state_update.block_hash != state_update.block_hash
-        {
-            return Err("🚨 L1 state verification failed: Verification mismatch".into());
+    // Verifying Block Hash and State Root against L2
+    if current_state_update.global_root != state_update.global_root
+        || current_state_update.block_hash != state_update.block_hash
+    {
+        return L1Verification::Mismatch(current_state_update);
+    }
+
+    L1Verification::Verified
+}
+
+/// Verifies every L1 head against the imported chain, as soon as the node has imported its block.
+///
+/// The last verified head and every mismatch are persisted in the meta db.
+pub async fn verify<B: BlockT>(chain: Arc<dyn ChainHandle>, backend: Arc<mc_db::Backend<B>>) {
+    let mut interval = tokio::time::interval(L1_VERIFICATION_INTERVAL);
+    let mut last_checked = match backend.meta().l1_verified_state() {
+        Ok(state) => state.map(|state| state.block_number),
+        Err(e) => {
+            log::error!("Failed to read the L1 verified state: {}", e);
+            None
         }
+    };
 
-        log::info!(
-            "✅ Verified L2 state via L1: #{}, Hash: {}, Root: {}",
-            state_update.block_number,
-            state_update.block_hash,
-            state_update.global_root
-        );
-    }
+    loop {
+        interval.tick().await;
+
+        let state_update = ETHEREUM_STATE_UPDATE.lock().unwrap().clone();
+        // The L1 head is unknown until the L1 sync fetches it
+        if state_update.block_hash == StarkHash::default() || last_checked == Some(state_update.block_number) {
+            continue;
+        }
 
-    Ok(())
+        let posted = L1BlockState {
+            block_number: state_update.block_number,
+            block_hash: state_update.block_hash,
+            global_root: state_update.global_root,
+            transaction_hash: state_update.transaction_hash,
+        };
+        let written = match verify_l1(&state_update, chain.as_ref()) {
+            L1Verification::Pending => continue,
+            L1Verification::Verified => {
+                log::info!(
+                    "✅ Verified L2 state via L1: #{}, Hash: {}, Root: {}",
+                    state_update.block_number,
+                    state_update.block_hash,
+                    state_update.global_root
+                );
+                backend.meta().write_l1_verified_state(&posted)
+            }
+            L1Verification::Mismatch(current_state_update) => {
+                log::error!(
+                    "🚨 L1 state verification failed at #{}: L1 has Hash: {}, Root: {}, node has Hash: {}, Root: {}",
+                    state_update.block_number,
+                    state_update.block_hash,
+                    state_update.global_root,
+                    current_state_update.block_hash,
+                    current_state_update.global_root
+                );
+                backend.meta().write_l1_state_mismatch(L1StateMismatch {
+                    posted,
+                    block_hash: current_state_update.block_hash,
+                    global_root: current_state_update.global_root,
+                })
+            }
+        };
+        if let Err(e) = written {
+            log::error!("Failed to store the L1 verification of block #{}: {}", state_update.block_number, e);
+        }
+        last_checked = Some(state_update.block_number);
+    }
 }
 
-/// Syncronize with the L1 latest state updates
-pub async fn sync(l1_url: Url) {
-    let client = EthereumClient::new(l1_url).await.expect("Failed to create EthereumClient");
+/// Syncronize with the L1 latest state updates, and index the messages sent to L2
+pub async fn sync<B: BlockT>(config: L1Config, backend: Arc<mc_db::Backend<B>>) {
+    let L1Config { url, core_contract, chain_id, l2_chain_id, messages_from_block } = config;
+    let client = EthereumClient::new(url, core_contract).await.expect("Failed to create EthereumClient");
+
+    // A core contract address is only meaningful on the chain it was deployed on
+    if let Some(expected_chain_id) = chain_id {
+        match client.provider.get_chainid().await {
+            Ok(chain_id) if chain_id == expected_chain_id.into() => {}
+            Ok(chain_id) => {
+                log::error!("L1 endpoint serves chain {}, expected chain {}", chain_id, expected_chain_id);
+                return;
+            }
+            Err(e) => {
+                log::error!("Failed to get L1 chain id: {}", e);
+                return;
+            }
+        }
+    }
 
     log::info!("🚀 Subscribed to L1 state verification");
 
@@ -252,10 +508,33 @@ pub async fn sync(l1_url: Url) {
     };
     update_l1(initial_state);
 
-    // Listen to LogStateUpdate (0x77552641) update and send changes continusly
+    // Listen to LogStateUpdate (0x77552641) update and send changes continusly, and index the
+    // messages sent to L2
     let start_block =
         EthereumClient::get_last_event_block_number(&client).await.expect("Failed to retrieve last event block number");
-    EthereumClient::listen_and_update_state(&client, start_block).await.unwrap();
+    tokio::join!(async { EthereumClient::listen_and_update_state(&client, start_block).await.unwrap() }, async {
+        // Messages are indexed from the first one, which was sent after the core contract was deployed
+        let messages_from_block = match messages_from_block {
+            Some(block_number) => block_number,
+            None => match client.get_core_contract_deployment_block().await {
+                Ok(block_number) => block_number,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to find the deployment block of the core contract, messages to L2 are only indexed \
+                         from L1 block {}: {}",
+                        start_block,
+                        e
+                    );
+                    start_block
+                }
+            },
+        };
+        if let Err(e) =
+            EthereumClient::listen_and_index_messages(&client, messages_from_block, l2_chain_id, backend).await
+        {
+            log::error!("Failed to index the messages sent to L2: {}", e);
+        }
+    });
 }
 
 #[cfg(test)]
@@ -269,6 +548,7 @@ mod l1_sync_tests {
 
     use super::*;
     use crate::l1::EthereumClient;
+    use crate::utils::constant::starknet_core_address;
 
     #[derive(Clone, Debug, EthEvent)]
     pub struct Transfer {
@@ -283,10 +563,14 @@ mod l1_sync_tests {
         pub const MAINNET: &str = "<ENTER-YOUR-RPC-URL-HERE>";
     }
 
+    fn core_contract() -> Address {
+        starknet_core_address::MAINNET.parse().expect("Failed to parse Starknet core address")
+    }
+
     #[tokio::test]
     async fn test_starting_block() {
         let url = Url::parse(eth_rpc::MAINNET).expect("Failed to parse URL");
-        let client = EthereumClient::new(url).await.expect("Failed to create EthereumClient");
+        let client = EthereumClient::new(url, core_contract()).await.expect("Failed to create EthereumClient");
 
         let start_block =
             EthereumClient::get_last_event_block_number(&client).await.expect("Failed to get last event block number");
@@ -296,7 +580,7 @@ mod l1_sync_tests {
     #[tokio::test]
     async fn test_initial_state() {
         let url = Url::parse(eth_rpc::MAINNET).expect("Failed to parse URL");
-        let client = EthereumClient::new(url).await.expect("Failed to create EthereumClient");
+        let client = EthereumClient::new(url, core_contract()).await.expect("Failed to create EthereumClient");
 
         let initial_state = EthereumClient::get_initial_state(&client).await.expect("Failed to get initial state");
         assert!(!initial_state.global_root.0.is_empty(), "Global root should not be empty");
@@ -345,9 +629,10 @@ mod l1_sync_tests {
 
     #[tokio::test]
     async fn listen_and_update_state() -> Result<(), Box<dyn std::error::Error>> {
-        let client = EthereumClient::new(Url::parse(eth_rpc::MAINNET).expect("Failed to parse rpc url"))
-            .await
-            .expect("Failed to create EthereumClient");
+        let client =
+            EthereumClient::new(Url::parse(eth_rpc::MAINNET).expect("Failed to parse rpc url"), core_contract())
+                .await
+                .expect("Failed to create EthereumClient");
         let start_block = EthereumClient::get_last_event_block_number(&client)
             .await
             .expect("Failed to retrieve last event block number");
@@ -1,33 +1,48 @@
 //! Contains the code required to fetch data from the feeder efficiently.
-use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
+use blockifier::execution::contract_class::{ContractClass as ContractClassBlockifier, ContractClassV1};
+use cairo_lang_casm_contract_class::CasmContractClass;
 use itertools::Itertools;
-use mc_db::BonsaiDbs;
-use mc_storage::OverrideHandle;
+use mc_db::bonsai_db::MAX_SAVED_SNAPSHOTS;
+use mc_db::{BonsaiDbs, CasmClassesDb, StoredReceipt, StoredSierraClass};
+use mc_rpc_core::ReorgData;
 use mp_block::state_update::StateUpdateWrapper;
 use mp_contract::class::{ClassUpdateWrapper, ContractClassData, ContractClassWrapper};
+use mp_contract::ContractAbi;
+use mp_convert::contract::{flattened_sierra_to_casm_contract_class, flattened_sierra_to_sierra_contract_class};
 use mp_felt::Felt252Wrapper;
-use mp_storage::StarknetStorageSchemaVersion;
+use mp_transactions::class_hash::compute_class_hash;
+use mp_transactions::from_broadcasted_transactions::get_casm_cotract_class_hash;
 use reqwest::Url;
 use serde::Deserialize;
 use sp_core::H256;
-use sp_runtime::generic::{Block, Header};
-use sp_runtime::traits::{BlakeTwo256, Block as BlockT};
-use sp_runtime::OpaqueExtrinsic;
+use sp_runtime::traits::Block as BlockT;
 use starknet_api::api_core::ClassHash;
-use starknet_api::hash::StarkHash;
-use starknet_core::types::BlockId as BlockIdCore;
+use starknet_api::hash::{StarkFelt, StarkHash};
+use starknet_core::types::{
+    ContractClass as ContractClassCore, FlattenedSierraClass, PendingBlockWithTxs, PendingStateUpdate,
+    PendingTransactionReceipt,
+};
 use starknet_ff::FieldElement;
 use starknet_providers::sequencer::models::state_update::{DeclaredContract, DeployedContract};
 use starknet_providers::sequencer::models::{BlockId, StateUpdate};
-use starknet_providers::{Provider, SequencerGatewayProvider};
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
-use tokio::task::JoinSet;
+use tokio::task::{JoinHandle, JoinSet};
 
+use crate::archive::Archive;
+use crate::chain::ChainHandle;
 use crate::commitments::lib::{build_commitment_state_diff, update_state_root};
-use crate::utility::{get_block_hash_by_number, update_highest_block_hash_and_number};
+use crate::commitments::verify::{verify_header, verify_state_root, ChainRules, GatewayBlock};
+use crate::failover::{FailoverSource, FeederSource};
+use crate::metrics::FetchMetrics;
+use crate::source::{BlockSource, RecordingSource, ReplaySource};
+use crate::utility::update_highest_block_hash_and_number;
 use crate::CommandSink;
 
 /// Contains the Starknet verified state on L2
@@ -60,6 +75,78 @@ lazy_static! {
     pub static ref STARKNET_HIGHEST_BLOCK_HASH_AND_NUMBER: Arc<Mutex<(FieldElement, u64)>> = Arc::new(Mutex::new((FieldElement::default(), 0)));
 }
 
+lazy_static! {
+    /// Reorgs handled by the sync loop, broadcast to the RPC subscribers
+    static ref REORGS: broadcast::Sender<ReorgData> = broadcast::channel(16).0;
+}
+
+lazy_static! {
+    /// The last pending block polled from the feeder gateway
+    static ref PENDING_BLOCK: RwLock<Option<Arc<PendingBlock>>> = RwLock::new(None);
+}
+
+/// Delay between two polls of the pending block.
+const PENDING_BLOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The pending block of the feeder gateway, in its rpc representation.
+///
+/// The pending block is built on top of the last block of the feeder gateway, which may not have
+/// been imported by the node yet: check its parent hash before serving it.
+#[derive(Debug, Clone)]
+pub struct PendingBlock {
+    pub block: PendingBlockWithTxs,
+    pub state_update: PendingStateUpdate,
+    /// Receipts of the transactions of the block, keyed by transaction hash.
+    pub receipts: HashMap<FieldElement, PendingTransactionReceipt>,
+}
+
+/// Delay before the first retry of a failed fetch.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound of the delay between two retries of a failed fetch.
+const MAX_BACKOFF: Duration = Duration::from_secs(16);
+/// How often the highest block of the feeder gateway is polled while syncing.
+const HIGHEST_BLOCK_POLL_INTERVAL: Duration = Duration::from_secs(20);
+/// How often the highest block of the feeder gateway is polled once the sync reached it.
+const TIP_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// An error raised while fetching data from the feeder gateway.
+#[derive(thiserror::Error, Debug)]
+enum FetchError {
+    /// The fetch failed, and may succeed when retried.
+    #[error("{0}")]
+    Transient(String),
+    /// The feeder gateway served invalid data, which retrying does not fix: the sync stops.
+    #[error("{0}")]
+    Fatal(String),
+}
+
+impl From<String> for FetchError {
+    fn from(e: String) -> Self {
+        Self::Transient(e)
+    }
+}
+
+/// A class which does not hash to the hash it was requested with.
+#[derive(thiserror::Error, Debug)]
+#[error("class hash mismatch: expected {expected:#x}, computed {computed:#x}")]
+struct ClassHashMismatch {
+    expected: FieldElement,
+    computed: FieldElement,
+}
+
+/// A compiled class which does not hash to the compiled class hash declared in the state diff.
+#[derive(thiserror::Error, Debug)]
+#[error("compiled class hash mismatch for class {class_hash:#x}: declared {declared:#x}, computed {computed:#x}")]
+struct CompiledClassHashMismatch {
+    class_hash: FieldElement,
+    declared: FieldElement,
+    computed: FieldElement,
+}
+
+/// Reverts the Substrate chain and the Madara mapping down to the given block number, which
+/// becomes the new best block.
+pub type BlockReverter = Box<dyn Fn(u64) -> Result<(), String> + Send + Sync>;
+
 /// The configuration of the worker responsible for fetching new blocks and state updates from the
 /// feeder.
 #[derive(Clone, Debug)]
@@ -70,10 +157,17 @@ pub struct FetchConfig {
     pub feeder_gateway: Url,
     /// The ID of the chain served by the sequencer gateway.
     pub chain_id: starknet_ff::FieldElement,
-    /// The number of tasks spawned to fetch blocks and state updates.
+    /// The number of blocks fetched ahead of their import, each by its own task.
     pub workers: u32,
     /// Whether to play a sound when a new block is fetched.
     pub sound: bool,
+    /// A recording of the feeder gateway to sync from instead of the network.
+    pub replay: Option<PathBuf>,
+    /// An archive in which every response of the feeder gateway is recorded.
+    pub record: Option<PathBuf>,
+    /// The feeder gateways the blocks are synced from, failed over from one to the next. The
+    /// feeder gateway above is used if none is given.
+    pub sources: Vec<FeederSource>,
 }
 
 impl Default for FetchConfig {
@@ -85,8 +179,39 @@ impl Default for FetchConfig {
             chain_id: starknet_ff::FieldElement::default(), // Adjust as necessary
             workers: 4,
             sound: false,
+            replay: None,
+            record: None,
+            sources: vec![],
+        }
+    }
+}
+
+impl FetchConfig {
+    /// Returns the source of the blocks to sync: the replayed recording if any, the feeder gateways
+    /// otherwise, recording their responses if asked to.
+    pub fn block_source(&self) -> Result<Arc<dyn BlockSource>, String> {
+        if let Some(path) = &self.replay {
+            return Ok(Arc::new(ReplaySource::open(path)?));
+        }
+
+        let source = self.failover_source()?;
+        match &self.record {
+            Some(path) => Ok(Arc::new(RecordingSource::new(source, Archive::open(path)?))),
+            None => Ok(Arc::new(source)),
         }
     }
+
+    /// Returns a source failing over between the configured feeder gateways, or the feeder
+    /// gateway above if none is configured.
+    fn failover_source(&self) -> Result<FailoverSource, String> {
+        let sources = if self.sources.is_empty() {
+            vec![FeederSource::new(self.feeder_gateway.clone())]
+        } else {
+            self.sources.clone()
+        };
+
+        FailoverSource::new(sources)
+    }
 }
 
 /// The configuration of the senders responsible for sending blocks and state
@@ -101,159 +226,286 @@ pub struct SenderConfig {
     /// The command sink used to notify the consensus engine that a new block
     /// should be created.
     pub command_sink: CommandSink,
-    // Storage overrides for accessing stored classes
-    pub overrides: Arc<OverrideHandle<Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>>>,
-}
-
-// TODO: find a better place to store this
-/// Stores a madara block hash and it's associated substrate hash.
-pub struct BlockHashEquivalence {
-    pub madara: FieldElement,
-    pub substrate: Option<H256>,
-}
-
-impl BlockHashEquivalence {
-    async fn new(state_update: &StateUpdate, block_number: u64, rpc_port: u16) -> Self {
-        // TODO: use an actual Substrate client to convert from Madara to Substrate block hash
-        let block_hash_madara = state_update.block_hash.unwrap();
-        let block_hash_substrate = &get_block_hash_by_number(rpc_port, block_number).await;
-
-        // WARNING: might causes issues related to eRFC 2497 (https://github.com/rust-lang/rust/issues/53667)
-        if block_number > 0 && let Some(block_hash_substrate) = block_hash_substrate {
-            BlockHashEquivalence {
-                madara: block_hash_madara,
-                substrate: Some(H256::from_str(&block_hash_substrate).unwrap()),
-            }
-        } else {
-            BlockHashEquivalence {
-                madara: block_hash_madara,
-                substrate: None,
-            }
-        }
-    }
+    /// The chain imported by the node, used to look up the classes it already stores.
+    pub chain: Arc<dyn ChainHandle>,
+    /// Reverts the blocks orphaned by a reorg of the feeder gateway.
+    pub block_reverter: BlockReverter,
 }
 
 /// Spawns workers to fetch blocks and state updates from the feeder.
+///
+/// Up to `config.workers` blocks are fetched ahead of the block being imported, along with their
+/// state updates and declared classes, but never beyond the highest block of the feeder gateway.
+/// Blocks are then imported strictly in order. Failed requests are retried with an exponential
+/// backoff.
+///
+/// Before a block is imported, its parent hash is checked against the hash of the last block of the
+/// chain, so that reorgs are also detected across restarts. On a mismatch the feeder has reorged:
+/// the node walks back to the common ancestor, reverts everything above it and resumes syncing from
+/// there. Only the last [`MAX_SAVED_SNAPSHOTS`] blocks of the chain can be reverted.
+///
+/// # Returns
+///
+/// The error which stopped the sync, such as a block which does not match its header.
 pub async fn sync<B: BlockT>(
     mut sender_config: SenderConfig,
     config: FetchConfig,
     start_at: u64,
-    rpc_port: u16,
     backend: Arc<mc_db::Backend<B>>,
-) {
+    metrics: Option<FetchMetrics>,
+) -> Result<(), String> {
     update_config(&config);
-    let SenderConfig { block_sender, state_update_sender, class_sender, command_sink, overrides } = &mut sender_config;
-    let client = SequencerGatewayProvider::new(config.gateway.clone(), config.feeder_gateway.clone(), config.chain_id);
+    let SenderConfig { block_sender, state_update_sender, class_sender, command_sink, chain, block_reverter } =
+        &mut sender_config;
+    let chain = Arc::clone(chain);
+    let client = config.block_source().map_err(|e| format!("Failed to open the block source: {e}"))?;
     let bonsai_dbs = BonsaiDbs {
         contract: Arc::clone(backend.bonsai_contract()),
         class: Arc::clone(backend.bonsai_class()),
         storage: Arc::clone(backend.bonsai_storage()),
     };
+    let casm_classes = Arc::clone(backend.casm_classes());
+    let rules = ChainRules::of(config.chain_id);
+    let workers = u64::from(config.workers.max(1));
     let mut current_block_number = start_at;
+    let mut next_to_fetch = start_at;
+    let mut pipeline = VecDeque::<JoinHandle<FetchedBlock>>::new();
     let mut last_block_hash = None;
-    let mut got_block = false;
-    let mut got_state_update = false;
-    let mut last_update_highest_block = tokio::time::Instant::now() - Duration::from_secs(20);
+    let mut highest_block = None;
+    let mut last_update_highest_block = tokio::time::Instant::now() - HIGHEST_BLOCK_POLL_INTERVAL;
     if current_block_number == 0 {
-        let _ = fetch_genesis_state_update(&client, bonsai_dbs.clone()).await;
+        let _ = fetch_genesis_state_update(client.as_ref(), bonsai_dbs.clone()).await;
     }
     loop {
-        if last_update_highest_block.elapsed() > Duration::from_secs(20) {
+        let at_tip = highest_block.is_some_and(|highest_block| next_to_fetch > highest_block);
+        let poll_interval = if at_tip { TIP_POLL_INTERVAL } else { HIGHEST_BLOCK_POLL_INTERVAL };
+        if last_update_highest_block.elapsed() >= poll_interval {
             last_update_highest_block = tokio::time::Instant::now();
-            if let Err(e) = update_highest_block_hash_and_number(&client).await {
-                eprintln!("Failed to update highest block hash and number: {}", e);
+            match update_highest_block_hash_and_number(client.as_ref()).await {
+                Ok(()) => highest_block = Some(get_highest_block_hash_and_number().1),
+                Err(e) => log::warn!("Failed to update highest block hash and number: {e}"),
             }
         }
-        let (block, state_update) = match (got_block, got_state_update) {
-            (false, false) => {
-                let block = fetch_block(&client, block_sender, current_block_number);
-                let state_update = fetch_state_and_class_update(
-                    &client,
-                    Arc::clone(&overrides),
-                    state_update_sender,
-                    class_sender,
+
+        // Blocks which the feeder gateway does not serve yet would only be retried in vain
+        while (pipeline.len() as u64) < workers
+            && highest_block.map_or(true, |highest_block| next_to_fetch <= highest_block)
+        {
+            pipeline.push_back(tokio::spawn(fetch_block_data(
+                Arc::clone(&client),
+                Arc::clone(&casm_classes),
+                next_to_fetch,
+                worker_label(next_to_fetch, workers),
+                metrics.clone(),
+            )));
+            next_to_fetch += 1;
+        }
+
+        let Some(fetching) = pipeline.pop_front() else {
+            // The sync reached the highest block of the feeder gateway
+            tokio::time::sleep(TIP_POLL_INTERVAL).await;
+            continue;
+        };
+        let worker = worker_label(current_block_number, workers);
+        let fetched = match fetching.await {
+            Ok(Ok(fetched)) => fetched,
+            Ok(Err(e)) => return Err(format!("Failed to fetch block {current_block_number}: {e}")),
+            Err(e) => {
+                log::warn!("Failed to fetch block {current_block_number}, fetching it again: {e}");
+                pipeline.push_front(tokio::spawn(fetch_block_data(
+                    Arc::clone(&client),
+                    Arc::clone(&casm_classes),
                     current_block_number,
-                    rpc_port,
-                    bonsai_dbs.clone(),
-                );
-                tokio::join!(block, state_update)
+                    worker,
+                    metrics.clone(),
+                )));
+                continue;
             }
-            (false, true) => (fetch_block(&client, block_sender, current_block_number).await, Ok(())),
-            (true, false) => (
-                Ok(()),
-                fetch_state_and_class_update(
-                    &client,
-                    Arc::clone(&overrides),
-                    state_update_sender,
-                    class_sender,
-                    current_block_number,
-                    rpc_port,
-                    bonsai_dbs.clone(),
-                )
-                .await,
-            ),
-            (true, true) => unreachable!(),
         };
 
-        got_block = got_block || block.is_ok();
-        got_state_update = got_state_update || state_update.is_ok();
-
-        match (block, state_update) {
-            (Ok(()), Ok(())) => match create_block(command_sink, &mut last_block_hash).await {
-                Ok(()) => {
-                    current_block_number += 1;
-                    got_block = false;
-                    got_state_update = false;
+        let parent_hash = current_block_number.checked_sub(1).and_then(|parent| chain.block_hash(parent));
+        if parent_hash.is_some_and(|parent_hash| parent_hash != fetched.block.block.parent_block_hash) {
+            // Blocks fetched ahead may belong to either chain
+            pipeline.drain(..).for_each(|handle| handle.abort());
+            match handle_reorg(client.as_ref(), chain.as_ref(), block_reverter, &bonsai_dbs).await {
+                Ok(ancestor) => {
+                    current_block_number = ancestor + 1;
+                    next_to_fetch = current_block_number;
                 }
-                Err(e) => {
-                    eprintln!("Failed to create block: {}", e);
-                    return;
-                }
-            },
-            (Err(a), Ok(())) => {
-                eprintln!("Failed to fetch block {}: {}", current_block_number, a);
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                Err(e) => return Err(format!("Failed to handle reorg at block {current_block_number}: {e}")),
             }
-            (_, Err(b)) => {
-                eprintln!("Failed to fetch state update {}: {}", current_block_number, b);
-                tokio::time::sleep(Duration::from_secs(10)).await;
+            continue;
+        }
+
+        // The header is verified before the state tries are updated, so that they never hold the
+        // state diff of a block which does not match its header
+        let (block, receipts) = crate::convert::block_with_receipts(fetched.block.block.clone(), config.chain_id).await;
+        verify_header(&rules, &fetched.block, &fetched.state_update, block.header())
+            .map_err(|e| format!("Failed to verify block {current_block_number}: {e}"))?;
+
+        let state_root = with_backoff("state root update", current_block_number, &worker, &metrics, || {
+            verify_l2(current_block_number, &fetched.state_update, bonsai_dbs.clone())
+        })
+        .await
+        .map_err(|e| format!("Failed to update the state root at block {current_block_number}: {e}"))?;
+        if let Err(e) = verify_state_root(current_block_number, fetched.block.block.state_root, state_root.into()) {
+            // The tries are left as they were before the block
+            if let Some(parent) = current_block_number.checked_sub(1) {
+                if let Err(e) = bonsai_dbs.revert_to_block(parent) {
+                    log::error!("Failed to revert the tries to block {parent}: {e}");
+                }
             }
+            return Err(format!("Failed to verify block {current_block_number}: {e}"));
         }
+        let missing_classes = with_backoff("missing classes", current_block_number, &worker, &metrics, || {
+            fetch_class_update(
+                &client,
+                &casm_classes,
+                &fetched.state_update,
+                &fetched.classes,
+                chain.as_ref(),
+                current_block_number,
+            )
+        })
+        .await
+        .map_err(|e| format!("Failed to fetch the classes of block {current_block_number}: {e}"))?;
+
+        dispatch_block(
+            block,
+            receipts,
+            fetched,
+            missing_classes,
+            &backend,
+            block_sender,
+            state_update_sender,
+            class_sender,
+        )
+        .await
+        .map_err(|e| format!("Failed to dispatch block {current_block_number}: {e}"))?;
+
+        create_block(command_sink, &mut last_block_hash)
+            .await
+            .map_err(|e| format!("Failed to create block {current_block_number}: {e}"))?;
+        current_block_number += 1;
     }
 }
 
-async fn fetch_block(
-    client: &SequencerGatewayProvider,
-    block_sender: &Sender<mp_block::Block>,
-    block_number: u64,
-) -> Result<(), String> {
-    let block =
-        client.get_block(BlockId::Number(block_number)).await.map_err(|e| format!("failed to get block: {e}"))?;
+/// A block fetched ahead of its import.
+struct FetchedBlock {
+    block: GatewayBlock,
+    state_update: StateUpdate,
+    /// Classes declared in the block.
+    classes: Vec<DownloadedClass>,
+}
 
-    let block_conv = crate::convert::block(block).await;
-    block_sender.send(block_conv).await.map_err(|e| format!("failed to dispatch block: {e}"))?;
+/// A class downloaded from the feeder gateway.
+struct DownloadedClass {
+    data: ContractClassData,
+    /// The definition served by the feeder gateway, kept for Sierra classes as the conversion to a
+    /// blockifier class loses the Sierra program.
+    sierra: Option<StoredSierraClass>,
+}
 
-    Ok(())
+/// Label of the worker in charge of a block in the fetch metrics.
+fn worker_label(block_number: u64, workers: u64) -> String {
+    (block_number % workers).to_string()
 }
 
-pub async fn fetch_genesis_block(config: FetchConfig) -> Result<mp_block::Block, String> {
-    let client = SequencerGatewayProvider::new(config.gateway.clone(), config.feeder_gateway.clone(), config.chain_id);
-    let block = client.get_block(BlockId::Number(0)).await.map_err(|e| format!("failed to get block: {e}"))?;
+/// Fetches a block, its state update and the classes it declares, retrying until all of them are
+/// available. Fails if the feeder gateway served invalid data.
+async fn fetch_block_data(
+    provider: Arc<dyn BlockSource>,
+    casm_classes: Arc<CasmClassesDb>,
+    block_number: u64,
+    worker: String,
+    metrics: Option<FetchMetrics>,
+) -> Result<FetchedBlock, String> {
+    let started = std::time::Instant::now();
+    let provider = &provider;
+
+    let (block, state_update) = tokio::join!(
+        with_backoff("block", block_number, &worker, &metrics, || async move {
+            provider.get_gateway_block(BlockId::Number(block_number)).await
+        }),
+        with_backoff("state update", block_number, &worker, &metrics, || async move {
+            provider.get_state_update(BlockId::Number(block_number)).await
+        })
+    );
+    let (block, state_update) = (block?, state_update?);
+
+    let declared_classes: Vec<FieldElement> = declared_classes(&state_update).into_iter().copied().collect();
+    let compiled_class_hashes = compiled_class_hashes(&state_update);
+    let classes = with_backoff("classes", block_number, &worker, &metrics, || {
+        download_classes(
+            declared_classes.clone(),
+            &compiled_class_hashes,
+            block_number,
+            Arc::clone(provider),
+            Arc::clone(&casm_classes),
+        )
+    })
+    .await?;
+
+    if let Some(metrics) = &metrics {
+        metrics.fetched_blocks.with_label_values(&[&worker]).inc();
+        metrics.fetch_duration.with_label_values(&[&worker]).observe(started.elapsed().as_secs_f64());
+    }
 
-    Ok(crate::convert::block(block).await)
+    Ok(FetchedBlock { block, state_update, classes })
 }
 
-async fn fetch_state_and_class_update<B: BlockT>(
-    provider: &SequencerGatewayProvider,
-    overrides: Arc<OverrideHandle<Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>>>,
+/// Retries `f` until it succeeds, doubling the delay between two attempts up to [`MAX_BACKOFF`].
+/// Gives up on a [`FetchError::Fatal`] error.
+async fn with_backoff<T, E, F, Fut>(
+    what: &str,
+    block_number: u64,
+    worker: &str,
+    metrics: &Option<FetchMetrics>,
+    mut f: F,
+) -> Result<T, String>
+where
+    E: Into<FetchError>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match f().await.map_err(Into::into) {
+            Ok(value) => return Ok(value),
+            Err(FetchError::Fatal(e)) => return Err(format!("invalid {what} of block {block_number}: {e}")),
+            Err(FetchError::Transient(e)) => {
+                log::debug!("Failed to get {what} of block {block_number}, retrying in {backoff:?}: {e}");
+                if let Some(metrics) = metrics {
+                    metrics.fetch_errors.with_label_values(&[worker]).inc();
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Dispatches a block, its state update and its classes to the consensus engine, and stores the
+/// receipts of its transactions and the definitions of its Sierra classes.
+async fn dispatch_block<B: BlockT>(
+    block: mp_block::Block,
+    receipts: Vec<(StarkFelt, StoredReceipt)>,
+    fetched: FetchedBlock,
+    missing_classes: Vec<DownloadedClass>,
+    backend: &mc_db::Backend<B>,
+    block_sender: &Sender<mp_block::Block>,
     state_update_sender: &Sender<StateUpdateWrapper>,
     class_sender: &Sender<ClassUpdateWrapper>,
-    block_number: u64,
-    rpc_port: u16,
-    bonsai_dbs: BonsaiDbs<B>,
 ) -> Result<(), String> {
-    let state_update = fetch_state_update(&provider, block_number, bonsai_dbs).await?;
-    let class_update = fetch_class_update(&provider, &state_update, overrides, block_number, rpc_port).await?;
+    let FetchedBlock { block: _, state_update, mut classes } = fetched;
+    classes.extend(missing_classes);
+
+    let sierra_classes: Vec<_> =
+        classes.iter_mut().filter_map(|class| class.sierra.take().map(|sierra| (class.data.hash, sierra))).collect();
+    backend
+        .sierra_classes()
+        .store_sierra_classes(&sierra_classes)
+        .map_err(|e| format!("failed to store sierra classes: {e}"))?;
 
     // Now send state_update, which moves it. This will be received
     // by QueryBlockConsensusDataProvider in deoxys/crates/node/src/service.rs
@@ -264,59 +516,183 @@ async fn fetch_state_and_class_update<B: BlockT>(
 
     // do the same to class update
     class_sender
-        .send(ClassUpdateWrapper(class_update))
+        .send(ClassUpdateWrapper(classes.into_iter().map(|class| class.data).collect()))
         .await
         .map_err(|e| format!("failed to dispatch class update: {e}"))?;
 
+    // Receipts are stored before the block is imported, so that they are available once it is
+    // mapped
+    backend.receipts().store_receipts(&receipts).map_err(|e| format!("failed to store receipts: {e}"))?;
+    block_sender.send(block).await.map_err(|e| format!("failed to dispatch block: {e}"))?;
+
     Ok(())
 }
 
-/// retrieves state update from Starknet sequencer
-async fn fetch_state_update<B: BlockT>(
-    provider: &SequencerGatewayProvider,
-    block_number: u64,
-    bonsai_dbs: BonsaiDbs<B>,
-) -> Result<StateUpdate, String> {
-    let state_update = provider
-        .get_state_update(BlockId::Number(block_number))
-        .await
-        .map_err(|e| format!("failed to get state update: {e}"))?;
+/// Walks back the blocks of the chain until one matches the feeder chain, and reverts every block
+/// above it.
+///
+/// # Returns
+///
+/// The number of the common ancestor, the last block kept.
+async fn handle_reorg<B: BlockT>(
+    provider: &dyn BlockSource,
+    chain: &dyn ChainHandle,
+    block_reverter: &BlockReverter,
+    bonsai_dbs: &BonsaiDbs<B>,
+) -> Result<u64, String> {
+    let best_block_number = chain.best_block_number();
+
+    // The tries cannot be reverted further than their oldest snapshot
+    let mut ancestor = None;
+    for block_number in (best_block_number.saturating_sub(MAX_SAVED_SNAPSHOTS)..=best_block_number).rev() {
+        let Some(block_hash) = chain.block_hash(block_number) else {
+            continue;
+        };
+        let block = provider.get_block(BlockId::Number(block_number)).await?;
+        if block.block_hash == Some(block_hash) {
+            ancestor = Some(block_number);
+            break;
+        }
+    }
+    let ancestor = ancestor.ok_or("the reorg is deeper than the blocks which can be reverted")?;
+    if ancestor == best_block_number {
+        return Ok(ancestor);
+    }
 
-    verify_l2(block_number, &state_update, bonsai_dbs).await?;
+    let (starting_block_number, ending_block_number) = (ancestor + 1, best_block_number);
+    let (Some(starting_block_hash), Some(ending_block_hash)) =
+        (chain.block_hash(starting_block_number), chain.block_hash(ending_block_number))
+    else {
+        return Err(format!("blocks {starting_block_number} to {ending_block_number} are missing from the chain"));
+    };
+    log::warn!("⚠️ Reorg detected, reverting blocks {starting_block_number} to {ending_block_number}");
 
-    Ok(state_update)
+    block_reverter(ancestor)?;
+    bonsai_dbs.revert_to_block(ancestor).map_err(|e| format!("failed to revert the tries: {e}"))?;
+
+    // Nobody listening is not an error
+    let _ =
+        REORGS.send(ReorgData { starting_block_hash, starting_block_number, ending_block_hash, ending_block_number });
+
+    Ok(ancestor)
+}
+
+/// Subscribes to the reorgs handled by the sync loop.
+pub fn subscribe_reorgs() -> broadcast::Receiver<ReorgData> {
+    REORGS.subscribe()
+}
+
+/// Polls the pending block of the feeder gateway and keeps the last one in memory.
+///
+/// Recordings have no pending block, so nothing is polled when replaying one.
+pub async fn sync_pending_block(config: FetchConfig) {
+    if config.replay.is_some() {
+        return;
+    }
+    // Pending blocks are never recorded, always ask the feeder gateways
+    let source = match config.failover_source() {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("Failed to open the feeder gateways to poll the pending block from: {e}");
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(PENDING_BLOCK_POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+
+        let pending_block = source
+            .fetch_pending_block()
+            .await
+            .and_then(|(block, state_update)| crate::convert::pending_block(block, state_update));
+
+        match pending_block {
+            Ok(pending_block) => *PENDING_BLOCK.write().unwrap() = Some(Arc::new(pending_block)),
+            Err(e) => log::debug!("Failed to fetch the pending block: {e}"),
+        }
+    }
+}
+
+/// Returns the last pending block polled from the feeder gateway.
+pub fn get_pending_block() -> Option<Arc<PendingBlock>> {
+    PENDING_BLOCK.read().unwrap().clone()
+}
+
+pub async fn fetch_genesis_block(config: FetchConfig) -> Result<mp_block::Block, String> {
+    let block = config.block_source()?.get_block(BlockId::Number(0)).await?;
+
+    Ok(crate::convert::block(block, config.chain_id).await)
 }
 
 async fn fetch_genesis_state_update<B: BlockT>(
-    provider: &SequencerGatewayProvider,
+    provider: &dyn BlockSource,
     bonsai_dbs: BonsaiDbs<B>,
 ) -> Result<StateUpdate, String> {
-    let state_update =
-        provider.get_state_update(BlockId::Number(0)).await.map_err(|e| format!("failed to get state update: {e}"))?;
+    let state_update = provider.get_state_update(BlockId::Number(0)).await?;
 
     verify_l2(0, &state_update, bonsai_dbs).await?;
 
     Ok(state_update)
 }
 
-/// retrieves class updates from Starknet sequencer
+/// Retrieves the classes used by a state update which are neither declared in the same block nor
+/// stored in the local Substrate db
 async fn fetch_class_update(
-    provider: &SequencerGatewayProvider,
+    provider: &Arc<dyn BlockSource>,
+    casm_classes: &Arc<CasmClassesDb>,
     state_update: &StateUpdate,
-    overrides: Arc<OverrideHandle<Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>>>,
+    declared: &[DownloadedClass],
+    chain: &dyn ChainHandle,
     block_number: u64,
-    rpc_port: u16,
-) -> Result<Vec<ContractClassData>, String> {
-    // defaults to downloading ALL classes if a substrate block hash could not be determined
-    let block_hash = BlockHashEquivalence::new(state_update, block_number - 1, rpc_port).await;
-    let missing_classes = match block_hash.substrate {
-        Some(block_hash_substrate) => fetch_missing_classes(state_update, overrides, block_hash_substrate),
-        None => aggregate_classes(state_update),
-    };
+) -> Result<Vec<DownloadedClass>, FetchError> {
+    // Classes are looked up as of the parent block, all of them are downloaded if it is unknown
+    let missing_classes = aggregate_classes(state_update)
+        .into_iter()
+        .filter(|class_hash| {
+            let class_hash = ClassHash(Felt252Wrapper::from(**class_hash).into());
+            declared.iter().all(|class| class.data.hash != class_hash)
+                && !block_number.checked_sub(1).is_some_and(|parent| chain.has_class(parent, class_hash))
+        })
+        .copied()
+        .collect();
+
+    download_classes(
+        missing_classes,
+        &compiled_class_hashes(state_update),
+        block_number,
+        Arc::clone(provider),
+        Arc::clone(casm_classes),
+    )
+    .await
+}
 
-    let arc_provider = Arc::new(provider.clone());
-    let mut task_set = missing_classes.into_iter().fold(JoinSet::new(), |mut set, class_hash| {
-        set.spawn(download_class(*class_hash, block_hash.madara, Arc::clone(&arc_provider)));
+/// Downloads class definitions concurrently from the Starknet sequencer.
+///
+/// # Arguments
+///
+/// * `class_hashes` - The hashes of the classes to download
+/// * `compiled_class_hashes` - The compiled class hashes of the Sierra classes declared in the
+///   block
+/// * `block_number` - The block at which the classes are downloaded
+/// * `provider` - The source of the classes
+/// * `casm_classes` - The cache of the compiled classes
+async fn download_classes(
+    class_hashes: Vec<FieldElement>,
+    compiled_class_hashes: &HashMap<FieldElement, FieldElement>,
+    block_number: u64,
+    provider: Arc<dyn BlockSource>,
+    casm_classes: Arc<CasmClassesDb>,
+) -> Result<Vec<DownloadedClass>, FetchError> {
+    let mut task_set = class_hashes.into_iter().fold(JoinSet::new(), |mut set, class_hash| {
+        set.spawn(download_class(
+            class_hash,
+            compiled_class_hashes.get(&class_hash).copied(),
+            block_number,
+            Arc::clone(&provider),
+            Arc::clone(&casm_classes),
+        ));
         set
     });
 
@@ -328,12 +704,15 @@ async fn fetch_class_update(
                 Ok(contract) => classes.push(contract),
                 Err(e) => {
                     task_set.abort_all();
-                    return Err(e.to_string());
+                    if e.is::<ClassHashMismatch>() || e.is::<CompiledClassHashMismatch>() {
+                        return Err(FetchError::Fatal(e.to_string()));
+                    }
+                    return Err(FetchError::Transient(e.to_string()));
                 }
             },
             Err(e) => {
                 task_set.abort_all();
-                return Err(e.to_string());
+                return Err(FetchError::Transient(e.to_string()));
             }
         }
     }
@@ -345,33 +724,169 @@ async fn fetch_class_update(
 /// of the current type hell this needs to be converted into a blockifier equivalent
 async fn download_class(
     class_hash: FieldElement,
-    block_hash: FieldElement,
-    provider: Arc<SequencerGatewayProvider>,
-) -> anyhow::Result<ContractClassData> {
+    compiled_class_hash: Option<FieldElement>,
+    block_number: u64,
+    provider: Arc<dyn BlockSource>,
+    casm_classes: Arc<CasmClassesDb>,
+) -> anyhow::Result<DownloadedClass> {
     // log::info!("💾 Downloading class {class_hash:#x}");
-    let core_class = provider.get_class(BlockIdCore::Hash(block_hash), class_hash).await?;
+    let core_class = provider.get_class(class_hash, block_number).await.map_err(anyhow::Error::msg)?;
+
+    // The gateway is not trusted to serve the class it was asked for. The hash of a legacy class
+    // depends on the serialization of its program, which the node is not known to reproduce for
+    // every legacy class of the networks, so a legacy class which hashes differently is only logged
+    match (compute_class_hash(&core_class), &core_class) {
+        (Ok(computed_class_hash), _) if computed_class_hash == class_hash => {}
+        (Ok(computed_class_hash), ContractClassCore::Legacy(_)) => {
+            log::warn!("Legacy {}", ClassHashMismatch { expected: class_hash, computed: computed_class_hash });
+        }
+        (Err(e), ContractClassCore::Legacy(_)) => {
+            log::warn!("Failed to compute the hash of legacy class {class_hash:#x}: {e}");
+        }
+        (Ok(computed_class_hash), ContractClassCore::Sierra(_)) => {
+            return Err(ClassHashMismatch { expected: class_hash, computed: computed_class_hash }.into());
+        }
+        (Err(e), ContractClassCore::Sierra(_)) => {
+            return Err(anyhow::anyhow!("failed to compute the hash of class {class_hash:#x}: {e}"));
+        }
+    }
 
     // Core classes have to be converted into Blockifier classes to gain support
     // for Substrate [`Encode`] and [`Decode`] traits
-    Ok(ContractClassData {
+    let (contract_class, sierra) = match core_class {
+        ContractClassCore::Sierra(flattened_sierra) => {
+            let casm_class = compile_class(
+                class_hash,
+                &flattened_sierra,
+                compiled_class_hash,
+                block_number,
+                provider.as_ref(),
+                &casm_classes,
+            )
+            .await?;
+            let contract = ContractClassV1::try_from(casm_class)
+                .map_err(|e| anyhow::anyhow!("failed to convert the compiled class {class_hash:#x}: {e}"))?;
+            let contract_class = ContractClassWrapper {
+                contract: ContractClassBlockifier::V1(contract),
+                abi: ContractAbi::Sierra(flattened_sierra.abi.clone()),
+            };
+            let sierra = StoredSierraClass {
+                contract_class_version: flattened_sierra.contract_class_version.clone(),
+                class: flattened_sierra_to_sierra_contract_class(Arc::new(flattened_sierra)),
+            };
+            (contract_class, Some(sierra))
+        }
+        legacy => (ContractClassWrapper::try_from(legacy)?, None),
+    };
+
+    let data = ContractClassData {
         // TODO: find a less roundabout way of converting from a Felt252Wrapper
         hash: ClassHash(Felt252Wrapper::from(class_hash).into()),
-        contract_class: ContractClassWrapper::try_from(core_class)?,
-    })
+        contract_class,
+    };
+
+    Ok(DownloadedClass { data, sierra })
 }
 
-/// Filters out class declarations in the Starknet sequencer state update
-/// and retains only those which are not stored in the local Substrate db.
-fn fetch_missing_classes(
-    state_update: &StateUpdate,
-    overrides: Arc<OverrideHandle<Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>>>,
-    block_hash_substrate: H256,
-) -> Vec<&FieldElement> {
-    aggregate_classes(state_update)
-        .into_iter()
-        .filter(|class_hash| {
-            is_missing_class(Arc::clone(&overrides), block_hash_substrate, Felt252Wrapper::from(**class_hash))
-        })
+/// Returns the CASM class of a Sierra class, unless it was already compiled and cached.
+///
+/// Classes were compiled by the sequencer with the compiler of their time, which may produce
+/// another CASM class than the compiler of the node. The class compiled by the sequencer is thus
+/// fetched first, and the class is only compiled by the node if the source does not serve it or
+/// serves a class which does not match the compiled class hash declared in the state diff. The
+/// CASM class is checked against that hash before it is cached, and rejected if they differ.
+///
+/// # Arguments
+///
+/// * `class_hash` - The hash of the Sierra class
+/// * `flattened_sierra` - The Sierra class
+/// * `compiled_class_hash` - The compiled class hash declared in the state diff, if the class is
+///   declared in the block being synced
+/// * `block_number` - The block the class is fetched for
+/// * `provider` - The source serving the classes compiled by the sequencer
+/// * `casm_classes` - The cache of the compiled classes
+async fn compile_class(
+    class_hash: FieldElement,
+    flattened_sierra: &FlattenedSierraClass,
+    compiled_class_hash: Option<FieldElement>,
+    block_number: u64,
+    provider: &dyn BlockSource,
+    casm_classes: &CasmClassesDb,
+) -> anyhow::Result<CasmContractClass> {
+    let class_hash_felt: StarkFelt = Felt252Wrapper::from(class_hash).into();
+    let compiled_class_hash = match compiled_class_hash {
+        Some(compiled_class_hash) => Some(compiled_class_hash),
+        None => casm_classes.get_compiled_class_hash(class_hash_felt)?.map(|hash| Felt252Wrapper::from(hash).into()),
+    };
+
+    if let Some(compiled_class_hash) = compiled_class_hash {
+        if let Some(raw) = casm_classes.get_casm_class(Felt252Wrapper::from(compiled_class_hash).into())? {
+            return Ok(serde_json::from_slice(&raw)?);
+        }
+    }
+
+    let served_class = match compiled_class_hash {
+        Some(compiled_class_hash) => match provider.get_compiled_class(class_hash, block_number).await {
+            Ok(casm_class) if get_casm_cotract_class_hash(&casm_class) == compiled_class_hash => Some(casm_class),
+            Ok(_) => {
+                log::warn!("The compiled class of {class_hash:#x} served by the gateway does not match its hash");
+                None
+            }
+            Err(e) => {
+                log::debug!("Compiling class {class_hash:#x}: {e}");
+                None
+            }
+        },
+        None => None,
+    };
+    let casm_class = match served_class {
+        Some(casm_class) => casm_class,
+        None => flattened_sierra_to_casm_contract_class(flattened_sierra)
+            .map_err(|e| anyhow::anyhow!("failed to compile class {class_hash:#x}: {e}"))?,
+    };
+    let computed_class_hash = get_casm_cotract_class_hash(&casm_class);
+    if let Some(compiled_class_hash) = compiled_class_hash {
+        if computed_class_hash != compiled_class_hash {
+            return Err(CompiledClassHashMismatch {
+                class_hash,
+                declared: compiled_class_hash,
+                computed: computed_class_hash,
+            }
+            .into());
+        }
+    }
+
+    casm_classes.store_casm_class(
+        class_hash_felt,
+        Felt252Wrapper::from(computed_class_hash).into(),
+        &serde_json::to_vec(&casm_class)?,
+    )?;
+
+    Ok(casm_class)
+}
+
+/// Maps the Sierra classes declared in a state update to their compiled class hash
+fn compiled_class_hashes(state_update: &StateUpdate) -> HashMap<FieldElement, FieldElement> {
+    state_update
+        .state_diff
+        .declared_classes
+        .iter()
+        .map(|DeclaredContract { class_hash, compiled_class_hash }| (*class_hash, *compiled_class_hash))
+        .collect()
+}
+
+/// Retrieves the class hashes declared in a state update, Sierra and Cairo alike
+fn declared_classes(state_update: &StateUpdate) -> Vec<&FieldElement> {
+    std::iter::empty()
+        .chain(
+            state_update
+                .state_diff
+                .declared_classes
+                .iter()
+                .map(|DeclaredContract { class_hash, compiled_class_hash: _ }| class_hash),
+        )
+        .chain(state_update.state_diff.old_declared_contracts.iter())
+        .unique()
         .collect()
 }
 
@@ -398,24 +913,6 @@ fn aggregate_classes(state_update: &StateUpdate) -> Vec<&FieldElement> {
         .collect()
 }
 
-/// Check if a class is stored in the local Substrate db.
-///
-/// Since a change in class definition will result in a change in class hash,
-/// this means we only need to check for class hashes in the db.
-fn is_missing_class(
-    overrides: Arc<OverrideHandle<Block<Header<u32, BlakeTwo256>, OpaqueExtrinsic>>>,
-    block_hash_substrate: H256,
-    class_hash: Felt252Wrapper,
-) -> bool {
-    match overrides
-        .for_schema_version(&StarknetStorageSchemaVersion::Undefined)
-        .contract_class_by_class_hash(block_hash_substrate, ClassHash::from(class_hash))
-    {
-        Some(_) => false,
-        None => true,
-    }
-}
-
 /// Notifies the consensus engine that a new block should be created.
 async fn create_block(cmds: &mut CommandSink, parent_hash: &mut Option<H256>) -> Result<(), String> {
     let (sender, receiver) = futures::channel::oneshot::channel();
@@ -446,19 +943,21 @@ pub fn update_l2(state_update: L2StateUpdate) {
     }
 }
 
-/// Verify and update the L2 state according to the latest state update
+/// Verify and update the L2 state according to the latest state update, returning the state root
+/// computed from its state diff
 pub async fn verify_l2<B: BlockT>(
     block_number: u64,
     state_update: &StateUpdate,
     bonsai_dbs: BonsaiDbs<B>,
-) -> Result<(), String> {
+) -> Result<Felt252Wrapper, String> {
     let state_update_wrapper = StateUpdateWrapper::from(state_update);
 
     let csd = build_commitment_state_diff(state_update_wrapper.clone());
 
     // Main l2 sync bottleneck HERE!
-    let state_root =
-        update_state_root(csd, bonsai_dbs).await.map_err(|e| format!("Failed to update state root: {e}"))?;
+    let state_root = update_state_root(csd, bonsai_dbs, block_number)
+        .await
+        .map_err(|e| format!("Failed to update state root: {e}"))?;
 
     let block_hash = state_update.block_hash.expect("Block hash not found in state update");
 
@@ -468,7 +967,7 @@ pub async fn verify_l2<B: BlockT>(
         block_hash: Felt252Wrapper::from(block_hash).into(),
     });
 
-    Ok(())
+    Ok(state_root)
 }
 
 pub fn get_highest_block_hash_and_number() -> (FieldElement, u64) {
@@ -484,3 +983,376 @@ fn update_config(config: &FetchConfig) {
 pub fn get_config() -> FetchConfig {
     CONFIG.lock().unwrap().clone()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use async_trait::async_trait;
+    use bitvec::prelude::{BitVec, Msb0};
+    use bonsai_trie::id::BasicId;
+    use bonsai_trie::{BonsaiStorage, BonsaiStorageConfig};
+    use futures::StreamExt;
+    use sc_client_db::DatabaseSource;
+    use sc_consensus_manual_seal::rpc::{CreatedBlock, EngineCommand};
+    use sp_runtime::generic::{Block as GenericBlock, Header};
+    use sp_runtime::traits::BlakeTwo256;
+    use sp_runtime::OpaqueExtrinsic;
+    use starknet_core::types::contract::legacy::LegacyContractClass;
+    use starknet_core::types::contract::SierraClass;
+    use starknet_providers::sequencer::models::Block as SequencerBlock;
+    use starknet_types_core::felt::Felt;
+    use starknet_types_core::hash::Pedersen;
+
+    use super::*;
+    use crate::archive::read_index;
+    use crate::source::{BLOCK_DIR, STATE_UPDATE_DIR};
+
+    type TestBlock = GenericBlock<Header<u32, BlakeTwo256>, OpaqueExtrinsic>;
+
+    /// The blocks imported by the node, reverted by the block reverter of the test.
+    #[derive(Default)]
+    struct TestChain(Mutex<BTreeMap<u64, FieldElement>>);
+
+    impl ChainHandle for TestChain {
+        fn best_block_number(&self) -> u64 {
+            self.0.lock().unwrap().keys().last().copied().unwrap_or_default()
+        }
+
+        fn substrate_block_hash(&self, _block_number: u64) -> Option<H256> {
+            None
+        }
+
+        fn block_hash(&self, block_number: u64) -> Option<FieldElement> {
+            self.0.lock().unwrap().get(&block_number).copied()
+        }
+
+        fn state_update(&self, _block_number: u64) -> Option<L2StateUpdate> {
+            None
+        }
+
+        fn has_class(&self, _block_number: u64, _class_hash: ClassHash) -> bool {
+            false
+        }
+    }
+
+    /// A feeder gateway serving the given block hashes.
+    struct TestFeeder(BTreeMap<u64, FieldElement>);
+
+    #[async_trait]
+    impl BlockSource for TestFeeder {
+        async fn get_block(&self, block_id: BlockId) -> Result<SequencerBlock, String> {
+            let BlockId::Number(block_number) = block_id else {
+                return Err(format!("{block_id:?} is not served"));
+            };
+            let block_hash = self.0.get(&block_number).ok_or("block not found")?;
+            serde_json::from_value(serde_json::json!({
+                "block_hash": format!("{block_hash:#x}"),
+                "block_number": block_number,
+                "parent_block_hash": "0x0",
+                "timestamp": 0,
+                "sequencer_address": "0x0",
+                "state_root": "0x0",
+                "transaction_commitment": "0x0",
+                "event_commitment": "0x0",
+                "status": "ACCEPTED_ON_L2",
+                "eth_l1_gas_price": "0x0",
+                "strk_l1_gas_price": "0x0",
+                "transactions": [],
+                "transaction_receipts": [],
+                "starknet_version": "0.12.3",
+            }))
+            .map_err(|e| e.to_string())
+        }
+
+        async fn get_state_update(&self, _block_id: BlockId) -> Result<StateUpdate, String> {
+            Err("state updates are not served".to_string())
+        }
+
+        async fn get_class(&self, class_hash: FieldElement, _block_number: u64) -> Result<ContractClassCore, String> {
+            Err(format!("class {class_hash:#x} is not served"))
+        }
+    }
+
+    /// Inserts a leaf in the contract trie and commits it as `block_number`, returning the new
+    /// root.
+    fn commit_contract_trie(bonsai_dbs: &BonsaiDbs<TestBlock>, block_number: u64) -> Felt {
+        let mut trie =
+            BonsaiStorage::<_, _, Pedersen>::new(bonsai_dbs.contract.as_ref(), BonsaiStorageConfig::default()).unwrap();
+        let key = BitVec::<u8, Msb0>::from_vec(block_number.to_be_bytes().to_vec());
+        trie.insert(&key, &Felt::from(block_number + 1)).unwrap();
+        trie.commit(BasicId::new(block_number)).unwrap();
+        bonsai_dbs.commit_block(block_number).unwrap();
+
+        trie.root_hash().unwrap()
+    }
+
+    #[tokio::test]
+    async fn reorg_reverts_the_chain_and_the_tries_to_the_common_ancestor() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = temp_backend(root.path());
+        let bonsai_dbs = BonsaiDbs {
+            contract: Arc::clone(backend.bonsai_contract()),
+            class: Arc::clone(backend.bonsai_class()),
+            storage: Arc::clone(backend.bonsai_storage()),
+        };
+
+        // Blocks 0 to 5 are imported, and the feeder gateway reorged blocks 3 to 5
+        let chain = Arc::new(TestChain::default());
+        let mut roots = Vec::new();
+        for block_number in 0..=5 {
+            chain.0.lock().unwrap().insert(block_number, FieldElement::from(block_number));
+            roots.push(commit_contract_trie(&bonsai_dbs, block_number));
+        }
+        let feeder = TestFeeder(
+            (0..=5).map(|n| (n, if n < 3 { FieldElement::from(n) } else { FieldElement::from(100 + n) })).collect(),
+        );
+
+        let reverted_chain = Arc::clone(&chain);
+        let block_reverter: BlockReverter = Box::new(move |ancestor| {
+            reverted_chain.0.lock().unwrap().retain(|block_number, _| *block_number <= ancestor);
+            Ok(())
+        });
+        let mut reorgs = subscribe_reorgs();
+
+        let ancestor = handle_reorg(&feeder, chain.as_ref(), &block_reverter, &bonsai_dbs).await.unwrap();
+
+        assert_eq!(ancestor, 2);
+        assert_eq!(chain.best_block_number(), 2);
+        assert_eq!(bonsai_dbs.head().unwrap(), Some(2));
+        let trie =
+            BonsaiStorage::<_, _, Pedersen>::new(bonsai_dbs.contract.as_ref(), BonsaiStorageConfig::default()).unwrap();
+        assert_eq!(trie.root_hash().unwrap(), roots[2]);
+
+        let reorg = reorgs.recv().await.unwrap();
+        assert_eq!((reorg.starting_block_number, reorg.ending_block_number), (3, 5));
+        assert_eq!(
+            (reorg.starting_block_hash, reorg.ending_block_hash),
+            (FieldElement::from(3_u64), FieldElement::from(5_u64))
+        );
+
+        // Nothing is reverted once the chain matches the feeder gateway again
+        let ancestor = handle_reorg(&feeder, chain.as_ref(), &block_reverter, &bonsai_dbs).await.unwrap();
+        assert_eq!(ancestor, 2);
+    }
+
+    /// A feeder gateway serving a single class, along with the class the sequencer compiled it to.
+    struct TestClassSource(ContractClassCore, Option<CasmContractClass>);
+
+    #[async_trait]
+    impl BlockSource for TestClassSource {
+        async fn get_block(&self, block_id: BlockId) -> Result<SequencerBlock, String> {
+            Err(format!("{block_id:?} is not served"))
+        }
+
+        async fn get_state_update(&self, block_id: BlockId) -> Result<StateUpdate, String> {
+            Err(format!("{block_id:?} is not served"))
+        }
+
+        async fn get_class(&self, _class_hash: FieldElement, _block_number: u64) -> Result<ContractClassCore, String> {
+            Ok(self.0.clone())
+        }
+
+        async fn get_compiled_class(
+            &self,
+            class_hash: FieldElement,
+            _block_number: u64,
+        ) -> Result<CasmContractClass, String> {
+            self.1.clone().ok_or_else(|| format!("the compiled class of {class_hash:#x} is not served"))
+        }
+    }
+
+    #[tokio::test]
+    async fn class_compiled_by_the_sequencer_is_checked_and_cached() {
+        // The OpenZeppelin account of the genesis, along with the hashes it is declared with
+        let sierra: SierraClass = serde_json::from_slice(include_bytes!(
+            "../../../../configs/genesis-assets/OpenZeppelinAccountCairoOne.sierra.json"
+        ))
+        .unwrap();
+        let casm_class: CasmContractClass = serde_json::from_slice(include_bytes!(
+            "../../../../configs/genesis-assets/OpenZeppelinAccountCairoOne.casm.json"
+        ))
+        .unwrap();
+        let class = sierra.flatten().unwrap();
+        let class_hash =
+            FieldElement::from_hex_be("0x04c6d6cf894f8bc96bb9c525e6853e5483177841f7388f74a46cfda6f028c755").unwrap();
+        let compiled_class_hash =
+            FieldElement::from_hex_be("0x06910afff92798e29b93649f7627cd34a852e0f9b04b52c51850b438a730224e").unwrap();
+        assert_eq!(class.class_hash(), class_hash);
+
+        let db = tempfile::tempdir().unwrap();
+        let backend = temp_backend(db.path());
+        let casm_classes = Arc::clone(backend.casm_classes());
+        let source = Arc::new(TestClassSource(ContractClassCore::Sierra(class), Some(casm_class.clone())));
+
+        let declared = HashMap::from([(class_hash, compiled_class_hash)]);
+        let classes =
+            download_classes(vec![class_hash], &declared, 1, source, Arc::clone(&casm_classes)).await.unwrap();
+
+        assert_eq!(classes.len(), 1);
+        assert!(classes[0].sierra.is_some());
+        let class_hash_felt: StarkFelt = Felt252Wrapper::from(class_hash).into();
+        let compiled_class_hash_felt: StarkFelt = Felt252Wrapper::from(compiled_class_hash).into();
+        assert_eq!(casm_classes.get_compiled_class_hash(class_hash_felt).unwrap(), Some(compiled_class_hash_felt));
+        let cached = casm_classes.get_casm_class(compiled_class_hash_felt).unwrap().unwrap();
+        assert_eq!(serde_json::from_slice::<CasmContractClass>(&cached).unwrap(), casm_class);
+    }
+
+    #[tokio::test]
+    async fn class_not_matching_its_compiled_class_hash_is_rejected() {
+        let sierra: SierraClass = serde_json::from_slice(include_bytes!(
+            "../../../../configs/genesis-assets/OpenZeppelinAccountCairoOne.sierra.json"
+        ))
+        .unwrap();
+        let class = sierra.flatten().unwrap();
+        let class_hash = class.class_hash();
+
+        let db = tempfile::tempdir().unwrap();
+        let backend = temp_backend(db.path());
+        let casm_classes = Arc::clone(backend.casm_classes());
+        let source = Arc::new(TestClassSource(ContractClassCore::Sierra(class), None));
+
+        // Retrying does not fix a class which compiles to another class than the declared one
+        let declared = HashMap::from([(class_hash, FieldElement::ONE)]);
+        let result = download_classes(vec![class_hash], &declared, 1, source, Arc::clone(&casm_classes)).await;
+        match result {
+            Err(FetchError::Fatal(e)) => assert!(e.contains("compiled class hash mismatch"), "{e}"),
+            Err(e) => panic!("expected a fatal error, got {e:?}"),
+            Ok(_) => panic!("the class was accepted"),
+        }
+        assert!(casm_classes.get_compiled_class_hash(Felt252Wrapper::from(class_hash).into()).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn legacy_class_not_matching_its_hash_is_accepted() {
+        let class: LegacyContractClass =
+            serde_json::from_slice(include_bytes!("../../../../cairo-contracts/build/test.json")).unwrap();
+        let class = ContractClassCore::Legacy(class.compress().unwrap());
+
+        let db = tempfile::tempdir().unwrap();
+        let backend = temp_backend(db.path());
+        let source = Arc::new(TestClassSource(class, None));
+
+        // The mismatch is logged, as the node may hash some legacy classes differently
+        let classes =
+            download_classes(vec![FieldElement::ONE], &HashMap::new(), 1, source, Arc::clone(backend.casm_classes()))
+                .await
+                .unwrap();
+
+        assert_eq!(classes.len(), 1);
+        assert!(classes[0].sierra.is_none());
+    }
+
+    /// Opens a backend in a temporary directory.
+    fn temp_backend(root: &Path) -> Arc<mc_db::Backend<TestBlock>> {
+        let source = DatabaseSource::RocksDb { path: root.to_path_buf(), cache_size: 0 };
+        Arc::new(mc_db::Backend::open(&source, root, false).unwrap())
+    }
+
+    /// The hash of a block, as stored in its header by the sync.
+    fn feeder_block_hash(block: &mp_block::Block) -> FieldElement {
+        let mut bytes = [0u8; 32];
+        block.header().extra_data.expect("the sync stores the block hash").to_big_endian(&mut bytes);
+        FieldElement::from_bytes_be(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn recorded_blocks_are_replayed_by_the_sync() {
+        // Blocks 0 to 2 of a chain, recorded in `resources/archive`
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources").join("archive");
+        let fixture_index = read_index(&fixture).unwrap().unwrap();
+        let _mocks: Vec<_> = fixture_index
+            .iter()
+            .map(|(key, object)| {
+                let (dir, block_number) = key.split_once('/').unwrap();
+                let endpoint = match dir {
+                    BLOCK_DIR => "get_block",
+                    STATE_UPDATE_DIR => "get_state_update",
+                    dir => panic!("unexpected {dir} in the fixture archive"),
+                };
+                mockito::mock("GET", format!("/record-replay/feeder_gateway/{endpoint}").as_str())
+                    .match_query(mockito::Matcher::UrlEncoded("blockNumber".to_string(), block_number.to_string()))
+                    .with_body(std::fs::read(object).unwrap())
+                    .create()
+            })
+            .collect();
+
+        let recording = tempfile::tempdir().unwrap();
+        let feeder_gateway = Url::parse(&format!("{}/record-replay/feeder_gateway", mockito::server_url())).unwrap();
+        let recorder = RecordingSource::new(
+            FailoverSource::new(vec![FeederSource::new(feeder_gateway)]).unwrap(),
+            Archive::open(recording.path()).unwrap(),
+        );
+        for block_number in 0..=2 {
+            recorder.get_block(BlockId::Number(block_number)).await.unwrap();
+            recorder.get_state_update(BlockId::Number(block_number)).await.unwrap();
+        }
+        drop(recorder);
+
+        // Responses are recorded verbatim, so they are stored under the objects of the fixture
+        let recorded_index = read_index(recording.path()).unwrap().unwrap();
+        assert_eq!(recorded_index.len(), fixture_index.len());
+        for (key, object) in &fixture_index {
+            assert_eq!(recorded_index[key].file_name(), object.file_name(), "{key} was not recorded verbatim");
+        }
+
+        let db = tempfile::tempdir().unwrap();
+        let backend = temp_backend(db.path());
+        let chain = Arc::new(TestChain::default());
+        let (block_sender, mut blocks) = tokio::sync::mpsc::channel(16);
+        let (state_update_sender, _state_updates) = tokio::sync::mpsc::channel(16);
+        let (class_sender, _classes) = tokio::sync::mpsc::channel(16);
+        let (command_sink, mut commands) = futures::channel::mpsc::channel(16);
+        let sender_config = SenderConfig {
+            block_sender,
+            state_update_sender,
+            class_sender,
+            command_sink,
+            chain: Arc::clone(&chain) as Arc<dyn ChainHandle>,
+            block_reverter: Box::new(|_| Ok(())),
+        };
+        let config = FetchConfig {
+            chain_id: FieldElement::from_byte_slice_be(b"SN_MAIN").unwrap(),
+            replay: Some(recording.path().to_path_buf()),
+            ..Default::default()
+        };
+        let syncing = tokio::spawn(sync(sender_config, config, 0, Arc::clone(&backend), None));
+
+        // Blocks are sealed as the consensus engine would, which imports them in the chain
+        let mut parent_hash = FieldElement::ZERO;
+        for block_number in 0..=2 {
+            let block = blocks.recv().await.expect("the sync stopped");
+            let Some(EngineCommand::SealNewBlock { sender: Some(sender), .. }) = commands.next().await else {
+                panic!("the sync did not seal block {block_number}");
+            };
+
+            assert_eq!(block.header().block_number, block_number);
+            assert_eq!(FieldElement::from(Felt252Wrapper::from(block.header().parent_block_hash)), parent_hash);
+            let block_hash = feeder_block_hash(&block);
+            chain.0.lock().unwrap().insert(block_number, block_hash);
+            parent_hash = block_hash;
+
+            let created: CreatedBlock<H256> = serde_json::from_value(serde_json::json!({
+                "hash": H256::from_low_u64_be(block_number + 1),
+                "aux": {
+                    "header_only": false,
+                    "clear_justification_requests": false,
+                    "needs_justification": false,
+                    "bad_justification": false,
+                    "is_new_best": true,
+                },
+                "proof_size": 0,
+            }))
+            .unwrap();
+            let _ = sender.send(Ok(created));
+        }
+        syncing.abort();
+        let _ = syncing.await;
+
+        let fixture_block: SequencerBlock =
+            serde_json::from_slice(&std::fs::read(&fixture_index["block/2"]).unwrap()).unwrap();
+        assert_eq!(Some(parent_hash), fixture_block.block_hash);
+        assert_eq!(backend.bonsai_contract().head().unwrap(), Some(2));
+    }
+}
@@ -3,15 +3,24 @@
 
 // use std::sync::Arc;
 // use sp_runtime::traits::Block as BlockT;
-// use reqwest::Url;
 
+pub mod archive;
+pub mod chain;
 pub mod commitments;
+pub mod failover;
 pub mod l1;
 pub mod l2;
+pub mod metrics;
+pub mod source;
 pub mod types;
 pub mod utils;
 
-pub use l2::{FetchConfig, SenderConfig};
+pub use chain::{ChainHandle, ClientChainHandle};
+pub use failover::{FailoverSource, FeederSource, GatewayDisagreement};
+pub use l1::L1Config;
+pub use l2::{BlockReverter, FetchConfig, SenderConfig};
+pub use metrics::FetchMetrics;
+pub use source::{BlockSource, RecordingSource, ReplaySource};
 pub use utils::{convert, m, utility};
 
 type CommandSink = futures::channel::mpsc::Sender<sc_consensus_manual_seal::rpc::EngineCommand<sp_core::H256>>;
@@ -19,23 +28,33 @@ type CommandSink = futures::channel::mpsc::Sender<sc_consensus_manual_seal::rpc:
 pub mod starknet_sync_worker {
     use std::sync::Arc;
 
-    use reqwest::Url;
     use sp_runtime::traits::Block as BlockT;
 
     use super::*;
 
+    /// Syncs the chain from the feeder gateway along with the states posted on L1.
+    ///
+    /// Returns once the L2 sync stopped, so that the essential task running it fails instead of
+    /// leaving the node serving a chain which is no longer synced.
     pub async fn sync<B: BlockT>(
         fetch_config: FetchConfig,
         sender_config: SenderConfig,
-        rpc_port: u16,
-        l1_url: Url,
+        l1_config: L1Config,
         backend: Arc<mc_db::Backend<B>>,
+        fetch_metrics: Option<FetchMetrics>,
     ) {
-        let first_block = utility::get_last_synced_block(rpc_port).await + 1;
-
-        let _ = tokio::join!(
-            l1::sync(l1_url.clone()),
-            l2::sync(sender_config, fetch_config.clone(), first_block, rpc_port, backend.clone())
-        );
+        let first_block = sender_config.chain.best_block_number() + 1;
+
+        let l1_and_pending =
+            async { tokio::join!(l1::sync(l1_config, backend.clone()), l2::sync_pending_block(fetch_config.clone())) };
+        tokio::select! {
+            result = l2::sync(sender_config, fetch_config.clone(), first_block, backend.clone(), fetch_metrics) => {
+                match result {
+                    Ok(()) => log::error!("The L2 sync stopped"),
+                    Err(e) => log::error!("The L2 sync stopped: {e}"),
+                }
+            }
+            _ = l1_and_pending => {}
+        }
     }
 }
@@ -0,0 +1,45 @@
+//! Prometheus metrics of the L2 fetch pipeline.
+use prometheus_endpoint::{
+    exponential_buckets, register, CounterVec, HistogramOpts, HistogramVec, Opts, PrometheusError, Registry, U64,
+};
+
+/// Metrics of the workers fetching blocks ahead of their import, labeled by worker.
+#[derive(Clone)]
+pub struct FetchMetrics {
+    /// Number of blocks fully fetched.
+    pub fetched_blocks: CounterVec<U64>,
+    /// Number of failed requests, each followed by a backoff.
+    pub fetch_errors: CounterVec<U64>,
+    /// Time taken to fetch a block, its state update and its declared classes.
+    pub fetch_duration: HistogramVec,
+}
+
+impl FetchMetrics {
+    /// Registers the metrics in the node registry.
+    pub fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            fetched_blocks: register(
+                CounterVec::new(
+                    Opts::new("deoxys_l2_fetched_blocks", "Number of blocks fetched from the feeder gateway"),
+                    &["worker"],
+                )?,
+                registry,
+            )?,
+            fetch_errors: register(
+                CounterVec::new(
+                    Opts::new("deoxys_l2_fetch_errors", "Number of failed requests to the feeder gateway"),
+                    &["worker"],
+                )?,
+                registry,
+            )?,
+            fetch_duration: register(
+                HistogramVec::new(
+                    HistogramOpts::new("deoxys_l2_fetch_duration_seconds", "Time taken to fetch a block")
+                        .buckets(exponential_buckets(0.1, 2.0, 10)?),
+                    &["worker"],
+                )?,
+                registry,
+            )?,
+        })
+    }
+}
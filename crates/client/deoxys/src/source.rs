@@ -0,0 +1,348 @@
+//! Sources of the blocks, state updates and classes synced by [`crate::l2::sync`].
+//!
+//! Blocks are fetched from the feeder gateways by default. A [`ReplaySource`] serves them from a
+//! local recording instead, which allows deterministic syncs without any network access. Such
+//! recordings are made with a [`RecordingSource`].
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use cairo_lang_casm_contract_class::CasmContractClass;
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
+use starknet_core::types::{BlockId as BlockIdCore, ContractClass, FieldElement};
+use starknet_providers::sequencer::models::{Block, BlockId, DeployedClass, StateUpdate};
+use starknet_providers::{Provider, SequencerGatewayProvider};
+use tempfile::TempDir;
+
+use crate::archive::{read_index, Archive, INDEX};
+use crate::commitments::verify::{GatewayBlock, PoseidonHeaderFields};
+use crate::failover::FailoverSource;
+
+/// Directory of the recorded blocks, named `<block_number>.json`.
+pub const BLOCK_DIR: &str = "block";
+/// Directory of the recorded state updates, named `<block_number>.json`.
+pub const STATE_UPDATE_DIR: &str = "state_update";
+/// Directory of the recorded classes, named `<class_hash>.json` with the hash in `0x` hex.
+pub const CLASS_DIR: &str = "class";
+/// Directory of the recorded compiled classes, named `<class_hash>.json` with the hash of the
+/// Sierra class in `0x` hex.
+pub const COMPILED_CLASS_DIR: &str = "compiled_class";
+
+/// Provides the data needed to sync a block.
+#[async_trait]
+pub trait BlockSource: Send + Sync {
+    /// Returns the block with the given id, as served by the `get_block` feeder endpoint.
+    async fn get_block(&self, block_id: BlockId) -> Result<Block, String>;
+
+    /// Returns the block with the given id along with the header fields its model lacks, which the
+    /// Poseidon hash of the blocks produced since Starknet 0.13.2 commits to.
+    ///
+    /// Sources which cannot serve those fields leave them out.
+    async fn get_gateway_block(&self, block_id: BlockId) -> Result<GatewayBlock, String> {
+        let block = self.get_block(block_id).await?;
+        Ok(GatewayBlock { block, header_fields: PoseidonHeaderFields::default() })
+    }
+
+    /// Returns the state update of the block with the given id, as served by the
+    /// `get_state_update` feeder endpoint.
+    async fn get_state_update(&self, block_id: BlockId) -> Result<StateUpdate, String>;
+
+    /// Returns the definition of a class, as known at the given block.
+    async fn get_class(&self, class_hash: FieldElement, block_number: u64) -> Result<ContractClass, String>;
+
+    /// Returns the CASM class the sequencer compiled a Sierra class to, as served by the
+    /// `get_compiled_class_by_class_hash` feeder endpoint.
+    ///
+    /// Sources which cannot serve compiled classes return an error, and the class is then compiled
+    /// by the node.
+    async fn get_compiled_class(
+        &self,
+        class_hash: FieldElement,
+        _block_number: u64,
+    ) -> Result<CasmContractClass, String> {
+        Err(format!("the compiled class of {class_hash:#x} is not served by this source"))
+    }
+}
+
+#[async_trait]
+impl BlockSource for SequencerGatewayProvider {
+    async fn get_block(&self, block_id: BlockId) -> Result<Block, String> {
+        SequencerGatewayProvider::get_block(self, block_id).await.map_err(|e| format!("failed to get block: {e}"))
+    }
+
+    async fn get_state_update(&self, block_id: BlockId) -> Result<StateUpdate, String> {
+        SequencerGatewayProvider::get_state_update(self, block_id)
+            .await
+            .map_err(|e| format!("failed to get state update: {e}"))
+    }
+
+    async fn get_class(&self, class_hash: FieldElement, block_number: u64) -> Result<ContractClass, String> {
+        Provider::get_class(self, BlockIdCore::Number(block_number), class_hash)
+            .await
+            .map_err(|e| format!("failed to get class {class_hash:#x}: {e}"))
+    }
+}
+
+/// Fetches from the feeder gateways and records every response in an [`Archive`], verbatim.
+pub struct RecordingSource {
+    source: FailoverSource,
+    archive: Archive,
+}
+
+impl RecordingSource {
+    pub fn new(source: FailoverSource, archive: Archive) -> Self {
+        Self { source, archive }
+    }
+}
+
+/// Checks that a block can be recorded: recordings are keyed by block number.
+fn check_recordable(block_id: BlockId) -> Result<(), String> {
+    match block_id {
+        BlockId::Number(_) | BlockId::Latest => Ok(()),
+        block_id => Err(format!("{block_id:?} can not be recorded")),
+    }
+}
+
+#[async_trait]
+impl BlockSource for RecordingSource {
+    async fn get_block(&self, block_id: BlockId) -> Result<Block, String> {
+        Ok(self.get_gateway_block(block_id).await?.block)
+    }
+
+    async fn get_gateway_block(&self, block_id: BlockId) -> Result<GatewayBlock, String> {
+        check_recordable(block_id)?;
+        let block = self.source.fetch_block(block_id).await?;
+
+        let block_number = block.value.block.block_number.ok_or("block number not found")?;
+        self.archive.record(&format!("{BLOCK_DIR}/{block_number}"), &block.raw)?;
+
+        Ok(block.value)
+    }
+
+    async fn get_state_update(&self, block_id: BlockId) -> Result<StateUpdate, String> {
+        let BlockId::Number(block_number) = block_id else {
+            return Err(format!("{block_id:?} can not be recorded"));
+        };
+        let state_update = self.source.fetch_state_update(block_id).await?;
+
+        self.archive.record(&format!("{STATE_UPDATE_DIR}/{block_number}"), &state_update.raw)?;
+
+        Ok(state_update.value)
+    }
+
+    async fn get_class(&self, class_hash: FieldElement, block_number: u64) -> Result<ContractClass, String> {
+        let class = self.source.fetch_class(class_hash, block_number).await?;
+
+        self.archive.record(&format!("{CLASS_DIR}/{class_hash:#x}"), &class.raw)?;
+
+        ContractClass::try_from(class.value).map_err(|_| format!("failed to convert class {class_hash:#x}"))
+    }
+
+    async fn get_compiled_class(
+        &self,
+        class_hash: FieldElement,
+        block_number: u64,
+    ) -> Result<CasmContractClass, String> {
+        let compiled_class = self.source.fetch_compiled_class(class_hash, block_number).await?;
+
+        self.archive.record(&format!("{COMPILED_CLASS_DIR}/{class_hash:#x}"), &compiled_class.raw)?;
+
+        Ok(compiled_class.value)
+    }
+}
+
+/// Serves the JSON responses of the feeder gateway recorded in a local directory or tarball.
+///
+/// The directory is either an [`Archive`] written by a [`RecordingSource`], or holds the
+/// responses of `get_block` and `get_state_update` in [`BLOCK_DIR`] and [`STATE_UPDATE_DIR`], those
+/// of `get_class_by_hash` in [`CLASS_DIR`] and those of `get_compiled_class_by_class_hash`, if any,
+/// in [`COMPILED_CLASS_DIR`]. Only blocks requested by number, or the latest recorded one, can be
+/// served.
+#[derive(Debug, Clone)]
+pub struct ReplaySource {
+    root: PathBuf,
+    /// The index of the archive, if the recording is one.
+    index: Option<HashMap<String, PathBuf>>,
+    /// The directory a tarball was unpacked in, removed once the last clone of the source is
+    /// dropped.
+    _unpacked: Option<Arc<TempDir>>,
+}
+
+impl ReplaySource {
+    /// Opens a recording.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A directory, or a `.tar`, `.tar.gz` or `.tgz` archive which is unpacked in a
+    ///   temporary directory, removed along with the source
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if path.is_dir() {
+            return Self::open_dir(path.to_path_buf(), None);
+        }
+
+        let file = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+        let unpacked = tempfile::Builder::new()
+            .prefix("deoxys-replay-")
+            .tempdir()
+            .map_err(|e| format!("failed to create a directory to unpack {}: {e}", path.display()))?;
+        let target = unpacked.path().to_path_buf();
+        let name = path.to_string_lossy();
+        let unpacked_archive = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            tar::Archive::new(GzDecoder::new(file)).unpack(&target)
+        } else {
+            tar::Archive::new(file).unpack(&target)
+        };
+        unpacked_archive.map_err(|e| format!("failed to unpack {}: {e}", path.display()))?;
+
+        // Archives usually wrap the recording in a single top level directory
+        let mut entries = std::fs::read_dir(&target)
+            .map_err(|e| format!("failed to read {}: {e}", target.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>();
+        let root = match (target.join(BLOCK_DIR).is_dir() || target.join(INDEX).is_file(), entries.len()) {
+            (false, 1) if entries[0].is_dir() => entries.remove(0),
+            _ => target,
+        };
+
+        Self::open_dir(root, Some(Arc::new(unpacked)))
+    }
+
+    fn open_dir(root: PathBuf, unpacked: Option<Arc<TempDir>>) -> Result<Self, String> {
+        let index = read_index(&root)?;
+        Ok(Self { root, index, _unpacked: unpacked })
+    }
+
+    /// Path of the recorded response with the given key, such as `block/12`.
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        match &self.index {
+            Some(index) => match index.get(key) {
+                Some(path) if path.is_file() => Ok(path.clone()),
+                Some(path) => Err(format!("{key} is recorded but its object {} is missing", path.display())),
+                None => Err(format!("{key} is not recorded")),
+            },
+            None => Ok(self.root.join(format!("{key}.json"))),
+        }
+    }
+
+    /// Number of the last block in the recording.
+    fn latest_block_number(&self) -> Result<u64, String> {
+        match &self.index {
+            Some(index) => index
+                .keys()
+                .filter_map(|key| key.strip_prefix(BLOCK_DIR)?.strip_prefix('/')?.parse::<u64>().ok())
+                .max()
+                .ok_or_else(|| format!("no block recorded in {}", self.root.display())),
+            None => latest_block_number(&self.root.join(BLOCK_DIR)),
+        }
+    }
+
+    fn block_number(&self, block_id: BlockId) -> Result<u64, String> {
+        match block_id {
+            BlockId::Number(block_number) => Ok(block_number),
+            BlockId::Latest => self.latest_block_number(),
+            block_id => Err(format!("{block_id:?} is not available in a replay")),
+        }
+    }
+}
+
+#[async_trait]
+impl BlockSource for ReplaySource {
+    async fn get_block(&self, block_id: BlockId) -> Result<Block, String> {
+        Ok(self.get_gateway_block(block_id).await?.block)
+    }
+
+    async fn get_gateway_block(&self, block_id: BlockId) -> Result<GatewayBlock, String> {
+        let block_number = self.block_number(block_id)?;
+        read_json(&self.path(&format!("{BLOCK_DIR}/{block_number}"))?).await
+    }
+
+    async fn get_state_update(&self, block_id: BlockId) -> Result<StateUpdate, String> {
+        let block_number = self.block_number(block_id)?;
+        read_json(&self.path(&format!("{STATE_UPDATE_DIR}/{block_number}"))?).await
+    }
+
+    async fn get_class(&self, class_hash: FieldElement, _block_number: u64) -> Result<ContractClass, String> {
+        let class: DeployedClass = read_json(&self.path(&format!("{CLASS_DIR}/{class_hash:#x}"))?).await?;
+        ContractClass::try_from(class).map_err(|_| format!("failed to convert class {class_hash:#x}"))
+    }
+
+    async fn get_compiled_class(
+        &self,
+        class_hash: FieldElement,
+        _block_number: u64,
+    ) -> Result<CasmContractClass, String> {
+        read_json(&self.path(&format!("{COMPILED_CLASS_DIR}/{class_hash:#x}"))?).await
+    }
+}
+
+async fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let raw = tokio::fs::read(path).await.map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    serde_json::from_slice(&raw).map_err(|e| format!("failed to parse {}: {e}", path.display()))
+}
+
+fn latest_block_number(dir: &Path) -> Result<u64, String> {
+    std::fs::read_dir(dir)
+        .map_err(|e| format!("failed to read {}: {e}", dir.display()))?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".json")?.parse::<u64>().ok())
+        .max()
+        .ok_or_else(|| format!("no block recorded in {}", dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::OBJECTS_DIR;
+
+    #[test]
+    fn latest_block_number_ignores_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        for name in ["0.json", "2.json", "10.json", "index.json", "11.json.tmp"] {
+            std::fs::write(dir.join(name), b"{}").unwrap();
+        }
+
+        assert_eq!(latest_block_number(dir), Ok(10));
+    }
+
+    #[tokio::test]
+    async fn missing_records_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let archive = Archive::open(root).unwrap();
+        archive.record(&format!("{BLOCK_DIR}/1"), b"{}").unwrap();
+        archive.record(&format!("{STATE_UPDATE_DIR}/1"), b"{}").unwrap();
+        std::fs::remove_dir_all(root.join(OBJECTS_DIR)).unwrap();
+
+        let replay = ReplaySource::open(root).unwrap();
+        let error = replay.get_block(BlockId::Number(1)).await.unwrap_err();
+        assert!(error.contains("block/1 is recorded but its object"), "{error}");
+        let error = replay.get_state_update(BlockId::Number(2)).await.unwrap_err();
+        assert_eq!(error, "state_update/2 is not recorded");
+    }
+    #[test]
+    fn unpacked_tarballs_are_removed_with_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = dir.path().join("recording");
+        std::fs::create_dir_all(recording.join(BLOCK_DIR)).unwrap();
+        std::fs::write(recording.join(BLOCK_DIR).join("3.json"), b"{}").unwrap();
+        let tarball = dir.path().join("recording.tar");
+        let mut builder = tar::Builder::new(File::create(&tarball).unwrap());
+        builder.append_dir_all("recording", &recording).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let replay = ReplaySource::open(&tarball).unwrap();
+        assert_eq!(replay.latest_block_number(), Ok(3));
+        let unpacked = replay.root.clone();
+        assert!(unpacked.is_dir());
+
+        let clone = replay.clone();
+        drop(replay);
+        assert!(unpacked.is_dir());
+        drop(clone);
+        assert!(!unpacked.exists());
+    }
+}
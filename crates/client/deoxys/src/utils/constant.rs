@@ -8,4 +8,10 @@ pub mod starknet_core_address {
     pub const SEPOLIA_INTEGRATION: &str = "0x4737c0c1B4D5b1A687B42610DdabEE781152359c";
 }
 
+pub mod l1_chain_id {
+    pub const MAINNET: u64 = 1;
+    pub const GOERLI: u64 = 5;
+    pub const SEPOLIA: u64 = 11155111;
+}
+
 pub const LOG_STATE_UPDTATE_TOPIC: &str = "0xd342ddf7a308dec111745b00315c14b7efb2bdae570a6856e088ed0c65a3576c";
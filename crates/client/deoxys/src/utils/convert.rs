@@ -1,14 +1,29 @@
 //! Converts types from [`starknet_providers`] to madara's expected types.
 
+use mc_db::{ReceiptEvent, ReceiptExecutionResources, ReceiptMessageToL1, StoredReceipt};
 use mp_fee::ResourcePrice;
 use mp_felt::Felt252Wrapper;
 use starknet_api::hash::StarkFelt;
+use starknet_core::types as core;
 use starknet_ff::FieldElement;
 use starknet_providers::sequencer::models as p;
 
 use crate::commitments::lib::calculate_commitments;
+use crate::l2::PendingBlock;
 
-pub async fn block(block: p::Block) -> mp_block::Block {
+pub async fn block(block: p::Block, chain_id: FieldElement) -> mp_block::Block {
+    block_with_receipts(block, chain_id).await.0
+}
+
+/// Converts a block along with the receipts of its transactions, which are stored by the sync
+/// rather than in the block.
+///
+/// The transaction commitment is computed from the hashes of the transactions on the chain with
+/// the given id.
+pub async fn block_with_receipts(
+    block: p::Block,
+    chain_id: FieldElement,
+) -> (mp_block::Block, Vec<(StarkFelt, StoredReceipt)>) {
     // converts starknet_provider transactions and events to mp_transactions and starknet_api events
     let transactions = transactions(block.transactions);
     let events = events(&block.transaction_receipts);
@@ -21,7 +36,8 @@ pub async fn block(block: p::Block) -> mp_block::Block {
     let transaction_count = transactions.len() as u128;
     let event_count = events.len() as u128;
 
-    let (transaction_commitment, event_commitment) = commitments(&transactions, &events, block_number).await;
+    let (transaction_commitment, event_commitment) =
+        commitments(&transactions, &events, chain_id.into(), block_number).await;
 
     let protocol_version = starknet_version(&block.starknet_version);
     let l1_gas_price = resource_price(block.eth_l1_gas_price);
@@ -50,7 +66,190 @@ pub async fn block(block: p::Block) -> mp_block::Block {
         .map(|(i, r)| mp_block::OrderedEvents::new(i as u128, r.events.iter().map(event).collect()))
         .collect();
 
-    mp_block::Block::new(header, transactions, ordered_events)
+    let receipts = block.transaction_receipts.into_iter().map(receipt).collect();
+
+    (mp_block::Block::new(header, transactions, ordered_events), receipts)
+}
+
+fn receipt(receipt: p::ConfirmedTransactionReceipt) -> (StarkFelt, StoredReceipt) {
+    let events = receipt
+        .events
+        .into_iter()
+        .map(|event| ReceiptEvent {
+            from_address: felt(event.from_address),
+            keys: event.keys.into_iter().map(felt).collect(),
+            data: event.data.into_iter().map(felt).collect(),
+        })
+        .collect();
+    let messages_sent = receipt
+        .l2_to_l1_messages
+        .into_iter()
+        .map(|message| {
+            let message = core::MsgToL1::from(message);
+            ReceiptMessageToL1 {
+                from_address: felt(message.from_address),
+                to_address: felt(message.to_address),
+                payload: message.payload.into_iter().map(felt).collect(),
+            }
+        })
+        .collect();
+    let revert_reason = match receipt.execution_status {
+        Some(p::TransactionExecutionStatus::Reverted) => Some(receipt.revert_error.unwrap_or_default()),
+        _ => None,
+    };
+    let resources = execution_resources(receipt.execution_resources);
+    let execution_resources = ReceiptExecutionResources {
+        steps: resources.steps,
+        memory_holes: resources.memory_holes,
+        range_check_builtin_applications: resources.range_check_builtin_applications,
+        pedersen_builtin_applications: resources.pedersen_builtin_applications,
+        poseidon_builtin_applications: resources.poseidon_builtin_applications,
+        ec_op_builtin_applications: resources.ec_op_builtin_applications,
+        ecdsa_builtin_applications: resources.ecdsa_builtin_applications,
+        bitwise_builtin_applications: resources.bitwise_builtin_applications,
+        keccak_builtin_applications: resources.keccak_builtin_applications,
+    };
+
+    let stored_receipt = StoredReceipt {
+        actual_fee: felt(receipt.actual_fee),
+        events,
+        messages_sent,
+        revert_reason,
+        execution_resources,
+    };
+
+    (felt(receipt.transaction_hash), stored_receipt)
+}
+
+/// Converts the pending block of the feeder gateway and its state update to their rpc
+/// representation.
+pub fn pending_block(mut block: p::Block, state_update: p::StateUpdate) -> Result<PendingBlock, String> {
+    let receipts = std::mem::take(&mut block.transaction_receipts);
+    let receipts = block
+        .transactions
+        .iter()
+        .zip(receipts)
+        .filter_map(|(transaction, receipt)| {
+            let transaction_hash = receipt.transaction_hash;
+            pending_receipt(transaction, receipt).map(|receipt| (transaction_hash, receipt))
+        })
+        .collect();
+
+    let block = match core::MaybePendingBlockWithTxs::try_from(block) {
+        Ok(core::MaybePendingBlockWithTxs::PendingBlock(block)) => block,
+        Ok(core::MaybePendingBlockWithTxs::Block(_)) => return Err("the pending block is already accepted".into()),
+        Err(_) => return Err("failed to convert the pending block".into()),
+    };
+    let state_update = match core::MaybePendingStateUpdate::try_from(state_update) {
+        Ok(core::MaybePendingStateUpdate::PendingUpdate(state_update)) => state_update,
+        Ok(core::MaybePendingStateUpdate::Update(_)) => {
+            return Err("the pending state update is already accepted".into());
+        }
+        Err(_) => return Err("failed to convert the pending state update".into()),
+    };
+
+    Ok(PendingBlock { block, state_update, receipts })
+}
+
+/// Builds the receipt of a pending transaction from the receipt served by the feeder gateway.
+fn pending_receipt(
+    transaction: &p::TransactionType,
+    receipt: p::ConfirmedTransactionReceipt,
+) -> Option<core::PendingTransactionReceipt> {
+    let transaction_hash = receipt.transaction_hash;
+    let actual_fee = receipt.actual_fee;
+    let messages_sent = receipt.l2_to_l1_messages.into_iter().map(Into::into).collect();
+    let events = receipt
+        .events
+        .into_iter()
+        .map(|event| core::Event { from_address: event.from_address, keys: event.keys, data: event.data })
+        .collect();
+    let execution_result = match receipt.execution_status {
+        Some(p::TransactionExecutionStatus::Reverted) => {
+            core::ExecutionResult::Reverted { reason: receipt.revert_error.unwrap_or_default() }
+        }
+        _ => core::ExecutionResult::Succeeded,
+    };
+    let execution_resources = execution_resources(receipt.execution_resources);
+
+    let receipt = match transaction {
+        p::TransactionType::InvokeFunction(_) => {
+            core::PendingTransactionReceipt::Invoke(core::PendingInvokeTransactionReceipt {
+                transaction_hash,
+                actual_fee,
+                messages_sent,
+                events,
+                execution_result,
+                execution_resources,
+            })
+        }
+        p::TransactionType::Declare(_) => {
+            core::PendingTransactionReceipt::Declare(core::PendingDeclareTransactionReceipt {
+                transaction_hash,
+                actual_fee,
+                messages_sent,
+                events,
+                execution_result,
+                execution_resources,
+            })
+        }
+        p::TransactionType::DeployAccount(tx) => {
+            core::PendingTransactionReceipt::DeployAccount(core::PendingDeployAccountTransactionReceipt {
+                transaction_hash,
+                actual_fee,
+                messages_sent,
+                events,
+                contract_address: tx.contract_address,
+                execution_result,
+                execution_resources,
+            })
+        }
+        p::TransactionType::L1Handler(_) => {
+            core::PendingTransactionReceipt::L1Handler(core::PendingL1HandlerTransactionReceipt {
+                // TODO(#1291): compute message hash correctly to L1HandlerTransactionReceipt
+                message_hash: core::Hash256::from_felt(&FieldElement::ZERO),
+                transaction_hash,
+                actual_fee,
+                messages_sent,
+                events,
+                execution_result,
+                execution_resources,
+            })
+        }
+        // Deploy transactions are no longer accepted by the sequencer
+        p::TransactionType::Deploy(_) => return None,
+    };
+
+    Some(receipt)
+}
+
+fn execution_resources(resources: Option<p::ExecutionResources>) -> core::ExecutionResources {
+    let Some(resources) = resources else {
+        return core::ExecutionResources {
+            steps: 0,
+            memory_holes: None,
+            range_check_builtin_applications: 0,
+            pedersen_builtin_applications: 0,
+            poseidon_builtin_applications: 0,
+            ec_op_builtin_applications: 0,
+            ecdsa_builtin_applications: 0,
+            bitwise_builtin_applications: 0,
+            keccak_builtin_applications: 0,
+        };
+    };
+    let builtins = resources.builtin_instance_counter;
+
+    core::ExecutionResources {
+        steps: resources.n_steps,
+        memory_holes: Some(resources.n_memory_holes),
+        range_check_builtin_applications: builtins.range_check_builtin.unwrap_or_default(),
+        pedersen_builtin_applications: builtins.pedersen_builtin.unwrap_or_default(),
+        poseidon_builtin_applications: builtins.poseidon_builtin.unwrap_or_default(),
+        ec_op_builtin_applications: builtins.ec_op_builtin.unwrap_or_default(),
+        ecdsa_builtin_applications: builtins.ecdsa_builtin.unwrap_or_default(),
+        bitwise_builtin_applications: builtins.bitwise_builtin.unwrap_or_default(),
+        keccak_builtin_applications: builtins.keccak_builtin.unwrap_or_default(),
+    }
 }
 
 fn transactions(txs: Vec<p::TransactionType>) -> Vec<mp_transactions::Transaction> {
@@ -200,19 +399,14 @@ fn event(event: &p::Event) -> starknet_api::transaction::Event {
 async fn commitments(
     transactions: &[mp_transactions::Transaction],
     events: &[starknet_api::transaction::Event],
+    chain_id: Felt252Wrapper,
     block_number: u64,
 ) -> (StarkFelt, StarkFelt) {
-    let chain_id = chain_id();
-
     let (commitment_tx, commitment_event) = calculate_commitments(transactions, events, chain_id, block_number).await;
 
     (commitment_tx.into(), commitment_event.into())
 }
 
-fn chain_id() -> mp_felt::Felt252Wrapper {
-    starknet_ff::FieldElement::from_byte_slice_be(b"SN_MAIN").unwrap().into()
-}
-
 fn felt(field_element: starknet_ff::FieldElement) -> starknet_api::hash::StarkFelt {
     starknet_api::hash::StarkFelt::new(field_element.to_bytes_be()).unwrap()
 }
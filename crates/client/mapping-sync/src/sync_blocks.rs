@@ -1,3 +1,4 @@
+use lazy_static::lazy_static;
 use mc_rpc_core::utils::get_block_by_block_hash;
 use mp_digest_log::{find_starknet_block, FindLogError};
 use mp_hashers::HasherT;
@@ -6,7 +7,27 @@ use pallet_starknet_runtime_api::StarknetRuntimeApi;
 use sc_client_api::backend::{Backend, StorageProvider};
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::{Backend as _, HeaderBackend};
-use sp_runtime::traits::{Block as BlockT, Header as HeaderT, Zero};
+use sp_runtime::traits::{Block as BlockT, Header as HeaderT, UniqueSaturatedInto, Zero};
+use tokio::sync::broadcast;
+
+/// A change of the blocks mapped in the Madara db.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingEvent {
+    /// The mapping of the block with this number has been written
+    Mapped(u64),
+    /// The mapping of every block above this number has been reverted
+    Reverted(u64),
+}
+
+lazy_static! {
+    /// Changes of the mapping db, broadcast to the RPC subscribers
+    static ref MAPPING_EVENTS: broadcast::Sender<MappingEvent> = broadcast::channel(64).0;
+}
+
+/// Subscribes to the changes of the mapping db made by the `MappingSyncWorker`.
+pub fn subscribe_mapping_events() -> broadcast::Receiver<MappingEvent> {
+    MAPPING_EVENTS.subscribe()
+}
 
 fn sync_block<B: BlockT, C, BE, H>(client: &C, backend: &mc_db::Backend<B>, header: &B::Header) -> anyhow::Result<()>
 where
@@ -36,25 +57,15 @@ where
                              db state ({storage_starknet_block_hash:?})"
                         ))
                     } else {
-                        let chain_id = client.runtime_api().chain_id(substrate_block_hash)?;
-
                         // Success, we write the Starknet to Substate hashes mapping to db
-                        let mapping_commitment = mc_db::MappingCommitment {
-                            block_hash: substrate_block_hash,
-                            starknet_block_hash: digest_starknet_block_hash.into(),
-                            starknet_transaction_hashes: digest_starknet_block
-                                .transactions()
-                                .iter()
-                                .map(|tx| {
-                                    tx.compute_hash::<H>(
-                                        chain_id,
-                                        false,
-                                        Some(digest_starknet_block.header().block_number),
-                                    )
-                                    .into()
-                                })
-                                .collect(),
-                        };
+                        let mapping_commitment =
+                            mapping_commitment::<_, _, H>(client, substrate_block_hash, &digest_starknet_block)?;
+
+                        // The events are indexed first so that a mapped block is always indexed
+                        backend.events().write_block(
+                            digest_starknet_block.header().block_number,
+                            &indexed_events(&digest_starknet_block, &mapping_commitment),
+                        )?;
 
                         backend.mapping().write_hashes(mapping_commitment).map_err(|e| anyhow::anyhow!(e))
                     }
@@ -69,6 +80,54 @@ where
     }
 }
 
+/// Computes the Starknet block and transaction hashes stored in the mapping db for a Substrate
+/// block wrapping `starknet_block`.
+fn mapping_commitment<B: BlockT, C, H>(
+    client: &C,
+    substrate_block_hash: B::Hash,
+    starknet_block: &mp_block::Block,
+) -> anyhow::Result<mc_db::MappingCommitment<B>>
+where
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B>,
+    H: HasherT,
+{
+    let chain_id = client.runtime_api().chain_id(substrate_block_hash)?;
+    let block_number = starknet_block.header().block_number;
+
+    Ok(mc_db::MappingCommitment {
+        block_hash: substrate_block_hash,
+        starknet_block_hash: starknet_block.header().hash::<H>().into(),
+        starknet_transaction_hashes: starknet_block
+            .transactions()
+            .iter()
+            .map(|tx| tx.compute_hash::<H>(chain_id, false, Some(block_number)).into())
+            .collect(),
+    })
+}
+
+/// Lists the events of `starknet_block` in the order they are served by the rpc, along with the
+/// hash of the transaction which emitted them.
+fn indexed_events<B: BlockT>(
+    starknet_block: &mp_block::Block,
+    mapping_commitment: &mc_db::MappingCommitment<B>,
+) -> Vec<mc_db::IndexedEvent> {
+    starknet_block
+        .events()
+        .iter()
+        .flat_map(|ordered_events| {
+            let transaction_hash = mapping_commitment.starknet_transaction_hashes[ordered_events.index() as usize];
+            ordered_events.events().iter().map(move |event| mc_db::IndexedEvent {
+                block_hash: mapping_commitment.starknet_block_hash,
+                transaction_hash,
+                from_address: *event.from_address.0.key(),
+                keys: event.content.keys.iter().map(|key| key.0).collect(),
+                data: event.content.data.0.clone(),
+            })
+        })
+        .collect()
+}
+
 fn sync_genesis_block<B: BlockT, C, H>(
     _client: &C,
     backend: &mc_db::Backend<B>,
@@ -144,14 +203,17 @@ where
         sync_genesis_block::<_, _, H>(client, madara_backend, &operating_header)?;
 
         madara_backend.meta().write_current_syncing_tips(current_syncing_tips)?;
-        Ok(true)
     } else {
         sync_block::<_, _, _, H>(client, madara_backend, &operating_header)?;
 
         current_syncing_tips.push(*operating_header.parent_hash());
         madara_backend.meta().write_current_syncing_tips(current_syncing_tips)?;
-        Ok(true)
     }
+
+    // Nobody listening is not an error
+    let _ = MAPPING_EVENTS.send(MappingEvent::Mapped((*operating_header.number()).unique_saturated_into()));
+
+    Ok(true)
 }
 
 pub fn sync_blocks<B: BlockT, C, BE, H>(
@@ -177,6 +239,70 @@ where
     Ok(synced_any)
 }
 
+/// Reverts the best chain down to `ancestor`, which becomes the new best block.
+///
+/// The mapping of every reverted block is removed from the Madara db before the blocks themselves
+/// are reverted in the Substrate backend, finalized blocks included.
+///
+/// # Arguments
+///
+/// * `client` - The Substrate client
+/// * `substrate_backend` - The Substrate backend holding the blocks to revert
+/// * `madara_backend` - The Madara backend holding the mapping db
+/// * `ancestor` - The number of the last block to keep
+pub fn revert_blocks<B: BlockT, C, BE, H>(
+    client: &C,
+    substrate_backend: &BE,
+    madara_backend: &mc_db::Backend<B>,
+    ancestor: <B::Header as HeaderT>::Number,
+) -> anyhow::Result<()>
+where
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B>,
+    C: HeaderBackend<B>,
+    BE: Backend<B>,
+    H: HasherT,
+{
+    let info = client.info();
+    if info.best_number <= ancestor {
+        return Ok(());
+    }
+
+    let mut checking_hash = info.best_hash;
+    while let Some(header) = client.header(checking_hash)? {
+        if header.number() <= &ancestor {
+            break;
+        }
+
+        match find_starknet_block(header.digest()) {
+            Ok(block) => {
+                let mapping_commitment = mapping_commitment::<_, _, H>(client, checking_hash, &block)?;
+                madara_backend
+                    .events()
+                    .revert_block(block.header().block_number, &indexed_events(&block, &mapping_commitment))?;
+                madara_backend.mapping().revert_hashes(mapping_commitment)?;
+            }
+            Err(FindLogError::NotLog) => madara_backend.mapping().revert_none(checking_hash)?,
+            Err(FindLogError::MultipleLogs) => return Err(anyhow::anyhow!("Multiple logs found")),
+        }
+
+        checking_hash = *header.parent_hash();
+    }
+
+    let to_revert = info.best_number - ancestor;
+    let (reverted, _) = substrate_backend.revert(to_revert, true)?;
+    if reverted != to_revert {
+        return Err(anyhow::anyhow!("Reverted {reverted} blocks out of {to_revert}"));
+    }
+
+    // The syncing tips may point to reverted blocks, restart from the leaves instead
+    madara_backend.meta().write_current_syncing_tips(Vec::new())?;
+
+    let _ = MAPPING_EVENTS.send(MappingEvent::Reverted(ancestor.unique_saturated_into()));
+
+    Ok(())
+}
+
 fn fetch_header<B: BlockT, BE>(
     substrate_backend: &BE,
     madara_backend: &mc_db::Backend<B>,
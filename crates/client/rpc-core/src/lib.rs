@@ -7,28 +7,56 @@
 #[cfg(test)]
 mod tests;
 
+use cairo_lang_casm_contract_class::CasmContractClass;
 use jsonrpsee::core::RpcResult;
 use jsonrpsee::proc_macros::rpc;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use sp_core::H256;
 
+pub mod proofs;
+pub mod pubsub;
+pub mod receipts;
+pub mod simulations;
 pub mod utils;
+pub mod v0_5;
 
 use mp_transactions::TransactionStatus;
+pub use proofs::{ContractData, EdgePath, GetStorageProofResult, ProofNode};
+pub use pubsub::{NewHead, ReorgData, TransactionStatusUpdate};
+pub use receipts::{
+    BlockWithReceipts, MaybePendingBlockWithReceipts, MessageStatus, PendingBlockWithReceipts,
+    PendingTransactionWithReceipt, TransactionWithReceipt,
+};
+pub use simulations::{ContractStateOverride, SimulationFlag, StorageEntry};
 use starknet_core::serde::unsigned_field_element::UfeHex;
 use starknet_core::types::{
     BlockHashAndNumber, BlockId, BroadcastedDeclareTransaction, BroadcastedDeployAccountTransaction,
     BroadcastedInvokeTransaction, BroadcastedTransaction, ContractClass, DeclareTransactionResult,
-    DeployAccountTransactionResult, EventFilterWithPage, EventsPage, FeeEstimate, FieldElement, FunctionCall,
-    InvokeTransactionResult, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs, MaybePendingTransactionReceipt,
-    MsgFromL1, SimulatedTransaction, SimulationFlag, StateUpdate, SyncStatusType, Transaction,
-    TransactionTraceWithHash,
+    DeployAccountTransactionResult, EmittedEvent, EventFilterWithPage, EventsPage, FeeEstimate, FieldElement,
+    FunctionCall, InvokeTransactionResult, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs,
+    MaybePendingStateUpdate, MaybePendingTransactionReceipt, MsgFromL1, SimulatedTransaction, SyncStatusType,
+    Transaction, TransactionTrace, TransactionTraceWithHash,
 };
+pub use v0_5::StarknetRpcApiV0_5Server;
 
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 pub struct Felt(#[serde_as(as = "UfeHex")] pub FieldElement);
 
+/// The latest block whose state posted on L1 matches the one of the node
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L1VerifiedBlock {
+    pub block_number: u64,
+    #[serde_as(as = "UfeHex")]
+    pub block_hash: FieldElement,
+    #[serde_as(as = "UfeHex")]
+    pub new_root: FieldElement,
+    /// Hash of the L1 transaction which posted the state, if known
+    pub l1_transaction_hash: Option<H256>,
+}
+
 /// Starknet write rpc interface.
 #[rpc(server, namespace = "starknet")]
 pub trait StarknetWriteRpcApi {
@@ -114,16 +142,21 @@ pub trait StarknetReadRpcApi {
     #[method(name = "getBlockWithTxs")]
     fn get_block_with_txs(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithTxs>;
 
+    /// Get block information with full transactions and their receipts given the block id
+    #[method(name = "getBlockWithReceipts")]
+    async fn get_block_with_receipts(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithReceipts>;
+
     /// Get the chain id
     #[method(name = "chainId")]
     fn chain_id(&self) -> RpcResult<Felt>;
 
-    /// Estimate the fee associated with transaction
+    /// Estimate the fee associated with transaction, optionally on top of an overridden state
     #[method(name = "estimateFee")]
     async fn estimate_fee(
         &self,
         request: Vec<BroadcastedTransaction>,
         block_id: BlockId,
+        state_overrides: Option<Vec<ContractStateOverride>>,
     ) -> RpcResult<Vec<FeeEstimate>>;
 
     /// Estimate the L2 fee of a message sent on L1
@@ -136,7 +169,7 @@ pub trait StarknetReadRpcApi {
 
     /// Get the information about the result of executing the requested block
     #[method(name = "getStateUpdate")]
-    fn get_state_update(&self, block_id: BlockId) -> RpcResult<StateUpdate>;
+    fn get_state_update(&self, block_id: BlockId) -> RpcResult<MaybePendingStateUpdate>;
 
     /// Returns all events matching the given filter
     #[method(name = "getEvents")]
@@ -152,21 +185,78 @@ pub trait StarknetReadRpcApi {
         &self,
         transaction_hash: FieldElement,
     ) -> RpcResult<MaybePendingTransactionReceipt>;
+
+    /// Returns the Merkle proofs of the given contract and storage keys at the given block id
+    #[method(name = "getStorageProof")]
+    fn get_storage_proof(
+        &self,
+        block_id: BlockId,
+        contract_address: FieldElement,
+        keys: Vec<FieldElement>,
+    ) -> RpcResult<GetStorageProofResult>;
+
+    /// Returns the latest block whose state posted on L1 matches the one of the node
+    #[method(name = "getL1VerifiedBlock")]
+    fn get_l1_verified_block(&self) -> RpcResult<L1VerifiedBlock>;
+
+    /// Returns the status of the L1 handler transactions triggered by the messages sent to L2 in
+    /// an L1 transaction
+    #[method(name = "getMessagesStatus")]
+    fn get_messages_status(&self, transaction_hash: H256) -> RpcResult<Vec<MessageStatus>>;
+
+    /// Returns the CASM class compiled from the Sierra class with the given hash
+    #[method(name = "getCompiledCasm")]
+    fn get_compiled_casm(&self, class_hash: FieldElement) -> RpcResult<CasmContractClass>;
 }
 
 /// Starknet trace rpc interface.
 #[rpc(server, namespace = "starknet")]
 pub trait StarknetTraceRpcApi {
-    /// Returns the execution trace of a transaction by simulating it in the runtime.
+    /// Returns the execution trace of a transaction by simulating it in the runtime, optionally
+    /// on top of an overridden state.
     #[method(name = "simulateTransactions")]
     async fn simulate_transactions(
         &self,
         block_id: BlockId,
         transactions: Vec<BroadcastedTransaction>,
         simulation_flags: Vec<SimulationFlag>,
+        state_overrides: Option<Vec<ContractStateOverride>>,
     ) -> RpcResult<Vec<SimulatedTransaction>>;
 
     #[method(name = "traceBlockTransactions")]
     /// Returns the execution traces of all transactions included in the given block
     async fn trace_block_transactions(&self, block_id: BlockId) -> RpcResult<Vec<TransactionTraceWithHash>>;
+
+    /// Returns the execution trace of a transaction included in a block
+    #[method(name = "traceTransaction")]
+    async fn trace_transaction(&self, transaction_hash: FieldElement) -> RpcResult<TransactionTrace>;
+}
+
+/// Starknet pubsub rpc interface.
+///
+/// Subscriptions are only available over WebSocket connections.
+#[rpc(server, namespace = "starknet")]
+pub trait StarknetPubSubRpcApi {
+    /// Notifies the subscriber every time the node reverts blocks orphaned by a reorganization of
+    /// the chain
+    #[subscription(name = "subscribeReorg", unsubscribe = "unsubscribeReorg", item = ReorgData)]
+    fn subscribe_reorg(&self);
+
+    /// Notifies the subscriber of every new block of the best chain
+    #[subscription(name = "subscribeNewHeads", unsubscribe = "unsubscribeNewHeads", item = NewHead)]
+    fn subscribe_new_heads(&self);
+
+    /// Notifies the subscriber of the events emitted in every new block of the best chain which
+    /// match the given filter
+    #[subscription(name = "subscribeEvents", unsubscribe = "unsubscribeEvents", item = EmittedEvent)]
+    fn subscribe_events(&self, from_address: Option<FieldElement>, keys: Option<Vec<Vec<FieldElement>>>);
+
+    /// Notifies the subscriber every time the status of a transaction changes, until it is
+    /// accepted on L1
+    #[subscription(
+        name = "subscribeTransactionStatus",
+        unsubscribe = "unsubscribeTransactionStatus",
+        item = TransactionStatusUpdate
+    )]
+    fn subscribe_transaction_status(&self, transaction_hash: FieldElement);
 }
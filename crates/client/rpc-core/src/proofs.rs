@@ -0,0 +1,74 @@
+//! Types returned by `starknet_getStorageProof`.
+//!
+//! The layout follows pathfinder's `pathfinder_getProof` so that existing light clients can
+//! consume it without changes.
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use starknet_core::serde::unsigned_field_element::UfeHex;
+use starknet_core::types::FieldElement;
+
+/// A node along the path from the root of a Merkle-Patricia trie to a leaf.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofNode {
+    /// An internal node with two children.
+    Binary {
+        #[serde_as(as = "UfeHex")]
+        left: FieldElement,
+        #[serde_as(as = "UfeHex")]
+        right: FieldElement,
+    },
+    /// A compressed path of `path.len` bits leading to `child`.
+    Edge {
+        #[serde_as(as = "UfeHex")]
+        child: FieldElement,
+        path: EdgePath,
+    },
+}
+
+/// The path of an edge node, as a felt holding `len` bits.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EdgePath {
+    #[serde_as(as = "UfeHex")]
+    pub value: FieldElement,
+    pub len: usize,
+}
+
+/// The state of a contract, along with the proofs of the requested storage keys.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractData {
+    #[serde_as(as = "UfeHex")]
+    pub class_hash: FieldElement,
+    #[serde_as(as = "UfeHex")]
+    pub nonce: FieldElement,
+    /// Root of the contract storage trie.
+    #[serde_as(as = "UfeHex")]
+    pub root: FieldElement,
+    #[serde_as(as = "UfeHex")]
+    pub contract_state_hash_version: FieldElement,
+    /// Proof of the class hash of the contract in the class trie, which only holds Sierra classes.
+    pub class_proof: Vec<ProofNode>,
+    /// One proof per requested storage key, in the order of the request. Unset keys are proven
+    /// absent from the storage trie.
+    pub storage_proofs: Vec<Vec<ProofNode>>,
+}
+
+/// Result of `starknet_getStorageProof`.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetStorageProofResult {
+    #[serde_as(as = "UfeHex")]
+    pub state_commitment: FieldElement,
+    #[serde_as(as = "UfeHex")]
+    pub class_commitment: FieldElement,
+    /// Proof of the contract leaf in the contract trie, or of its absence if the contract is not
+    /// deployed.
+    pub contract_proof: Vec<ProofNode>,
+    /// `None` if the contract is not deployed at the requested block, in which case
+    /// `contract_proof` proves that its address is not in the contract trie.
+    pub contract_data: Option<ContractData>,
+}
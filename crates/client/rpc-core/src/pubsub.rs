@@ -0,0 +1,51 @@
+//! Types pushed to the subscribers of the Starknet pubsub interface.
+
+use mp_transactions::TransactionStatus;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use starknet_core::serde::unsigned_field_element::UfeHex;
+use starknet_core::types::{FieldElement, ResourcePrice};
+
+/// The range of blocks orphaned by a reorganization of the chain.
+///
+/// Every block from `starting_block_number` to `ending_block_number` included has been removed
+/// from the node and will be replaced by the blocks of the new chain.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReorgData {
+    /// Hash of the first orphaned block.
+    #[serde_as(as = "UfeHex")]
+    pub starting_block_hash: FieldElement,
+    pub starting_block_number: u64,
+    /// Hash of the last orphaned block.
+    #[serde_as(as = "UfeHex")]
+    pub ending_block_hash: FieldElement,
+    pub ending_block_number: u64,
+}
+
+/// The header of a new block of the best chain.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewHead {
+    #[serde_as(as = "UfeHex")]
+    pub block_hash: FieldElement,
+    #[serde_as(as = "UfeHex")]
+    pub parent_hash: FieldElement,
+    pub block_number: u64,
+    #[serde_as(as = "UfeHex")]
+    pub new_root: FieldElement,
+    pub timestamp: u64,
+    #[serde_as(as = "UfeHex")]
+    pub sequencer_address: FieldElement,
+    pub l1_gas_price: ResourcePrice,
+    pub starknet_version: String,
+}
+
+/// A new status of a transaction.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionStatusUpdate {
+    #[serde_as(as = "UfeHex")]
+    pub transaction_hash: FieldElement,
+    pub status: TransactionStatus,
+}
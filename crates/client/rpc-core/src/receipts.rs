@@ -0,0 +1,78 @@
+//! Types returned by the rpc methods serving transactions along with their receipt.
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use starknet_core::serde::unsigned_field_element::UfeHex;
+use starknet_core::types::{
+    BlockStatus, FieldElement, PendingTransactionReceipt, ResourcePrice, Transaction, TransactionExecutionStatus,
+    TransactionFinalityStatus, TransactionReceipt,
+};
+
+/// A transaction of a block along with its receipt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionWithReceipt {
+    pub transaction: Transaction,
+    pub receipt: TransactionReceipt,
+}
+
+/// A transaction of the pending block along with its receipt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTransactionWithReceipt {
+    pub transaction: Transaction,
+    pub receipt: PendingTransactionReceipt,
+}
+
+/// A block along with the receipts of its transactions.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockWithReceipts {
+    pub status: BlockStatus,
+    #[serde_as(as = "UfeHex")]
+    pub block_hash: FieldElement,
+    #[serde_as(as = "UfeHex")]
+    pub parent_hash: FieldElement,
+    pub block_number: u64,
+    #[serde_as(as = "UfeHex")]
+    pub new_root: FieldElement,
+    pub timestamp: u64,
+    #[serde_as(as = "UfeHex")]
+    pub sequencer_address: FieldElement,
+    pub l1_gas_price: ResourcePrice,
+    pub starknet_version: String,
+    pub transactions: Vec<TransactionWithReceipt>,
+}
+
+/// The pending block along with the receipts of its transactions.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingBlockWithReceipts {
+    #[serde_as(as = "UfeHex")]
+    pub parent_hash: FieldElement,
+    pub timestamp: u64,
+    #[serde_as(as = "UfeHex")]
+    pub sequencer_address: FieldElement,
+    pub l1_gas_price: ResourcePrice,
+    pub starknet_version: String,
+    pub transactions: Vec<PendingTransactionWithReceipt>,
+}
+
+/// Either a block or the pending block, along with the receipts of its transactions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaybePendingBlockWithReceipts {
+    Block(BlockWithReceipts),
+    PendingBlock(PendingBlockWithReceipts),
+}
+
+/// The status of the L1 handler transaction triggered by a message sent from L1.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageStatus {
+    #[serde_as(as = "UfeHex")]
+    pub transaction_hash: FieldElement,
+    pub finality_status: TransactionFinalityStatus,
+    pub execution_status: TransactionExecutionStatus,
+    /// The reason of the revert of the transaction, if it was reverted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+}
@@ -0,0 +1,81 @@
+//! Parameters of the simulations run by `simulateTransactions` and `estimateFee`.
+//!
+//! Both extend the specification: the flags accept `SKIP_NONCE_CHECK`, and the state can be
+//! overridden before the transactions are executed, which account abstraction tooling needs to
+//! simulate transactions of accounts in a hypothetical state.
+
+use mp_felt::Felt252Wrapper;
+use mp_simulations::{ContractOverride, SimulationFlags, StateOverrides};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use starknet_core::serde::unsigned_field_element::UfeHex;
+use starknet_core::types::FieldElement;
+
+/// A simulation flag, either one of the specification or `SKIP_NONCE_CHECK`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SimulationFlag {
+    SkipValidate,
+    SkipFeeCharge,
+    SkipNonceCheck,
+}
+
+/// Builds the flags passed to the runtime.
+pub fn simulation_flags(flags: &[SimulationFlag]) -> SimulationFlags {
+    SimulationFlags {
+        skip_validate: flags.contains(&SimulationFlag::SkipValidate),
+        skip_fee_charge: flags.contains(&SimulationFlag::SkipFeeCharge),
+        skip_nonce_check: flags.contains(&SimulationFlag::SkipNonceCheck),
+    }
+}
+
+/// Changes made to the state of a contract before the simulation.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractStateOverride {
+    #[serde_as(as = "UfeHex")]
+    pub address: FieldElement,
+    #[serde_as(as = "Option<UfeHex>")]
+    pub nonce: Option<FieldElement>,
+    /// Replaces the class of the contract.
+    #[serde_as(as = "Option<UfeHex>")]
+    pub class_hash: Option<FieldElement>,
+    #[serde(default)]
+    pub storage: Vec<StorageEntry>,
+    /// Balance of the contract in the fee token.
+    #[serde_as(as = "Option<UfeHex>")]
+    pub balance: Option<FieldElement>,
+}
+
+/// A storage slot of a contract.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageEntry {
+    #[serde_as(as = "UfeHex")]
+    pub key: FieldElement,
+    #[serde_as(as = "UfeHex")]
+    pub value: FieldElement,
+}
+
+impl From<ContractStateOverride> for ContractOverride {
+    fn from(state_override: ContractStateOverride) -> Self {
+        Self {
+            nonce: state_override.nonce.map(|nonce| Felt252Wrapper::from(nonce).into()),
+            class_hash: state_override.class_hash.map(|class_hash| Felt252Wrapper::from(class_hash).into()),
+            storage: state_override
+                .storage
+                .into_iter()
+                .map(|entry| (Felt252Wrapper::from(entry.key).into(), Felt252Wrapper::from(entry.value).into()))
+                .collect(),
+            fee_token_balance: state_override.balance.map(|balance| Felt252Wrapper::from(balance).into()),
+        }
+    }
+}
+
+/// Builds the overrides passed to the runtime, in the order they were given.
+pub fn state_overrides(overrides: Vec<ContractStateOverride>) -> StateOverrides {
+    overrides
+        .into_iter()
+        .map(|state_override| (Felt252Wrapper::from(state_override.address).into(), state_override.into()))
+        .collect()
+}
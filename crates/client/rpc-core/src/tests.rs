@@ -1,4 +1,4 @@
-use starknet_core::types::BlockTag;
+use starknet_core::types::{BlockTag, TransactionExecutionStatus, TransactionFinalityStatus};
 
 use super::*;
 
@@ -38,3 +38,51 @@ fn block_id_deserialization() {
         BlockId::Number(42)
     );
 }
+
+#[test]
+fn simulation_flags_deserialization() {
+    let flags: Vec<SimulationFlag> =
+        serde_json::from_str("[\"SKIP_VALIDATE\", \"SKIP_NONCE_CHECK\"]").expect("valid flags");
+    let flags = simulations::simulation_flags(&flags);
+
+    assert!(flags.skip_validate);
+    assert!(!flags.skip_fee_charge);
+    assert!(flags.skip_nonce_check);
+}
+
+#[test]
+fn state_override_deserialization() {
+    let state_override: ContractStateOverride = serde_json::from_value(serde_json::json!({
+        "address": "0x1",
+        "nonce": "0x5",
+        "storage": [{ "key": "0x2", "value": "0x3" }]
+    }))
+    .expect("valid state override");
+
+    assert_eq!(state_override.nonce, Some(FieldElement::from(5u64)));
+    assert_eq!(state_override.class_hash, None);
+    assert_eq!(state_override.balance, None);
+    assert_eq!(
+        state_override.storage,
+        vec![StorageEntry { key: FieldElement::from(2u64), value: FieldElement::from(3u64) }]
+    );
+}
+
+#[test]
+fn message_status_serialization() {
+    let status = MessageStatus {
+        transaction_hash: FieldElement::from(1u64),
+        finality_status: TransactionFinalityStatus::AcceptedOnL2,
+        execution_status: TransactionExecutionStatus::Succeeded,
+        failure_reason: None,
+    };
+
+    assert_eq!(
+        serde_json::to_value(&status).expect("serializable status"),
+        serde_json::json!({
+            "transaction_hash": "0x1",
+            "finality_status": "ACCEPTED_ON_L2",
+            "execution_status": "SUCCEEDED"
+        })
+    );
+}
@@ -0,0 +1,26 @@
+//! Revision 0.5 of the Starknet rpc specification, served at `/rpc/v0_5`.
+//!
+//! The shared api follows this revision, so only the version is overridden and the methods
+//! introduced by later revisions are not mounted.
+
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::proc_macros::rpc;
+
+/// The version of the specification returned by `specVersion`
+pub const SPEC_VERSION: &str = "0.5.1";
+
+/// Methods of the shared api which are not part of this revision
+pub const UNSUPPORTED_METHODS: &[&str] = &[
+    "starknet_getBlockWithReceipts",
+    "starknet_getMessagesStatus",
+    "starknet_getStorageProof",
+    "starknet_getCompiledCasm",
+];
+
+/// Methods of the 0.5 revision which differ from the shared api.
+#[rpc(server, namespace = "starknet")]
+pub trait StarknetRpcApiV0_5 {
+    /// Get the Version of the StarkNet JSON-RPC Specification Being Used
+    #[method(name = "specVersion")]
+    fn spec_version(&self) -> RpcResult<String>;
+}
@@ -2,3 +2,7 @@
 pub const MAX_EVENTS_KEYS: usize = 100;
 /// Maximum number of events that can be fetched in a single chunk for the `get_events` RPC.
 pub const MAX_EVENTS_CHUNK_SIZE: usize = 1000;
+/// Maximum number of storage keys that can be proven in a single `get_storage_proof` RPC call.
+pub const MAX_STORAGE_PROOF_KEYS: usize = 100;
+/// Maximum number of blocks whose transaction traces are kept in memory by the trace RPCs.
+pub const TRACE_CACHE_SIZE: usize = 128;
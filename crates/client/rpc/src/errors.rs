@@ -1,6 +1,6 @@
 use jsonrpsee::types::error::{CallError, ErrorObject};
 use pallet_starknet_runtime_api::StarknetTransactionExecutionError;
-use starknet_core::types::StarknetError;
+use starknet_core::types::{FunctionInvocation, StarknetError};
 
 // Comes from the RPC Spec:
 // https://github.com/starkware-libs/starknet-specs/blob/0e859ff905795f789f1dfd6f7340cdaf5015acc8/api/starknet_write_api.json#L227
@@ -90,6 +90,34 @@ impl From<StarknetRpcApiError> for jsonrpsee::core::Error {
     }
 }
 
+/// A `TRANSACTION_EXECUTION_ERROR`, reporting which transaction failed, why, and the calls it
+/// completed before failing.
+///
+/// The partial trace only holds the top level calls which completed, along with their inner calls.
+/// The failing call is the top level call at `failing_call_index`, counting the validation and
+/// execution calls in the order the transaction runs them. Its own inner calls are not traced,
+/// since the blockifier drops the call info of a failing call; its error trace is in
+/// `execution_error`.
+#[derive(Debug)]
+pub struct TxnExecutionError {
+    pub transaction_index: u32,
+    pub execution_error: String,
+    pub partial_trace: Vec<FunctionInvocation>,
+}
+
+impl From<TxnExecutionError> for jsonrpsee::core::Error {
+    fn from(err: TxnExecutionError) -> Self {
+        let code = StarknetRpcApiError::TxnExecutionError;
+        let data = serde_json::json!({
+            "transaction_index": err.transaction_index,
+            "execution_error": err.execution_error,
+            "failing_call_index": err.partial_trace.len(),
+            "partial_trace": err.partial_trace,
+        });
+        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(code as i32, code.to_string(), Some(data))))
+    }
+}
+
 impl From<StarknetError> for StarknetRpcApiError {
     fn from(err: StarknetError) -> Self {
         match err {
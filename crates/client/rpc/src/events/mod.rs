@@ -3,6 +3,7 @@ mod tests;
 
 use jsonrpsee::core::RpcResult;
 use log::error;
+use mc_db::EventBloom;
 use mc_rpc_core::utils::get_block_by_block_hash;
 use mp_felt::Felt252Wrapper;
 use mp_hashers::HasherT;
@@ -105,6 +106,63 @@ where
         Ok(emitted_events)
     }
 
+    /// Helper function to get the events of a block matching a filter
+    ///
+    /// Blocks indexed by the mapping worker are skipped when their bloom filter rules out a match,
+    /// and the events of a given emitter are read from the index. The other blocks are fetched and
+    /// filtered.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_number` - The Starknet block number
+    /// * `from_address` - Address to use to filter the events
+    /// * `keys` - Keys to use to filter the events
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<EmittedEvent>` - The events of the block matching the filter, in order
+    pub fn get_filtered_block_events(
+        &self,
+        block_number: u64,
+        from_address: Option<Felt252Wrapper>,
+        keys: &[Vec<FieldElement>],
+    ) -> Result<Vec<EmittedEvent>, StarknetRpcApiError> {
+        let bloom = self.backend.events().bloom(block_number).unwrap_or_else(|e| {
+            error!("Failed to read the event bloom of block {block_number}: {e}");
+            None
+        });
+        let Some(bloom) = bloom else {
+            return Ok(filter_events_by_params(self.get_block_events(block_number)?, from_address, keys));
+        };
+
+        if !bloom_may_match(&bloom, from_address, keys) {
+            return Ok(Vec::new());
+        }
+
+        let Some(from_address) = from_address else {
+            return Ok(filter_events_by_params(self.get_block_events(block_number)?, None, keys));
+        };
+
+        let indexed_events =
+            self.backend.events().events_from_address(&from_address.into(), block_number).map_err(|e| {
+                error!("Failed to read the events of block {block_number}: {e}");
+                StarknetRpcApiError::InternalServerError
+            })?;
+        let emitted_events = indexed_events
+            .into_iter()
+            .map(|event| EmittedEvent {
+                from_address: Felt252Wrapper::from(event.from_address).0,
+                keys: event.keys.into_iter().map(|felt| Felt252Wrapper::from(felt).0).collect(),
+                data: event.data.into_iter().map(|felt| Felt252Wrapper::from(felt).0).collect(),
+                block_hash: Felt252Wrapper::from(event.block_hash).0,
+                block_number,
+                transaction_hash: Felt252Wrapper::from(event.transaction_hash).0,
+            })
+            .collect();
+
+        Ok(filter_events_by_params(emitted_events, Some(from_address), keys))
+    }
+
     /// Helper function to filter Starknet events provided a RPC event filter
     ///
     /// # Arguments
@@ -129,9 +187,7 @@ where
 
         // Iterate on block range
         while current_block <= to_block {
-            let emitted_events = self.get_block_events(current_block)?;
-
-            let block_filtered_events: Vec<EmittedEvent> = filter_events_by_params(emitted_events, from_address, &keys);
+            let block_filtered_events = self.get_filtered_block_events(current_block, from_address, &keys)?;
 
             if current_block == from_block && (block_filtered_events.len() as u64) < continuation_token.event_n {
                 return Err(StarknetRpcApiError::InvalidContinuationToken.into());
@@ -166,6 +222,16 @@ where
     }
 }
 
+/// Whether the bloom filter of a block allows an event matching the address and keys
+fn bloom_may_match(bloom: &EventBloom, address: Option<Felt252Wrapper>, keys: &[Vec<FieldElement>]) -> bool {
+    let match_from_address = address.map_or(true, |address| bloom.may_contain_address(&address.into()));
+    let match_keys = keys.iter().enumerate().all(|(i, keys)| {
+        keys.is_empty() || keys.iter().any(|key| bloom.may_contain_key(i, &Felt252Wrapper::from(*key).into()))
+    });
+
+    match_from_address && match_keys
+}
+
 /// Helper function to get filter events using address and keys
 
 /// # Arguments
@@ -1,9 +1,10 @@
+use mc_db::{EventBloom, IndexedEvent};
 use mp_felt::Felt252Wrapper;
 use rstest::*;
 use starknet_core::types::EmittedEvent;
 use starknet_ff::FieldElement;
 
-use crate::events::filter_events_by_params;
+use crate::events::{bloom_may_match, filter_events_by_params};
 
 #[derive(Debug, Clone)]
 struct TestCase<'a> {
@@ -147,3 +148,48 @@ fn build_event_wrapper_for_test(keys: &[&str], address_int: u64) -> EmittedEvent
         transaction_hash: Default::default(),
     }
 }
+
+#[test]
+fn bloom_never_rules_out_matching_events() {
+    for case in build_test_case().iter() {
+        let indexed_events: Vec<_> = case
+            .events
+            .iter()
+            .map(|event| IndexedEvent {
+                block_hash: Default::default(),
+                transaction_hash: Default::default(),
+                from_address: Felt252Wrapper::from(event.from_address).into(),
+                keys: event.keys.iter().map(|key| Felt252Wrapper::from(*key).into()).collect(),
+                data: vec![],
+            })
+            .collect();
+        let bloom = EventBloom::from_events(&indexed_events);
+
+        if !case.expected_events.is_empty() {
+            assert!(bloom_may_match(&bloom, case.filter_address, &case.filter_keys), "{}", case._name);
+        }
+    }
+
+    let bloom = EventBloom::from_events(&[]);
+    assert!(!bloom_may_match(&bloom, Some(Felt252Wrapper::from(1u64)), &[]));
+    assert!(!bloom_may_match(&bloom, None, &[vec![FieldElement::ONE]]));
+    assert!(bloom_may_match(&bloom, None, &[vec![]]));
+}
+
+#[test]
+fn bloom_of_a_busy_block_still_rules_out_events() {
+    let indexed_events: Vec<_> = (0..5_000u64)
+        .map(|i| IndexedEvent {
+            block_hash: Default::default(),
+            transaction_hash: Default::default(),
+            from_address: Felt252Wrapper::from(i).into(),
+            keys: vec![Felt252Wrapper::from(1_000_000 + i).into(), Felt252Wrapper::from(2_000_000 + i).into()],
+            data: vec![],
+        })
+        .collect();
+    let bloom = EventBloom::from_events(&indexed_events);
+
+    let false_positives =
+        (10_000_000..10_010_000u64).filter(|i| bloom_may_match(&bloom, Some(Felt252Wrapper::from(*i)), &[])).count();
+    assert!(false_positives < 100, "{false_positives} addresses out of 10000 match the bloom");
+}
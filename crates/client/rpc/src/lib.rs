@@ -6,36 +6,45 @@ mod constants;
 mod errors;
 mod events;
 mod madara_backend_client;
+mod proofs;
+mod pubsub;
 mod trace_api;
+mod trace_cache;
 mod types;
 mod utils;
+mod versions;
 
 use std::marker::PhantomData;
 use std::sync::Arc;
 
-use blockifier::execution::contract_class::{ContractClass as ContractClassBf, ContractClassV1 as ContractClassV1Bf};
+use blockifier::execution::contract_class::ContractClass as ContractClassBf;
 use blockifier::execution::entry_point::CallInfo;
+use cairo_lang_casm_contract_class::CasmContractClass;
 use errors::StarknetRpcApiError;
 use jsonrpsee::core::{async_trait, RpcResult};
 use jsonrpsee::types::error::CallError;
 use log::error;
+use mc_deoxys::chain::feeder_block_hash;
 use mc_deoxys::commitments::transactions;
-use mc_deoxys::l2::get_config;
+use mc_deoxys::l2::{get_config, get_pending_block, PendingBlock};
 use mc_deoxys::utility::get_highest_block_hash_and_number;
 use mc_genesis_data_provider::GenesisProvider;
 pub use mc_rpc_core::utils::*;
-pub use mc_rpc_core::{Felt, StarknetReadRpcApiServer, StarknetTraceRpcApiServer, StarknetWriteRpcApiServer};
+pub use mc_rpc_core::{
+    BlockWithReceipts, ContractStateOverride, Felt, GetStorageProofResult, L1VerifiedBlock,
+    MaybePendingBlockWithReceipts, MessageStatus, PendingBlockWithReceipts, PendingTransactionWithReceipt,
+    StarknetPubSubRpcApiServer, StarknetReadRpcApiServer, StarknetTraceRpcApiServer, StarknetWriteRpcApiServer,
+    TransactionWithReceipt,
+};
 use mc_storage::OverrideHandle;
-use mp_block::BlockStatus;
+use mp_block::{Block as StarknetBlock, BlockStatus};
 use mp_contract::class::ContractClassWrapper;
-use mp_convert::contract::flattened_sierra_to_sierra_contract_class;
+use mp_convert::contract::{flattened_sierra_to_sierra_contract_class, sierra_contract_class_to_flattened_sierra};
 use mp_felt::{Felt252Wrapper, Felt252WrapperError};
 use mp_hashers::HasherT;
 use mp_transactions::compute_hash::ComputeTransactionHash;
 use mp_transactions::to_starknet_core_transaction::to_starknet_core_tx;
-use mp_transactions::{
-    DeclareTransaction, Transaction as TransactionMp, TransactionStatus, UserOrL1HandlerTransaction, UserTransaction,
-};
+use mp_transactions::{Transaction as TransactionMp, TransactionStatus, UserTransaction};
 use pallet_starknet_runtime_api::{ConvertTransactionRuntimeApi, StarknetRuntimeApi};
 use sc_client_api::backend::{Backend, StorageProvider};
 use sc_client_api::BlockBackend;
@@ -58,18 +67,20 @@ use starknet_core::types::{
     BlockHashAndNumber, BlockId, BlockTag, BlockWithTxHashes, BlockWithTxs, BroadcastedDeclareTransaction,
     BroadcastedDeployAccountTransaction, BroadcastedInvokeTransaction, BroadcastedTransaction, ContractClass,
     DeclareTransactionReceipt, DeclareTransactionResult, DeployAccountTransactionReceipt,
-    DeployAccountTransactionResult, DeployTransactionReceipt, Event, EventFilterWithPage, EventsPage,
-    ExecutionResources, ExecutionResult, FeeEstimate, FieldElement, FunctionCall, Hash256, InvokeTransactionReceipt,
-    InvokeTransactionResult, L1HandlerTransactionReceipt, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs,
+    DeployAccountTransactionResult, DeployTransactionReceipt, EventFilterWithPage, EventsPage, ExecutionResult,
+    FeeEstimate, FieldElement, FunctionCall, InvokeTransactionReceipt, InvokeTransactionResult,
+    L1HandlerTransactionReceipt, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs, MaybePendingStateUpdate,
     MaybePendingTransactionReceipt, MsgFromL1, MsgToL1, StateDiff, StateUpdate, SyncStatus, SyncStatusType,
     Transaction, TransactionExecutionStatus, TransactionFinalityStatus, TransactionReceipt,
 };
 use starknet_providers::{Provider, ProviderError, SequencerGatewayProvider};
 
-use crate::constants::{MAX_EVENTS_CHUNK_SIZE, MAX_EVENTS_KEYS};
+use crate::constants::{MAX_EVENTS_CHUNK_SIZE, MAX_EVENTS_KEYS, TRACE_CACHE_SIZE};
+use crate::trace_cache::TraceCache;
 use crate::types::RpcEventFilter;
 use crate::utils::{
-    blockifier_call_info_to_starknet_resources, extract_events_from_call_info, extract_messages_from_call_info,
+    actual_resources_to_starknet_resources, extract_messages_from_call_info, l1_handler_message_hash,
+    starknet_api_to_starknet_rs_event, stored_receipt_outcome,
 };
 
 /// A Starknet RPC server for Madara
@@ -83,6 +94,7 @@ pub struct Starknet<A: ChainApi, B: BlockT, BE, G, C, P, H> {
     sync_service: Arc<SyncingService<B>>,
     starting_block: <<B>::Header as HeaderT>::Number,
     genesis_provider: Arc<G>,
+    trace_cache: Arc<TraceCache>,
     _marker: PhantomData<(B, BE, H)>,
 }
 
@@ -118,6 +130,25 @@ impl<A: ChainApi, B: BlockT, BE, G, C, P, H> Starknet<A, B, BE, G, C, P, H> {
             sync_service,
             starting_block,
             genesis_provider,
+            trace_cache: Arc::new(TraceCache::new(TRACE_CACHE_SIZE)),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// Deriving `Clone` would require every type parameter to be `Clone`
+impl<A: ChainApi, B: BlockT, BE, G, C, P, H> Clone for Starknet<A, B, BE, G, C, P, H> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            backend: self.backend.clone(),
+            overrides: self.overrides.clone(),
+            pool: self.pool.clone(),
+            graph: self.graph.clone(),
+            sync_service: self.sync_service.clone(),
+            starting_block: self.starting_block,
+            genesis_provider: self.genesis_provider.clone(),
+            trace_cache: self.trace_cache.clone(),
             _marker: PhantomData,
         }
     }
@@ -139,7 +170,7 @@ where
     C: HeaderBackend<B> + 'static,
 {
     pub fn current_spec_version(&self) -> RpcResult<String> {
-        Ok("0.5.1".to_string())
+        Ok(mc_rpc_core::v0_5::SPEC_VERSION.to_string())
     }
 }
 
@@ -161,6 +192,17 @@ where
         Ok(starknet_block.header().hash::<H>().into())
     }
 
+    /// Returns the pending block of the feeder gateway, if it is built on top of the best block of
+    /// the node.
+    fn pending_block(&self) -> Option<Arc<PendingBlock>> {
+        let pending_block = get_pending_block()?;
+        let best_block = get_block_by_block_hash(self.client.as_ref(), self.client.info().best_hash).ok()?;
+        // The parent hash is the feeder hash, which the header hash only matches for Pedersen blocks
+        let best_block_hash = feeder_block_hash::<H>(best_block.header())?;
+
+        (pending_block.block.parent_hash == best_block_hash).then_some(pending_block)
+    }
+
     /// Returns the substrate block hash corresponding to the given Starknet block id
     fn substrate_block_hash_from_starknet_block(&self, block_id: BlockId) -> Result<B::Hash, StarknetRpcApiError> {
         match block_id {
@@ -205,6 +247,21 @@ where
         Ok(starknet_block.header().block_number)
     }
 
+    /// Whether the state of the given block has been posted on L1 and matches the imported one.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_number` - The Starknet block number
+    fn is_accepted_on_l1(&self, block_number: u64) -> bool {
+        match self.backend.meta().l1_verified_state() {
+            Ok(state) => state.is_some_and(|state| block_number <= state.block_number),
+            Err(e) => {
+                error!("Failed to read the L1 verified state: {e}");
+                false
+            }
+        }
+    }
+
     /// Returns a list of all transaction hashes in the given block.
     ///
     /// # Arguments
@@ -517,18 +574,28 @@ where
                     .map(|tx| to_starknet_core_tx(tx.clone(), transaction_hash))
             };
 
+        let stored_receipt =
+            self.backend.receipts().get_receipt(Felt252Wrapper(transaction_hash).into()).map_err(|e| {
+                error!("Failed to retrieve the receipt of transaction {transaction_hash:#x}: {e}");
+                StarknetRpcApiError::InternalServerError
+            })?;
+
         let execution_status = {
-            let revert_error = self
-                .client
-                .runtime_api()
-                .get_tx_execution_outcome(substrate_block_hash, Felt252Wrapper(transaction_hash).into())
-                .map_err(|e| {
-                    error!(
-                        "Failed to get transaction execution outcome. Substrate block hash: {substrate_block_hash}, \
-                         transaction hash: {transaction_hash}, error: {e}"
-                    );
-                    StarknetRpcApiError::InternalServerError
-                })?;
+            // The outcome of the transactions imported by the sync is only known from their receipt
+            let revert_error = match stored_receipt {
+                Some(receipt) => receipt.revert_reason.map(String::into_bytes),
+                None => self
+                    .client
+                    .runtime_api()
+                    .get_tx_execution_outcome(substrate_block_hash, Felt252Wrapper(transaction_hash).into())
+                    .map_err(|e| {
+                        error!(
+                            "Failed to get transaction execution outcome. Substrate block hash: \
+                             {substrate_block_hash}, transaction hash: {transaction_hash}, error: {e}"
+                        );
+                        StarknetRpcApiError::InternalServerError
+                    })?,
+            };
 
             if revert_error.is_none() {
                 TransactionExecutionStatus::Succeeded
@@ -537,7 +604,13 @@ where
             }
         };
 
-        Ok(TransactionStatus { finality_status: TransactionFinalityStatus::AcceptedOnL2, execution_status })
+        let finality_status = if self.is_accepted_on_l1(starknet_block.header().block_number) {
+            TransactionFinalityStatus::AcceptedOnL1
+        } else {
+            TransactionFinalityStatus::AcceptedOnL2
+        };
+
+        Ok(TransactionStatus { finality_status, execution_status })
     }
 
     /// Get the value of the storage at the given address and key.
@@ -570,6 +643,24 @@ where
     /// * `STORAGE_KEY_NOT_FOUND` - If the specified storage key does not exist within the given
     ///   contract.
     fn get_storage_at(&self, contract_address: FieldElement, key: FieldElement, block_id: BlockId) -> RpcResult<Felt> {
+        if let BlockId::Tag(BlockTag::Pending) = block_id {
+            let pending_value = self.pending_block().and_then(|pending_block| {
+                pending_block
+                    .state_update
+                    .state_diff
+                    .storage_diffs
+                    .iter()
+                    .filter(|diff| diff.address == contract_address)
+                    .flat_map(|diff| diff.storage_entries.iter())
+                    .find(|entry| entry.key == key)
+                    .map(|entry| entry.value)
+            });
+            // Storage untouched by the pending block is read from the best block
+            if let Some(value) = pending_value {
+                return Ok(Felt(value));
+            }
+        }
+
         let substrate_block_hash = self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| {
             error!("'{e}'");
             StarknetRpcApiError::BlockNotFound
@@ -674,6 +765,19 @@ where
                 StarknetRpcApiError::ContractNotFound
             })?;
 
+        // Blockifier classes do not store the Sierra program, the original class is served instead
+        if let ContractClassBf::V1(_) = contract_class {
+            let class_hash = self
+                .overrides
+                .for_block_hash(self.client.as_ref(), substrate_block_hash)
+                .contract_class_hash_by_address(substrate_block_hash, contract_address_wrapped);
+            if let Some(class_hash) = class_hash {
+                if let Some(sierra_class) = self.stored_sierra_class(class_hash)? {
+                    return Ok(sierra_class);
+                }
+            }
+        }
+
         // Blockifier classes do not store ABI, has to be retrieved separately
         let contract_abi = self
             .overrides
@@ -823,6 +927,13 @@ where
                 StarknetRpcApiError::ClassHashNotFound
             })?;
 
+        // Blockifier classes do not store the Sierra program, the original class is served instead
+        if let ContractClassBf::V1(_) = contract_class {
+            if let Some(sierra_class) = self.stored_sierra_class(class_hash)? {
+                return Ok(sierra_class);
+            }
+        }
+
         // Blockifier classes do not store ABI, has to be retrieved separately
         let contract_abi = self
             .overrides
@@ -865,9 +976,7 @@ where
         let l1_gas_price = starknet_block.header().l1_gas_price;
         let block_hash = starknet_block.header().hash::<H>();
 
-        let actual_status = if starknet_block.header().block_number
-            <= mc_deoxys::l1::ETHEREUM_STATE_UPDATE.lock().unwrap().block_number
-        {
+        let actual_status = if self.is_accepted_on_l1(starknet_block.header().block_number) {
             BlockStatus::AcceptedOnL1.into()
         } else {
             BlockStatus::AcceptedOnL2.into()
@@ -885,20 +994,6 @@ where
                 .map(FieldElement::from)
                 .collect()
         };
-        let block_status = match self.backend.messaging().last_synced_l1_block_with_event() {
-            Ok(l1_block) => {
-                if l1_block.block_number >= starknet_block.header().block_number {
-                    BlockStatus::AcceptedOnL1
-                } else {
-                    BlockStatus::AcceptedOnL2
-                }
-            }
-            Err(e) => {
-                error!("Failed to get last synced l1 block, error: {e}");
-                Err(StarknetRpcApiError::InternalServerError)?
-            }
-        };
-
         let parent_blockhash = starknet_block.header().parent_block_hash;
         let block_with_tx_hashes = BlockWithTxHashes {
             transactions: transaction_hashes,
@@ -933,6 +1028,25 @@ where
     /// `BLOCK_NOT_FOUND` or `CONTRACT_NOT_FOUND`, returns a `StarknetRpcApiError` indicating the
     /// specific issue.
     fn get_nonce(&self, block_id: BlockId, contract_address: FieldElement) -> RpcResult<Felt> {
+        if let BlockId::Tag(BlockTag::Pending) = block_id {
+            let pending_nonce = self.pending_block().and_then(|pending_block| {
+                let state_diff = &pending_block.state_update.state_diff;
+                let nonce = state_diff.nonces.iter().find(|update| update.contract_address == contract_address);
+                match nonce {
+                    Some(update) => Some(update.nonce),
+                    // Contracts deployed in the pending block do not exist in the best block
+                    None => state_diff
+                        .deployed_contracts
+                        .iter()
+                        .any(|deployed| deployed.address == contract_address)
+                        .then_some(FieldElement::ZERO),
+                }
+            });
+            if let Some(nonce) = pending_nonce {
+                return Ok(Felt(nonce));
+            }
+        }
+
         let substrate_block_hash = self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| {
             error!("'{e}'");
             StarknetRpcApiError::BlockNotFound
@@ -979,6 +1093,7 @@ where
     ///
     /// * `request` - starknet transaction request
     /// * `block_id` - hash of the requested block, number (height), or tag
+    /// * `state_overrides` - changes made to the state before estimating the fees
     ///
     /// # Returns
     ///
@@ -987,6 +1102,7 @@ where
         &self,
         request: Vec<BroadcastedTransaction>,
         block_id: BlockId,
+        state_overrides: Option<Vec<ContractStateOverride>>,
     ) -> RpcResult<Vec<FeeEstimate>> {
         let substrate_block_hash = self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| {
             error!("'{e}'");
@@ -1000,11 +1116,12 @@ where
                 error!("Failed to convert BroadcastedTransaction to UserTransaction: {e}");
                 StarknetRpcApiError::InternalServerError
             })?;
+        let state_overrides = mc_rpc_core::simulations::state_overrides(state_overrides.unwrap_or_default());
 
         let fee_estimates = self
             .client
             .runtime_api()
-            .estimate_fee(substrate_block_hash, transactions)
+            .estimate_fee(substrate_block_hash, transactions, state_overrides)
             .map_err(|e| {
                 error!("Request parameters error: {e}");
                 StarknetRpcApiError::InternalServerError
@@ -1142,6 +1259,12 @@ where
     /// transactions. In case the specified block is not found, returns a `StarknetRpcApiError` with
     /// `BlockNotFound`.
     fn get_block_with_txs(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithTxs> {
+        if let BlockId::Tag(BlockTag::Pending) = block_id {
+            if let Some(pending_block) = self.pending_block() {
+                return Ok(MaybePendingBlockWithTxs::PendingBlock(pending_block.block.clone()));
+            }
+        }
+
         let substrate_block_hash = self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| {
             error!("Block not found: '{e}'");
             StarknetRpcApiError::BlockNotFound
@@ -1152,9 +1275,7 @@ where
         let block_hash = starknet_block.header().hash::<H>();
         let starknet_version = starknet_block.header().protocol_version;
 
-        let actual_status = if starknet_block.header().block_number
-            <= mc_deoxys::l1::ETHEREUM_STATE_UPDATE.lock().unwrap().block_number
-        {
+        let actual_status = if self.is_accepted_on_l1(starknet_block.header().block_number) {
             BlockStatus::AcceptedOnL1.into()
         } else {
             BlockStatus::AcceptedOnL2.into()
@@ -1197,6 +1318,87 @@ where
         Ok(MaybePendingBlockWithTxs::Block(block_with_txs))
     }
 
+    /// Get block information with full transactions and receipts given the block id.
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_id` - The hash of the requested block, or number (height) of the requested block,
+    ///   or a block tag.
+    ///
+    /// ### Returns
+    ///
+    /// Returns the block along with its transactions and their receipts, which can be a confirmed
+    /// block or the pending block.
+    ///
+    /// ### Errors
+    ///
+    /// Returns `BLOCK_NOT_FOUND` if the block does not exist.
+    async fn get_block_with_receipts(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithReceipts> {
+        if let BlockId::Tag(BlockTag::Pending) = block_id {
+            if let Some(pending_block) = self.pending_block() {
+                let block = &pending_block.block;
+                let transactions = block
+                    .transactions
+                    .iter()
+                    .filter_map(|transaction| {
+                        let receipt = pending_block.receipts.get(transaction.transaction_hash())?.clone();
+                        Some(PendingTransactionWithReceipt { transaction: transaction.clone(), receipt })
+                    })
+                    .collect();
+
+                return Ok(MaybePendingBlockWithReceipts::PendingBlock(PendingBlockWithReceipts {
+                    parent_hash: block.parent_hash,
+                    timestamp: block.timestamp,
+                    sequencer_address: block.sequencer_address,
+                    l1_gas_price: block.l1_gas_price.clone(),
+                    starknet_version: block.starknet_version.clone(),
+                    transactions,
+                }));
+            }
+        }
+
+        let substrate_block_hash = self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| {
+            error!("Block not found: '{e}'");
+            StarknetRpcApiError::BlockNotFound
+        })?;
+
+        let starknet_block = get_block_by_block_hash(self.client.as_ref(), substrate_block_hash)?;
+        let block_hash: FieldElement = starknet_block.header().hash::<H>().into();
+
+        let MaybePendingBlockWithTxs::Block(block) = self.get_block_with_txs(BlockId::Hash(block_hash))? else {
+            error!("Block with hash {block_hash:#x} was returned as pending");
+            return Err(StarknetRpcApiError::InternalServerError.into());
+        };
+
+        let transactions = block
+            .transactions
+            .into_iter()
+            .enumerate()
+            .map(|(tx_index, transaction)| {
+                let receipt = self.transaction_receipt(
+                    substrate_block_hash,
+                    &starknet_block,
+                    tx_index,
+                    *transaction.transaction_hash(),
+                )?;
+                Ok(TransactionWithReceipt { transaction, receipt })
+            })
+            .collect::<RpcResult<Vec<_>>>()?;
+
+        Ok(MaybePendingBlockWithReceipts::Block(BlockWithReceipts {
+            status: block.status,
+            block_hash: block.block_hash,
+            parent_hash: block.parent_hash,
+            block_number: block.block_number,
+            new_root: block.new_root,
+            timestamp: block.timestamp,
+            sequencer_address: block.sequencer_address,
+            l1_gas_price: block.l1_gas_price,
+            starknet_version: block.starknet_version,
+            transactions,
+        }))
+    }
+
     /// Get the information about the result of executing the requested block.
     ///
     /// This function fetches details about the state update resulting from executing a specific
@@ -1215,7 +1417,13 @@ where
     /// the state of the network as a result of the block's execution. This can include a confirmed
     /// state update or a pending state update. If the block is not found, returns a
     /// `StarknetRpcApiError` with `BlockNotFound`.
-    fn get_state_update(&self, block_id: BlockId) -> RpcResult<StateUpdate> {
+    fn get_state_update(&self, block_id: BlockId) -> RpcResult<MaybePendingStateUpdate> {
+        if let BlockId::Tag(BlockTag::Pending) = block_id {
+            if let Some(pending_block) = self.pending_block() {
+                return Ok(MaybePendingStateUpdate::PendingUpdate(pending_block.state_update.clone()));
+            }
+        }
+
         let substrate_block_hash = self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| {
             error!("'{e}'");
             StarknetRpcApiError::BlockNotFound
@@ -1247,12 +1455,12 @@ where
             StarknetRpcApiError::InternalServerError
         })?;
 
-        Ok(StateUpdate {
+        Ok(MaybePendingStateUpdate::Update(StateUpdate {
             block_hash: starknet_block.header().hash::<H>().into(),
             new_root: Felt252Wrapper::from(starknet_block.header().global_state_root).into(),
             old_root,
             state_diff,
-        })
+        }))
     }
 
     /// Returns all events matching the given filter.
@@ -1427,25 +1635,22 @@ where
             .map_err(|e| {
                 log::error!("Failed to retrieve substrate block hash: {e}");
                 StarknetRpcApiError::InternalServerError
-            })?
-            .ok_or(StarknetRpcApiError::TxnHashNotFound)?;
+            })?;
+        let Some(substrate_block_hash) = substrate_block_hash else {
+            // The transaction may be in the pending block
+            return self
+                .pending_block()
+                .and_then(|pending_block| pending_block.receipts.get(&transaction_hash).cloned())
+                .map(MaybePendingTransactionReceipt::PendingReceipt)
+                .ok_or_else(|| StarknetRpcApiError::TxnHashNotFound.into());
+        };
 
         let block = get_block_by_block_hash(self.client.as_ref(), substrate_block_hash)?;
         let block_header = block.header();
-        let block_number = block_header.block_number;
         let block_hash: Felt252Wrapper = block_header.hash::<H>().into();
-        let starknet_version = block_header.protocol_version;
 
         let chain_id = self.chain_id()?.0;
 
-        let previous_substrate_block_hash = {
-            let previous_block_number = block_number - 1;
-            self.substrate_block_hash_from_starknet_block(BlockId::Number(previous_block_number)).map_err(|e| {
-                log::error!("Failed to retrieve previous substrate block hash: {e}");
-                StarknetRpcApiError::InternalServerError
-            })
-        }?;
-
         let block_txs_hashes: Vec<_> = if let Some(tx_hashes) = self.get_cached_transaction_hashes(block_hash.into()) {
             tx_hashes
                 .into_iter()
@@ -1467,166 +1672,334 @@ where
                 .collect()
         };
 
-        let (tx_index, _) =
-            block_txs_hashes.into_iter().enumerate().find(|(_, hash)| hash == &transaction_hash.into()).unwrap().into();
-
-        let transaction = block.transactions().get(tx_index).ok_or_else(|| {
-            log::error!("Failed to retrieve transaction at index {tx_index} from block with hash {block_hash:?}");
+        let tx_index = block_txs_hashes.into_iter().position(|hash| hash == transaction_hash).ok_or_else(|| {
+            log::error!("Transaction {transaction_hash:#x} is not in block with hash {block_hash:?}");
             StarknetRpcApiError::InternalServerError
         })?;
 
-        // TODO: remove this line when deploy is supported
-        if let TransactionMp::Deploy(..) = transaction {
-            log::error!("re executing a deploy transaction is not supported yet");
-            return Err(StarknetRpcApiError::UnimplementedMethod.into());
-        }
+        let receipt = self.transaction_receipt(substrate_block_hash, &block, tx_index, transaction_hash)?;
 
-        let transactions = block
-            .transactions()
-            .iter()
-            .take(tx_index + 1)
-            .filter(|tx| !matches!(tx, TransactionMp::Deploy(_))) // TODO: remove this line when deploy is supported
-            .map(|tx| match tx {
-                TransactionMp::Invoke(invoke_tx) => {
-                    RpcResult::Ok(UserOrL1HandlerTransaction::User(UserTransaction::Invoke(invoke_tx.clone())))
-                }
-                TransactionMp::DeployAccount(deploy_account_tx) => {
-                    Ok(UserOrL1HandlerTransaction::User(UserTransaction::DeployAccount(deploy_account_tx.clone())))
-                }
-                TransactionMp::Declare(declare_tx) => {
-                    let class_hash = ClassHash::from(*declare_tx.class_hash());
-
-                    match declare_tx {
-                        DeclareTransaction::V0(_) | DeclareTransaction::V1(_) => {
-                            let contract_class = self
-                                .overrides
-                                .for_block_hash(self.client.as_ref(), substrate_block_hash)
-                                .contract_class_by_class_hash(substrate_block_hash, class_hash)
-                                .ok_or_else(|| {
-                                    log::error!("Failed to retrieve contract class from hash '{class_hash}'");
-                                    StarknetRpcApiError::InternalServerError
-                                })?;
-
-                            Ok(UserOrL1HandlerTransaction::User(UserTransaction::Declare(
-                                declare_tx.clone(),
-                                contract_class,
-                            )))
-                        }
-                        DeclareTransaction::V2(tx) => {
-                            let contract_class = self
-                                .backend
-                                .sierra_classes()
-                                .get_sierra_class(class_hash)
-                                .map_err(|e| {
-                                    log::error!("Failed to fetch sierra class with hash {class_hash}: {e}");
-                                    StarknetRpcApiError::InternalServerError
-                                })?
-                                .ok_or_else(|| {
-                                    log::error!("The sierra class with hash {class_hash} is not present in db backend");
-                                    StarknetRpcApiError::InternalServerError
-                                })?;
-                            let contract_class = mp_transactions::utils::sierra_to_casm_contract_class(contract_class)
-                                .map_err(|e| {
-                                    log::error!("Failed to convert the SierraContractClass to CasmContractClass: {e}");
-                                    StarknetRpcApiError::InternalServerError
-                                })?;
-                            let contract_class =
-                                ContractClassBf::V1(ContractClassV1Bf::try_from(contract_class).map_err(|e| {
-                                    log::error!(
-                                        "Failed to convert the compiler CasmContractClass to blockifier \
-                                         CasmContractClass: {e}"
-                                    );
-                                    StarknetRpcApiError::InternalServerError
-                                })?);
-
-                            Ok(UserOrL1HandlerTransaction::User(UserTransaction::Declare(
-                                declare_tx.clone(),
-                                contract_class,
-                            )))
-                        }
-                    }
-                }
-                TransactionMp::L1Handler(handle_l1_message_tx) => {
-                    let chain_id = self.chain_id()?.0.into();
-                    let tx_hash = handle_l1_message_tx.compute_hash::<H>(chain_id, false, Some(block_number));
-                    let paid_fee =
-                        self.backend.l1_handler_paid_fee().get_fee_paid_for_l1_handler_tx(tx_hash.into()).map_err(
-                            |e| {
-                                log::error!("Failed to retrieve fee paid on l1 for tx with hash `{tx_hash:?}`: {e}");
-                                StarknetRpcApiError::InternalServerError
-                            },
-                        )?;
-
-                    Ok(UserOrL1HandlerTransaction::L1Handler(handle_l1_message_tx.clone(), paid_fee))
-                }
-                TransactionMp::Deploy(_) => todo!(),
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        Ok(MaybePendingTransactionReceipt::Receipt(receipt))
+    }
 
-        let execution_infos = self
-            .client
-            .runtime_api()
-            .re_execute_transactions(previous_substrate_block_hash, transactions.clone())
-            .map_err(|e| {
-                log::error!("Failed to execute runtime API call: {e}");
-                StarknetRpcApiError::InternalServerError
-            })?
+    /// Returns the Merkle proofs of a contract and of some of its storage keys.
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_id` - The hash of the requested block, or number (height) of the requested block,
+    ///   or a block tag
+    /// * `contract_address` - The address of the contract
+    /// * `keys` - The storage keys to prove
+    ///
+    /// ### Returns
+    ///
+    /// * `GetStorageProofResult` - The state and class commitments, the proof of the contract in
+    ///   the contract trie and, if the contract is deployed, its state along with the proof of its
+    ///   class in the class trie and one proof per storage key. Contracts and keys which are not in
+    ///   the state are proven absent. The result can be checked offline with
+    ///   `mc_deoxys::commitments::proofs::verify_storage_proof`.
+    ///
+    /// ### Errors
+    ///
+    /// Returns `PROOF_LIMIT_EXCEEDED` if too many keys are requested, and `BLOCK_NOT_FOUND` if the
+    /// block does not exist or its state tries were pruned.
+    fn get_storage_proof(
+        &self,
+        block_id: BlockId,
+        contract_address: FieldElement,
+        keys: Vec<FieldElement>,
+    ) -> RpcResult<GetStorageProofResult> {
+        self.storage_proof(block_id, contract_address, keys)
+    }
+
+    /// Returns the latest block whose state posted on L1 matches the one of the node.
+    ///
+    /// ### Returns
+    ///
+    /// The number, hash and state root of the block, along with the hash of the L1 transaction
+    /// which posted its state when it is known.
+    ///
+    /// ### Errors
+    ///
+    /// Returns `BLOCK_NOT_FOUND` if no state posted on L1 has been verified yet.
+    fn get_l1_verified_block(&self) -> RpcResult<L1VerifiedBlock> {
+        let state = self
+            .backend
+            .meta()
+            .l1_verified_state()
             .map_err(|e| {
-                log::error!("Failed to reexecute the transactions: {e:?}");
-                StarknetRpcApiError::InternalServerError
-            })?
-            .map_err(|_| {
-                log::error!("One of the transaction failed during it's reexecution");
+                error!("Failed to read the L1 verified state: {e}");
                 StarknetRpcApiError::InternalServerError
             })?
-            .pop() // get only the last transaction execution info
-            .ok_or_else(|| {
-                log::error!("No execution info returned for the last transaction");
+            .ok_or(StarknetRpcApiError::BlockNotFound)?;
+
+        Ok(L1VerifiedBlock {
+            block_number: state.block_number,
+            block_hash: Felt252Wrapper::from(state.block_hash).into(),
+            new_root: Felt252Wrapper::from(state.global_root).into(),
+            l1_transaction_hash: state.transaction_hash,
+        })
+    }
+
+    /// Returns the status of the L1 handler transactions triggered by the messages sent to L2 in
+    /// an L1 transaction.
+    ///
+    /// ### Arguments
+    ///
+    /// * `transaction_hash` - The hash of the L1 transaction which sent the messages
+    ///
+    /// ### Returns
+    ///
+    /// The finality and execution status of each L1 handler transaction, in the order the messages
+    /// were sent. The transactions which have not been included on L2 yet are omitted.
+    ///
+    /// ### Errors
+    ///
+    /// Returns `TXN_HASH_NOT_FOUND` if no message sent by the L1 transaction has been indexed.
+    fn get_messages_status(&self, transaction_hash: H256) -> RpcResult<Vec<MessageStatus>> {
+        let l1_handler_transactions =
+            self.backend.messaging().l1_handler_transactions(transaction_hash).map_err(|e| {
+                error!("Failed to retrieve the messages sent in L1 transaction {transaction_hash:?}: {e}");
                 StarknetRpcApiError::InternalServerError
             })?;
+        if l1_handler_transactions.is_empty() {
+            return Err(StarknetRpcApiError::TxnHashNotFound.into());
+        }
+
+        let mut statuses = Vec::with_capacity(l1_handler_transactions.len());
+        for l1_handler_transaction in l1_handler_transactions {
+            let Ok(status) = self.get_transaction_status(Felt252Wrapper::from(l1_handler_transaction).0) else {
+                continue;
+            };
+
+            let failure_reason = match status.execution_status {
+                TransactionExecutionStatus::Reverted => self
+                    .backend
+                    .receipts()
+                    .get_receipt(l1_handler_transaction)
+                    .map_err(|e| {
+                        error!("Failed to retrieve the receipt of transaction {l1_handler_transaction}: {e}");
+                        StarknetRpcApiError::InternalServerError
+                    })?
+                    .and_then(|receipt| receipt.revert_reason),
+                TransactionExecutionStatus::Succeeded => None,
+            };
+
+            statuses.push(MessageStatus {
+                transaction_hash: Felt252Wrapper::from(l1_handler_transaction).0,
+                finality_status: status.finality_status,
+                execution_status: status.execution_status,
+                failure_reason,
+            });
+        }
+
+        Ok(statuses)
+    }
+
+    /// Returns the CASM class compiled from a Sierra class.
+    ///
+    /// ### Arguments
+    ///
+    /// * `class_hash` - The hash of the Sierra class
+    ///
+    /// ### Returns
+    ///
+    /// The compiled class cached by the sync.
+    ///
+    /// ### Errors
+    ///
+    /// Returns `CLASS_HASH_NOT_FOUND` if no Sierra class with this hash has been synced.
+    fn get_compiled_casm(&self, class_hash: FieldElement) -> RpcResult<CasmContractClass> {
+        let class_hash: StarkHash = Felt252Wrapper(class_hash).into();
+
+        Ok(self.cached_casm_class(class_hash)?.ok_or(StarknetRpcApiError::ClassHashNotFound)?)
+    }
+}
+
+impl<A, B, BE, G, C, P, H> Starknet<A, B, BE, G, C, P, H>
+where
+    A: ChainApi<Block = B> + 'static,
+    B: BlockT,
+    P: TransactionPool<Block = B> + 'static,
+    BE: Backend<B> + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + StorageProvider<B, BE> + 'static,
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B> + ConvertTransactionRuntimeApi<B>,
+    G: GenesisProvider + Send + Sync + 'static,
+    H: HasherT + Send + Sync + 'static,
+{
+    /// Returns the CASM class of a Sierra class, as cached by the sync when it imported the class,
+    /// or `None` if the class was not synced.
+    ///
+    /// # Arguments
+    ///
+    /// * `class_hash` - The hash of the Sierra class
+    fn cached_casm_class(&self, class_hash: StarkHash) -> Result<Option<CasmContractClass>, StarknetRpcApiError> {
+        let casm_classes = self.backend.casm_classes();
+
+        let compiled_class_hash = casm_classes.get_compiled_class_hash(class_hash).map_err(|e| {
+            error!("Failed to read the compiled class hash of class {class_hash}: {e}");
+            StarknetRpcApiError::InternalServerError
+        })?;
+        let Some(compiled_class_hash) = compiled_class_hash else {
+            return Ok(None);
+        };
+        let raw = casm_classes.get_casm_class(compiled_class_hash).map_err(|e| {
+            error!("Failed to read the compiled class of class {class_hash}: {e}");
+            StarknetRpcApiError::InternalServerError
+        })?;
 
-        // TODO(#1291): compute message hash correctly to L1HandlerTransactionReceipt
-        let message_hash: Hash256 = Hash256::from_felt(&FieldElement::default());
+        raw.map(|raw| {
+            serde_json::from_slice(&raw).map_err(|e| {
+                error!("Failed to deserialize the compiled class of class {class_hash}: {e}");
+                StarknetRpcApiError::InternalServerError
+            })
+        })
+        .transpose()
+    }
+
+    /// Returns a Sierra class as it was served by the feeder gateway, or `None` if it was not
+    /// stored by the sync.
+    ///
+    /// # Arguments
+    ///
+    /// * `class_hash` - The hash of the class
+    fn stored_sierra_class(&self, class_hash: ClassHash) -> Result<Option<ContractClass>, StarknetRpcApiError> {
+        let stored = self.backend.sierra_classes().get_stored_sierra_class(class_hash).map_err(|e| {
+            error!("Failed to fetch sierra class with hash {class_hash}: {e}");
+            StarknetRpcApiError::InternalServerError
+        })?;
+
+        Ok(stored.map(|stored| {
+            ContractClass::Sierra(sierra_contract_class_to_flattened_sierra(
+                &stored.class,
+                stored.contract_class_version,
+            ))
+        }))
+    }
+
+    /// Builds the receipt of a transaction of a block.
+    ///
+    /// The receipts stored by the sync are served as is. The other transactions are re-executed on
+    /// top of the state of the parent block.
+    ///
+    /// # Arguments
+    ///
+    /// * `substrate_block_hash` - The hash of the Substrate block wrapping the block
+    /// * `block` - The block including the transaction
+    /// * `tx_index` - The position of the transaction in the block
+    /// * `transaction_hash` - The hash of the transaction
+    fn transaction_receipt(
+        &self,
+        substrate_block_hash: B::Hash,
+        block: &StarknetBlock,
+        tx_index: usize,
+        transaction_hash: FieldElement,
+    ) -> RpcResult<TransactionReceipt> {
+        let block_number = block.header().block_number;
+        let block_hash: Felt252Wrapper = block.header().hash::<H>().into();
 
-        let actual_fee = execution_infos.actual_fee.0.into();
+        let transaction = block.transactions().get(tx_index).ok_or_else(|| {
+            log::error!("Failed to retrieve transaction at index {tx_index} from block with hash {block_hash:?}");
+            StarknetRpcApiError::InternalServerError
+        })?;
 
-        let actual_status = if block_number <= mc_deoxys::l1::ETHEREUM_STATE_UPDATE.lock().unwrap().block_number {
+        // The events are the ones stored with the block, which also covers the transactions that
+        // cannot be re-executed
+        let events = block
+            .events()
+            .iter()
+            .filter(|ordered_events| ordered_events.index() == tx_index as u128)
+            .flat_map(|ordered_events| ordered_events.events())
+            .map(starknet_api_to_starknet_rs_event)
+            .collect();
+
+        let actual_status = if self.is_accepted_on_l1(block_number) {
             TransactionFinalityStatus::AcceptedOnL1.into()
         } else {
             TransactionFinalityStatus::AcceptedOnL2.into()
         };
 
-        let execution_result = match execution_infos.revert_error.clone() {
-            Some(err) => ExecutionResult::Reverted { reason: err },
-            None => ExecutionResult::Succeeded,
-        };
+        let stored_receipt =
+            self.backend.receipts().get_receipt(Felt252Wrapper::from(transaction_hash).into()).map_err(|e| {
+                log::error!("Failed to retrieve the receipt of transaction {transaction_hash:#x}: {e}");
+                StarknetRpcApiError::InternalServerError
+            })?;
 
-        let execution_resources = match execution_infos.execute_call_info {
-            Some(ref call_info) => blockifier_call_info_to_starknet_resources(call_info),
-            None => ExecutionResources {
-                steps: 0,
-                memory_holes: None,
-                range_check_builtin_applications: 0,
-                pedersen_builtin_applications: 0,
-                poseidon_builtin_applications: 0,
-                ec_op_builtin_applications: 0,
-                ecdsa_builtin_applications: 0,
-                bitwise_builtin_applications: 0,
-                keccak_builtin_applications: 0,
-            },
-        };
+        // The transactions imported by the sync have their receipt stored. Legacy deploy transactions
+        // cannot be re-executed by the blockifier, but they were not charged any fee and their
+        // constructor could not send messages to L1.
+        let (actual_fee, messages_sent, execution_result, execution_resources) = if let Some(stored_receipt) =
+            stored_receipt
+        {
+            stored_receipt_outcome(stored_receipt)
+        } else if let TransactionMp::Deploy(_) = transaction {
+            (
+                FieldElement::ZERO,
+                vec![],
+                ExecutionResult::Succeeded,
+                actual_resources_to_starknet_resources(&Default::default()),
+            )
+        } else {
+            // The genesis block has no parent state to re-execute its transactions on
+            let previous_block_number = block_number.checked_sub(1).ok_or_else(|| {
+                log::error!("Transaction {transaction_hash:#x} of the genesis block has no stored receipt");
+                StarknetRpcApiError::InternalServerError
+            })?;
+            let previous_substrate_block_hash =
+                self.substrate_block_hash_from_starknet_block(BlockId::Number(previous_block_number)).map_err(|e| {
+                    log::error!("Failed to retrieve previous substrate block hash: {e}");
+                    StarknetRpcApiError::InternalServerError
+                })?;
 
-        let events = match execution_infos.execute_call_info {
-            Some(ref call_info) => extract_events_from_call_info(call_info),
-            None => vec![],
-        };
+            let transactions = block
+                .transactions()
+                .iter()
+                .take(tx_index + 1)
+                .filter(|tx| !matches!(tx, TransactionMp::Deploy(_))) // Legacy deploy transactions are not re-executed
+                .map(|tx| self.to_user_or_l1_handler_transaction(substrate_block_hash, block_number, tx))
+                .collect::<Result<Vec<_>, _>>()?;
 
-        let messages_sent = match execution_infos.execute_call_info {
-            Some(ref call_info) => extract_messages_from_call_info(call_info),
-            None => vec![],
+            let execution_infos = self
+                .client
+                .runtime_api()
+                .re_execute_transactions(previous_substrate_block_hash, transactions.clone())
+                .map_err(|e| {
+                    log::error!("Failed to execute runtime API call: {e}");
+                    StarknetRpcApiError::InternalServerError
+                })?
+                .map_err(|e| {
+                    log::error!("Failed to reexecute the transactions: {e:?}");
+                    StarknetRpcApiError::InternalServerError
+                })?
+                .map_err(|_| {
+                    log::error!("One of the transaction failed during it's reexecution");
+                    StarknetRpcApiError::InternalServerError
+                })?
+                .pop() // get only the last transaction execution info
+                .ok_or_else(|| {
+                    log::error!("No execution info returned for the last transaction");
+                    StarknetRpcApiError::InternalServerError
+                })?;
+
+            let actual_fee = execution_infos.actual_fee.0.into();
+
+            let execution_result = match execution_infos.revert_error.clone() {
+                Some(err) => ExecutionResult::Reverted { reason: err },
+                None => ExecutionResult::Succeeded,
+            };
+
+            // The resources of the whole transaction, including its validation and the fee transfer
+            let execution_resources = actual_resources_to_starknet_resources(&execution_infos.actual_resources);
+
+            let messages_sent = match execution_infos.execute_call_info {
+                Some(ref call_info) => extract_messages_from_call_info(call_info),
+                None => vec![],
+            };
+
+            (actual_fee, messages_sent, execution_result, execution_resources)
         };
 
-        // TODO: use actual execution ressources
         let receipt = match transaction {
             mp_transactions::Transaction::Declare(_) => TransactionReceipt::Declare(DeclareTransactionReceipt {
                 transaction_hash: transaction_hash.into(),
@@ -1653,7 +2026,7 @@ where
                     execution_resources,
                 })
             }
-            mp_transactions::Transaction::Deploy(tx) => TransactionReceipt::Deploy(DeployTransactionReceipt {
+            mp_transactions::Transaction::Invoke(_) => TransactionReceipt::Invoke(InvokeTransactionReceipt {
                 transaction_hash,
                 actual_fee,
                 finality_status: actual_status,
@@ -1661,11 +2034,11 @@ where
                 block_number,
                 messages_sent,
                 events,
-                contract_address: tx.get_account_address(),
                 execution_result,
                 execution_resources,
             }),
-            mp_transactions::Transaction::Invoke(_) => TransactionReceipt::Invoke(InvokeTransactionReceipt {
+            mp_transactions::Transaction::L1Handler(tx) => TransactionReceipt::L1Handler(L1HandlerTransactionReceipt {
+                message_hash: l1_handler_message_hash(tx),
                 transaction_hash,
                 actual_fee,
                 finality_status: actual_status,
@@ -1676,8 +2049,7 @@ where
                 execution_result,
                 execution_resources,
             }),
-            mp_transactions::Transaction::L1Handler(_) => TransactionReceipt::L1Handler(L1HandlerTransactionReceipt {
-                message_hash,
+            mp_transactions::Transaction::Deploy(tx) => TransactionReceipt::Deploy(DeployTransactionReceipt {
                 transaction_hash,
                 actual_fee,
                 finality_status: actual_status,
@@ -1685,12 +2057,13 @@ where
                 block_number,
                 messages_sent,
                 events,
+                contract_address: tx.get_account_address(),
                 execution_result,
                 execution_resources,
             }),
         };
 
-        Ok(MaybePendingTransactionReceipt::Receipt(receipt))
+        Ok(receipt)
     }
 }
 
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use jsonrpsee::core::RpcResult;
+use log::error;
+use mc_db::BonsaiDbs;
+use mc_deoxys::commitments::proofs::{get_storage_proof, ProofError};
+use mc_rpc_core::GetStorageProofResult;
+use mp_felt::Felt252Wrapper;
+use mp_hashers::HasherT;
+use pallet_starknet_runtime_api::{ConvertTransactionRuntimeApi, StarknetRuntimeApi};
+use sc_client_api::backend::{Backend, StorageProvider};
+use sc_client_api::BlockBackend;
+use sc_transaction_pool::ChainApi;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::Block as BlockT;
+use starknet_api::api_core::ContractAddress;
+use starknet_core::types::BlockId;
+use starknet_ff::FieldElement;
+
+use crate::constants::MAX_STORAGE_PROOF_KEYS;
+use crate::errors::StarknetRpcApiError;
+use crate::Starknet;
+
+impl<A: ChainApi, B, BE, G, C, P, H> Starknet<A, B, BE, G, C, P, H>
+where
+    B: BlockT,
+    C: HeaderBackend<B> + BlockBackend<B> + StorageProvider<B, BE> + 'static,
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B> + ConvertTransactionRuntimeApi<B>,
+    BE: Backend<B>,
+    H: HasherT + Send + Sync + 'static,
+{
+    /// Helper function to build the Merkle proofs of a contract and its storage
+    ///
+    /// # Arguments
+    ///
+    /// * `block_id` - The Starknet block id
+    /// * `contract_address` - The address of the contract
+    /// * `keys` - The storage keys to prove
+    ///
+    /// # Returns
+    ///
+    /// * `GetStorageProofResult` - The contract trie proof and, if the contract is deployed, its
+    ///   state, the class trie proof of its class and the storage trie proofs. Contracts and keys
+    ///   which are not in the state are proven absent
+    pub fn storage_proof(
+        &self,
+        block_id: BlockId,
+        contract_address: FieldElement,
+        keys: Vec<FieldElement>,
+    ) -> RpcResult<GetStorageProofResult> {
+        if keys.len() > MAX_STORAGE_PROOF_KEYS {
+            return Err(StarknetRpcApiError::ProofLimitExceeded.into());
+        }
+
+        let substrate_block_hash = self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| {
+            error!("'{e}'");
+            StarknetRpcApiError::BlockNotFound
+        })?;
+        let block_number = self.substrate_block_number_from_starknet_block(block_id)?;
+
+        let address: ContractAddress = Felt252Wrapper(contract_address).into();
+        let overrides = self.overrides.for_block_hash(self.client.as_ref(), substrate_block_hash);
+        let class_hash = overrides
+            .contract_class_hash_by_address(substrate_block_hash, address)
+            .map(|class_hash| Felt252Wrapper::from(class_hash).into());
+        let nonce = overrides
+            .nonce(substrate_block_hash, address)
+            .map(|nonce| Felt252Wrapper::from(nonce).into())
+            .unwrap_or(FieldElement::ZERO);
+
+        let bonsai_dbs = BonsaiDbs {
+            contract: Arc::clone(self.backend.bonsai_contract()),
+            class: Arc::clone(self.backend.bonsai_class()),
+            storage: Arc::clone(self.backend.bonsai_storage()),
+        };
+
+        get_storage_proof(block_number, address, &keys, class_hash, nonce, &bonsai_dbs).map_err(|e| {
+            error!("Failed to build the storage proof at block {block_number}: {e}");
+            match e {
+                ProofError::SnapshotUnavailable(_) => StarknetRpcApiError::BlockNotFound.into(),
+                _ => StarknetRpcApiError::InternalServerError.into(),
+            }
+        })
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+
+use futures::{future, stream, Stream, StreamExt};
+use jsonrpsee::types::SubscriptionResult;
+use jsonrpsee::SubscriptionSink;
+use log::error;
+use mc_deoxys::l2::subscribe_reorgs;
+use mc_genesis_data_provider::GenesisProvider;
+use mc_mapping_sync::{subscribe_mapping_events, MappingEvent};
+use mc_rpc_core::utils::get_block_by_block_hash;
+use mc_rpc_core::{NewHead, StarknetPubSubRpcApiServer, StarknetReadRpcApiServer, TransactionStatusUpdate};
+use mp_felt::Felt252Wrapper;
+use mp_hashers::HasherT;
+use mp_transactions::TransactionStatus;
+use pallet_starknet_runtime_api::{ConvertTransactionRuntimeApi, StarknetRuntimeApi};
+use sc_client_api::{Backend, BlockBackend, StorageProvider};
+use sc_transaction_pool::ChainApi;
+use sc_transaction_pool_api::TransactionPool;
+use sp_api::ProvideRuntimeApi;
+use sp_arithmetic::traits::UniqueSaturatedInto;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::Block as BlockT;
+use starknet_core::types::{BlockId, FieldElement, TransactionFinalityStatus};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::constants::MAX_EVENTS_KEYS;
+use crate::errors::StarknetRpcApiError;
+use crate::Starknet;
+
+impl<A, B, BE, G, C, P, H> Starknet<A, B, BE, G, C, P, H>
+where
+    A: ChainApi<Block = B> + 'static,
+    B: BlockT,
+    BE: Backend<B> + 'static,
+    G: GenesisProvider + Send + Sync + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + StorageProvider<B, BE> + 'static,
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B> + ConvertTransactionRuntimeApi<B>,
+    P: TransactionPool<Block = B> + 'static,
+    H: HasherT + Send + Sync + 'static,
+{
+    /// Streams the numbers of the new blocks of the best chain, in order and without gaps.
+    ///
+    /// A block is only yielded once the `MappingSyncWorker` has written its Starknet mapping, so
+    /// that it can be served by the other rpc methods. The blocks reverted by a reorg are yielded
+    /// again once the blocks of the new chain are mapped.
+    fn mapped_blocks(self) -> impl Stream<Item = u64> + Send {
+        let best_number: u64 = self.client.info().best_number.unique_saturated_into();
+        let state = (self, subscribe_mapping_events(), best_number + 1, VecDeque::new());
+
+        stream::unfold(state, |(starknet, mut events, mut next_block, mut pending)| async move {
+            loop {
+                if let Some(block_number) = pending.pop_front() {
+                    return Some((block_number, (starknet, events, next_block, pending)));
+                }
+
+                match events.recv().await {
+                    Ok(MappingEvent::Reverted(ancestor)) => {
+                        next_block = next_block.min(ancestor + 1);
+                        continue;
+                    }
+                    // A lagging subscriber catches up from the mapping db
+                    Ok(MappingEvent::Mapped(_)) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return None,
+                }
+
+                pending.extend(starknet.next_mapped_blocks(&mut next_block));
+            }
+        })
+    }
+
+    /// Returns the blocks of the best chain from `next_block` whose mapping has been written,
+    /// stopping at the first one which is not mapped yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `next_block` - The number of the first block to return, moved past the returned blocks
+    fn next_mapped_blocks(&self, next_block: &mut u64) -> Vec<u64> {
+        let best_number: u64 = self.client.info().best_number.unique_saturated_into();
+
+        let mut block_numbers = Vec::new();
+        while *next_block <= best_number {
+            let is_mapped = match self.client.hash((*next_block).unique_saturated_into()) {
+                Ok(Some(substrate_block_hash)) => {
+                    self.backend.mapping().is_synced(&substrate_block_hash).unwrap_or_else(|e| {
+                        error!("Failed to read the mapping of block {next_block}: {e}");
+                        false
+                    })
+                }
+                _ => false,
+            };
+            if !is_mapped {
+                break;
+            }
+
+            block_numbers.push(*next_block);
+            *next_block += 1;
+        }
+
+        block_numbers
+    }
+
+    /// Returns the header of a block of the best chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_number` - The Starknet block number
+    fn new_head(&self, block_number: u64) -> Result<NewHead, StarknetRpcApiError> {
+        let substrate_block_hash = self.substrate_block_hash_from_starknet_block(BlockId::Number(block_number))?;
+        let starknet_block = get_block_by_block_hash(self.client.as_ref(), substrate_block_hash).map_err(|e| {
+            error!("Failed to get block {block_number}: {e}");
+            StarknetRpcApiError::BlockNotFound
+        })?;
+        let header = starknet_block.header();
+
+        Ok(NewHead {
+            block_hash: header.hash::<H>().into(),
+            parent_hash: Felt252Wrapper::from(header.parent_block_hash).into(),
+            block_number: header.block_number,
+            new_root: Felt252Wrapper::from(header.global_state_root).into(),
+            timestamp: header.block_timestamp,
+            sequencer_address: Felt252Wrapper::from(header.sequencer_address).into(),
+            l1_gas_price: header.l1_gas_price.into(),
+            starknet_version: header.protocol_version.from_utf8().map_err(|_| {
+                error!("Invalid starknet version in block {block_number}");
+                StarknetRpcApiError::InternalServerError
+            })?,
+        })
+    }
+}
+
+impl<A, B, BE, G, C, P, H> StarknetPubSubRpcApiServer for Starknet<A, B, BE, G, C, P, H>
+where
+    A: ChainApi<Block = B> + 'static,
+    B: BlockT,
+    BE: Backend<B> + 'static,
+    G: GenesisProvider + Send + Sync + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + StorageProvider<B, BE> + 'static,
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B> + ConvertTransactionRuntimeApi<B>,
+    P: TransactionPool<Block = B> + 'static,
+    H: HasherT + Send + Sync + 'static,
+{
+    fn subscribe_reorg(&self, mut sink: SubscriptionSink) -> SubscriptionResult {
+        let reorgs = stream::unfold(subscribe_reorgs(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(reorg) => return Some((reorg, receiver)),
+                    // A lagging subscriber misses the oldest reorgs instead of slowing down the sync
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            sink.pipe_from_stream(Box::pin(reorgs)).await;
+        });
+
+        Ok(())
+    }
+
+    fn subscribe_new_heads(&self, mut sink: SubscriptionSink) -> SubscriptionResult {
+        let starknet = self.clone();
+        let heads = self.clone().mapped_blocks().filter_map(move |block_number| {
+            future::ready(
+                starknet
+                    .new_head(block_number)
+                    .map_err(|e| error!("Failed to get the header of block {block_number}: {e}"))
+                    .ok(),
+            )
+        });
+
+        tokio::spawn(async move {
+            sink.pipe_from_stream(Box::pin(heads)).await;
+        });
+
+        Ok(())
+    }
+
+    fn subscribe_events(
+        &self,
+        mut sink: SubscriptionSink,
+        from_address: Option<FieldElement>,
+        keys: Option<Vec<Vec<FieldElement>>>,
+    ) -> SubscriptionResult {
+        let keys = keys.unwrap_or_default();
+        if keys.len() > MAX_EVENTS_KEYS {
+            let _ = sink.reject(StarknetRpcApiError::TooManyKeysInFilter);
+            return Ok(());
+        }
+        let from_address = from_address.map(Felt252Wrapper::from);
+
+        let starknet = self.clone();
+        let events = self.clone().mapped_blocks().flat_map(move |block_number| {
+            let block_events =
+                starknet.get_filtered_block_events(block_number, from_address, &keys).unwrap_or_else(|e| {
+                    error!("Failed to get the events of block {block_number}: {e}");
+                    Vec::new()
+                });
+            stream::iter(block_events)
+        });
+
+        tokio::spawn(async move {
+            sink.pipe_from_stream(Box::pin(events)).await;
+        });
+
+        Ok(())
+    }
+
+    fn subscribe_transaction_status(
+        &self,
+        mut sink: SubscriptionSink,
+        transaction_hash: FieldElement,
+    ) -> SubscriptionResult {
+        let blocks = Box::pin(self.clone().mapped_blocks());
+        let state = (self.clone(), blocks, None::<TransactionStatus>);
+
+        // The status can only change when a block is mapped: the transaction is included, or the
+        // L1 verified head moves past its block
+        let statuses = stream::unfold(state, move |(starknet, mut blocks, last_status)| async move {
+            if last_status
+                .as_ref()
+                .is_some_and(|status| status.finality_status == TransactionFinalityStatus::AcceptedOnL1)
+            {
+                return None;
+            }
+
+            loop {
+                if let Ok(status) = starknet.get_transaction_status(transaction_hash) {
+                    if last_status.as_ref() != Some(&status) {
+                        let update = TransactionStatusUpdate { transaction_hash, status: status.clone() };
+                        return Some((update, (starknet, blocks, Some(status))));
+                    }
+                }
+
+                blocks.next().await?;
+            }
+        });
+
+        tokio::spawn(async move {
+            sink.pipe_from_stream(Box::pin(statuses)).await;
+        });
+
+        Ok(())
+    }
+}
@@ -6,13 +6,17 @@ use blockifier::transaction::errors::TransactionExecutionError;
 use blockifier::transaction::objects::TransactionExecutionInfo;
 use jsonrpsee::core::{async_trait, RpcResult};
 use log::error;
+use mc_db::DbError;
 use mc_genesis_data_provider::GenesisProvider;
 use mc_rpc_core::utils::get_block_by_block_hash;
-use mc_rpc_core::{StarknetReadRpcApiServer, StarknetTraceRpcApiServer};
+use mc_rpc_core::{
+    simulations, ContractStateOverride, SimulationFlag, StarknetReadRpcApiServer, StarknetTraceRpcApiServer,
+};
 use mc_storage::StorageOverride;
+use mp_block::Block as StarknetBlock;
 use mp_felt::Felt252Wrapper;
 use mp_hashers::HasherT;
-use mp_simulations::{PlaceHolderErrorTypeForFailedStarknetExecution, SimulationFlags};
+use mp_simulations::TransactionExecutionFailure;
 use mp_transactions::compute_hash::ComputeTransactionHash;
 use mp_transactions::{DeclareTransaction, Transaction, TxType, UserOrL1HandlerTransaction, UserTransaction};
 use pallet_starknet_runtime_api::{ConvertTransactionRuntimeApi, StarknetRuntimeApi};
@@ -23,15 +27,17 @@ use sp_api::ProvideRuntimeApi;
 use sp_blockchain::HeaderBackend;
 use sp_runtime::traits::Block as BlockT;
 use starknet_api::api_core::{ClassHash, ContractAddress};
+use starknet_api::hash::StarkFelt;
+use starknet_api::transaction::Fee;
 use starknet_core::types::{
     BlockId, BroadcastedTransaction, DeclareTransactionTrace, DeployAccountTransactionTrace, ExecuteInvocation,
     FeeEstimate, InvokeTransactionTrace, L1HandlerTransactionTrace, RevertedInvocation, SimulatedTransaction,
-    SimulationFlag, TransactionTrace, TransactionTraceWithHash,
+    TransactionTrace, TransactionTraceWithHash,
 };
 use starknet_ff::FieldElement;
 use thiserror::Error;
 
-use crate::errors::StarknetRpcApiError;
+use crate::errors::{StarknetRpcApiError, TxnExecutionError};
 use crate::Starknet;
 
 #[async_trait]
@@ -53,6 +59,7 @@ where
         block_id: BlockId,
         transactions: Vec<BroadcastedTransaction>,
         simulation_flags: Vec<SimulationFlag>,
+        state_overrides: Option<Vec<ContractStateOverride>>,
     ) -> RpcResult<Vec<SimulatedTransaction>> {
         let substrate_block_hash =
             self.substrate_block_hash_from_starknet_block(block_id).map_err(|e| StarknetRpcApiError::BlockNotFound)?;
@@ -74,12 +81,13 @@ where
                 },
             )?;
 
-        let simulation_flags = SimulationFlags::from(simulation_flags);
+        let simulation_flags = simulations::simulation_flags(&simulation_flags);
+        let state_overrides = simulations::state_overrides(state_overrides.unwrap_or_default());
 
         let res = self
             .client
             .runtime_api()
-            .simulate_transactions(substrate_block_hash, user_transactions, simulation_flags)
+            .simulate_transactions(substrate_block_hash, user_transactions, simulation_flags, state_overrides)
             .map_err(|e| {
                 error!("Request parameters error: {e}");
                 StarknetRpcApiError::InternalServerError
@@ -90,9 +98,17 @@ where
             })?;
 
         let storage_override = self.overrides.for_block_hash(self.client.as_ref(), substrate_block_hash);
-        let simulated_transactions =
-            tx_execution_infos_to_simulated_transactions(&**storage_override, substrate_block_hash, tx_types, res)
-                .map_err(StarknetRpcApiError::from)?;
+        let execution_infos = res
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|failure| transaction_execution_error(&**storage_override, substrate_block_hash, failure))?;
+        let simulated_transactions = tx_execution_infos_to_simulated_transactions(
+            &**storage_override,
+            substrate_block_hash,
+            tx_types,
+            execution_infos,
+        )
+        .map_err(StarknetRpcApiError::from)?;
 
         Ok(simulated_transactions)
     }
@@ -107,103 +123,141 @@ where
             error!("Failed to get block for block hash {substrate_block_hash}: '{e}'");
             StarknetRpcApiError::InternalServerError
         })?;
+        let block_hash: FieldElement = starknet_block.header().hash::<H>().into();
 
-        let block_transactions = starknet_block
-            .transactions()
-            .iter()
-            .map(|tx| match tx {
-                Transaction::Invoke(invoke_tx) => {
-                    RpcResult::Ok(UserOrL1HandlerTransaction::User(UserTransaction::Invoke(invoke_tx.clone())))
-                }
-                Transaction::DeployAccount(deploy_account_tx) => {
-                    Ok(UserOrL1HandlerTransaction::User(UserTransaction::DeployAccount(deploy_account_tx.clone())))
-                }
-                Transaction::Declare(declare_tx) => {
-                    let class_hash = ClassHash::from(*declare_tx.class_hash());
-
-                    match declare_tx {
-                        DeclareTransaction::V0(_) | DeclareTransaction::V1(_) => {
-                            let contract_class = self
-                                .overrides
-                                .for_block_hash(self.client.as_ref(), substrate_block_hash)
-                                .contract_class_by_class_hash(substrate_block_hash, class_hash)
-                                .ok_or_else(|| {
-                                    error!("Failed to retrieve contract class from hash '{class_hash}'");
-                                    StarknetRpcApiError::InternalServerError
-                                })?;
-
-                            Ok(UserOrL1HandlerTransaction::User(UserTransaction::Declare(
-                                declare_tx.clone(),
-                                contract_class,
-                            )))
-                        }
-                        DeclareTransaction::V2(tx) => {
-                            let contract_class = self
-                                .backend
-                                .sierra_classes()
-                                .get_sierra_class(class_hash)
-                                .map_err(|e| {
-                                    error!("Failed to fetch sierra class with hash {class_hash}: {e}");
-                                    StarknetRpcApiError::InternalServerError
-                                })?
-                                .ok_or_else(|| {
-                                    error!("The sierra class with hash {class_hash} is not present in db backend");
-                                    StarknetRpcApiError::InternalServerError
-                                })?;
-                            let contract_class = mp_transactions::utils::sierra_to_casm_contract_class(contract_class)
-                                .map_err(|e| {
-                                    error!("Failed to convert the SierraContractClass to CasmContractClass: {e}");
-                                    StarknetRpcApiError::InternalServerError
-                                })?;
-                            let contract_class =
-                                ContractClass::V1(ContractClassV1::try_from(contract_class).map_err(|e| {
-                                    error!(
-                                        "Failed to convert the compiler CasmContractClass to blockifier \
-                                         CasmContractClass: {e}"
-                                    );
-                                    StarknetRpcApiError::InternalServerError
-                                })?);
-
-                            Ok(UserOrL1HandlerTransaction::User(UserTransaction::Declare(
-                                declare_tx.clone(),
-                                contract_class,
-                            )))
-                        }
-                    }
-                }
-                Transaction::L1Handler(handle_l1_message_tx) => {
-                    let chain_id = self.chain_id()?.0.into();
-                    let tx_hash = handle_l1_message_tx.compute_hash::<H>(
-                        chain_id,
-                        false,
-                        Some(starknet_block.header().block_number),
-                    );
-                    let paid_fee =
-                        self.backend.l1_handler_paid_fee().get_fee_paid_for_l1_handler_tx(tx_hash.into()).map_err(
-                            |e| {
-                                error!("Failed to retrieve fee paid on l1 for tx with hash `{tx_hash:?}`: {e}");
-                                StarknetRpcApiError::InternalServerError
-                            },
-                        )?;
+        if let Some(traces) = self.trace_cache.block_traces(block_hash) {
+            return Ok(traces);
+        }
 
-                    Ok(UserOrL1HandlerTransaction::L1Handler(handle_l1_message_tx.clone(), paid_fee))
-                }
-                Transaction::Deploy(_) => todo!(),
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        let traces = self.trace_transactions(substrate_block_hash, &starknet_block, starknet_block.transactions())?;
+        self.trace_cache.insert(block_hash, traces.clone(), true);
+
+        Ok(traces)
+    }
+
+    async fn trace_transaction(&self, transaction_hash: FieldElement) -> RpcResult<TransactionTrace> {
+        let substrate_block_hash = self
+            .backend
+            .mapping()
+            .block_hash_from_transaction_hash(Felt252Wrapper::from(transaction_hash).into())
+            .map_err(|e| {
+                error!("Failed to retrieve substrate block hash: {e}");
+                StarknetRpcApiError::InternalServerError
+            })?
+            .ok_or(StarknetRpcApiError::TxnHashNotFound)?;
+
+        let starknet_block = get_block_by_block_hash(self.client.as_ref(), substrate_block_hash).map_err(|e| {
+            error!("Failed to get block for block hash {substrate_block_hash}: '{e}'");
+            StarknetRpcApiError::InternalServerError
+        })?;
+        let block_hash: FieldElement = starknet_block.header().hash::<H>().into();
+
+        if let Some(trace) = self.trace_cache.transaction_trace(block_hash, transaction_hash) {
+            return Ok(trace);
+        }
+
+        // Only the transactions up to the traced one have to be re-executed
+        let tx_index = self.transaction_index(substrate_block_hash, &starknet_block, transaction_hash)?;
+        let transactions = &starknet_block.transactions()[..=tx_index];
+        let traces = self.trace_transactions(substrate_block_hash, &starknet_block, transactions)?;
+
+        let trace = traces.last().map(|trace| trace.trace_root.clone()).ok_or_else(|| {
+            error!("No trace returned for transaction with hash {transaction_hash:#x}");
+            StarknetRpcApiError::InternalServerError
+        })?;
+        self.trace_cache.insert(block_hash, traces, transactions.len() == starknet_block.transactions().len());
+
+        Ok(trace)
+    }
+}
+
+impl<A, B, BE, G, C, P, H> Starknet<A, B, BE, G, C, P, H>
+where
+    A: ChainApi<Block = B> + 'static,
+    B: BlockT,
+    BE: Backend<B> + 'static,
+    G: GenesisProvider + Send + Sync + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + StorageProvider<B, BE> + 'static,
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B> + ConvertTransactionRuntimeApi<B>,
+    P: TransactionPool<Block = B> + 'static,
+    H: HasherT + Send + Sync + 'static,
+{
+    /// Returns the position of a transaction in its block.
+    ///
+    /// # Arguments
+    ///
+    /// * `substrate_block_hash` - The hash of the Substrate block wrapping the block
+    /// * `starknet_block` - The block including the transaction
+    /// * `transaction_hash` - The hash of the transaction
+    fn transaction_index(
+        &self,
+        substrate_block_hash: B::Hash,
+        starknet_block: &StarknetBlock,
+        transaction_hash: FieldElement,
+    ) -> RpcResult<usize> {
+        let chain_id = Felt252Wrapper(self.chain_id()?.0);
+        let extrinsics = self
+            .client
+            .block_body(substrate_block_hash)
+            .map_err(|e| {
+                error!("Failed to get block body for block hash {substrate_block_hash}: '{e}'");
+                StarknetRpcApiError::InternalServerError
+            })?
+            .unwrap_or_default();
 
-        let previous_block_substrate_hash = {
-            let starknet_block = get_block_by_block_hash(self.client.as_ref(), substrate_block_hash).map_err(|e| {
-                error!("Failed to starknet block for substate block with hash {substrate_block_hash}: {e}");
+        let runtime_api = self.client.runtime_api();
+        let index_and_tx = runtime_api
+            .get_index_and_tx_for_tx_hash(substrate_block_hash, extrinsics.clone(), chain_id, transaction_hash.into())
+            .map_err(|e| {
+                error!("Failed to execute runtime API call: {e}");
                 StarknetRpcApiError::InternalServerError
             })?;
-            let block_number = starknet_block.header().block_number;
-            let previous_block_number = block_number - 1;
-            self.substrate_block_hash_from_starknet_block(BlockId::Number(previous_block_number)).map_err(|e| {
+
+        if let Some((extrinsic_index, _)) = index_and_tx {
+            // The extrinsics of the block also include inherents, which are not Starknet transactions
+            let previous_transactions = runtime_api
+                .extrinsic_filter(substrate_block_hash, extrinsics[..extrinsic_index as usize].to_vec())
+                .map_err(|e| {
+                    error!("Failed to execute runtime API call: {e}");
+                    StarknetRpcApiError::InternalServerError
+                })?;
+            return Ok(previous_transactions.len());
+        }
+
+        // Blocks imported by the sync carry their transactions in a digest rather than as extrinsics
+        starknet_block
+            .transactions_hashes::<H>(chain_id, Some(starknet_block.header().block_number))
+            .position(|hash| FieldElement::from(hash) == transaction_hash)
+            .ok_or_else(|| StarknetRpcApiError::TxnHashNotFound.into())
+    }
+
+    /// Re-executes the first transactions of a block on top of the state of its parent and
+    /// returns their traces.
+    ///
+    /// # Arguments
+    ///
+    /// * `substrate_block_hash` - The hash of the Substrate block wrapping the block
+    /// * `starknet_block` - The block including the transactions
+    /// * `transactions` - The transactions to trace, which must be a prefix of those of the block
+    fn trace_transactions(
+        &self,
+        substrate_block_hash: B::Hash,
+        starknet_block: &StarknetBlock,
+        transactions: &[Transaction],
+    ) -> RpcResult<Vec<TransactionTraceWithHash>> {
+        let block_number = starknet_block.header().block_number;
+
+        let block_transactions = transactions
+            .iter()
+            .map(|tx| self.to_user_or_l1_handler_transaction(substrate_block_hash, block_number, tx))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let previous_block_substrate_hash =
+            self.substrate_block_hash_from_starknet_block(BlockId::Number(block_number - 1)).map_err(|e| {
                 error!("Failed to retrieve previous block substrate hash: {e}");
                 StarknetRpcApiError::InternalServerError
-            })
-        }?;
+            })?;
 
         let execution_infos = self
             .client
@@ -241,7 +295,7 @@ where
                 )
                 .map(|trace_root| TransactionTraceWithHash {
                     transaction_hash: block_transactions[tx_idx]
-                        .compute_hash::<H>(chain_id, false, Some(starknet_block.header().block_number))
+                        .compute_hash::<H>(chain_id, false, Some(block_number))
                         .into(),
                     trace_root,
                 })
@@ -251,12 +305,118 @@ where
 
         Ok(traces)
     }
+
+    /// Returns the fee paid on L1 for an L1 handler transaction.
+    ///
+    /// The paid fee is only known for the messages consumed by this node. For the transactions
+    /// imported by the sync, the actual fee of their stored receipt is used instead, which is all
+    /// the re-execution needs.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_hash` - The hash of the L1 handler transaction
+    pub(crate) fn l1_handler_paid_fee(&self, transaction_hash: StarkFelt) -> Result<Fee, StarknetRpcApiError> {
+        match self.backend.l1_handler_paid_fee().get_fee_paid_for_l1_handler_tx(transaction_hash) {
+            Ok(paid_fee) => return Ok(paid_fee),
+            Err(DbError::ValueNotInitialized(..)) => {}
+            Err(e) => {
+                error!("Failed to retrieve fee paid on l1 for tx with hash `{transaction_hash}`: {e}");
+                return Err(StarknetRpcApiError::InternalServerError);
+            }
+        }
+
+        let receipt = self
+            .backend
+            .receipts()
+            .get_receipt(transaction_hash)
+            .map_err(|e| {
+                error!("Failed to retrieve the receipt of tx with hash `{transaction_hash}`: {e}");
+                StarknetRpcApiError::InternalServerError
+            })?
+            .ok_or_else(|| {
+                error!("Neither the fee paid on l1 nor the receipt of tx with hash `{transaction_hash}` are stored");
+                StarknetRpcApiError::InternalServerError
+            })?;
+
+        u128::try_from(Felt252Wrapper::from(receipt.actual_fee)).map(Fee).map_err(|e| {
+            error!("Invalid actual fee for tx with hash `{transaction_hash}`: {e}");
+            StarknetRpcApiError::InternalServerError
+        })
+    }
+
+    /// Converts a transaction of a block into the form re-executed by the runtime, retrieving the
+    /// class declared or the fee paid on L1 when needed.
+    pub(crate) fn to_user_or_l1_handler_transaction(
+        &self,
+        substrate_block_hash: B::Hash,
+        block_number: u64,
+        tx: &Transaction,
+    ) -> RpcResult<UserOrL1HandlerTransaction> {
+        match tx {
+            Transaction::Invoke(invoke_tx) => {
+                Ok(UserOrL1HandlerTransaction::User(UserTransaction::Invoke(invoke_tx.clone())))
+            }
+            Transaction::DeployAccount(deploy_account_tx) => {
+                Ok(UserOrL1HandlerTransaction::User(UserTransaction::DeployAccount(deploy_account_tx.clone())))
+            }
+            Transaction::Declare(declare_tx) => {
+                let class_hash = ClassHash::from(*declare_tx.class_hash());
+
+                match declare_tx {
+                    DeclareTransaction::V0(_) | DeclareTransaction::V1(_) => {
+                        let contract_class = self
+                            .overrides
+                            .for_block_hash(self.client.as_ref(), substrate_block_hash)
+                            .contract_class_by_class_hash(substrate_block_hash, class_hash)
+                            .ok_or_else(|| {
+                                error!("Failed to retrieve contract class from hash '{class_hash}'");
+                                StarknetRpcApiError::InternalServerError
+                            })?;
+
+                        Ok(UserOrL1HandlerTransaction::User(UserTransaction::Declare(
+                            declare_tx.clone(),
+                            contract_class,
+                        )))
+                    }
+                    DeclareTransaction::V2(_) => {
+                        let contract_class = self.cached_casm_class(class_hash.0)?.ok_or_else(|| {
+                            error!("The compiled class of class {class_hash} is not present in db backend");
+                            StarknetRpcApiError::InternalServerError
+                        })?;
+                        let contract_class =
+                            ContractClass::V1(ContractClassV1::try_from(contract_class).map_err(|e| {
+                                error!(
+                                    "Failed to convert the compiler CasmContractClass to blockifier \
+                                     CasmContractClass: {e}"
+                                );
+                                StarknetRpcApiError::InternalServerError
+                            })?);
+
+                        Ok(UserOrL1HandlerTransaction::User(UserTransaction::Declare(
+                            declare_tx.clone(),
+                            contract_class,
+                        )))
+                    }
+                }
+            }
+            Transaction::L1Handler(handle_l1_message_tx) => {
+                let chain_id = self.chain_id()?.0.into();
+                let tx_hash = handle_l1_message_tx.compute_hash::<H>(chain_id, false, Some(block_number));
+                let paid_fee = self.l1_handler_paid_fee(tx_hash.into())?;
+
+                Ok(UserOrL1HandlerTransaction::L1Handler(handle_l1_message_tx.clone(), paid_fee))
+            }
+            // TODO: support re-executing deploy transactions
+            Transaction::Deploy(_) => {
+                error!("Re-executing a deploy transaction is not supported yet");
+                Err(StarknetRpcApiError::UnimplementedMethod.into())
+            }
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum ConvertCallInfoToExecuteInvocationError {
-    #[error("One of the simulated transaction failed")]
-    TransactionExecutionFailed,
     #[error(transparent)]
     GetFunctionInvocation(#[from] TryFuntionInvocationFromCallInfoError),
 }
@@ -264,7 +424,6 @@ pub enum ConvertCallInfoToExecuteInvocationError {
 impl From<ConvertCallInfoToExecuteInvocationError> for StarknetRpcApiError {
     fn from(err: ConvertCallInfoToExecuteInvocationError) -> Self {
         match err {
-            ConvertCallInfoToExecuteInvocationError::TransactionExecutionFailed => StarknetRpcApiError::ContractError,
             ConvertCallInfoToExecuteInvocationError::GetFunctionInvocation(_) => {
                 StarknetRpcApiError::InternalServerError
             }
@@ -479,32 +638,59 @@ fn tx_execution_infos_to_simulated_transactions<B: BlockT>(
     storage_override: &dyn StorageOverride<B>,
     substrate_block_hash: B::Hash,
     tx_types: Vec<TxType>,
-    transaction_execution_results: Vec<
-        Result<TransactionExecutionInfo, PlaceHolderErrorTypeForFailedStarknetExecution>,
-    >,
+    tx_execution_infos: Vec<TransactionExecutionInfo>,
 ) -> Result<Vec<SimulatedTransaction>, ConvertCallInfoToExecuteInvocationError> {
     let mut results = vec![];
-    for (tx_type, res) in tx_types.into_iter().zip(transaction_execution_results.into_iter()) {
-        match res {
-            Ok(tx_exec_info) => {
-                let transaction_trace =
-                    tx_execution_infos_to_tx_trace(storage_override, substrate_block_hash, tx_type, &tx_exec_info)?;
-                let gas_consumed =
-                    tx_exec_info.execute_call_info.as_ref().map(|x| x.execution.gas_consumed).unwrap_or_default();
-                let overall_fee = tx_exec_info.actual_fee.0 as u64;
-                // TODO: Shouldn't the gas price be taken from the block header instead?
-                let gas_price = if gas_consumed > 0 { overall_fee / gas_consumed } else { 0 };
-
-                results.push(SimulatedTransaction {
-                    transaction_trace,
-                    fee_estimation: FeeEstimate { gas_consumed, gas_price, overall_fee },
-                });
-            }
-            Err(_) => {
-                return Err(ConvertCallInfoToExecuteInvocationError::TransactionExecutionFailed);
-            }
-        }
+    for (tx_type, tx_exec_info) in tx_types.into_iter().zip(tx_execution_infos.into_iter()) {
+        let transaction_trace =
+            tx_execution_infos_to_tx_trace(storage_override, substrate_block_hash, tx_type, &tx_exec_info)?;
+        let gas_consumed =
+            tx_exec_info.execute_call_info.as_ref().map(|x| x.execution.gas_consumed).unwrap_or_default();
+        let overall_fee = tx_exec_info.actual_fee.0 as u64;
+        // TODO: Shouldn't the gas price be taken from the block header instead?
+        let gas_price = if gas_consumed > 0 { overall_fee / gas_consumed } else { 0 };
+
+        results.push(SimulatedTransaction {
+            transaction_trace,
+            fee_estimation: FeeEstimate { gas_consumed, gas_price, overall_fee },
+        });
     }
 
     Ok(results)
 }
+
+/// Reports a failed simulation as a `TRANSACTION_EXECUTION_ERROR`, along with the trace of the
+/// calls the failing transaction completed.
+fn transaction_execution_error<B: BlockT>(
+    storage_override: &dyn StorageOverride<B>,
+    substrate_block_hash: B::Hash,
+    failure: TransactionExecutionFailure,
+) -> jsonrpsee::core::Error {
+    let mut class_hash_cache: HashMap<ContractAddress, FieldElement> = HashMap::new();
+
+    let partial_trace = failure
+        .partial_call_infos
+        .iter()
+        .map(|call_info| {
+            try_get_funtion_invocation_from_call_info(
+                storage_override,
+                substrate_block_hash,
+                call_info,
+                &mut class_hash_cache,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>();
+
+    match partial_trace {
+        Ok(partial_trace) => TxnExecutionError {
+            transaction_index: failure.transaction_index,
+            execution_error: failure.revert_reason,
+            partial_trace,
+        }
+        .into(),
+        Err(e) => {
+            error!("Failed to trace failed transaction at index {}: {e}", failure.transaction_index);
+            StarknetRpcApiError::InternalServerError.into()
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Cache of the transaction traces computed by the trace rpc methods.
+//!
+//! Traces are computed by re-executing the transactions of a block on top of the state of its
+//! parent, which is costly. The traces of the most recently traced blocks are kept in memory,
+//! keyed by the hash of their Starknet block so that a reorg never serves stale traces.
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use starknet_core::types::{TransactionTrace, TransactionTraceWithHash};
+use starknet_ff::FieldElement;
+
+/// The traces of the first transactions of a block.
+struct BlockTraces {
+    traces: Vec<TransactionTraceWithHash>,
+    /// Whether `traces` covers every transaction of the block.
+    complete: bool,
+}
+
+struct Inner {
+    blocks: HashMap<FieldElement, BlockTraces>,
+    /// Block hashes from the least to the most recently used.
+    usage: VecDeque<FieldElement>,
+}
+
+impl Inner {
+    fn touch(&mut self, block_hash: FieldElement) {
+        if let Some(position) = self.usage.iter().position(|hash| hash == &block_hash) {
+            self.usage.remove(position);
+        }
+        self.usage.push_back(block_hash);
+    }
+}
+
+/// A least recently used cache of block traces.
+pub struct TraceCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl TraceCache {
+    /// Creates a cache holding the traces of at most `capacity` blocks.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, inner: Mutex::new(Inner { blocks: HashMap::new(), usage: VecDeque::new() }) }
+    }
+
+    /// Returns the traces of all the transactions of a block, if they have all been computed.
+    pub fn block_traces(&self, block_hash: FieldElement) -> Option<Vec<TransactionTraceWithHash>> {
+        let mut inner = self.inner.lock().unwrap();
+        let traces = inner.blocks.get(&block_hash).filter(|block| block.complete)?.traces.clone();
+        inner.touch(block_hash);
+
+        Some(traces)
+    }
+
+    /// Returns the trace of a transaction of a block, if it has been computed.
+    pub fn transaction_trace(
+        &self,
+        block_hash: FieldElement,
+        transaction_hash: FieldElement,
+    ) -> Option<TransactionTrace> {
+        let mut inner = self.inner.lock().unwrap();
+        let trace = inner
+            .blocks
+            .get(&block_hash)?
+            .traces
+            .iter()
+            .find(|trace| trace.transaction_hash == transaction_hash)?
+            .trace_root
+            .clone();
+        inner.touch(block_hash);
+
+        Some(trace)
+    }
+
+    /// Stores the traces of the first transactions of a block, evicting the least recently used
+    /// block if the cache is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_hash` - The hash of the Starknet block
+    /// * `traces` - The traces of the transactions of the block, in order, from the first one
+    /// * `complete` - Whether `traces` covers every transaction of the block
+    pub fn insert(&self, block_hash: FieldElement, traces: Vec<TransactionTraceWithHash>, complete: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        match inner.blocks.get_mut(&block_hash) {
+            // Only keep the longest prefix computed so far
+            Some(block) if block.complete || block.traces.len() >= traces.len() => {}
+            Some(block) => *block = BlockTraces { traces, complete },
+            None => {
+                if inner.blocks.len() >= self.capacity {
+                    if let Some(evicted) = inner.usage.pop_front() {
+                        inner.blocks.remove(&evicted);
+                    }
+                }
+                inner.blocks.insert(block_hash, BlockTraces { traces, complete });
+            }
+        }
+        inner.touch(block_hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet_core::types::{CallType, EntryPointType, FunctionInvocation, L1HandlerTransactionTrace};
+
+    use super::*;
+
+    fn trace(transaction_hash: u64) -> TransactionTraceWithHash {
+        TransactionTraceWithHash {
+            transaction_hash: FieldElement::from(transaction_hash),
+            trace_root: TransactionTrace::L1Handler(L1HandlerTransactionTrace {
+                function_invocation: FunctionInvocation {
+                    contract_address: FieldElement::ZERO,
+                    entry_point_selector: FieldElement::ZERO,
+                    calldata: vec![],
+                    caller_address: FieldElement::ZERO,
+                    class_hash: FieldElement::ZERO,
+                    entry_point_type: EntryPointType::L1Handler,
+                    call_type: CallType::Call,
+                    result: vec![],
+                    calls: vec![],
+                    events: vec![],
+                    messages: vec![],
+                },
+                state_diff: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn partial_traces_only_serve_transactions() {
+        let cache = TraceCache::new(2);
+        let block = FieldElement::from(1u64);
+
+        cache.insert(block, vec![trace(1)], false);
+        assert!(cache.transaction_trace(block, FieldElement::from(1u64)).is_some());
+        assert!(cache.transaction_trace(block, FieldElement::from(2u64)).is_none());
+        assert!(cache.block_traces(block).is_none());
+
+        cache.insert(block, vec![trace(1), trace(2)], true);
+        // A shorter prefix does not replace the full block
+        cache.insert(block, vec![trace(1)], false);
+        assert_eq!(cache.block_traces(block).map(|traces| traces.len()), Some(2));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_block() {
+        let cache = TraceCache::new(2);
+        let (first, second, third) = (FieldElement::from(1u64), FieldElement::from(2u64), FieldElement::from(3u64));
+
+        cache.insert(first, vec![trace(1)], true);
+        cache.insert(second, vec![trace(2)], true);
+        assert!(cache.block_traces(first).is_some());
+        cache.insert(third, vec![trace(3)], true);
+
+        assert!(cache.block_traces(first).is_some());
+        assert!(cache.block_traces(second).is_none());
+        assert!(cache.block_traces(third).is_some());
+    }
+}
@@ -1,35 +1,20 @@
 use blockifier::execution::entry_point::CallInfo;
+use blockifier::transaction::objects::ResourcesMapping;
+use mc_db::StoredReceipt;
 pub use mc_rpc_core::{Felt, StarknetReadRpcApiServer, StarknetTraceRpcApiServer, StarknetWriteRpcApiServer};
-use starknet_core::types::{Event, ExecutionResources, FieldElement, MsgToL1};
+use mp_felt::Felt252Wrapper;
+use mp_transactions::HandleL1MessageTransaction;
+use sp_core::hashing::keccak_256;
+use starknet_api::hash::StarkFelt;
+use starknet_core::types::{Event, ExecutionResources, ExecutionResult, FieldElement, Hash256, MsgToL1};
 
-pub fn extract_events_from_call_info(call_info: &CallInfo) -> Vec<Event> {
-    let address = call_info.call.storage_address;
-    let events: Vec<_> = call_info
-        .execution
-        .events
-        .iter()
-        .map(|ordered_event| Event {
-            from_address: FieldElement::from_byte_slice_be(address.0.0.bytes()).unwrap(),
-            keys: ordered_event
-                .event
-                .keys
-                .iter()
-                .map(|key| FieldElement::from_byte_slice_be(key.0.bytes()).unwrap())
-                .collect(),
-            data: ordered_event
-                .event
-                .data
-                .0
-                .iter()
-                .map(|data_item| FieldElement::from_byte_slice_be(data_item.bytes()).unwrap())
-                .collect(),
-        })
-        .collect();
-
-    let inner_events: Vec<_> =
-        call_info.inner_calls.iter().flat_map(|inner_call| extract_events_from_call_info(inner_call)).collect();
-
-    events.into_iter().chain(inner_events).collect()
+/// Converts an event stored in a block to its rpc representation.
+pub fn starknet_api_to_starknet_rs_event(event: &starknet_api::transaction::Event) -> Event {
+    Event {
+        from_address: Felt252Wrapper::from(event.from_address).0,
+        keys: event.content.keys.iter().map(|key| Felt252Wrapper::from(key.0).0).collect(),
+        data: event.content.data.0.iter().map(|data_item| Felt252Wrapper::from(*data_item).0).collect(),
+    }
 }
 
 pub fn extract_messages_from_call_info(call_info: &CallInfo) -> Vec<MsgToL1> {
@@ -57,36 +42,84 @@ pub fn extract_messages_from_call_info(call_info: &CallInfo) -> Vec<MsgToL1> {
     events.into_iter().chain(inner_messages).collect()
 }
 
-pub fn blockifier_call_info_to_starknet_resources(callinfo: &CallInfo) -> ExecutionResources {
-    let vm_ressources = &callinfo.vm_resources;
+/// Converts the resources used by a whole transaction, as computed by the blockifier, to the
+/// execution resources of its receipt.
+///
+/// The memory holes are already counted in the steps by the blockifier.
+pub fn actual_resources_to_starknet_resources(actual_resources: &ResourcesMapping) -> ExecutionResources {
+    let resource = |name: &str| *actual_resources.0.get(name).unwrap_or(&0) as u64;
+
+    ExecutionResources {
+        steps: resource("n_steps"),
+        memory_holes: None,
+        range_check_builtin_applications: resource("range_check_builtin"),
+        pedersen_builtin_applications: resource("pedersen_builtin"),
+        poseidon_builtin_applications: resource("poseidon_builtin"),
+        ec_op_builtin_applications: resource("ec_op_builtin"),
+        ecdsa_builtin_applications: resource("ecdsa_builtin"),
+        bitwise_builtin_applications: resource("bitwise_builtin"),
+        keccak_builtin_applications: resource("keccak_builtin"),
+    }
+}
+
+/// Converts the outcome of a transaction stored by the sync to the fee, messages, execution result
+/// and execution resources of its receipt.
+pub fn stored_receipt_outcome(
+    receipt: StoredReceipt,
+) -> (FieldElement, Vec<MsgToL1>, ExecutionResult, ExecutionResources) {
+    let felt = |felt: StarkFelt| Felt252Wrapper::from(felt).0;
 
-    let steps = vm_ressources.n_steps as u64;
-    let memory_holes = match vm_ressources.n_memory_holes as u64 {
-        0 => None,
-        n => Some(n),
+    let messages_sent = receipt
+        .messages_sent
+        .into_iter()
+        .map(|message| MsgToL1 {
+            from_address: felt(message.from_address),
+            to_address: felt(message.to_address),
+            payload: message.payload.into_iter().map(felt).collect(),
+        })
+        .collect();
+    let execution_result = match receipt.revert_reason {
+        Some(reason) => ExecutionResult::Reverted { reason },
+        None => ExecutionResult::Succeeded,
+    };
+    let resources = receipt.execution_resources;
+    let execution_resources = ExecutionResources {
+        steps: resources.steps,
+        memory_holes: resources.memory_holes,
+        range_check_builtin_applications: resources.range_check_builtin_applications,
+        pedersen_builtin_applications: resources.pedersen_builtin_applications,
+        poseidon_builtin_applications: resources.poseidon_builtin_applications,
+        ec_op_builtin_applications: resources.ec_op_builtin_applications,
+        ecdsa_builtin_applications: resources.ecdsa_builtin_applications,
+        bitwise_builtin_applications: resources.bitwise_builtin_applications,
+        keccak_builtin_applications: resources.keccak_builtin_applications,
     };
 
-    let builtin_insstance = &vm_ressources.builtin_instance_counter;
+    (felt(receipt.actual_fee), messages_sent, execution_result, execution_resources)
+}
 
-    let range_check_builtin_applications = *builtin_insstance.get("range_check_builtin").unwrap_or(&0) as u64;
-    let pedersen_builtin_applications = *builtin_insstance.get("pedersen_builtin").unwrap_or(&0) as u64;
-    let poseidon_builtin_applications = *builtin_insstance.get("poseidon_builtin").unwrap_or(&0) as u64;
-    let ec_op_builtin_applications = *builtin_insstance.get("ec_op_builtin").unwrap_or(&0) as u64;
-    let ecdsa_builtin_applications = *builtin_insstance.get("ecdsa_builtin").unwrap_or(&0) as u64;
-    let bitwise_builtin_applications = *builtin_insstance.get("bitwise_builtin").unwrap_or(&0) as u64;
-    let keccak_builtin_applications = *builtin_insstance.get("keccak_builtin").unwrap_or(&0) as u64;
+/// Computes the hash of the L1 to L2 message consumed by an L1 handler transaction.
+///
+/// The first element of the calldata of an L1 handler is the L1 sender of the message, the rest
+/// being its payload. The hash is the one computed by the Starknet core contract on L1:
+/// `keccak256(from_address, to_address, nonce, selector, payload_len, payload)`.
+pub fn l1_handler_message_hash(transaction: &HandleL1MessageTransaction) -> Hash256 {
+    let (from_address, payload) = match transaction.calldata.split_first() {
+        Some((from_address, payload)) => (from_address.0, payload),
+        None => (FieldElement::ZERO, &[][..]),
+    };
 
-    ExecutionResources {
-        steps,
-        memory_holes,
-        range_check_builtin_applications,
-        pedersen_builtin_applications,
-        poseidon_builtin_applications,
-        ec_op_builtin_applications,
-        ecdsa_builtin_applications,
-        bitwise_builtin_applications,
-        keccak_builtin_applications,
+    let mut encoded = Vec::with_capacity(32 * (5 + payload.len()));
+    encoded.extend_from_slice(&from_address.to_bytes_be());
+    encoded.extend_from_slice(&transaction.contract_address.0.to_bytes_be());
+    encoded.extend_from_slice(&FieldElement::from(transaction.nonce).to_bytes_be());
+    encoded.extend_from_slice(&transaction.entry_point_selector.0.to_bytes_be());
+    encoded.extend_from_slice(&FieldElement::from(payload.len() as u64).to_bytes_be());
+    for item in payload {
+        encoded.extend_from_slice(&item.0.to_bytes_be());
     }
+
+    Hash256::from_bytes(keccak_256(&encoded))
 }
 
 pub fn blockifier_to_starknet_rs_ordered_events(
@@ -0,0 +1,84 @@
+//! Revisions of the Starknet rpc specification, layered on the shared api.
+//!
+//! Each revision is served by its own rpc module: the methods of the shared api, minus the ones
+//! the revision does not support, and with the methods of the revision trait replacing their
+//! shared counterpart.
+
+use jsonrpsee::core::{Error as JsonRpseeError, RpcResult};
+use jsonrpsee::RpcModule;
+use mc_genesis_data_provider::GenesisProvider;
+use mc_rpc_core::{
+    v0_5, StarknetReadRpcApiServer, StarknetRpcApiV0_5Server, StarknetTraceRpcApiServer, StarknetWriteRpcApiServer,
+};
+use mp_hashers::HasherT;
+use pallet_starknet_runtime_api::{ConvertTransactionRuntimeApi, StarknetRuntimeApi};
+use sc_client_api::backend::{Backend, StorageProvider};
+use sc_client_api::BlockBackend;
+use sc_transaction_pool::ChainApi;
+use sc_transaction_pool_api::TransactionPool;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::Block as BlockT;
+
+use crate::Starknet;
+
+impl<A, B, BE, G, C, P, H> Starknet<A, B, BE, G, C, P, H>
+where
+    A: ChainApi<Block = B> + 'static,
+    B: BlockT,
+    P: TransactionPool<Block = B> + 'static,
+    BE: Backend<B> + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + StorageProvider<B, BE> + 'static,
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B> + ConvertTransactionRuntimeApi<B>,
+    G: GenesisProvider + Send + Sync + 'static,
+    H: HasherT + Send + Sync + 'static,
+{
+    /// Builds the rpc module serving the 0.5 revision of the specification
+    pub fn into_rpc_v0_5(self) -> Result<RpcModule<()>, JsonRpseeError> {
+        let revision = StarknetRpcApiV0_5Server::into_rpc(self.clone());
+        self.into_revision_rpc(revision, v0_5::UNSUPPORTED_METHODS)
+    }
+
+    /// Merges the methods of a revision with the ones of the shared api.
+    ///
+    /// # Arguments
+    ///
+    /// * `revision` - The methods of the revision which differ from the shared api
+    /// * `unsupported_methods` - The methods of the shared api which are not part of the revision
+    fn into_revision_rpc(
+        self,
+        revision: RpcModule<Self>,
+        unsupported_methods: &[&'static str],
+    ) -> Result<RpcModule<()>, JsonRpseeError> {
+        let mut module = RpcModule::new(());
+        module.merge(StarknetReadRpcApiServer::into_rpc(self.clone()))?;
+        module.merge(StarknetWriteRpcApiServer::into_rpc(self.clone()))?;
+        module.merge(StarknetTraceRpcApiServer::into_rpc(self))?;
+
+        let replaced_methods: Vec<_> = revision.method_names().collect();
+        for method in unsupported_methods.iter().copied().chain(replaced_methods) {
+            module.remove_method(method);
+        }
+        module.merge(revision)?;
+
+        Ok(module)
+    }
+}
+
+impl<A, B, BE, G, C, P, H> StarknetRpcApiV0_5Server for Starknet<A, B, BE, G, C, P, H>
+where
+    A: ChainApi<Block = B> + 'static,
+    B: BlockT,
+    P: TransactionPool<Block = B> + 'static,
+    BE: Backend<B> + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + StorageProvider<B, BE> + 'static,
+    C: ProvideRuntimeApi<B>,
+    C::Api: StarknetRuntimeApi<B> + ConvertTransactionRuntimeApi<B>,
+    G: GenesisProvider + Send + Sync + 'static,
+    H: HasherT + Send + Sync + 'static,
+{
+    fn spec_version(&self) -> RpcResult<String> {
+        Ok(v0_5::SPEC_VERSION.to_string())
+    }
+}
@@ -1,8 +1,10 @@
 use std::path::PathBuf;
 use std::result::Result as StdResult;
 
+use ethers::types::Address;
 use madara_runtime::SealingMode;
 use mc_deoxys::l2::fetch_genesis_block;
+use mc_deoxys::utils::constant::{l1_chain_id, starknet_core_address};
 use reqwest::Url;
 use sc_cli::{Result, RpcMethods, RunCmd, SubstrateCli};
 use serde::{Deserialize, Serialize};
@@ -92,11 +94,30 @@ impl NetworkType {
     pub fn chain_id(&self) -> starknet_core::types::FieldElement {
         match self {
             NetworkType::Main => starknet_core::types::FieldElement::from_byte_slice_be(b"SN_MAIN").unwrap(),
-            NetworkType::Test => starknet_core::types::FieldElement::from_byte_slice_be(b"SN_TEST").unwrap(),
+            NetworkType::Test => starknet_core::types::FieldElement::from_byte_slice_be(b"SN_GOERLI").unwrap(),
             NetworkType::Integration => starknet_core::types::FieldElement::from_byte_slice_be(b"SN_INTE").unwrap(),
         }
     }
 
+    /// The address of the Starknet core contract the network settles on.
+    pub fn l1_core_contract(&self) -> Address {
+        let address = match self {
+            NetworkType::Main => starknet_core_address::MAINNET,
+            NetworkType::Test => starknet_core_address::GOERLI_TESTNET,
+            NetworkType::Integration => starknet_core_address::SEPOLIA_INTEGRATION,
+        };
+        address.parse().unwrap()
+    }
+
+    /// The ID of the Ethereum chain the network settles on.
+    pub fn l1_chain_id(&self) -> u64 {
+        match self {
+            NetworkType::Main => l1_chain_id::MAINNET,
+            NetworkType::Test => l1_chain_id::GOERLI,
+            NetworkType::Integration => l1_chain_id::SEPOLIA,
+        }
+    }
+
     pub fn block_fetch_config(&self) -> mc_deoxys::FetchConfig {
         let uri = self.uri();
         let chain_id = self.chain_id();
@@ -104,7 +125,16 @@ impl NetworkType {
         let gateway = format!("{uri}/gateway").parse().unwrap();
         let feeder_gateway = format!("{uri}/feeder_gateway").parse().unwrap();
 
-        mc_deoxys::FetchConfig { gateway, feeder_gateway, chain_id, workers: 5, sound: false }
+        mc_deoxys::FetchConfig {
+            gateway,
+            feeder_gateway,
+            chain_id,
+            workers: 5,
+            sound: false,
+            replay: None,
+            record: None,
+            sources: vec![],
+        }
     }
 }
 
@@ -112,6 +142,14 @@ fn parse_url(s: &str) -> StdResult<Url, url::ParseError> {
     s.parse()
 }
 
+fn parse_feeder_source(s: &str) -> StdResult<mc_deoxys::FeederSource, String> {
+    s.parse()
+}
+
+fn parse_address(s: &str) -> StdResult<Address, String> {
+    s.parse().map_err(|e| format!("invalid address {s}: {e}"))
+}
+
 #[derive(Clone, Debug, clap::Args)]
 pub struct ExtendedRunCmd {
     #[clap(flatten)]
@@ -125,6 +163,17 @@ pub struct ExtendedRunCmd {
     #[clap(long, value_parser = parse_url)]
     pub l1_endpoint: Option<Url>,
 
+    /// The address of the Starknet core contract on L1, overriding the one of `--network`. Useful
+    /// for devnets settling on a local L1 such as anvil, whose chain id is then not checked.
+    #[clap(long, value_parser = parse_address)]
+    pub l1_core_contract: Option<Address>,
+
+    /// The L1 block the messages sent to L2 are indexed from on the first run. Defaults to the
+    /// block the core contract was deployed in, which is only found when the L1 endpoint serves
+    /// the state of past blocks, as archive nodes do.
+    #[clap(long)]
+    pub l1_messages_from_block: Option<u64>,
+
     /// The network type to connect to.
     #[clap(long, short, default_value = "integration")]
     pub network: NetworkType,
@@ -141,6 +190,23 @@ pub struct ExtendedRunCmd {
     #[clap(long)]
     pub sound: bool,
 
+    /// Sync from a recording of the feeder gateway instead of the network: a directory, or a
+    /// `.tar`/`.tar.gz` archive of it, holding the `block`, `state_update` and `class` responses.
+    #[clap(long, value_hint = clap::ValueHint::AnyPath)]
+    pub replay: Option<PathBuf>,
+
+    /// Record every block, state update and class fetched from the feeder gateways in a
+    /// content-addressed archive at this path, which `--replay` can sync from later on.
+    #[clap(long, value_hint = clap::ValueHint::DirPath, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// A feeder gateway to sync from, as `<url>[,api-key=<key>][,rate-limit=<requests per
+    /// second>]`. May be repeated: requests fail over from one gateway to the next when it errors
+    /// or rate-limits the node, and the block hashes are cross-checked between gateways. Defaults
+    /// to the feeder gateway of `--network`.
+    #[clap(long = "feeder-source", value_parser = parse_feeder_source, conflicts_with = "replay")]
+    pub feeder_sources: Vec<mc_deoxys::FeederSource>,
+
     /// This wrap a specific deoxys environment for a node quick start.
     #[clap(long)]
     pub deoxys: bool,
@@ -158,8 +224,19 @@ pub fn run_node(mut cli: Cli) -> Result<()> {
     let runner = cli.create_runner(&cli.run.base)?;
 
     // TODO: verify that the l1_endpoint is valid
-    let l1_endpoint = if let Some(url) = cli.run.l1_endpoint {
-        url
+    let l1_config = if let Some(url) = cli.run.l1_endpoint {
+        // A custom core contract lives on a custom chain
+        let (core_contract, chain_id) = match cli.run.l1_core_contract {
+            Some(core_contract) => (core_contract, None),
+            None => (cli.run.network.l1_core_contract(), Some(cli.run.network.l1_chain_id())),
+        };
+        mc_deoxys::L1Config {
+            url,
+            core_contract,
+            chain_id,
+            l2_chain_id: cli.run.network.chain_id(),
+            messages_from_block: cli.run.l1_messages_from_block,
+        }
     } else {
         return Err(sc_cli::Error::Input(
             "Missing required --l1-endpoint argument please reffer to https://deoxys-docs.kasar.io".to_string(),
@@ -170,19 +247,14 @@ pub fn run_node(mut cli: Cli) -> Result<()> {
         let sealing = cli.run.sealing.map(Into::into).unwrap_or_default();
         let cache = cli.run.cache;
         let mut fetch_block_config = cli.run.network.block_fetch_config();
+        fetch_block_config.replay = cli.run.replay.clone();
+        fetch_block_config.record = cli.run.record.clone();
+        fetch_block_config.sources = cli.run.feeder_sources.clone();
         let genesis_block = fetch_genesis_block(fetch_block_config.clone()).await.unwrap();
         fetch_block_config.sound = cli.run.sound;
 
-        service::new_full(
-            config,
-            sealing,
-            cli.run.base.rpc_port.unwrap(),
-            l1_endpoint,
-            cache,
-            fetch_block_config,
-            genesis_block,
-        )
-        .map_err(sc_cli::Error::Service)
+        service::new_full(config, sealing, l1_config, cache, fetch_block_config, genesis_block)
+            .map_err(sc_cli::Error::Service)
     })
 }
 
@@ -5,6 +5,7 @@
 
 #![warn(missing_docs)]
 
+pub mod server;
 mod starknet;
 use std::sync::Arc;
 
@@ -15,14 +16,21 @@ use madara_runtime::{AccountId, Hash, Index, StarknetHasher};
 use mc_genesis_data_provider::GenesisProvider;
 use sc_client_api::{Backend, BlockBackend, StorageProvider};
 use sc_consensus_manual_seal::rpc::EngineCommand;
+use sc_rpc::system::SystemInfo;
+use sc_rpc::SubscriptionTaskExecutor;
 pub use sc_rpc_api::DenyUnsafe;
-use sc_transaction_pool::{ChainApi, Pool};
+use sc_transaction_pool::{ChainApi, FullPool, Pool};
 use sc_transaction_pool_api::TransactionPool;
+use sc_utils::mpsc::TracingUnboundedSender;
+pub use server::RpcRevisions;
 use sp_api::ProvideRuntimeApi;
 use sp_block_builder::BlockBuilder;
 use sp_blockchain::{Error as BlockChainError, HeaderBackend, HeaderMetadata};
+use sp_keystore::KeystorePtr;
 pub use starknet::StarknetDeps;
 
+use crate::service::FullClient;
+
 /// Full client dependencies.
 pub struct FullDeps<A: ChainApi, C, G: GenesisProvider, P> {
     /// The client instance to use.
@@ -39,6 +47,47 @@ pub struct FullDeps<A: ChainApi, C, G: GenesisProvider, P> {
     pub starknet: StarknetDeps<C, G, Block>,
 }
 
+/// Dependencies of the rpc methods of Substrate, which the server of `sc_service` serves too.
+pub struct SubstrateDeps {
+    /// The client instance to use.
+    pub client: Arc<FullClient>,
+    /// Transaction pool instance.
+    pub pool: Arc<FullPool<Block, FullClient>>,
+    /// The keystore of the node, for the `author_*` methods.
+    pub keystore: KeystorePtr,
+    /// The description of the node and of its chain, for the `system_*` methods.
+    pub system_info: SystemInfo,
+    /// The channel to the network worker, for the `system_*` methods.
+    pub system_rpc_tx: TracingUnboundedSender<sc_rpc::system::Request<Block>>,
+    /// The executor of the subscriptions.
+    pub executor: SubscriptionTaskExecutor,
+    /// Whether to deny unsafe calls
+    pub deny_unsafe: DenyUnsafe,
+}
+
+/// Instantiate the `chain_*`, `state_*`, `childstate_*`, `author_*` and `system_*` rpc methods of
+/// Substrate, as `sc_service` does for its server.
+pub fn create_substrate(deps: SubstrateDeps) -> Result<RpcModule<()>, Box<dyn std::error::Error + Send + Sync>> {
+    use sc_rpc::author::Author;
+    use sc_rpc::system::System;
+    use sc_rpc_api::author::AuthorApiServer;
+    use sc_rpc_api::chain::ChainApiServer;
+    use sc_rpc_api::state::{ChildStateApiServer, StateApiServer};
+    use sc_rpc_api::system::SystemApiServer;
+
+    let mut module = RpcModule::new(());
+    let SubstrateDeps { client, pool, keystore, system_info, system_rpc_tx, executor, deny_unsafe } = deps;
+
+    let (state, child_state) = sc_rpc::state::new_full(client.clone(), executor.clone(), deny_unsafe);
+    module.merge(sc_rpc::chain::new_full(client.clone(), executor.clone()).into_rpc())?;
+    module.merge(state.into_rpc())?;
+    module.merge(child_state.into_rpc())?;
+    module.merge(Author::new(client, pool, keystore, deny_unsafe, executor).into_rpc())?;
+    module.merge(System::new(system_info, system_rpc_tx, deny_unsafe).into_rpc())?;
+
+    Ok(module)
+}
+
 /// Instantiate all full RPC extensions.
 pub fn create_full<A, C, G, P, BE>(
     deps: FullDeps<A, C, G, P>,
@@ -60,7 +109,10 @@ where
     P: TransactionPool<Block = Block> + 'static,
     BE: Backend<Block> + 'static,
 {
-    use mc_rpc::{Starknet, StarknetReadRpcApiServer, StarknetTraceRpcApiServer, StarknetWriteRpcApiServer};
+    use mc_rpc::{
+        Starknet, StarknetPubSubRpcApiServer, StarknetReadRpcApiServer, StarknetTraceRpcApiServer,
+        StarknetWriteRpcApiServer,
+    };
     use sc_consensus_manual_seal::rpc::{ManualSeal, ManualSealApiServer};
     use substrate_frame_rpc_system::{System, SystemApiServer};
 
@@ -89,6 +141,16 @@ where
         starknet_params.genesis_provider.clone(),
     )))?;
     module.merge(StarknetTraceRpcApiServer::into_rpc(Starknet::<_, _, _, _, _, _, StarknetHasher>::new(
+        client.clone(),
+        starknet_params.madara_backend.clone(),
+        starknet_params.overrides.clone(),
+        pool.clone(),
+        graph.clone(),
+        starknet_params.sync_service.clone(),
+        starknet_params.starting_block,
+        starknet_params.genesis_provider.clone(),
+    )))?;
+    module.merge(StarknetPubSubRpcApiServer::into_rpc(Starknet::<_, _, _, _, _, _, StarknetHasher>::new(
         client,
         starknet_params.madara_backend,
         starknet_params.overrides,
@@ -109,3 +171,43 @@ where
 
     Ok(module)
 }
+
+/// Instantiate the rpc modules of the revisions of the Starknet rpc specification, by path.
+pub fn create_revisions<A, C, G, P, BE>(
+    pool: Arc<P>,
+    graph: Arc<Pool<A>>,
+    starknet_params: StarknetDeps<C, G, Block>,
+) -> Result<RpcRevisions, Box<dyn std::error::Error + Send + Sync>>
+where
+    A: ChainApi<Block = Block> + 'static,
+    C: ProvideRuntimeApi<Block>,
+    C: HeaderBackend<Block>
+        + BlockBackend<Block>
+        + HeaderMetadata<Block, Error = BlockChainError>
+        + StorageProvider<Block, BE>
+        + 'static,
+    C: Send + Sync + 'static,
+    C::Api: pallet_starknet_runtime_api::StarknetRuntimeApi<Block>
+        + pallet_starknet_runtime_api::ConvertTransactionRuntimeApi<Block>,
+    G: GenesisProvider + Send + Sync + 'static,
+    P: TransactionPool<Block = Block> + 'static,
+    BE: Backend<Block> + 'static,
+{
+    use mc_rpc::Starknet;
+
+    let starknet = Starknet::<_, _, _, _, _, _, StarknetHasher>::new(
+        starknet_params.client,
+        starknet_params.madara_backend,
+        starknet_params.overrides,
+        pool,
+        graph,
+        starknet_params.sync_service,
+        starknet_params.starting_block,
+        starknet_params.genesis_provider,
+    );
+
+    let mut revisions = RpcRevisions::new();
+    revisions.insert("/rpc/v0_5", starknet.into_rpc_v0_5()?);
+
+    Ok(revisions)
+}
@@ -0,0 +1,269 @@
+//! The rpc server of the node, which mounts each revision of the Starknet rpc specification under
+//! its own path.
+//!
+//! The server started by `sc_service::spawn_tasks` cannot be given a middleware, so the node starts
+//! its own jsonrpsee server on the rpc address, with the same limits, and moves the one of
+//! `sc_service` to a port of localhost. A request to the path of a revision, such as `/rpc/v0_5`,
+//! is answered by the module of that revision, a request to any other path by the module of the
+//! node, which also holds the `chain_*`, `state_*`, `author_*`, `system_*` and `rpc_methods`
+//! methods of `sc_service`.
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Buf;
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Method, Request, Response, StatusCode};
+use http_body::Body;
+use jsonrpsee::server::{AllowHosts, ServerBuilder, ServerHandle};
+use jsonrpsee::RpcModule;
+use sc_rpc_api::DenyUnsafe;
+use sc_service::config::RpcMethods;
+use sc_service::Configuration;
+use tower::{Layer, Service};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// The rpc modules of the revisions of the specification, by path
+pub type RpcRevisions = HashMap<&'static str, RpcModule<()>>;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+const MEGABYTE: u32 = 1024 * 1024;
+
+const PARSE_ERROR: &str = r#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"Parse error"},"id":null}"#;
+const INVALID_REQUEST: &str = r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request"},"id":null}"#;
+
+/// The settings of the rpc server, taken from the configuration of the node.
+pub struct RpcServerConfig {
+    addr: SocketAddr,
+    cors: Option<Vec<String>>,
+    methods: RpcMethods,
+    max_connections: u32,
+    max_request_size: u32,
+    max_response_size: u32,
+    max_subs_per_conn: u32,
+}
+
+impl RpcServerConfig {
+    /// Takes the rpc settings of the node, and moves the rpc server of `sc_service` to a port of
+    /// localhost chosen by the system.
+    ///
+    /// The server of `sc_service` then denies the unsafe methods whenever the server of the node
+    /// does.
+    pub fn take(config: &mut Configuration) -> Self {
+        let addr = config.rpc_addr.unwrap_or_else(|| SocketAddr::from((Ipv4Addr::LOCALHOST, config.rpc_port)));
+        let server_config = Self {
+            addr,
+            cors: config.rpc_cors.clone(),
+            methods: config.rpc_methods,
+            max_connections: config.rpc_max_connections,
+            max_request_size: config.rpc_max_request_size.saturating_mul(MEGABYTE),
+            max_response_size: config.rpc_max_response_size.saturating_mul(MEGABYTE),
+            max_subs_per_conn: config.rpc_max_subs_per_conn,
+        };
+
+        config.rpc_addr = Some(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)));
+        if matches!(server_config.deny_unsafe(), DenyUnsafe::Yes) {
+            config.rpc_methods = RpcMethods::Safe;
+        }
+
+        server_config
+    }
+
+    /// Whether the unsafe methods are denied, decided as `sc_service` does for its server.
+    pub fn deny_unsafe(&self) -> DenyUnsafe {
+        match (self.addr.ip().is_loopback(), self.methods) {
+            (_, RpcMethods::Unsafe) | (true, RpcMethods::Auto) => DenyUnsafe::No,
+            _ => DenyUnsafe::Yes,
+        }
+    }
+}
+
+/// Starts the rpc server of the node.
+///
+/// # Arguments
+///
+/// * `config` - The settings of the server
+/// * `module` - The rpc module served on any path but the ones of the revisions, to which
+///   `rpc_methods` is added
+/// * `revisions` - The rpc module of each revision, by path
+///
+/// # Returns
+///
+/// The handle of the server, which must be kept alive for as long as the node runs.
+pub async fn start_server(
+    config: RpcServerConfig,
+    module: RpcModule<()>,
+    revisions: RpcRevisions,
+) -> Result<ServerHandle, BoxError> {
+    let mut revision_paths = revisions.keys().copied().collect::<Vec<_>>();
+    revision_paths.sort_unstable();
+
+    let middleware = tower::ServiceBuilder::new()
+        .layer(cors_layer(config.cors.as_ref())?)
+        .layer(RevisionsLayer::new(revisions, config.max_request_size));
+
+    let server = ServerBuilder::new()
+        .max_request_body_size(config.max_request_size)
+        .max_response_body_size(config.max_response_size)
+        .max_connections(config.max_connections)
+        .max_subscriptions_per_connection(config.max_subs_per_conn)
+        .set_host_filtering(host_filter(config.cors.is_some(), config.addr))
+        .set_middleware(middleware)
+        .build(config.addr)
+        .await?;
+
+    log::info!(
+        "Running the Starknet rpc server on {}, with the revisions at {}",
+        server.local_addr()?,
+        revision_paths.join(", ")
+    );
+
+    Ok(server.start(with_rpc_methods(module)?)?)
+}
+
+/// Adds `rpc_methods` to the module, listing its methods like `sc_service` does for its server.
+fn with_rpc_methods(mut module: RpcModule<()>) -> Result<RpcModule<()>, BoxError> {
+    let mut methods = module.method_names().collect::<Vec<_>>();
+    methods.push("rpc_methods");
+    methods.sort_unstable();
+
+    module.register_method("rpc_methods", move |_, _| Ok(serde_json::json!({ "methods": methods })))?;
+
+    Ok(module)
+}
+
+/// Only the listening address is allowed as host when cors is restricted, like `sc_service` does.
+fn host_filter(enabled: bool, addr: SocketAddr) -> AllowHosts {
+    if enabled {
+        AllowHosts::Only(vec![format!("localhost:{}", addr.port()).into(), format!("127.0.0.1:{}", addr.port()).into()])
+    } else {
+        AllowHosts::Any
+    }
+}
+
+fn cors_layer(cors: Option<&Vec<String>>) -> Result<CorsLayer, BoxError> {
+    match cors {
+        Some(origins) => {
+            let origins = origins.iter().map(|origin| HeaderValue::from_str(origin)).collect::<Result<Vec<_>, _>>()?;
+            Ok(CorsLayer::new().allow_origin(AllowOrigin::list(origins)))
+        }
+        None => Ok(CorsLayer::permissive()),
+    }
+}
+
+/// A middleware answering the requests sent to the path of a revision with its rpc module.
+#[derive(Clone)]
+pub struct RevisionsLayer {
+    revisions: Arc<RpcRevisions>,
+    max_request_size: u32,
+}
+
+impl RevisionsLayer {
+    /// Creates a middleware over the given revisions, whose requests are limited to
+    /// `max_request_size` bytes like the ones of the server.
+    pub fn new(revisions: RpcRevisions, max_request_size: u32) -> Self {
+        Self { revisions: Arc::new(revisions), max_request_size }
+    }
+}
+
+impl<S> Layer<S> for RevisionsLayer {
+    type Service = Revisions<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Revisions { layer: self.clone(), inner }
+    }
+}
+
+/// The service of [`RevisionsLayer`], passing the requests to other paths to the server.
+#[derive(Clone)]
+pub struct Revisions<S> {
+    layer: RevisionsLayer,
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Revisions<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>, Error = BoxError>,
+    S::Future: Send + 'static,
+    ReqBody: Body + Send + Unpin + 'static,
+    ReqBody::Data: Send,
+    ReqBody::Error: StdError + Send + Sync + 'static,
+    ResBody: From<String> + Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        match self.layer.revisions.get(request.uri().path().trim_end_matches('/')) {
+            Some(module) => Box::pin(answer(module.clone(), request, self.layer.max_request_size)),
+            None => Box::pin(self.inner.call(request)),
+        }
+    }
+}
+
+/// Answers a request, or a batch of requests, with the module of a revision.
+async fn answer<ReqBody, ResBody>(
+    module: RpcModule<()>,
+    request: Request<ReqBody>,
+    max_request_size: u32,
+) -> Result<Response<ResBody>, BoxError>
+where
+    ReqBody: Body + Unpin,
+    ReqBody::Error: StdError + Send + Sync + 'static,
+    ResBody: From<String> + Default,
+{
+    if request.method() != Method::POST {
+        return Ok(status(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    let mut body = request.into_body();
+    let mut raw = Vec::new();
+    while let Some(chunk) = body.data().await {
+        raw.extend_from_slice(chunk?.chunk());
+        if raw.len() > max_request_size as usize {
+            return Ok(status(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+    }
+
+    let response = match serde_json::from_slice::<serde_json::Value>(&raw) {
+        Ok(serde_json::Value::Array(calls)) if calls.is_empty() => INVALID_REQUEST.to_string(),
+        Ok(serde_json::Value::Array(calls)) => {
+            let mut responses = Vec::with_capacity(calls.len());
+            for call in calls {
+                responses.push(call_method(&module, &call.to_string()).await);
+            }
+            format!("[{}]", responses.join(","))
+        }
+        Ok(call) => call_method(&module, &call.to_string()).await,
+        Err(_) => PARSE_ERROR.to_string(),
+    };
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .body(ResBody::from(response))?)
+}
+
+/// Returns the serialized response to a single call.
+async fn call_method(module: &RpcModule<()>, call: &str) -> String {
+    match module.raw_json_request(call).await {
+        Ok((response, _)) => response.result,
+        Err(_) => INVALID_REQUEST.to_string(),
+    }
+}
+
+fn status<ResBody: Default>(status: StatusCode) -> Response<ResBody> {
+    let mut response = Response::new(ResBody::default());
+    *response.status_mut() = status;
+    response
+}
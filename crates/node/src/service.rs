@@ -12,9 +12,9 @@ use futures::prelude::*;
 use madara_runtime::opaque::Block;
 use madara_runtime::{self, Hash, RuntimeApi, SealingMode, StarknetHasher};
 use mc_commitment_state_diff::{verify_l2, CommitmentStateDiffWorker};
-use mc_deoxys::starknet_sync_worker;
+use mc_deoxys::{starknet_sync_worker, ChainHandle, ClientChainHandle, FetchMetrics, L1Config};
 use mc_genesis_data_provider::OnDiskGenesisConfig;
-use mc_mapping_sync::MappingSyncWorker;
+use mc_mapping_sync::{revert_blocks, MappingSyncWorker};
 use mc_storage::overrides_handle;
 use mp_block::state_update::StateUpdateWrapper;
 use mp_contract::class::ClassUpdateWrapper;
@@ -23,7 +23,6 @@ use mp_sequencer_address::{
 };
 use parity_scale_codec::Encode;
 use prometheus_endpoint::Registry;
-use reqwest::Url;
 use sc_basic_authorship::ProposerFactory;
 use sc_client_api::{Backend, BlockBackend, BlockchainEvents, HeaderBackend};
 use sc_consensus::{BasicQueue, BlockImportParams};
@@ -271,10 +270,9 @@ where
 ///
 /// - `cache`: whether more information should be cached when storing the block in the database.
 pub fn new_full(
-    config: Configuration,
+    mut config: Configuration,
     sealing: SealingMode,
-    rpc_port: u16,
-    l1_url: Url,
+    l1_config: L1Config,
     cache_more_things: bool,
     fetch_config: mc_deoxys::FetchConfig,
     genesis_block: mp_block::Block,
@@ -374,6 +372,50 @@ pub fn new_full(
         genesis_provider: genesis_data.into(),
     };
 
+    // The rpc server of the node takes the place of the one of `sc_service`, so that the revisions
+    // of the Starknet rpc specification can be mounted on it. It serves the methods of Substrate as
+    // well as the ones of the node.
+    let rpc_server_config = crate::rpc::server::RpcServerConfig::take(&mut config);
+    let mut rpc_module = crate::rpc::create_substrate(crate::rpc::SubstrateDeps {
+        client: client.clone(),
+        pool: transaction_pool.clone(),
+        keystore: keystore_container.keystore(),
+        system_info: sc_rpc::system::SystemInfo {
+            impl_name: config.impl_name.clone(),
+            impl_version: config.impl_version.clone(),
+            chain_name: config.chain_spec.name().into(),
+            properties: config.chain_spec.properties(),
+            chain_type: config.chain_spec.chain_type(),
+        },
+        system_rpc_tx: system_rpc_tx.clone(),
+        executor: Arc::new(task_manager.spawn_handle()),
+        deny_unsafe: rpc_server_config.deny_unsafe(),
+    })
+    .map_err(|e| ServiceError::Other(format!("Failed to build the rpc module of Substrate: {e}")))?;
+    let node_rpc_module = crate::rpc::create_full(crate::rpc::FullDeps {
+        client: client.clone(),
+        pool: transaction_pool.clone(),
+        graph: transaction_pool.pool().clone(),
+        deny_unsafe: rpc_server_config.deny_unsafe(),
+        starknet: starknet_rpc_params.clone(),
+        command_sink: command_sink.clone(),
+    })
+    .map_err(|e| ServiceError::Other(format!("Failed to build the rpc module: {e}")))?;
+    rpc_module
+        .merge(node_rpc_module)
+        .map_err(|e| ServiceError::Other(format!("Failed to merge the rpc modules: {e}")))?;
+    let revisions = crate::rpc::create_revisions(
+        transaction_pool.clone(),
+        transaction_pool.pool().clone(),
+        starknet_rpc_params.clone(),
+    )
+    .map_err(|e| ServiceError::Other(format!("Failed to build the versioned rpc modules: {e}")))?;
+    let rpc_server = tokio::task::block_in_place(|| {
+        config.tokio_handle.block_on(crate::rpc::server::start_server(rpc_server_config, rpc_module, revisions))
+    })
+    .map_err(|e| ServiceError::Other(format!("Failed to start the rpc server: {e}")))?;
+    task_manager.keep_alive(rpc_server);
+
     let rpc_extensions_builder = {
         let client = client.clone();
         let pool = transaction_pool.clone();
@@ -440,20 +482,48 @@ pub fn new_full(
     let (state_update_sender, state_update_receiver) = tokio::sync::mpsc::channel::<StateUpdateWrapper>(100);
     let (class_sender, class_receiver) = tokio::sync::mpsc::channel::<ClassUpdateWrapper>(100);
 
+    let block_reverter: mc_deoxys::BlockReverter = {
+        let client = client.clone();
+        let backend = backend.clone();
+        let madara_backend = madara_backend.clone();
+        Box::new(move |ancestor| {
+            let ancestor = ancestor.try_into().map_err(|_| format!("block number {ancestor} is out of range"))?;
+            revert_blocks::<_, _, _, StarknetHasher>(client.as_ref(), backend.as_ref(), &madara_backend, ancestor)
+                .map_err(|e| format!("failed to revert blocks: {e}"))
+        })
+    };
+
+    let chain: Arc<dyn ChainHandle> = Arc::new(ClientChainHandle::<_, _, FullBackend, StarknetHasher>::new(
+        client.clone(),
+        madara_backend.clone(),
+        overrides,
+    ));
+
     let sender_config = mc_deoxys::SenderConfig {
         block_sender,
         state_update_sender,
         command_sink: command_sink.unwrap().clone(),
         class_sender,
-        overrides,
+        chain: Arc::clone(&chain),
+        block_reverter,
     };
 
+    let fetch_metrics = prometheus_registry.as_ref().and_then(|registry| match FetchMetrics::register(registry) {
+        Ok(metrics) => Some(metrics),
+        Err(e) => {
+            log::warn!("Failed to register the L2 fetch metrics: {e}");
+            None
+        }
+    });
+
     task_manager.spawn_essential_handle().spawn(
         "starknet-sync-worker",
         Some("madara"),
-        starknet_sync_worker::sync(fetch_config, sender_config, rpc_port, l1_url, madara_backend),
+        starknet_sync_worker::sync(fetch_config, sender_config, l1_config, madara_backend.clone(), fetch_metrics),
     );
 
+    task_manager.spawn_handle().spawn("l1-verifier", Some("madara"), mc_deoxys::l1::verify(chain, madara_backend));
+
     task_manager.spawn_essential_handle().spawn(
         "commitment-state-logger",
         Some("madara"),
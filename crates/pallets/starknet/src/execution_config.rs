@@ -29,6 +29,7 @@ impl RuntimeExecutionConfigBuilder {
     pub fn with_simulation_mode(mut self, simulation_flags: &SimulationFlags) -> Self {
         self.0.disable_fee_charge = simulation_flags.skip_fee_charge;
         self.0.disable_validation = simulation_flags.skip_validate;
+        self.0.disable_nonce_validation |= simulation_flags.skip_nonce_check;
         self
     }
     #[must_use]
@@ -528,6 +528,7 @@ pub mod pallet {
         FailedToCreateATransactionalStorageExecution,
         L1MessageAlreadyExecuted,
         MissingL1GasUsage,
+        InvalidStateOverride,
     }
 
     /// The Starknet pallet external functions.
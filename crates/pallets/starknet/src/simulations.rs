@@ -1,32 +1,42 @@
 use alloc::vec::Vec;
 
+use blockifier::abi::abi_utils::get_erc20_balance_var_addresses;
 use blockifier::block_context::BlockContext;
-use blockifier::transaction::errors::TransactionExecutionError;
 use blockifier::transaction::objects::TransactionExecutionInfo;
 use frame_support::storage;
 use mp_felt::Felt252Wrapper;
-use mp_simulations::{PlaceHolderErrorTypeForFailedStarknetExecution, SimulationFlags};
-use mp_transactions::execution::{Execute, ExecutionConfig};
+use mp_simulations::{SimulationFlags, StateOverrides, TransactionExecutionFailure};
+use mp_transactions::execution::{Execute, ExecutionConfig, FailedExecution};
 use mp_transactions::{HandleL1MessageTransaction, UserOrL1HandlerTransaction, UserTransaction};
 use sp_core::Get;
 use sp_runtime::DispatchError;
+use starknet_api::hash::StarkFelt;
 use starknet_api::transaction::Fee;
 
 use crate::blockifier_state_adapter::BlockifierStateAdapter;
 use crate::execution_config::RuntimeExecutionConfigBuilder;
-use crate::{Config, Error, Pallet};
+use crate::{Config, ContractClassHashes, Error, Nonces, Pallet, StorageView};
 
 impl<T: Config> Pallet<T> {
-    pub fn estimate_fee(transactions: Vec<UserTransaction>) -> Result<Vec<(u64, u64)>, DispatchError> {
+    pub fn estimate_fee(
+        transactions: Vec<UserTransaction>,
+        state_overrides: &StateOverrides,
+    ) -> Result<Vec<(u64, u64)>, DispatchError> {
         storage::transactional::with_transaction(|| {
             storage::TransactionOutcome::Rollback(Result::<_, DispatchError>::Ok(Self::estimate_fee_inner(
                 transactions,
+                state_overrides,
             )))
         })
         .map_err(|_| Error::<T>::FailedToCreateATransactionalStorageExecution)?
     }
 
-    fn estimate_fee_inner(transactions: Vec<UserTransaction>) -> Result<Vec<(u64, u64)>, DispatchError> {
+    fn estimate_fee_inner(
+        transactions: Vec<UserTransaction>,
+        state_overrides: &StateOverrides,
+    ) -> Result<Vec<(u64, u64)>, DispatchError> {
+        Self::apply_state_overrides(state_overrides)?;
+
         let transactions_len = transactions.len();
         let chain_id = Self::chain_id();
         let block_context = Self::get_block_context();
@@ -39,8 +49,8 @@ impl<T: Config> Pallet<T> {
 
                 match Self::execute_user_transaction(tx, chain_id, &block_context, &execution_config) {
                     Ok(execution_info) if !execution_info.is_reverted() => Ok(execution_info),
-                    Err(e) => {
-                        log::error!("Transaction execution failed during fee estimation: {e}");
+                    Err(failure) => {
+                        log::error!("Transaction execution failed during fee estimation: {}", failure.error);
                         Err(Error::<T>::TransactionExecutionFailed)
                     }
                     Ok(execution_info) => {
@@ -74,12 +84,13 @@ impl<T: Config> Pallet<T> {
     pub fn simulate_transactions(
         transactions: Vec<UserTransaction>,
         simulation_flags: &SimulationFlags,
-    ) -> Result<Vec<Result<TransactionExecutionInfo, PlaceHolderErrorTypeForFailedStarknetExecution>>, DispatchError>
-    {
+        state_overrides: &StateOverrides,
+    ) -> Result<Vec<Result<TransactionExecutionInfo, TransactionExecutionFailure>>, DispatchError> {
         storage::transactional::with_transaction(|| {
             storage::TransactionOutcome::Rollback(Result::<_, DispatchError>::Ok(Self::simulate_transactions_inner(
                 transactions,
                 simulation_flags,
+                state_overrides,
             )))
         })
         .map_err(|_| Error::<T>::FailedToCreateATransactionalStorageExecution)?
@@ -88,8 +99,10 @@ impl<T: Config> Pallet<T> {
     fn simulate_transactions_inner(
         transactions: Vec<UserTransaction>,
         simulation_flags: &SimulationFlags,
-    ) -> Result<Vec<Result<TransactionExecutionInfo, PlaceHolderErrorTypeForFailedStarknetExecution>>, DispatchError>
-    {
+        state_overrides: &StateOverrides,
+    ) -> Result<Vec<Result<TransactionExecutionInfo, TransactionExecutionFailure>>, DispatchError> {
+        Self::apply_state_overrides(state_overrides)?;
+
         let chain_id = Self::chain_id();
         let block_context = Self::get_block_context();
         let mut execution_config =
@@ -97,12 +110,13 @@ impl<T: Config> Pallet<T> {
 
         let tx_execution_results = transactions
             .into_iter()
-            .map(|tx| {
+            .enumerate()
+            .map(|(index, tx)| {
                 execution_config.set_offset_version(tx.offset_version());
 
-                Self::execute_user_transaction(tx, chain_id, &block_context, &execution_config).map_err(|e| {
-                    log::error!("Transaction execution failed during simulation: {e}");
-                    PlaceHolderErrorTypeForFailedStarknetExecution
+                Self::execute_user_transaction(tx, chain_id, &block_context, &execution_config).map_err(|failure| {
+                    log::error!("Transaction execution failed during simulation: {}", failure.error);
+                    TransactionExecutionFailure::new(index, failure)
                 })
             })
             .collect();
@@ -143,8 +157,7 @@ impl<T: Config> Pallet<T> {
 
     pub fn re_execute_transactions(
         transactions: Vec<UserOrL1HandlerTransaction>,
-    ) -> Result<Result<Vec<TransactionExecutionInfo>, PlaceHolderErrorTypeForFailedStarknetExecution>, DispatchError>
-    {
+    ) -> Result<Result<Vec<TransactionExecutionInfo>, TransactionExecutionFailure>, DispatchError> {
         storage::transactional::with_transaction(|| {
             storage::TransactionOutcome::Rollback(Result::<_, DispatchError>::Ok(Self::re_execute_transactions_inner(
                 transactions,
@@ -155,77 +168,117 @@ impl<T: Config> Pallet<T> {
 
     fn re_execute_transactions_inner(
         transactions: Vec<UserOrL1HandlerTransaction>,
-    ) -> Result<Result<Vec<TransactionExecutionInfo>, PlaceHolderErrorTypeForFailedStarknetExecution>, DispatchError>
-    {
+    ) -> Result<Result<Vec<TransactionExecutionInfo>, TransactionExecutionFailure>, DispatchError> {
         let chain_id = Self::chain_id();
         let block_context = Self::get_block_context();
         let execution_config = RuntimeExecutionConfigBuilder::new::<T>().build();
 
         let execution_infos = transactions
             .iter()
-            .map(|user_or_l1_tx| match user_or_l1_tx {
-                UserOrL1HandlerTransaction::User(tx) => match tx {
-                    UserTransaction::Declare(tx, contract_class) => tx
-                        .try_into_executable::<T::SystemHash>(chain_id, contract_class.clone(), false)
-                        .map_err(|e| {
-                            log::error!("Failed to reexecute a tx: {}", e);
-                            PlaceHolderErrorTypeForFailedStarknetExecution
-                        })
-                        .and_then(|executable| {
-                            executable
-                                .execute(&mut BlockifierStateAdapter::<T>::default(), &block_context, &execution_config)
-                                .map_err(|e| {
-                                    log::error!("Failed to reexecute a tx: {}", e);
-                                    PlaceHolderErrorTypeForFailedStarknetExecution
-                                })
-                        }),
-                    UserTransaction::DeployAccount(tx) => tx
-                        .into_executable::<T::SystemHash>(chain_id, false)
-                        .execute(&mut BlockifierStateAdapter::<T>::default(), &block_context, &execution_config)
-                        .map_err(|e| {
-                            log::error!("Failed to reexecute a tx: {}", e);
-                            PlaceHolderErrorTypeForFailedStarknetExecution
-                        }),
-                    UserTransaction::Invoke(tx) => tx
-                        .into_executable::<T::SystemHash>(chain_id, false)
-                        .execute(&mut BlockifierStateAdapter::<T>::default(), &block_context, &execution_config)
-                        .map_err(|e| {
-                            log::error!("Failed to reexecute a tx: {}", e);
-                            PlaceHolderErrorTypeForFailedStarknetExecution
-                        }),
-                },
-                UserOrL1HandlerTransaction::L1Handler(tx, fee) => tx
-                    .into_executable::<T::SystemHash>(chain_id, *fee, false)
-                    .execute(&mut BlockifierStateAdapter::<T>::default(), &block_context, &execution_config)
-                    .map_err(|e| {
-                        log::error!("Failed to reexecute a tx: {}", e);
-                        PlaceHolderErrorTypeForFailedStarknetExecution
-                    }),
+            .enumerate()
+            .map(|(index, user_or_l1_tx)| {
+                let mut state = BlockifierStateAdapter::<T>::default();
+                let execution_result = match user_or_l1_tx {
+                    UserOrL1HandlerTransaction::User(tx) => match tx {
+                        UserTransaction::Declare(tx, contract_class) => tx
+                            .try_into_executable::<T::SystemHash>(chain_id, contract_class.clone(), false)
+                            .map_err(FailedExecution::from)
+                            .and_then(|executable| {
+                                executable.execute_with_partial_trace(&mut state, &block_context, &execution_config)
+                            }),
+                        UserTransaction::DeployAccount(tx) => tx
+                            .into_executable::<T::SystemHash>(chain_id, false)
+                            .execute_with_partial_trace(&mut state, &block_context, &execution_config),
+                        UserTransaction::Invoke(tx) => tx
+                            .into_executable::<T::SystemHash>(chain_id, false)
+                            .execute_with_partial_trace(&mut state, &block_context, &execution_config),
+                    },
+                    UserOrL1HandlerTransaction::L1Handler(tx, fee) => tx
+                        .into_executable::<T::SystemHash>(chain_id, *fee, false)
+                        .execute_with_partial_trace(&mut state, &block_context, &execution_config),
+                };
+
+                execution_result.map_err(|failure| {
+                    log::error!("Failed to reexecute a tx: {}", failure.error);
+                    TransactionExecutionFailure::new(index, failure)
+                })
             })
             .collect::<Result<Vec<_>, _>>();
 
         Ok(execution_infos)
     }
 
+    /// Writes the state overrides of a simulation to the storage.
+    ///
+    /// Must only be called within a transactional storage layer which is rolled back afterwards.
+    fn apply_state_overrides(state_overrides: &StateOverrides) -> Result<(), DispatchError> {
+        for (contract_address, contract_override) in state_overrides {
+            if let Some(nonce) = contract_override.nonce {
+                Nonces::<T>::insert(contract_address, nonce);
+            }
+            if let Some(class_hash) = contract_override.class_hash {
+                ContractClassHashes::<T>::insert(contract_address, class_hash);
+            }
+            for (key, value) in contract_override.storage.iter() {
+                StorageView::<T>::insert((*contract_address, *key), *value);
+            }
+            if let Some(balance) = contract_override.fee_token_balance {
+                let (low_key, high_key) =
+                    get_erc20_balance_var_addresses(contract_address).map_err(|_| Error::<T>::InvalidStateOverride)?;
+                // The balance is an u256 stored as two 128 bits felts
+                let (high, low) = balance.bytes().split_at(16);
+                let mut low_bytes = [0; 32];
+                low_bytes[16..].copy_from_slice(low);
+                let mut high_bytes = [0; 32];
+                high_bytes[16..].copy_from_slice(high);
+
+                let fee_token_address = Self::fee_token_address();
+                StorageView::<T>::insert(
+                    (fee_token_address, low_key),
+                    StarkFelt::new(low_bytes).map_err(|_| Error::<T>::InvalidStateOverride)?,
+                );
+                StorageView::<T>::insert(
+                    (fee_token_address, high_key),
+                    StarkFelt::new(high_bytes).map_err(|_| Error::<T>::InvalidStateOverride)?,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     fn execute_user_transaction(
         transaction: UserTransaction,
         chain_id: Felt252Wrapper,
         block_context: &BlockContext,
         execution_config: &ExecutionConfig,
-    ) -> Result<TransactionExecutionInfo, TransactionExecutionError> {
+    ) -> Result<TransactionExecutionInfo, FailedExecution> {
         match transaction {
-            UserTransaction::Declare(tx, contract_class) => {
-                tx.try_into_executable::<T::SystemHash>(chain_id, contract_class.clone(), tx.offset_version()).and_then(
-                    |exec| exec.execute(&mut BlockifierStateAdapter::<T>::default(), block_context, execution_config),
-                )
-            }
+            UserTransaction::Declare(tx, contract_class) => tx
+                .try_into_executable::<T::SystemHash>(chain_id, contract_class.clone(), tx.offset_version())
+                .map_err(FailedExecution::from)
+                .and_then(|exec| {
+                    exec.execute_with_partial_trace(
+                        &mut BlockifierStateAdapter::<T>::default(),
+                        block_context,
+                        execution_config,
+                    )
+                }),
             UserTransaction::DeployAccount(tx) => {
                 let executable = tx.into_executable::<T::SystemHash>(chain_id, tx.offset_version());
-                executable.execute(&mut BlockifierStateAdapter::<T>::default(), block_context, execution_config)
+                executable.execute_with_partial_trace(
+                    &mut BlockifierStateAdapter::<T>::default(),
+                    block_context,
+                    execution_config,
+                )
             }
             UserTransaction::Invoke(tx) => {
                 let executable = tx.into_executable::<T::SystemHash>(chain_id, tx.offset_version());
-                executable.execute(&mut BlockifierStateAdapter::<T>::default(), block_context, execution_config)
+                executable.execute_with_partial_trace(
+                    &mut BlockifierStateAdapter::<T>::default(),
+                    block_context,
+                    execution_config,
+                )
             }
         }
     }
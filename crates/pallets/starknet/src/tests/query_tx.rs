@@ -1,7 +1,10 @@
 use frame_support::{assert_err, assert_ok};
 use mp_felt::Felt252Wrapper;
+use mp_simulations::ContractOverride;
 use mp_transactions::compute_hash::ComputeTransactionHash;
 use mp_transactions::UserTransaction;
+use starknet_api::api_core::{ContractAddress, Nonce};
+use starknet_api::hash::StarkFelt;
 
 use super::mock::default_mock::*;
 use super::mock::new_test_ext;
@@ -22,7 +25,7 @@ fn estimates_tx_fee_successfully_no_validate() {
 
         let txs = vec![tx_1, tx_2];
 
-        let fees = Starknet::estimate_fee(txs).expect("estimate should not fail");
+        let fees = Starknet::estimate_fee(txs, &vec![]).expect("estimate should not fail");
 
         let (actual, l1_gas_usage) = fees[0];
         assert!(actual > 0, "actual fee is missing");
@@ -45,7 +48,7 @@ fn estimates_tx_fee_with_query_version() {
 
         let tx_vec = vec![tx];
 
-        assert_ok!(Starknet::estimate_fee(tx_vec));
+        assert_ok!(Starknet::estimate_fee(tx_vec, &vec![]));
 
         assert!(pre_storage == Starknet::pending().len(), "estimate should not add a tx to pending");
     });
@@ -64,7 +67,7 @@ fn executable_tx_should_not_be_estimable() {
         let tx_vec = vec![UserTransaction::Invoke(tx.clone().into())];
 
         // it should be valid for estimate calls
-        assert_ok!(Starknet::estimate_fee(tx_vec));
+        assert_ok!(Starknet::estimate_fee(tx_vec, &vec![]));
 
         // it should be executable
         assert_ok!(Starknet::invoke(RuntimeOrigin::none(), tx.clone().into()));
@@ -85,7 +88,7 @@ fn query_tx_should_not_be_executable() {
         let tx_vec = vec![UserTransaction::Invoke(tx.clone().into())];
 
         // it should be valid for estimate calls
-        assert_ok!(Starknet::estimate_fee(tx_vec));
+        assert_ok!(Starknet::estimate_fee(tx_vec, &vec![]));
 
         // it should not be executable
         assert_err!(
@@ -94,3 +97,25 @@ fn query_tx_should_not_be_executable() {
         );
     });
 }
+
+#[test]
+fn estimates_tx_fee_with_nonce_override() {
+    new_test_ext::<MockRuntime>().execute_with(|| {
+        basic_test_setup(2);
+
+        let tx = get_invoke_dummy(Felt252Wrapper::from(5u64));
+        let sender_address = ContractAddress::from(tx.sender_address);
+        let tx_vec = vec![UserTransaction::Invoke(tx.into())];
+
+        assert!(Starknet::estimate_fee(tx_vec.clone(), &vec![]).is_err());
+
+        let state_overrides = vec![(
+            sender_address,
+            ContractOverride { nonce: Some(Nonce(StarkFelt::from(5u128))), ..Default::default() },
+        )];
+        assert_ok!(Starknet::estimate_fee(tx_vec, &state_overrides));
+
+        // The override is rolled back with the rest of the simulation
+        assert_eq!(Starknet::nonce(sender_address), Nonce(StarkFelt::from(0u128)));
+    });
+}
@@ -11,6 +11,9 @@ use starknet_api::hash::StarkHash;
 use starknet_api::stdlib::collections::HashMap;
 use starknet_core::types::FieldElement;
 
+/// First mainnet block produced by Starknet 0.7.0, which changed the block hash formula.
+pub const MAINNET_V0_7_BLOCK_NUMBER: u64 = 833;
+
 /// Block status.
 ///
 /// The status of the block.
@@ -128,9 +131,23 @@ impl Header {
         }
     }
 
-    /// Compute the hash of the header.
+    /// Compute the hash of the header, as on Starknet mainnet.
     pub fn hash<H: HasherT>(&self) -> Felt252Wrapper {
-        if self.block_number >= 833 {
+        self.hash_on_chain::<H>(
+            Felt252Wrapper(FieldElement::from_byte_slice_be(b"SN_MAIN").unwrap()),
+            MAINNET_V0_7_BLOCK_NUMBER,
+        )
+    }
+
+    /// Compute the hash of the header on a given chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain_id` - The id of the chain, hashed in place of the sequencer address by the blocks
+    ///   produced before Starknet 0.7.0
+    /// * `v0_7_block_number` - The first block of the chain produced by Starknet 0.7.0
+    pub fn hash_on_chain<H: HasherT>(&self, chain_id: Felt252Wrapper, v0_7_block_number: u64) -> Felt252Wrapper {
+        if self.block_number >= v0_7_block_number {
             // Computes the block hash for blocks generated after Cairo 0.7.0
             let data: &[Felt252Wrapper] = &[
                 self.block_number.into(),           // block number
@@ -160,14 +177,10 @@ impl Header {
                 Felt252Wrapper::ZERO,
                 Felt252Wrapper::ZERO,
                 Felt252Wrapper::ZERO,
-                Felt252Wrapper(FieldElement::from_byte_slice_be(b"SN_MAIN").unwrap()),
+                chain_id,
                 self.parent_block_hash.into(),
             ];
 
-            // for (i, item) in data.iter().enumerate() {
-            //     frame_support::log::info!("data[{}]: {:?}", i, item);
-            // }
-
             H::compute_hash_on_wrappers(data)
         }
     }
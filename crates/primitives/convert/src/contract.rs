@@ -124,6 +124,36 @@ pub fn flattened_sierra_to_sierra_contract_class(
     }
 }
 
+/// Converts a Sierra class stored by the node back to the [FlattenedSierraClass] it was built from
+///
+/// # Arguments
+///
+/// * `contract_class` - The Sierra class, as built by [flattened_sierra_to_sierra_contract_class]
+/// * `contract_class_version` - The version of the Sierra language the class is written in
+pub fn sierra_contract_class_to_flattened_sierra(
+    contract_class: &starknet_api::state::ContractClass,
+    contract_class_version: String,
+) -> FlattenedSierraClass {
+    let entry_points = |entry_point_type| -> Vec<SierraEntryPoint> {
+        contract_class
+            .entry_point_by_type
+            .get(&entry_point_type)
+            .map(|entry_points| entry_points.iter().map(starknet_api_entry_point_to_rpc_entry_point).collect())
+            .unwrap_or_default()
+    };
+
+    FlattenedSierraClass {
+        sierra_program: contract_class.sierra_program.iter().map(|f| Felt252Wrapper::from(*f).into()).collect(),
+        contract_class_version,
+        entry_points_by_type: EntryPointsByType {
+            constructor: entry_points(starknet_api::state::EntryPointType::Constructor),
+            external: entry_points(starknet_api::state::EntryPointType::External),
+            l1_handler: entry_points(starknet_api::state::EntryPointType::L1Handler),
+        },
+        abi: contract_class.abi.clone(),
+    }
+}
+
 /// Converts a [EntryPointsByType] to a [ContractEntryPoints]
 fn entry_points_by_type_to_contract_entry_points(value: EntryPointsByType) -> ContractEntryPoints {
     fn sierra_entry_point_to_contract_entry_point(value: SierraEntryPoint) -> ContractEntryPoint {
@@ -146,6 +176,10 @@ fn rpc_entry_point_to_starknet_api_entry_point(value: &SierraEntryPoint) -> star
     }
 }
 
+fn starknet_api_entry_point_to_rpc_entry_point(value: &starknet_api::state::EntryPoint) -> SierraEntryPoint {
+    SierraEntryPoint { selector: Felt252Wrapper::from(value.selector.0).into(), function_idx: value.function_idx.0 }
+}
+
 /// Returns a compressed vector of bytes
 pub(crate) fn compress(data: &[u8]) -> anyhow::Result<Vec<u8>> {
     let mut gzip_encoder = GzEncoder::new(Vec::new(), flate2::Compression::fast());
@@ -3,18 +3,43 @@
 #[doc(hidden)]
 pub extern crate alloc;
 
+use alloc::string::String;
 use alloc::vec::Vec;
 
+use blockifier::execution::entry_point::CallInfo;
+use mp_transactions::execution::FailedExecution;
+use starknet_api::api_core::{ClassHash, ContractAddress, Nonce};
+use starknet_api::hash::StarkFelt;
+use starknet_api::state::StorageKey;
 use starknet_core::types::SimulationFlag;
 
-// TODO: This is a placeholder
-// https://github.com/starkware-libs/starknet-specs/blob/master/api/starknet_api_openrpc.json#L3919
-// The official rpc expect use to return the trace up to the point of failure.
-// Figuring out how to get that is a problem for later
-#[derive(Debug)]
+/// A transaction which failed while being simulated or re-executed.
+///
+/// Holds what the `TRANSACTION_EXECUTION_ERROR` of the RPC specification reports, along with the
+/// trace of the failing transaction up to the point of failure.
+/// https://github.com/starkware-libs/starknet-specs/blob/master/api/starknet_api_openrpc.json#L3919
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "scale-info", derive(scale_info::TypeInfo))]
 #[cfg_attr(feature = "parity-scale-codec", derive(parity_scale_codec::Encode, parity_scale_codec::Decode))]
-pub struct PlaceHolderErrorTypeForFailedStarknetExecution;
+pub struct TransactionExecutionFailure {
+    /// Index of the failing transaction among the executed ones.
+    pub transaction_index: u32,
+    /// Why the transaction failed, with the Cairo VM trace of the failing entry point if any.
+    pub revert_reason: String,
+    /// The top level calls of the transaction which completed before the failure, with their inner
+    /// calls. See [`FailedExecution::completed_calls`].
+    pub partial_call_infos: Vec<CallInfo>,
+}
+
+impl TransactionExecutionFailure {
+    pub fn new(transaction_index: usize, failure: FailedExecution) -> Self {
+        Self {
+            transaction_index: transaction_index as u32,
+            revert_reason: failure.revert_reason,
+            partial_call_infos: failure.completed_calls,
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "parity-scale-codec", derive(parity_scale_codec::Encode, parity_scale_codec::Decode))]
@@ -22,6 +47,8 @@ pub struct PlaceHolderErrorTypeForFailedStarknetExecution;
 pub struct SimulationFlags {
     pub skip_validate: bool,
     pub skip_fee_charge: bool,
+    /// Not part of the RPC specification, allows simulating transactions with any nonce.
+    pub skip_nonce_check: bool,
 }
 
 impl From<Vec<SimulationFlag>> for SimulationFlags {
@@ -39,6 +66,24 @@ impl From<Vec<SimulationFlag>> for SimulationFlags {
             }
         }
 
-        Self { skip_validate, skip_fee_charge }
+        Self { skip_validate, skip_fee_charge, skip_nonce_check: false }
     }
 }
+
+/// Changes made to the state of a contract before simulating transactions or estimating their
+/// fees.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "parity-scale-codec", derive(parity_scale_codec::Encode, parity_scale_codec::Decode))]
+#[cfg_attr(feature = "scale-info", derive(scale_info::TypeInfo))]
+pub struct ContractOverride {
+    pub nonce: Option<Nonce>,
+    pub class_hash: Option<ClassHash>,
+    pub storage: Vec<(StorageKey, StarkFelt)>,
+    /// Balance of the contract in the fee token, as a felt, so balances from the field prime up
+    /// cannot be set. Its low and high 128 bits are written to the two halves of the `u256`
+    /// balance.
+    pub fee_token_balance: Option<StarkFelt>,
+}
+
+/// The changes made to the state before a simulation, applied in order.
+pub type StateOverrides = Vec<(ContractAddress, ContractOverride)>;
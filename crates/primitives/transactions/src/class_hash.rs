@@ -0,0 +1,149 @@
+//! Computation of the hash of a contract class.
+//!
+//! Classes are served by the feeder gateway alongside their hash: the hash is recomputed from the
+//! class itself so that a class can be checked against the hash it was declared with.
+
+use flate2::read::GzDecoder;
+use mp_hashers::pedersen::PedersenHasher;
+use mp_hashers::poseidon::PoseidonHasher;
+use mp_hashers::HasherT;
+use starknet_core::types::contract::legacy::{LegacyContractClass, LegacyProgram};
+use starknet_core::types::{
+    CompressedLegacyContractClass, ContractClass, FlattenedSierraClass, LegacyContractEntryPoint, SierraEntryPoint,
+};
+use starknet_core::utils::{cairo_short_string_to_felt, starknet_keccak};
+use starknet_crypto::FieldElement;
+use thiserror::Error;
+
+use crate::from_broadcasted_transactions::to_raw_legacy_entry_points;
+
+/// Version of the hash of legacy classes
+const LEGACY_API_VERSION: FieldElement = FieldElement::ZERO;
+/// Prefix of the version of a Sierra class, as hashed
+const SIERRA_VERSION_PREFIX: &str = "CONTRACT_CLASS_V";
+
+#[derive(Debug, Error)]
+pub enum ClassHashError {
+    #[error("Failed to decompress the program")]
+    ProgramDecompressionFailed,
+    #[error("Failed to deserialize the program")]
+    ProgramDeserializationFailed,
+    #[error("Failed to compute the hinted class hash")]
+    HintedClassHashComputationFailed,
+    #[error("Invalid short string: {0}")]
+    InvalidShortString(String),
+}
+
+/// Computes the hash of a contract class.
+///
+/// # Arguments
+///
+/// * `contract_class` - The class, as served by the feeder gateway
+///
+/// # Returns
+///
+/// The Pedersen based hash of a legacy class, or the Poseidon based hash of a Sierra class.
+pub fn compute_class_hash(contract_class: &ContractClass) -> Result<FieldElement, ClassHashError> {
+    match contract_class {
+        ContractClass::Legacy(legacy) => compute_legacy_class_hash(legacy),
+        ContractClass::Sierra(sierra) => compute_sierra_class_hash(sierra),
+    }
+}
+
+/// Computes the hash of a legacy (Cairo 0) class.
+///
+/// # Arguments
+///
+/// * `contract_class` - The class, whose program is compressed
+///
+/// # Returns
+///
+/// The Pedersen hash of the entry points, builtins, hinted class hash and bytecode of the class.
+pub fn compute_legacy_class_hash(
+    contract_class: &CompressedLegacyContractClass,
+) -> Result<FieldElement, ClassHashError> {
+    let mut decompressed_program = Vec::new();
+    std::io::Read::read_to_end(&mut GzDecoder::new(&contract_class.program[..]), &mut decompressed_program)
+        .map_err(|_| ClassHashError::ProgramDecompressionFailed)?;
+    let program: LegacyProgram =
+        serde_json::from_slice(&decompressed_program).map_err(|_| ClassHashError::ProgramDeserializationFailed)?;
+
+    let builtins =
+        program.builtins.iter().map(|builtin| short_string_to_felt(builtin)).collect::<Result<Vec<_>, _>>()?;
+    let bytecode_hash = PedersenHasher::compute_hash_on_elements(&program.data);
+
+    let entry_points = &contract_class.entry_points_by_type;
+    let external_hash = legacy_entry_points_hash(&entry_points.external);
+    let l1_handler_hash = legacy_entry_points_hash(&entry_points.l1_handler);
+    let constructor_hash = legacy_entry_points_hash(&entry_points.constructor);
+
+    // The hinted class hash covers the program with its hints and the abi, as serialized by the
+    // Cairo 0 compiler
+    let hinted_class_hash = LegacyContractClass {
+        program,
+        abi: contract_class.abi.as_ref().map(|abi| abi.iter().cloned().map(|entry| entry.into()).collect()),
+        entry_points_by_type: to_raw_legacy_entry_points(contract_class.entry_points_by_type.clone()),
+    }
+    .hinted_class_hash()
+    .map_err(|_| ClassHashError::HintedClassHashComputationFailed)?;
+
+    Ok(PedersenHasher::compute_hash_on_elements(&[
+        LEGACY_API_VERSION,
+        external_hash,
+        l1_handler_hash,
+        constructor_hash,
+        PedersenHasher::compute_hash_on_elements(&builtins),
+        hinted_class_hash,
+        bytecode_hash,
+    ]))
+}
+
+/// Computes the hash of a Sierra (Cairo 1) class.
+///
+/// # Arguments
+///
+/// * `contract_class` - The class, whose abi is the string served by the feeder gateway
+///
+/// # Returns
+///
+/// The Poseidon hash of the version, entry points, abi and Sierra program of the class.
+pub fn compute_sierra_class_hash(contract_class: &FlattenedSierraClass) -> Result<FieldElement, ClassHashError> {
+    let version = short_string_to_felt(&format!("{SIERRA_VERSION_PREFIX}{}", contract_class.contract_class_version))?;
+
+    let entry_points = &contract_class.entry_points_by_type;
+
+    Ok(PoseidonHasher::compute_hash_on_elements(&[
+        version,
+        sierra_entry_points_hash(&entry_points.external),
+        sierra_entry_points_hash(&entry_points.l1_handler),
+        sierra_entry_points_hash(&entry_points.constructor),
+        starknet_keccak(contract_class.abi.as_bytes()),
+        PoseidonHasher::compute_hash_on_elements(&contract_class.sierra_program),
+    ]))
+}
+
+fn legacy_entry_points_hash(entry_points: &[LegacyContractEntryPoint]) -> FieldElement {
+    let elements: Vec<_> = entry_points
+        .iter()
+        .flat_map(|entry_point| [entry_point.selector, FieldElement::from(entry_point.offset)])
+        .collect();
+
+    PedersenHasher::compute_hash_on_elements(&elements)
+}
+
+fn sierra_entry_points_hash(entry_points: &[SierraEntryPoint]) -> FieldElement {
+    let elements: Vec<_> = entry_points
+        .iter()
+        .flat_map(|entry_point| [entry_point.selector, FieldElement::from(entry_point.function_idx)])
+        .collect();
+
+    PoseidonHasher::compute_hash_on_elements(&elements)
+}
+
+fn short_string_to_felt(string: &str) -> Result<FieldElement, ClassHashError> {
+    cairo_short_string_to_felt(string).map_err(|_| ClassHashError::InvalidShortString(string.to_string()))
+}
+
+#[cfg(test)]
+#[path = "class_hash_tests.rs"]
+mod class_hash_tests;
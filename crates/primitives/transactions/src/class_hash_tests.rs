@@ -0,0 +1,74 @@
+use starknet_core::types::contract::legacy::LegacyContractClass;
+use starknet_core::types::contract::SierraClass;
+
+use super::*;
+
+/// Hash of the OpenZeppelin account class of `resources/classes/open_zeppelin_account.json`
+const OPEN_ZEPPELIN_ACCOUNT_CLASS_HASH: &str = "0x04c6d6cf894f8bc96bb9c525e6853e5483177841f7388f74a46cfda6f028c755";
+
+/// Reads a class as served by the `get_class_by_hash` endpoint of the feeder gateway.
+fn gateway_class(bytes: &[u8]) -> ContractClass {
+    serde_json::from_slice(bytes).unwrap()
+}
+
+#[test]
+fn legacy_class_hash_works_like_starknet_core_impl() {
+    let contract_class_bytes = include_bytes!("../../../../cairo-contracts/build/test.json");
+    let contract_class: LegacyContractClass = serde_json::from_slice(contract_class_bytes).unwrap();
+    let expected = contract_class.class_hash().unwrap();
+
+    let compressed_contract_class = contract_class.compress().unwrap();
+
+    assert_eq!(compute_legacy_class_hash(&compressed_contract_class).unwrap(), expected);
+}
+
+#[test]
+fn sierra_class_hash_works_like_starknet_core_impl() {
+    let contract_class_bytes = include_bytes!("../../../../cairo-contracts/build/cairo_1/HelloStarknet.sierra.json");
+    let contract_class: SierraClass = serde_json::from_slice(contract_class_bytes).unwrap();
+    let flattened_contract_class = contract_class.flatten().unwrap();
+
+    assert_eq!(compute_sierra_class_hash(&flattened_contract_class).unwrap(), flattened_contract_class.class_hash());
+}
+
+#[test]
+fn legacy_class_hash_fails_on_bad_gzip() {
+    let contract_class_bytes = include_bytes!("../../../../cairo-contracts/build/test.json");
+    let contract_class: LegacyContractClass = serde_json::from_slice(contract_class_bytes).unwrap();
+    let mut compressed_contract_class = contract_class.compress().unwrap();
+    compressed_contract_class.program[0] = 0;
+
+    assert!(matches!(
+        compute_legacy_class_hash(&compressed_contract_class),
+        Err(ClassHashError::ProgramDecompressionFailed)
+    ));
+}
+
+#[test]
+fn sierra_class_served_by_the_gateway_hashes_to_its_declared_hash() {
+    let contract_class = gateway_class(include_bytes!("../resources/classes/open_zeppelin_account.json"));
+    assert!(matches!(contract_class, ContractClass::Sierra(_)));
+
+    assert_eq!(
+        compute_class_hash(&contract_class).unwrap(),
+        FieldElement::from_hex_be(OPEN_ZEPPELIN_ACCOUNT_CLASS_HASH).unwrap()
+    );
+}
+
+#[test]
+fn sierra_abi_is_hashed_as_served() {
+    let ContractClass::Sierra(mut contract_class) =
+        gateway_class(include_bytes!("../resources/classes/open_zeppelin_account.json"))
+    else {
+        panic!("expected a Sierra class");
+    };
+
+    // The abi is hashed as the string it was declared with, which serializing it again changes
+    let abi: serde_json::Value = serde_json::from_str(&contract_class.abi).unwrap();
+    contract_class.abi = serde_json::to_string(&abi).unwrap();
+
+    assert_ne!(
+        compute_sierra_class_hash(&contract_class).unwrap(),
+        FieldElement::from_hex_be(OPEN_ZEPPELIN_ACCOUNT_CLASS_HASH).unwrap()
+    );
+}
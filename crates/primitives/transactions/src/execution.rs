@@ -1,4 +1,4 @@
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -77,6 +77,41 @@ impl ValidateExecuteCallInfo {
     }
 }
 
+/// A transaction execution which failed, along with the calls which completed before the failure.
+#[derive(Debug)]
+pub struct FailedExecution {
+    pub error: TransactionExecutionError,
+    /// The error trace of the failing entry point, or the error itself if it happened outside of
+    /// the Cairo VM.
+    pub revert_reason: String,
+    /// The top level calls which completed before the failure, in execution order, each with its
+    /// inner calls.
+    ///
+    /// The failing call itself is the next top level call, at index `completed_calls.len()`. The
+    /// blockifier does not return the call info of a failing call, so the inner calls it made
+    /// before failing are lost.
+    pub completed_calls: Vec<CallInfo>,
+}
+
+impl FailedExecution {
+    fn new(
+        error: TransactionExecutionError,
+        context: &EntryPointExecutionContext,
+        completed_calls: Vec<CallInfo>,
+    ) -> Self {
+        let error_trace = context.error_trace();
+        let revert_reason = if error_trace.is_empty() { error.to_string() } else { error_trace };
+
+        Self { error, revert_reason, completed_calls }
+    }
+}
+
+impl From<TransactionExecutionError> for FailedExecution {
+    fn from(error: TransactionExecutionError) -> Self {
+        Self { revert_reason: error.to_string(), error, completed_calls: Vec::new() }
+    }
+}
+
 pub trait GetAccountTransactionContext {
     fn get_account_transaction_context(&self, offset_version: bool) -> AccountTransactionContext;
 }
@@ -302,7 +337,7 @@ pub trait Execute: Sized + GetAccountTransactionContext + GetTransactionCalldata
         remaining_gas: &mut u64,
         account_tx_context: &AccountTransactionContext,
         disable_validation: bool,
-    ) -> TransactionExecutionResult<ValidateExecuteCallInfo>;
+    ) -> Result<ValidateExecuteCallInfo, FailedExecution>;
 
     fn handle_nonce(
         account_tx_context: &AccountTransactionContext,
@@ -373,6 +408,16 @@ pub trait Execute: Sized + GetAccountTransactionContext + GetTransactionCalldata
         block_context: &BlockContext,
         execution_config: &ExecutionConfig,
     ) -> TransactionExecutionResult<TransactionExecutionInfo> {
+        self.execute_with_partial_trace(state, block_context, execution_config).map_err(|failure| failure.error)
+    }
+
+    /// Same as [`Execute::execute`], but keeps the calls which completed before a failure.
+    fn execute_with_partial_trace<S: State + StateChanges>(
+        &self,
+        state: &mut S,
+        block_context: &BlockContext,
+        execution_config: &ExecutionConfig,
+    ) -> Result<TransactionExecutionInfo, FailedExecution> {
         let mut execution_resources = ExecutionResources::default();
         let mut remaining_gas = TX_INITIAL_AVAILABLE_GAS;
 
@@ -391,15 +436,20 @@ pub trait Execute: Sized + GetAccountTransactionContext + GetTransactionCalldata
             execution_config.disable_validation,
         )?;
 
-        let (actual_fee, fee_transfer_call_info, actual_resources) = self.handle_fee(
-            state,
-            &execute_call_info,
-            &validate_call_info,
-            &mut execution_resources,
-            block_context,
-            account_tx_context,
-            execution_config,
-        )?;
+        let (actual_fee, fee_transfer_call_info, actual_resources) = self
+            .handle_fee(
+                state,
+                &execute_call_info,
+                &validate_call_info,
+                &mut execution_resources,
+                block_context,
+                account_tx_context,
+                execution_config,
+            )
+            .map_err(|error| FailedExecution {
+                completed_calls: validate_call_info.iter().chain(execute_call_info.iter()).cloned().collect(),
+                ..FailedExecution::from(error)
+            })?;
 
         let tx_execution_info = TransactionExecutionInfo {
             validate_call_info,
@@ -460,7 +510,7 @@ impl Execute for InvokeTransaction {
         remaining_gas: &mut u64,
         account_tx_context: &AccountTransactionContext,
         disable_validation: bool,
-    ) -> TransactionExecutionResult<ValidateExecuteCallInfo> {
+    ) -> Result<ValidateExecuteCallInfo, FailedExecution> {
         let mut context = EntryPointExecutionContext::new(
             block_context.clone(),
             account_tx_context.clone(),
@@ -474,14 +524,17 @@ impl Execute for InvokeTransaction {
                 remaining_gas,
                 &mut context,
                 GetTransactionCalldata::calldata(self),
-            )?
+            )
+            .map_err(|e| FailedExecution::new(e, &context, Vec::new()))?
         } else {
             None
         };
         let validate_execute_call_info = match self.tx {
             // V0 tx cannot revert, we cannot charge the failling ones
             starknet_api::transaction::InvokeTransaction::V0(_) => {
-                let execute_call_info = self.run_execute(state, resources, &mut context, remaining_gas)?;
+                let execute_call_info = self
+                    .run_execute(state, resources, &mut context, remaining_gas)
+                    .map_err(|e| FailedExecution::new(e, &context, validate_call_info.iter().cloned().collect()))?;
                 ValidateExecuteCallInfo::new_accepted(validate_call_info, execute_call_info)
             }
             starknet_api::transaction::InvokeTransaction::V1(_) => {
@@ -514,7 +567,7 @@ impl Execute for DeclareTransaction {
         remaining_gas: &mut u64,
         account_tx_context: &AccountTransactionContext,
         disable_validation: bool,
-    ) -> TransactionExecutionResult<ValidateExecuteCallInfo> {
+    ) -> Result<ValidateExecuteCallInfo, FailedExecution> {
         let mut context = EntryPointExecutionContext::new(
             block_context.clone(),
             account_tx_context.clone(),
@@ -522,14 +575,17 @@ impl Execute for DeclareTransaction {
         );
 
         let validate_call_info = if !disable_validation {
-            self.validate_tx_inner(state, resources, remaining_gas, &mut context, self.calldata())?
+            self.validate_tx_inner(state, resources, remaining_gas, &mut context, self.calldata())
+                .map_err(|e| FailedExecution::new(e, &context, Vec::new()))?
         } else {
             None
         };
         let validate_execute_call_info = match self.tx() {
             // V0 tx cannot revert, we cannot charge the failling ones
             starknet_api::transaction::DeclareTransaction::V0(_) => {
-                let execute_call_info = self.run_execute(state, resources, &mut context, remaining_gas)?;
+                let execute_call_info = self
+                    .run_execute(state, resources, &mut context, remaining_gas)
+                    .map_err(|e| FailedExecution::new(e, &context, validate_call_info.iter().cloned().collect()))?;
                 ValidateExecuteCallInfo::new_accepted(validate_call_info, execute_call_info)
             }
             starknet_api::transaction::DeclareTransaction::V1(_)
@@ -560,7 +616,7 @@ impl Execute for DeployAccountTransaction {
         remaining_gas: &mut u64,
         account_tx_context: &AccountTransactionContext,
         disable_validation: bool,
-    ) -> TransactionExecutionResult<ValidateExecuteCallInfo> {
+    ) -> Result<ValidateExecuteCallInfo, FailedExecution> {
         let mut context = EntryPointExecutionContext::new(
             block_context.clone(),
             account_tx_context.clone(),
@@ -569,9 +625,12 @@ impl Execute for DeployAccountTransaction {
 
         // In order to be verified the tx must first be executed
         // so that the `constructor` method can initialize the account state
-        let execute_call_info = self.run_execute(state, resources, &mut context, remaining_gas)?;
+        let execute_call_info = self
+            .run_execute(state, resources, &mut context, remaining_gas)
+            .map_err(|e| FailedExecution::new(e, &context, Vec::new()))?;
         let validate_call_info = if !disable_validation {
-            self.validate_tx_inner(state, resources, remaining_gas, &mut context, self.calldata())?
+            self.validate_tx_inner(state, resources, remaining_gas, &mut context, self.calldata())
+                .map_err(|e| FailedExecution::new(e, &context, execute_call_info.iter().cloned().collect()))?
         } else {
             None
         };
@@ -593,14 +652,16 @@ impl Execute for L1HandlerTransaction {
         remaining_gas: &mut u64,
         account_tx_context: &AccountTransactionContext,
         _disable_validation: bool,
-    ) -> TransactionExecutionResult<ValidateExecuteCallInfo> {
+    ) -> Result<ValidateExecuteCallInfo, FailedExecution> {
         let mut context = EntryPointExecutionContext::new(
             block_context.clone(),
             account_tx_context.clone(),
             block_context.invoke_tx_max_n_steps,
         );
 
-        let execute_call_info = self.run_execute(state, resources, &mut context, remaining_gas)?;
+        let execute_call_info = self
+            .run_execute(state, resources, &mut context, remaining_gas)
+            .map_err(|e| FailedExecution::new(e, &context, Vec::new()))?;
 
         Ok(ValidateExecuteCallInfo::new_accepted(None, execute_call_info))
     }
@@ -241,7 +241,7 @@ fn to_raw_legacy_entry_point(entry_point: LegacyContractEntryPoint) -> RawLegacy
     RawLegacyEntryPoint { offset: LegacyEntrypointOffset::U64AsInt(entry_point.offset), selector: entry_point.selector }
 }
 
-fn to_raw_legacy_entry_points(entry_points: LegacyEntryPointsByType) -> RawLegacyEntryPoints {
+pub(crate) fn to_raw_legacy_entry_points(entry_points: LegacyEntryPointsByType) -> RawLegacyEntryPoints {
     RawLegacyEntryPoints {
         constructor: entry_points.constructor.into_iter().map(to_raw_legacy_entry_point).collect(),
         external: entry_points.external.into_iter().map(to_raw_legacy_entry_point).collect(),
@@ -4,6 +4,8 @@
 #[doc(hidden)]
 pub extern crate alloc;
 
+#[cfg(feature = "client")]
+pub mod class_hash;
 pub mod compute_hash;
 pub mod conversions;
 pub mod execution;
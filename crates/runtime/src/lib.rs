@@ -30,7 +30,7 @@ pub use frame_system::Call as SystemCall;
 use frame_system::{EventRecord, Phase};
 use mp_contract::ContractAbi;
 use mp_felt::Felt252Wrapper;
-use mp_simulations::{PlaceHolderErrorTypeForFailedStarknetExecution, SimulationFlags};
+use mp_simulations::{SimulationFlags, StateOverrides, TransactionExecutionFailure};
 use mp_transactions::compute_hash::ComputeTransactionHash;
 use mp_transactions::{HandleL1MessageTransaction, Transaction, UserOrL1HandlerTransaction, UserTransaction};
 use pallet_grandpa::{fg_primitives, AuthorityId as GrandpaId, AuthorityList as GrandpaAuthorityList};
@@ -282,11 +282,11 @@ impl_runtime_apis! {
             Starknet::is_transaction_fee_disabled()
         }
 
-        fn estimate_fee(transactions: Vec<UserTransaction>) -> Result<Vec<(u64, u64)>, DispatchError> {
-            Starknet::estimate_fee(transactions)
+        fn estimate_fee(transactions: Vec<UserTransaction>, state_overrides: StateOverrides) -> Result<Vec<(u64, u64)>, DispatchError> {
+            Starknet::estimate_fee(transactions, &state_overrides)
         }
 
-        fn re_execute_transactions(transactions: Vec<UserOrL1HandlerTransaction>) -> Result<Result<Vec<TransactionExecutionInfo>, PlaceHolderErrorTypeForFailedStarknetExecution>, DispatchError> {
+        fn re_execute_transactions(transactions: Vec<UserOrL1HandlerTransaction>) -> Result<Result<Vec<TransactionExecutionInfo>, TransactionExecutionFailure>, DispatchError> {
             Starknet::re_execute_transactions(transactions)
         }
 
@@ -294,8 +294,8 @@ impl_runtime_apis! {
             Starknet::estimate_message_fee(message)
         }
 
-        fn simulate_transactions(transactions: Vec<UserTransaction>, simulation_flags: SimulationFlags) -> Result<Vec<Result<TransactionExecutionInfo, PlaceHolderErrorTypeForFailedStarknetExecution>>, DispatchError> {
-            Starknet::simulate_transactions(transactions, &simulation_flags)
+        fn simulate_transactions(transactions: Vec<UserTransaction>, simulation_flags: SimulationFlags, state_overrides: StateOverrides) -> Result<Vec<Result<TransactionExecutionInfo, TransactionExecutionFailure>>, DispatchError> {
+            Starknet::simulate_transactions(transactions, &simulation_flags, &state_overrides)
         }
 
         fn get_starknet_events_and_their_associated_tx_index() -> Vec<(u32, StarknetEvent)> {